@@ -0,0 +1,11 @@
+//! Run with `cargo +nightly fuzz run extract_request_body` from the repository root
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // Any input has to be either parsed or rejected, panics are bugs
+    if let Ok((_metadata, file_content)) = registry_server::extract_request_body(data) {
+        assert!(data.ends_with(file_content));
+    }
+});
@@ -0,0 +1,20 @@
+CREATE TABLE users (
+    user_id SERIAL PRIMARY KEY,
+    login TEXT NOT NULL UNIQUE,
+    email TEXT
+);
+
+-- Only the SHA-256 hex digest of a token is stored
+CREATE TABLE tokens (
+    token_id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    token_hash TEXT NOT NULL UNIQUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMPTZ
+);
+
+CREATE TABLE crate_owners (
+    crate_id INTEGER NOT NULL REFERENCES crates (crate_id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    PRIMARY KEY (crate_id, user_id)
+);
@@ -0,0 +1,4 @@
+ALTER TABLE crates
+    ADD COLUMN deprecated BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN deprecation_message TEXT,
+    ADD COLUMN deprecation_replacement TEXT;
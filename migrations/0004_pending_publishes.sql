@@ -0,0 +1,9 @@
+-- Write-ahead log of publishes whose index and database writes haven't both finished.
+-- Rows are written before touching the index and removed once the publish is consistent.
+CREATE TABLE pending_publishes (
+    pending_id SERIAL PRIMARY KEY,
+    crate_name TEXT NOT NULL,
+    vers TEXT NOT NULL,
+    index_line TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,6 @@
+CREATE TABLE crate_badges (
+    crate_id INTEGER NOT NULL REFERENCES crates (crate_id) ON DELETE CASCADE,
+    badge_type TEXT NOT NULL,
+    badge_params JSONB NOT NULL,
+    PRIMARY KEY (crate_id, badge_type)
+);
@@ -0,0 +1,23 @@
+-- Append-only record of who did what to which crate
+CREATE TABLE audit_events (
+    event_id BIGSERIAL PRIMARY KEY,
+    occurred_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    -- Login of the authenticated user, NULL for unauthenticated requests
+    actor TEXT,
+    action TEXT NOT NULL,
+    crate_name TEXT,
+    version TEXT,
+    request_id TEXT NOT NULL,
+    outcome TEXT NOT NULL CHECK (outcome IN ('success', 'failure')),
+    -- Set for failures only
+    error_category TEXT
+);
+CREATE INDEX audit_events_occurred_at ON audit_events (occurred_at);
+CREATE INDEX audit_events_crate_name ON audit_events (crate_name, occurred_at);
+
+CREATE FUNCTION reject_audit_event_change() RETURNS TRIGGER
+    LANGUAGE plpgsql
+    AS $$ BEGIN RAISE EXCEPTION 'audit_events is append-only'; END $$;
+CREATE TRIGGER audit_events_append_only
+    BEFORE UPDATE OR DELETE ON audit_events
+    FOR EACH ROW EXECUTE FUNCTION reject_audit_event_change();
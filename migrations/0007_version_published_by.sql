@@ -0,0 +1,2 @@
+-- NULL for versions published without authentication
+ALTER TABLE versions ADD COLUMN published_by INTEGER REFERENCES users (user_id);
@@ -0,0 +1,12 @@
+-- Pending invitations only, accepting or declining removes the row
+CREATE TABLE crate_owner_invitations (
+    invitation_id SERIAL PRIMARY KEY,
+    crate_id INTEGER NOT NULL REFERENCES crates (crate_id) ON DELETE CASCADE,
+    invitee_id INTEGER NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    inviter_id INTEGER NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMPTZ NOT NULL,
+    UNIQUE (crate_id, invitee_id)
+);
+
+CREATE INDEX crate_owner_invitations_invitee ON crate_owner_invitations (invitee_id);
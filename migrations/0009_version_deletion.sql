@@ -0,0 +1,25 @@
+-- Versions published before this migration have no publish time and are never in the grace window
+ALTER TABLE versions ADD COLUMN created_at TIMESTAMPTZ;
+ALTER TABLE versions ALTER COLUMN created_at SET DEFAULT NOW();
+
+ALTER TABLE versions ADD COLUMN yanked BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE version_downloads (
+    crate_id INTEGER NOT NULL,
+    vers TEXT NOT NULL,
+    date DATE NOT NULL DEFAULT CURRENT_DATE,
+    count BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (crate_id, vers, date),
+    FOREIGN KEY (crate_id, vers) REFERENCES versions (crate, vers) ON DELETE CASCADE
+);
+
+-- Dependencies on crates of this registry, to find reverse dependencies
+CREATE TABLE version_dependencies (
+    crate_id INTEGER NOT NULL,
+    vers TEXT NOT NULL,
+    dependency_name TEXT NOT NULL,
+    version_req TEXT NOT NULL,
+    FOREIGN KEY (crate_id, vers) REFERENCES versions (crate, vers) ON DELETE CASCADE
+);
+
+CREATE INDEX version_dependencies_name ON version_dependencies (normalize_crate_name(dependency_name));
@@ -0,0 +1,21 @@
+-- Teams are named like crates.io teams, e.g. `github:org:team`, and managed by the operator
+CREATE TABLE teams (
+    team_id SERIAL PRIMARY KEY,
+    login TEXT NOT NULL UNIQUE,
+    name TEXT
+);
+
+CREATE TABLE team_members (
+    team_id INTEGER NOT NULL REFERENCES teams (team_id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    PRIMARY KEY (team_id, user_id)
+);
+CREATE INDEX team_members_user_id ON team_members (user_id);
+
+-- An owner is either a user or a team
+ALTER TABLE crate_owners DROP CONSTRAINT crate_owners_pkey;
+ALTER TABLE crate_owners ALTER COLUMN user_id DROP NOT NULL;
+ALTER TABLE crate_owners ADD COLUMN team_id INTEGER REFERENCES teams (team_id) ON DELETE CASCADE;
+ALTER TABLE crate_owners ADD CONSTRAINT crate_owners_user_or_team CHECK ((user_id IS NULL) <> (team_id IS NULL));
+ALTER TABLE crate_owners ADD CONSTRAINT crate_owners_user UNIQUE (crate_id, user_id);
+ALTER TABLE crate_owners ADD CONSTRAINT crate_owners_team UNIQUE (crate_id, team_id);
@@ -0,0 +1,8 @@
+-- Size of the crate file, 0 for versions published before sizes were recorded
+ALTER TABLE versions ADD COLUMN file_size BIGINT NOT NULL DEFAULT 0;
+
+-- Bytes of crate files each user published, yanked versions included
+CREATE TABLE user_storage (
+    user_id INTEGER PRIMARY KEY REFERENCES users (user_id) ON DELETE CASCADE,
+    used_bytes BIGINT NOT NULL DEFAULT 0
+);
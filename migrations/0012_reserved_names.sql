@@ -0,0 +1,15 @@
+-- Names nobody but the allowed users may publish, matched against normalized crate names
+CREATE TABLE reserved_names (
+    reservation_id SERIAL PRIMARY KEY,
+    -- Normalized name, or the prefix if is_prefix is set
+    name TEXT NOT NULL,
+    is_prefix BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (name, is_prefix)
+);
+
+CREATE TABLE reserved_name_users (
+    reservation_id INTEGER NOT NULL REFERENCES reserved_names (reservation_id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    PRIMARY KEY (reservation_id, user_id)
+);
@@ -0,0 +1,2 @@
+-- Dependencies as published, NULL for versions published before they were stored
+ALTER TABLE versions ADD COLUMN deps JSONB;
@@ -0,0 +1,21 @@
+-- Versions are stored without build metadata, the index keeps it
+ALTER TABLE version_features
+    DROP CONSTRAINT version_features_crate_id_crate_version_fkey,
+    ADD FOREIGN KEY (crate_id, crate_version) REFERENCES versions (crate, vers) ON UPDATE CASCADE;
+ALTER TABLE feature_dependencies
+    DROP CONSTRAINT feature_dependencies_crate_id_crate_version_feature_name_fkey,
+    ADD FOREIGN KEY (crate_id, crate_version, feature_name)
+        REFERENCES version_features (crate_id, crate_version, feature_name) ON UPDATE CASCADE;
+ALTER TABLE version_authors
+    DROP CONSTRAINT version_authors_crate_id_version_fkey,
+    ADD FOREIGN KEY (crate_id, version) REFERENCES versions (crate, vers) ON UPDATE CASCADE;
+ALTER TABLE version_downloads
+    DROP CONSTRAINT version_downloads_crate_id_vers_fkey,
+    ADD FOREIGN KEY (crate_id, vers) REFERENCES versions (crate, vers)
+        ON UPDATE CASCADE ON DELETE CASCADE;
+ALTER TABLE version_dependencies
+    DROP CONSTRAINT version_dependencies_crate_id_vers_fkey,
+    ADD FOREIGN KEY (crate_id, vers) REFERENCES versions (crate, vers)
+        ON UPDATE CASCADE ON DELETE CASCADE;
+
+UPDATE versions SET vers = split_part(vers, '+', 1) WHERE vers LIKE '%+%';
@@ -0,0 +1,23 @@
+-- Deleting a crate or version removes everything attached to it
+ALTER TABLE keywords
+    DROP CONSTRAINT keywords_crate_id_fkey,
+    ADD FOREIGN KEY (crate_id) REFERENCES crates (crate_id) ON DELETE CASCADE;
+ALTER TABLE crate_categories
+    DROP CONSTRAINT crate_categories_crate_id_fkey,
+    ADD FOREIGN KEY (crate_id) REFERENCES crates (crate_id) ON DELETE CASCADE;
+ALTER TABLE versions
+    DROP CONSTRAINT versions_crate_fkey,
+    ADD FOREIGN KEY (crate) REFERENCES crates (crate_id) ON DELETE CASCADE;
+ALTER TABLE version_features
+    DROP CONSTRAINT version_features_crate_id_crate_version_fkey,
+    ADD FOREIGN KEY (crate_id, crate_version) REFERENCES versions (crate, vers)
+        ON UPDATE CASCADE ON DELETE CASCADE;
+ALTER TABLE feature_dependencies
+    DROP CONSTRAINT feature_dependencies_crate_id_crate_version_feature_name_fkey,
+    ADD FOREIGN KEY (crate_id, crate_version, feature_name)
+        REFERENCES version_features (crate_id, crate_version, feature_name)
+        ON UPDATE CASCADE ON DELETE CASCADE;
+ALTER TABLE version_authors
+    DROP CONSTRAINT version_authors_crate_id_version_fkey,
+    ADD FOREIGN KEY (crate_id, version) REFERENCES versions (crate, vers)
+        ON UPDATE CASCADE ON DELETE CASCADE;
@@ -0,0 +1,2 @@
+-- Serves the feed of recently published versions, versions without a publish time aren't in it
+CREATE INDEX versions_created_at ON versions (created_at DESC) WHERE created_at IS NOT NULL;
@@ -0,0 +1,13 @@
+-- Sort keys of the crate listings, updated by downloads, publishes and version deletions
+ALTER TABLE crates ADD COLUMN downloads BIGINT NOT NULL DEFAULT 0;
+-- Publish time of the newest version, NULL if none of its versions has one
+ALTER TABLE crates ADD COLUMN updated_at TIMESTAMPTZ;
+
+UPDATE crates SET
+    downloads = (SELECT COALESCE(SUM(count), 0) FROM version_downloads
+        WHERE version_downloads.crate_id = crates.crate_id),
+    updated_at = (SELECT MAX(created_at) FROM versions WHERE versions.crate = crates.crate_id);
+
+-- The name breaks ties, so pages don't overlap
+CREATE INDEX crates_downloads ON crates (downloads DESC, original_name);
+CREATE INDEX crates_updated_at ON crates (updated_at DESC NULLS LAST, original_name);
@@ -0,0 +1,39 @@
+-- Name, keywords and description, weighted in that order. Names are split at - and _
+CREATE FUNCTION crate_search_vector(id INTEGER, name TEXT, description TEXT) RETURNS TSVECTOR
+    LANGUAGE SQL STABLE
+    AS $$
+        SELECT setweight(to_tsvector('english', translate(name, '-_', '  ')), 'A')
+            || setweight(to_tsvector('english', COALESCE(
+                (SELECT string_agg(keyword, ' ') FROM keywords WHERE crate_id = id), '')), 'B')
+            || setweight(to_tsvector('english', description), 'C')
+    $$;
+
+ALTER TABLE crates ADD COLUMN search_vector TSVECTOR NOT NULL DEFAULT '';
+UPDATE crates SET search_vector = crate_search_vector(crate_id, original_name, description);
+CREATE INDEX crates_search_vector ON crates USING GIN (search_vector);
+
+-- Every way of changing a crate's text goes through these, publishes and metadata updates alike
+CREATE FUNCTION update_crate_search_vector() RETURNS TRIGGER
+    LANGUAGE plpgsql
+    AS $$
+    BEGIN
+        NEW.search_vector := crate_search_vector(NEW.crate_id, NEW.original_name, NEW.description);
+        RETURN NEW;
+    END
+    $$;
+CREATE TRIGGER crates_search_vector BEFORE INSERT OR UPDATE OF original_name, description ON crates
+    FOR EACH ROW EXECUTE FUNCTION update_crate_search_vector();
+
+CREATE FUNCTION update_keyword_search_vector() RETURNS TRIGGER
+    LANGUAGE plpgsql
+    AS $$
+    DECLARE
+        changed INTEGER := CASE WHEN TG_OP = 'DELETE' THEN OLD.crate_id ELSE NEW.crate_id END;
+    BEGIN
+        UPDATE crates SET search_vector = crate_search_vector(crate_id, original_name, description)
+        WHERE crate_id = changed;
+        RETURN NULL;
+    END
+    $$;
+CREATE TRIGGER keywords_search_vector AFTER INSERT OR DELETE ON keywords
+    FOR EACH ROW EXECUTE FUNCTION update_keyword_search_vector();
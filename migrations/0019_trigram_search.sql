@@ -0,0 +1,17 @@
+-- Near-matches for misspelled crate names in search, skipped by the server without pg_trgm
+DO $$
+BEGIN
+    CREATE EXTENSION IF NOT EXISTS pg_trgm;
+EXCEPTION WHEN OTHERS THEN
+    RAISE WARNING 'pg_trgm is unavailable, search won''t suggest similar names: %', SQLERRM;
+END
+$$;
+
+DO $$
+BEGIN
+    IF EXISTS (SELECT FROM pg_extension WHERE extname = 'pg_trgm') THEN
+        CREATE INDEX IF NOT EXISTS crates_name_trigrams
+            ON crates USING GIN (normalize_crate_name(original_name) gin_trgm_ops);
+    END IF;
+END
+$$;
@@ -0,0 +1,2 @@
+-- README as packaged in the crate file, NULL if there is none or it was published before they were stored
+ALTER TABLE versions ADD COLUMN readme TEXT;
@@ -0,0 +1,2 @@
+-- Updated in the background whenever the token authenticates a request, NULL if it never did
+ALTER TABLE tokens ADD COLUMN last_used_at TIMESTAMPTZ;
@@ -0,0 +1,2 @@
+-- Whether rustdoc output was uploaded for the version, it is served below /docs/
+ALTER TABLE versions ADD COLUMN has_docs BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,4 @@
+-- Parsed from `author` on publish, NULL for authors stored before and parts that weren't there
+ALTER TABLE version_authors ADD COLUMN author_name TEXT;
+ALTER TABLE version_authors ADD COLUMN author_email TEXT;
+CREATE INDEX version_authors_email ON version_authors (lower(author_email));
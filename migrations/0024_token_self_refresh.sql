@@ -0,0 +1,2 @@
+-- Tokens the operator allows to extend their own expiry
+ALTER TABLE tokens ADD COLUMN self_refresh BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,8 @@
+-- Keywords are stored trimmed and lowercase, spellings that only differed in case are merged
+UPDATE keywords SET keyword = lower(trim(keyword));
+DELETE FROM keywords duplicate
+USING keywords kept
+WHERE duplicate.crate_id = kept.crate_id
+AND duplicate.keyword = kept.keyword
+AND duplicate.ctid > kept.ctid;
+CREATE UNIQUE INDEX keywords_crate_keyword ON keywords (crate_id, keyword);
@@ -0,0 +1,9 @@
+-- Dependencies of a feature keep the order they had in the manifest
+ALTER TABLE feature_dependencies ADD COLUMN position BIGINT;
+UPDATE feature_dependencies SET position = numbered.position
+FROM (
+    SELECT ctid, row_number() OVER (PARTITION BY crate_id, crate_version, feature_name ORDER BY ctid) AS position
+    FROM feature_dependencies
+) numbered
+WHERE feature_dependencies.ctid = numbered.ctid;
+ALTER TABLE feature_dependencies ALTER COLUMN position SET NOT NULL;
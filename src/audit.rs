@@ -0,0 +1,168 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+
+use crate::{
+    auth::Admin,
+    crate_name::CrateName,
+    middleware::internal_server_error,
+    postgres::{add_audit_event, get_audit_events},
+    request_id::RequestId,
+    ServerState,
+};
+
+const DEFAULT_PER_PAGE: u32 = 50;
+const MAX_PER_PAGE: u32 = 500;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuditAction {
+    Publish,
+    AddOwner,
+    InviteOwner,
+    RemoveOwner,
+    Yank,
+    Unyank,
+    DeleteVersion,
+    DeleteCrate,
+    TransferOwners,
+    UploadDocs,
+}
+impl AuditAction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Publish => "publish",
+            Self::AddOwner => "add_owner",
+            Self::InviteOwner => "invite_owner",
+            Self::RemoveOwner => "remove_owner",
+            Self::Yank => "yank",
+            Self::Unyank => "unyank",
+            Self::DeleteVersion => "delete_version",
+            Self::DeleteCrate => "delete_crate",
+            Self::TransferOwners => "transfer_owners",
+            Self::UploadDocs => "upload_docs",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuditOutcome {
+    Success,
+    /// Coarse reason, derived from the response status
+    Failure(&'static str),
+}
+impl AuditOutcome {
+    pub fn from_status(status: StatusCode) -> Self {
+        let category = match status {
+            status if status.is_success() => return Self::Success,
+            StatusCode::BAD_REQUEST => "invalid_request",
+            StatusCode::UNAUTHORIZED => "unauthenticated",
+            StatusCode::FORBIDDEN => "forbidden",
+            StatusCode::NOT_FOUND => "not_found",
+            StatusCode::CONFLICT => "conflict",
+            StatusCode::PAYLOAD_TOO_LARGE => "too_large",
+            StatusCode::TOO_MANY_REQUESTS => "rate_limited",
+            status if status.is_server_error() => "internal",
+            _ => "other",
+        };
+        Self::Failure(category)
+    }
+}
+
+#[derive(Clone, Debug)]
+/// Who is acting in a request, shared by all audit events it records
+pub struct AuditContext {
+    pub actor: Option<String>,
+    pub request_id: RequestId,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct AuditEvent<'a> {
+    pub context: &'a AuditContext,
+    pub action: AuditAction,
+    pub crate_name: Option<&'a CrateName>,
+    pub version: Option<&'a Version>,
+    pub outcome: AuditOutcome,
+}
+
+/// Records a failed action outside of the (rolled back) transaction, errors are only logged
+pub async fn record_failure(event: AuditEvent<'_>, database_connection_pool: &Pool<Postgres>) {
+    if let Err(e) = add_audit_event(event, database_connection_pool).await {
+        eprintln!("Failed to record audit event: {e}");
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuditQuery {
+    #[serde(rename = "crate")]
+    krate: Option<String>,
+    /// RFC 3339 timestamp, inclusive
+    since: Option<DateTime<Utc>>,
+    /// RFC 3339 timestamp, exclusive
+    until: Option<DateTime<Utc>>,
+    per_page: Option<u32>,
+    page: Option<u32>,
+}
+
+pub async fn audit_log_handler(
+    _admin: Admin,
+    State(ServerState {
+        database_connection_pool,
+        ..
+    }): State<ServerState>,
+    Query(AuditQuery {
+        krate,
+        since,
+        until,
+        per_page,
+        page,
+    }): Query<AuditQuery>,
+) -> Result<Json<AuditLogResponse>, axum::response::Response> {
+    let per_page = per_page.unwrap_or(DEFAULT_PER_PAGE).clamp(1, MAX_PER_PAGE);
+    let offset = u64::from(page.unwrap_or(1).max(1) - 1) * u64::from(per_page);
+    let (events, total) = get_audit_events(
+        krate.as_deref(),
+        since,
+        until,
+        per_page.into(),
+        offset as i64,
+        &*database_connection_pool,
+    )
+    .await
+    .inspect_err(|e| eprintln!("Failed to query audit events: {e}"))
+    .map_err(|_e| internal_server_error("couldn't query audit events"))?;
+    Ok(Json(AuditLogResponse {
+        events,
+        meta: AuditLogMeta { total },
+    }))
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct AuditRecord {
+    pub event_id: i64,
+    pub occurred_at: DateTime<Utc>,
+    pub actor: Option<String>,
+    pub action: String,
+    #[serde(rename = "crate")]
+    pub crate_name: Option<String>,
+    pub version: Option<String>,
+    pub request_id: String,
+    pub outcome: String,
+    pub error_category: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuditLogResponse {
+    events: Vec<AuditRecord>,
+    meta: AuditLogMeta,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuditLogMeta {
+    total: i64,
+}
@@ -0,0 +1,221 @@
+use std::sync::Arc;
+
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, State},
+    http::{header::AUTHORIZATION, request::Parts, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::{PgConnection, Pool, Postgres};
+
+use crate::{
+    crate_name::CrateName,
+    middleware::{bad_request, internal_server_error},
+    postgres::{
+        crate_exists_exact, delete_expired_tokens, extend_token_expiry, get_token_expiry,
+        get_user_by_token_hash, is_crate_owner_or_team_member, set_token_last_used,
+    },
+    ServerState,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// Database id of a user in the `users` table
+pub struct UserId(pub i32);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// Database id of a token in the `tokens` table
+pub struct TokenId(pub i32);
+
+#[derive(Clone, Debug)]
+/// User that sent a valid, unexpired token in the `Authorization` header
+///
+/// Cargo sends the raw token as header value, a `Bearer ` prefix is accepted as well.
+/// Tokens are stored as SHA-256 hex digests and provisioned by the operator.
+pub struct AuthenticatedUser {
+    pub user_id: UserId,
+    pub login: String,
+    /// The token the request was authenticated with
+    pub token_id: TokenId,
+}
+
+#[async_trait]
+impl FromRequestParts<ServerState> for AuthenticatedUser {
+    type Rejection = Response;
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &ServerState,
+    ) -> Result<Self, Self::Rejection> {
+        let token = token_from_headers(&parts.headers).ok_or_else(|| {
+            (StatusCode::UNAUTHORIZED, "missing authorization token").into_response()
+        })?;
+        let mut connection = state
+            .database_connection_pool
+            .acquire()
+            .await
+            .map_err(|_e| internal_server_error("couldn't check token"))?;
+        let user = get_user_by_token_hash(&hash_token(token), &mut connection)
+            .await
+            .inspect_err(|e| eprintln!("Failed to look up token: {e}"))
+            .map_err(|_e| internal_server_error("couldn't check token"))?
+            .ok_or_else(|| {
+                (
+                    StatusCode::FORBIDDEN,
+                    "invalid or expired authorization token",
+                )
+                    .into_response()
+            })?;
+        // Not awaited, the request shouldn't wait for bookkeeping
+        let pool = state.database_connection_pool.clone();
+        let token_id = user.token_id;
+        tokio::spawn(async move {
+            if let Err(e) = set_token_last_used(token_id, &*pool).await {
+                eprintln!("Failed to record token use: {e}");
+            }
+        });
+        Ok(user)
+    }
+}
+
+/// Deletes tokens past their expiry, they can't authenticate anymore anyway
+pub async fn cleanup_expired_tokens(pool: Arc<Pool<Postgres>>) {
+    match delete_expired_tokens(&*pool).await {
+        Ok(deleted) => eprintln!("Deleted {deleted} expired tokens"),
+        Err(e) => eprintln!("Failed to delete expired tokens: {e}"),
+    }
+}
+
+/// Moves the expiry of the sending token to the configured lifetime from now
+///
+/// Only tokens the operator marked as `self_refresh` may do this, and only before they expire.
+/// Unknown tokens are answered like expired ones, expired tokens are deleted by
+/// [`cleanup_expired_tokens`].
+pub async fn refresh_token_handler(
+    State(ServerState {
+        database_connection_pool,
+        token_lifetime,
+        ..
+    }): State<ServerState>,
+    headers: HeaderMap,
+) -> Result<Json<RefreshedToken>, Response> {
+    let token = token_from_headers(&headers)
+        .ok_or_else(|| (StatusCode::UNAUTHORIZED, "missing authorization token").into_response())?;
+    let mut transaction = database_connection_pool
+        .begin()
+        .await
+        .map_err(|_e| internal_server_error("couldn't start transaction"))?;
+    let expiry = get_token_expiry(&hash_token(token), &mut transaction)
+        .await
+        .inspect_err(|e| eprintln!("Failed to look up token: {e}"))
+        .map_err(|_e| internal_server_error("couldn't check token"))?
+        .filter(|expiry| !expiry.expired)
+        .ok_or_else(|| {
+            (
+                StatusCode::UNAUTHORIZED,
+                "the token has expired, authenticate again for a new one",
+            )
+                .into_response()
+        })?;
+    if !expiry.self_refresh {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "the token isn't allowed to refresh itself",
+        )
+            .into_response());
+    }
+    if expiry.expires_at.is_none() {
+        return Err(bad_request("the token never expires"));
+    }
+    let expires_at = extend_token_expiry(expiry.token_id, token_lifetime, &mut transaction)
+        .await
+        .inspect_err(|e| eprintln!("Failed to refresh token: {e}"))
+        .map_err(|_e| internal_server_error("couldn't refresh token"))?;
+    transaction
+        .commit()
+        .await
+        .map_err(|_e| internal_server_error("committing to database failed"))?;
+    Ok(Json(RefreshedToken { expires_at }))
+}
+
+#[derive(Debug, Serialize)]
+pub struct RefreshedToken {
+    expires_at: DateTime<Utc>,
+}
+
+/// Rejects with 404 if the crate doesn't exist and with 403 if `user` doesn't own it
+///
+/// Members of a team owning the crate count as owners.
+pub async fn ensure_crate_owner(
+    crate_name: &CrateName,
+    user: &AuthenticatedUser,
+    exec: &mut PgConnection,
+) -> Result<(), Response> {
+    if !crate_exists_exact(crate_name, &mut *exec)
+        .await
+        .map_err(|_e| internal_server_error("couldn't check if crate exists"))?
+    {
+        return Err((StatusCode::NOT_FOUND, "crate doesn't exist").into_response());
+    }
+    if !is_crate_owner_or_team_member(crate_name, user.user_id, exec)
+        .await
+        .inspect_err(|e| eprintln!("Failed to check ownership: {e}"))
+        .map_err(|_e| internal_server_error("couldn't check crate ownership"))?
+    {
+        return Err((
+            StatusCode::FORBIDDEN,
+            format!("user {} is not an owner of crate {crate_name}", user.login),
+        )
+            .into_response());
+    }
+    Ok(())
+}
+
+/// Request authenticated with the operator's admin token
+///
+/// Admin endpoints are disabled unless `REGISTRY_SERVER_ADMIN_TOKEN` is set.
+pub struct Admin;
+impl Admin {
+    /// Actor recorded in the audit log for requests made with the admin token
+    pub const AUDIT_ACTOR: &'static str = "(admin)";
+}
+
+#[async_trait]
+impl FromRequestParts<ServerState> for Admin {
+    type Rejection = Response;
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &ServerState,
+    ) -> Result<Self, Self::Rejection> {
+        let Some(admin_token_hash) = &state.admin_token_hash else {
+            return Err((StatusCode::FORBIDDEN, "admin API is disabled").into_response());
+        };
+        let token = token_from_headers(&parts.headers).ok_or_else(|| {
+            (StatusCode::UNAUTHORIZED, "missing authorization token").into_response()
+        })?;
+        // Comparing digests keeps the comparison time independent of the token
+        if hash_token(token) == **admin_token_hash {
+            Ok(Self)
+        } else {
+            Err((StatusCode::FORBIDDEN, "invalid admin token").into_response())
+        }
+    }
+}
+
+/// Token sent in the `Authorization` header, if any
+pub fn token_from_headers(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.strip_prefix("Bearer ").unwrap_or(value).trim())
+        .filter(|token| !token.is_empty())
+}
+
+pub fn hash_token(token: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(token.as_bytes());
+    let hash_res = hasher.finalize();
+    format!("{hash_res:x}")
+}
@@ -0,0 +1,83 @@
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// An entry of a manifest's `authors`, split like cargo's own `Name <email>` convention
+pub struct Author<'a> {
+    pub name: Option<&'a str>,
+    pub email: Option<&'a str>,
+}
+impl<'a> Author<'a> {
+    /// Entries that aren't `Name <email>` with a plausible email are a name only
+    pub fn parse(raw: &'a str) -> Self {
+        let raw = raw.trim();
+        let name_only = Self {
+            name: (!raw.is_empty()).then_some(raw),
+            email: None,
+        };
+        let Some((name, email)) = raw
+            .strip_suffix('>')
+            .and_then(|without_bracket| without_bracket.rsplit_once('<'))
+        else {
+            return name_only;
+        };
+        if !is_plausible_email(email) {
+            return name_only;
+        }
+        let name = name.trim();
+        Self {
+            name: (!name.is_empty()).then_some(name),
+            email: Some(email),
+        }
+    }
+}
+
+/// Something before and after a single `@`, without whitespace or brackets
+fn is_plausible_email(email: &str) -> bool {
+    let Some((local, domain)) = email.split_once('@') else {
+        return false;
+    };
+    !local.is_empty()
+        && !domain.is_empty()
+        && !domain.contains('@')
+        && !email.contains(|c: char| c.is_whitespace() || c == '<' || c == '>')
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::author::Author;
+
+    #[test]
+    fn name_and_email_are_split() {
+        assert_eq!(
+            Author::parse("Jane Doe <jane@x.com>"),
+            Author {
+                name: Some("Jane Doe"),
+                email: Some("jane@x.com"),
+            }
+        );
+        assert_eq!(
+            Author::parse("<jane@x.com>"),
+            Author {
+                name: None,
+                email: Some("jane@x.com"),
+            }
+        );
+    }
+    #[test]
+    fn other_entries_are_names() {
+        for raw in [
+            "Jane Doe",
+            "Jane Doe <not an email>",
+            "Jane Doe <jane@>",
+            "Jane Doe <jane@x.com",
+            "jane@x.com",
+        ] {
+            assert_eq!(
+                Author::parse(raw),
+                Author {
+                    name: Some(raw),
+                    email: None,
+                },
+                "{raw}"
+            );
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    auth::Admin,
+    middleware::{bad_request, internal_server_error},
+    postgres::{add_category, delete_category, get_categories, lock_category_for_deletion},
+    ServerState,
+};
+
+pub async fn list_categories_handler(
+    _admin: Admin,
+    State(ServerState {
+        database_connection_pool,
+        ..
+    }): State<ServerState>,
+) -> Result<Json<CategoriesResponse>, Response> {
+    let mut connection = database_connection_pool
+        .acquire()
+        .await
+        .map_err(|_e| internal_server_error("couldn't connect to database"))?;
+    let categories = get_categories(&mut connection)
+        .await
+        .inspect_err(|e| eprintln!("Failed to get categories: {e}"))
+        .map_err(|_e| internal_server_error("couldn't get categories"))?;
+    Ok(Json(CategoriesResponse { categories }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CategoryBody {
+    category: String,
+}
+
+pub async fn add_category_handler(
+    _admin: Admin,
+    State(ServerState {
+        database_connection_pool,
+        ..
+    }): State<ServerState>,
+    Json(CategoryBody { category }): Json<CategoryBody>,
+) -> Result<Json<CategoriesChanged>, Response> {
+    let category = category.trim();
+    if category.is_empty() {
+        return Err(bad_request("category name is empty"));
+    }
+    let mut connection = database_connection_pool
+        .acquire()
+        .await
+        .map_err(|_e| internal_server_error("couldn't connect to database"))?;
+    let category_id = add_category(category, &mut connection)
+        .await
+        .inspect_err(|e| eprintln!("Failed to add category: {e}"))
+        .map_err(|_e| internal_server_error("couldn't add category"))?
+        .ok_or_else(|| {
+            (
+                StatusCode::CONFLICT,
+                format!("category {category} already exists"),
+            )
+                .into_response()
+        })?;
+    Ok(Json(CategoriesChanged {
+        ok: true,
+        msg: format!("added category {category} with id {category_id}"),
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteCategoryQuery {
+    /// Takes the category off the crates in it instead of refusing to delete it
+    #[serde(default)]
+    cascade: bool,
+}
+
+pub async fn delete_category_handler(
+    _admin: Admin,
+    State(ServerState {
+        database_connection_pool,
+        ..
+    }): State<ServerState>,
+    Path(category_id): Path<i32>,
+    Query(DeleteCategoryQuery { cascade }): Query<DeleteCategoryQuery>,
+) -> Result<Json<CategoriesChanged>, Response> {
+    let mut transaction = database_connection_pool
+        .begin()
+        .await
+        .map_err(|_e| internal_server_error("couldn't start transaction"))?;
+    let crates = lock_category_for_deletion(category_id, &mut transaction)
+        .await
+        .inspect_err(|e| eprintln!("Failed to get category: {e}"))
+        .map_err(|_e| internal_server_error("couldn't get category"))?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "category doesn't exist").into_response())?;
+    if crates > 0 && !cascade {
+        return Err((
+            StatusCode::CONFLICT,
+            format!("{crates} crates are in the category, pass cascade=true to delete it anyway"),
+        )
+            .into_response());
+    }
+    delete_category(category_id, &mut transaction)
+        .await
+        .inspect_err(|e| eprintln!("Failed to delete category: {e}"))
+        .map_err(|_e| internal_server_error("couldn't delete category"))?;
+    transaction
+        .commit()
+        .await
+        .map_err(|_e| internal_server_error("committing to database failed"))?;
+    Ok(Json(CategoriesChanged {
+        ok: true,
+        msg: format!("category {category_id} deleted, {crates} crates were in it"),
+    }))
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Category {
+    pub id: i32,
+    pub category: String,
+    /// Number of crates in the category
+    pub crates: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CategoriesResponse {
+    categories: Vec<Category>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CategoriesChanged {
+    ok: bool,
+    msg: String,
+}
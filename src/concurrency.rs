@@ -0,0 +1,138 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use axum::{
+    extract::{Request, State},
+    http::{header::RETRY_AFTER, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Seconds clients are asked to wait after being shed
+const RETRY_AFTER_SECONDS: u64 = 5;
+
+#[derive(Debug)]
+/// Allows `limit` requests to run at once and up to `queue` more to wait for a slot.
+///
+/// Anything beyond that is rejected right away instead of queueing unboundedly.
+pub struct ConcurrencyLimit {
+    semaphore: Arc<Semaphore>,
+    capacity: usize,
+    admitted: AtomicUsize,
+    in_flight: AtomicUsize,
+}
+
+impl ConcurrencyLimit {
+    pub fn new(limit: usize, queue: usize) -> Self {
+        Self {
+            semaphore: Arc::new(Semaphore::new(limit)),
+            capacity: limit + queue,
+            admitted: AtomicUsize::new(0),
+            in_flight: AtomicUsize::new(0),
+        }
+    }
+    /// Requests currently holding a slot
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+    /// Requests waiting for a slot
+    pub fn queued(&self) -> usize {
+        self.admitted
+            .load(Ordering::Relaxed)
+            .saturating_sub(self.in_flight())
+    }
+    /// `None` if both the slots and the queue are full
+    pub async fn acquire(self: &Arc<Self>) -> Option<ConcurrencyPermit> {
+        if self.admitted.fetch_add(1, Ordering::AcqRel) >= self.capacity {
+            self.admitted.fetch_sub(1, Ordering::AcqRel);
+            return None;
+        }
+        let admission = Admission(self.clone());
+        let permit = self
+            .semaphore
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("semaphore is never closed");
+        self.in_flight.fetch_add(1, Ordering::AcqRel);
+        Some(ConcurrencyPermit {
+            _permit: permit,
+            admission,
+        })
+    }
+}
+
+/// Counts a request from admission on, even if the future is dropped while queued
+struct Admission(Arc<ConcurrencyLimit>);
+
+impl Drop for Admission {
+    fn drop(&mut self) {
+        self.0.admitted.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+pub struct ConcurrencyPermit {
+    _permit: OwnedSemaphorePermit,
+    admission: Admission,
+}
+
+impl Drop for ConcurrencyPermit {
+    fn drop(&mut self) {
+        self.admission.0.in_flight.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// For requests that waited too long for the index lock, e.g. behind slow index commits
+pub fn index_busy() -> Response {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        [(RETRY_AFTER, RETRY_AFTER_SECONDS.to_string())],
+        "index is busy, try again later",
+    )
+        .into_response()
+}
+
+pub async fn limit_concurrency(
+    State(limit): State<Arc<ConcurrencyLimit>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(_permit) = limit.acquire().await else {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            [(RETRY_AFTER, RETRY_AFTER_SECONDS.to_string())],
+            "server is busy, try again later",
+        )
+            .into_response();
+    };
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::concurrency::ConcurrencyLimit;
+
+    #[tokio::test]
+    async fn sheds_beyond_limit_and_queue() {
+        let limit = Arc::new(ConcurrencyLimit::new(1, 1));
+        let running = limit.acquire().await.unwrap();
+        let queued = tokio::spawn({
+            let limit = limit.clone();
+            async move { limit.acquire().await.is_some() }
+        });
+        while limit.queued() == 0 {
+            tokio::task::yield_now().await;
+        }
+        assert!(limit.acquire().await.is_none());
+        assert_eq!(limit.in_flight(), 1);
+        drop(running);
+        assert!(queued.await.unwrap());
+        assert_eq!(limit.in_flight(), 0);
+        assert_eq!(limit.queued(), 0);
+    }
+}
@@ -0,0 +1,280 @@
+use std::{
+    collections::HashSet,
+    fmt::Display,
+    io::Read,
+    path::{Component, Path, PathBuf},
+};
+
+use flate2::read::GzDecoder;
+use serde::Deserialize;
+
+/// Written by cargo into the package root when publishing from a git checkout
+const VCS_INFO_FILE_NAME: &str = ".cargo_vcs_info.json";
+
+#[derive(Clone, Copy, Debug, Default)]
+/// What the server demands from uploaded `.crate` archives
+pub struct ArchivePolicy {
+    /// Rejects crates not published from a clean git checkout
+    pub require_vcs_info: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct VcsInfo {
+    git: GitInfo,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitInfo {
+    sha1: String,
+    /// Set by `cargo publish --allow-dirty` if there were uncommitted changes
+    #[serde(default)]
+    dirty: bool,
+}
+
+pub fn validate_crate_archive(
+    file_content: &[u8],
+    policy: ArchivePolicy,
+) -> Result<(), ArchiveError> {
+    if !policy.require_vcs_info {
+        return Ok(());
+    }
+    let mut archive = tar::Archive::new(GzDecoder::new(file_content));
+    for entry in archive.entries().map_err(ArchiveError::Read)? {
+        let mut entry = entry.map_err(ArchiveError::Read)?;
+        let path = entry.path().map_err(ArchiveError::Read)?;
+        if !is_in_package_root(&path, VCS_INFO_FILE_NAME) {
+            continue;
+        }
+        let mut content = Vec::new();
+        entry
+            .read_to_end(&mut content)
+            .map_err(ArchiveError::Read)?;
+        let vcs_info: VcsInfo =
+            serde_json::from_slice(&content).map_err(ArchiveError::InvalidVcsInfo)?;
+        if vcs_info.git.sha1.is_empty() {
+            return Err(ArchiveError::MissingVcsInfo);
+        }
+        if vcs_info.git.dirty {
+            return Err(ArchiveError::DirtyVcsInfo);
+        }
+        return Ok(());
+    }
+    Err(ArchiveError::MissingVcsInfo)
+}
+
+/// Returns the `referenced` paths, relative to the package root, that the archive doesn't contain
+///
+/// Cargo copies files from outside the package into its root, so those are looked up by name.
+pub fn missing_files<'a>(
+    file_content: &[u8],
+    referenced: &[&'a str],
+) -> Result<Vec<&'a str>, ArchiveError> {
+    let mut packaged = HashSet::new();
+    let mut archive = tar::Archive::new(GzDecoder::new(file_content));
+    for entry in archive.entries().map_err(ArchiveError::Read)? {
+        let entry = entry.map_err(ArchiveError::Read)?;
+        let path = entry.path().map_err(ArchiveError::Read)?;
+        // Strips the `<name>-<version>` directory
+        packaged.insert(path.components().skip(1).collect::<PathBuf>());
+    }
+    Ok(referenced
+        .iter()
+        .copied()
+        .filter(|path| !packaged.contains(&packaged_path(Path::new(path))))
+        .collect())
+}
+
+/// Reads the file at `path`, relative to the package root, `None` if the archive doesn't contain it
+///
+/// Paths are resolved like in [`missing_files`]. Files above `max_size` bytes are errors.
+pub fn read_packaged_file(
+    file_content: &[u8],
+    path: &str,
+    max_size: u64,
+) -> Result<Option<Vec<u8>>, ArchiveError> {
+    let wanted = packaged_path(Path::new(path));
+    let mut archive = tar::Archive::new(GzDecoder::new(file_content));
+    for entry in archive.entries().map_err(ArchiveError::Read)? {
+        let entry = entry.map_err(ArchiveError::Read)?;
+        let path = entry.path().map_err(ArchiveError::Read)?;
+        if path.components().skip(1).collect::<PathBuf>() != wanted {
+            continue;
+        }
+        if entry.size() > max_size {
+            return Err(ArchiveError::FileTooLarge {
+                path: wanted,
+                max_size,
+            });
+        }
+        let mut content = Vec::new();
+        // The header's size could be wrong
+        entry
+            .take(max_size + 1)
+            .read_to_end(&mut content)
+            .map_err(ArchiveError::Read)?;
+        if content.len() as u64 > max_size {
+            return Err(ArchiveError::FileTooLarge {
+                path: wanted,
+                max_size,
+            });
+        }
+        return Ok(Some(content));
+    }
+    Ok(None)
+}
+
+fn packaged_path(path: &Path) -> PathBuf {
+    if path.components().any(|c| c == Component::ParentDir) {
+        return path.file_name().map(PathBuf::from).unwrap_or_default();
+    }
+    path.components()
+        .filter(|c| *c != Component::CurDir)
+        .collect()
+}
+
+/// Archives contain a single `<name>-<version>` directory
+fn is_in_package_root(path: &Path, file_name: &str) -> bool {
+    let mut components = path.components();
+    components.next().is_some()
+        && components
+            .next()
+            .is_some_and(|c| c.as_os_str() == file_name)
+        && components.next().is_none()
+}
+
+#[derive(Debug)]
+pub enum ArchiveError {
+    Read(std::io::Error),
+    MissingVcsInfo,
+    InvalidVcsInfo(serde_json::Error),
+    DirtyVcsInfo,
+    FileTooLarge { path: PathBuf, max_size: u64 },
+}
+impl std::error::Error for ArchiveError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Read(io) => Some(io),
+            Self::InvalidVcsInfo(json) => Some(json),
+            Self::MissingVcsInfo | Self::DirtyVcsInfo | Self::FileTooLarge { .. } => None,
+        }
+    }
+}
+impl Display for ArchiveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Read(io) => write!(f, "invalid crate archive: {io}"),
+            Self::MissingVcsInfo => write!(
+                f,
+                "crate archive has no {VCS_INFO_FILE_NAME}, publish from a git checkout"
+            ),
+            Self::InvalidVcsInfo(json) => write!(f, "invalid {VCS_INFO_FILE_NAME}: {json}"),
+            Self::DirtyVcsInfo => f.write_str(
+                "crate was published with uncommitted changes, commit them and publish again",
+            ),
+            Self::FileTooLarge { path, max_size } => write!(
+                f,
+                "{} in crate archive is larger than {max_size} bytes",
+                path.display()
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use flate2::{write::GzEncoder, Compression};
+
+    use crate::crate_archive::{
+        missing_files, read_packaged_file, validate_crate_archive, ArchiveError, ArchivePolicy,
+    };
+
+    const REQUIRED: ArchivePolicy = ArchivePolicy {
+        require_vcs_info: true,
+    };
+
+    fn archive(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::fast()));
+        for (path, content) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, path, content.as_bytes())
+                .unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    #[test]
+    fn clean_vcs_info_passes() {
+        let file = archive(&[
+            ("foo-1.0.0/Cargo.toml", ""),
+            (
+                "foo-1.0.0/.cargo_vcs_info.json",
+                r#"{"git":{"sha1":"abc"},"path_in_vcs":""}"#,
+            ),
+        ]);
+        assert!(validate_crate_archive(&file, REQUIRED).is_ok());
+    }
+    #[test]
+    fn missing_vcs_info_is_rejected() {
+        let file = archive(&[
+            ("foo-1.0.0/Cargo.toml", ""),
+            ("foo-1.0.0/src/.cargo_vcs_info.json", "{}"),
+        ]);
+        assert!(matches!(
+            validate_crate_archive(&file, REQUIRED),
+            Err(ArchiveError::MissingVcsInfo)
+        ));
+        assert!(validate_crate_archive(&file, ArchivePolicy::default()).is_ok());
+    }
+    #[test]
+    fn dirty_vcs_info_is_rejected() {
+        let file = archive(&[(
+            "foo-1.0.0/.cargo_vcs_info.json",
+            r#"{"git":{"sha1":"abc","dirty":true}}"#,
+        )]);
+        assert!(matches!(
+            validate_crate_archive(&file, REQUIRED),
+            Err(ArchiveError::DirtyVcsInfo)
+        ));
+    }
+    #[test]
+    fn absent_license_file_is_missing() {
+        let file = archive(&[
+            ("foo-1.0.0/Cargo.toml", ""),
+            ("foo-1.0.0/README.md", ""),
+            ("foo-1.0.0/LICENSE-MIT", ""),
+        ]);
+        assert_eq!(
+            missing_files(&file, &["./README.md", "LICENSE"]).unwrap(),
+            ["LICENSE"]
+        );
+        // Copied into the package root by cargo
+        assert!(missing_files(&file, &["../LICENSE-MIT"])
+            .unwrap()
+            .is_empty());
+    }
+    #[test]
+    fn packaged_files_are_read_up_to_the_size_limit() {
+        let file = archive(&[
+            ("foo-1.0.0/Cargo.toml", ""),
+            ("foo-1.0.0/README.md", "# foo"),
+            ("foo-1.0.0/docs/README.md", "# nested"),
+        ]);
+        assert_eq!(
+            read_packaged_file(&file, "./README.md", 5).unwrap(),
+            Some(b"# foo".to_vec())
+        );
+        assert_eq!(
+            read_packaged_file(&file, "../../README.md", 5).unwrap(),
+            Some(b"# foo".to_vec())
+        );
+        assert_eq!(read_packaged_file(&file, "README", 5).unwrap(), None);
+        assert!(matches!(
+            read_packaged_file(&file, "docs/README.md", 5),
+            Err(ArchiveError::FileTooLarge { .. })
+        ));
+    }
+}
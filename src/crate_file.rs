@@ -1,61 +1,103 @@
-use std::path::PathBuf;
+use std::{fmt::Debug, path::PathBuf};
 
-use semver::{BuildMetadata, Version};
+use axum::async_trait;
+use semver::Version;
 use tokio::{
-    fs::{create_dir_all, OpenOptions},
+    fs::{create_dir_all, metadata, remove_file, OpenOptions},
     io::{AsyncReadExt, AsyncWriteExt},
 };
 
-use crate::crate_name::CrateName;
+use crate::{crate_name::CrateName, version::without_build_metadata};
 
-const CRATE_BASE_FILE_PATH: &str = "./target/test_filesystem/download_files/";
+/// Where crate files are kept if nothing else is configured
+pub const DEFAULT_CRATE_STORAGE_PATH: &str = "./target/test_filesystem/download_files/";
 
-fn crate_directory_path(crate_name: &CrateName) -> PathBuf {
-    PathBuf::from(CRATE_BASE_FILE_PATH).join(crate_name.normalized())
-}
-fn crate_file_path(
-    crate_name: &CrateName,
-    Version {
-        major,
-        minor,
-        patch,
-        pre,
-        ..
-    }: Version,
-) -> PathBuf {
-    let version_no_build = Version {
-        major,
-        minor,
-        patch,
-        pre,
-        build: BuildMetadata::EMPTY,
-    };
-    crate_directory_path(crate_name).join(version_no_build.to_string())
+#[async_trait]
+/// Keeps the uploaded `.crate` files
+///
+/// Versions differing only in build metadata share a file.
+pub trait CrateStorage: Debug + Send + Sync {
+    /// Fails if there already is a file for the version
+    async fn store(
+        &self,
+        crate_name: &CrateName,
+        version: &Version,
+        file_content: &[u8],
+    ) -> Result<(), std::io::Error>;
+    /// Fails with [`std::io::ErrorKind::NotFound`] if there is no file for the version
+    async fn get(
+        &self,
+        crate_name: &CrateName,
+        version: &Version,
+    ) -> Result<Vec<u8>, std::io::Error>;
+    /// Bytes of the file, fails like [`CrateStorage::get`] without reading it
+    async fn size(&self, crate_name: &CrateName, version: &Version) -> Result<u64, std::io::Error>;
+    /// Removing a file that doesn't exist is not an error
+    async fn delete(&self, crate_name: &CrateName, version: &Version)
+        -> Result<(), std::io::Error>;
 }
 
-pub async fn create_crate_file(
-    file_content: &[u8],
-    version: Version,
-    crate_name: &CrateName,
-) -> Result<(), std::io::Error> {
-    create_dir_all(&crate_directory_path(crate_name)).await?;
-    let mut file = OpenOptions::new()
-        .write(true)
-        .create_new(true)
-        .open(crate_file_path(crate_name, version))
-        .await?;
-    file.write_all(file_content).await
+#[derive(Clone, Debug)]
+/// Stores crate files as `<base path>/<normalized name>/<version>` on the local filesystem
+pub struct LocalCrateStorage {
+    base_path: PathBuf,
+}
+impl LocalCrateStorage {
+    pub fn new(base_path: PathBuf) -> Self {
+        Self { base_path }
+    }
+    fn crate_directory_path(&self, crate_name: &CrateName) -> PathBuf {
+        self.base_path.join(crate_name.normalized().as_str())
+    }
+    fn crate_file_path(&self, crate_name: &CrateName, version: &Version) -> PathBuf {
+        self.crate_directory_path(crate_name)
+            .join(without_build_metadata(version).to_string())
+    }
 }
-pub async fn get_crate_file(
-    version: Version,
-    crate_name: &CrateName,
-) -> Result<Vec<u8>, std::io::Error> {
-    let mut buf = Vec::new();
-    OpenOptions::new()
-        .read(true)
-        .open(crate_file_path(crate_name, version))
-        .await?
-        .read_to_end(&mut buf)
-        .await?;
-    Ok(buf)
+
+#[async_trait]
+impl CrateStorage for LocalCrateStorage {
+    async fn store(
+        &self,
+        crate_name: &CrateName,
+        version: &Version,
+        file_content: &[u8],
+    ) -> Result<(), std::io::Error> {
+        create_dir_all(&self.crate_directory_path(crate_name)).await?;
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(self.crate_file_path(crate_name, version))
+            .await?;
+        file.write_all(file_content).await
+    }
+    async fn get(
+        &self,
+        crate_name: &CrateName,
+        version: &Version,
+    ) -> Result<Vec<u8>, std::io::Error> {
+        let mut buf = Vec::new();
+        OpenOptions::new()
+            .read(true)
+            .open(self.crate_file_path(crate_name, version))
+            .await?
+            .read_to_end(&mut buf)
+            .await?;
+        Ok(buf)
+    }
+    async fn size(&self, crate_name: &CrateName, version: &Version) -> Result<u64, std::io::Error> {
+        Ok(metadata(self.crate_file_path(crate_name, version))
+            .await?
+            .len())
+    }
+    async fn delete(
+        &self,
+        crate_name: &CrateName,
+        version: &Version,
+    ) -> Result<(), std::io::Error> {
+        match remove_file(self.crate_file_path(crate_name, version)).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
 }
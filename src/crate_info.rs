@@ -0,0 +1,450 @@
+use std::collections::BTreeMap;
+
+use axum::{
+    extract::{Path, State},
+    http::{header::CACHE_CONTROL, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use semver::Version;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    auth::AuthenticatedUser,
+    crate_name::CrateName,
+    index::read_index_file,
+    middleware::internal_server_error,
+    postgres::{
+        count_authors, crate_exists_or_normalized, get_badges, get_crate_record,
+        get_documented_versions, get_links_owner, get_original_crate_name, get_total_downloads,
+        get_version_cksum, get_version_download_count, get_version_features, get_version_state,
+        is_name_reserved_for, list_authors, version_exists, CrateExists, CrateRecord,
+    },
+    ServerState,
+};
+
+pub async fn crate_info_handler(
+    State(ServerState {
+        database_connection_pool,
+        ..
+    }): State<ServerState>,
+    Path(crate_name): Path<CrateName>,
+) -> Result<Json<CrateInfo>, Response> {
+    let mut connection = database_connection_pool
+        .acquire()
+        .await
+        .map_err(|_e| internal_server_error("couldn't connect to database"))?;
+    let record = get_crate_record(&crate_name, &mut connection)
+        .await
+        .inspect_err(|e| eprintln!("Failed to get crate: {e}"))
+        .map_err(|_e| internal_server_error("couldn't get crate"))?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "crate doesn't exist").into_response())?;
+    let documented_versions = get_documented_versions(&crate_name, &mut connection)
+        .await
+        .inspect_err(|e| eprintln!("Failed to get documented versions: {e}"))
+        .map_err(|_e| internal_server_error("couldn't get documented versions"))?;
+    Ok(Json(CrateInfo::new(record, documented_versions)))
+}
+
+/// Whether publishing a new crate under the name would be accepted,
+/// reservations the authenticated user is allowed past don't count
+pub async fn crate_availability_handler(
+    State(ServerState {
+        database_connection_pool,
+        reserved_names,
+        name_policy,
+        ..
+    }): State<ServerState>,
+    user: Option<AuthenticatedUser>,
+    Path(crate_name): Path<CrateName>,
+) -> Result<Json<Availability>, Response> {
+    if let Err(e) = crate_name.ensure_publishable(name_policy) {
+        return Ok(Json(Availability::unavailable(e.to_string())));
+    }
+    let mut connection = database_connection_pool
+        .acquire()
+        .await
+        .map_err(|_e| internal_server_error("couldn't connect to database"))?;
+    match crate_exists_or_normalized(&crate_name, &mut connection)
+        .await
+        .inspect_err(|e| eprintln!("Failed to check if crate exists: {e}"))
+        .map_err(|_e| internal_server_error("couldn't check if crate exists"))?
+    {
+        CrateExists::Yes => return Ok(Json(Availability::unavailable("taken"))),
+        CrateExists::NoButNormalized => {
+            return Ok(Json(Availability::unavailable(
+                "taken by a name that normalizes the same",
+            )))
+        }
+        CrateExists::No => {}
+    }
+    let reserved = reserved_names.contains(&crate_name)
+        || is_name_reserved_for(&crate_name, user.map(|user| user.user_id), &mut connection)
+            .await
+            .inspect_err(|e| eprintln!("Failed to check reserved names: {e}"))
+            .map_err(|_e| internal_server_error("couldn't check reserved names"))?;
+    if reserved {
+        return Ok(Json(Availability::unavailable("reserved")));
+    }
+    Ok(Json(Availability {
+        available: true,
+        reason: None,
+    }))
+}
+
+#[derive(Debug, Serialize)]
+pub struct Availability {
+    available: bool,
+    reason: Option<String>,
+}
+impl Availability {
+    fn unavailable(reason: impl Into<String>) -> Self {
+        Self {
+            available: false,
+            reason: Some(reason.into()),
+        }
+    }
+}
+
+/// Downloads of all versions and days as one number
+pub async fn total_downloads_handler(
+    State(ServerState {
+        database_connection_pool,
+        ..
+    }): State<ServerState>,
+    Path(crate_name): Path<CrateName>,
+) -> Result<Json<TotalDownloads>, Response> {
+    let total_downloads = get_total_downloads(&crate_name, &*database_connection_pool)
+        .await
+        .inspect_err(|e| eprintln!("Failed to count downloads: {e}"))
+        .map_err(|_e| internal_server_error("couldn't count downloads"))?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "crate doesn't exist").into_response())?;
+    Ok(Json(TotalDownloads { total_downloads }))
+}
+
+#[derive(Debug, Serialize)]
+pub struct TotalDownloads {
+    total_downloads: i64,
+}
+
+/// Seconds badge services and proxies may cache a download count
+const DOWNLOAD_COUNT_MAX_AGE: u32 = 300;
+
+/// Downloads of one version on all days, a single number for badges
+pub async fn version_download_count_handler(
+    State(ServerState {
+        database_connection_pool,
+        ..
+    }): State<ServerState>,
+    Path(VersionPath {
+        crate_name,
+        version,
+    }): Path<VersionPath>,
+) -> Result<impl IntoResponse, Response> {
+    let downloads = get_version_download_count(&crate_name, &version, &*database_connection_pool)
+        .await
+        .inspect_err(|e| eprintln!("Failed to count downloads: {e}"))
+        .map_err(|_e| internal_server_error("couldn't count downloads"))?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "crate or version doesn't exist").into_response())?;
+    Ok((
+        [(
+            CACHE_CONTROL,
+            format!("public, max-age={DOWNLOAD_COUNT_MAX_AGE}"),
+        )],
+        Json(VersionDownloads { downloads }),
+    ))
+}
+
+#[derive(Debug, Serialize)]
+pub struct VersionDownloads {
+    downloads: i64,
+}
+
+/// The crate's index file as cargo reads it, to compare the index with the database
+pub async fn index_entries_handler(
+    State(state): State<ServerState>,
+    Path(crate_name): Path<CrateName>,
+) -> Result<String, Response> {
+    // The index file is named after the name the crate was published under
+    let stored_name = get_original_crate_name(&crate_name, &*state.database_connection_pool)
+        .await
+        .inspect_err(|e| eprintln!("Failed to look up crate: {e}"))
+        .map_err(|_e| internal_server_error("couldn't look up crate"))?
+        .and_then(|name| name.parse::<CrateName>().ok())
+        .unwrap_or(crate_name);
+    read_index_file(&stored_name, state.index_repository())
+        .await
+        .inspect_err(|e| eprintln!("Failed to read index: {e}"))
+        .map_err(|_e| internal_server_error("failed to read index"))?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "crate has no index file").into_response())
+}
+
+/// The crate owning a native library name, to diagnose `links` conflicts
+pub async fn links_owner_handler(
+    State(ServerState {
+        database_connection_pool,
+        ..
+    }): State<ServerState>,
+    Path(links): Path<String>,
+) -> Result<Json<LinksOwner>, Response> {
+    let krate = get_links_owner(&links, &*database_connection_pool)
+        .await
+        .inspect_err(|e| eprintln!("Failed to look up links owner: {e}"))
+        .map_err(|_e| internal_server_error("couldn't look up links owner"))?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "no crate links this library").into_response())?;
+    Ok(Json(LinksOwner { krate }))
+}
+
+#[derive(Debug, Serialize)]
+pub struct LinksOwner {
+    #[serde(rename = "crate")]
+    krate: String,
+}
+
+pub async fn badges_handler(
+    State(ServerState {
+        database_connection_pool,
+        ..
+    }): State<ServerState>,
+    Path(crate_name): Path<CrateName>,
+) -> Result<Json<BadgesResponse>, Response> {
+    let mut connection = database_connection_pool
+        .acquire()
+        .await
+        .map_err(|_e| internal_server_error("couldn't connect to database"))?;
+    let badges = get_badges(&crate_name, &mut connection)
+        .await
+        .inspect_err(|e| eprintln!("Failed to get badges: {e}"))
+        .map_err(|_e| internal_server_error("couldn't get badges"))?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "crate doesn't exist").into_response())?;
+    Ok(Json(BadgesResponse {
+        badges: badges
+            .into_iter()
+            .map(|(badge_type, attributes)| Badge {
+                badge_type,
+                attributes,
+            })
+            .collect(),
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VersionPath {
+    pub(crate) crate_name: CrateName,
+    pub(crate) version: Version,
+}
+
+/// Checksum of the crate file, without reading the file itself
+pub async fn checksum_handler(
+    State(ServerState {
+        database_connection_pool,
+        ..
+    }): State<ServerState>,
+    Path(VersionPath {
+        crate_name,
+        version,
+    }): Path<VersionPath>,
+) -> Result<Json<ChecksumResponse>, Response> {
+    let cksum = get_version_cksum(&crate_name, &version, &*database_connection_pool)
+        .await
+        .inspect_err(|e| eprintln!("Failed to get checksum: {e}"))
+        .map_err(|_e| internal_server_error("couldn't get checksum"))?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "crate or version doesn't exist").into_response())?;
+    Ok(Json(ChecksumResponse { cksum }))
+}
+
+/// Features of a version as in its index line, without fetching the index
+pub async fn version_features_handler(
+    State(ServerState {
+        database_connection_pool,
+        ..
+    }): State<ServerState>,
+    Path(VersionPath {
+        crate_name,
+        version,
+    }): Path<VersionPath>,
+) -> Result<Json<FeaturesResponse>, Response> {
+    let features = get_version_features(&crate_name, &version, &*database_connection_pool)
+        .await
+        .inspect_err(|e| eprintln!("Failed to get features: {e}"))
+        .map_err(|_e| internal_server_error("couldn't get features"))?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "crate or version doesn't exist").into_response())?;
+    Ok(Json(FeaturesResponse { features }))
+}
+
+/// Only the authors of a version, as given in the manifest on publish
+pub async fn version_authors_handler(
+    State(ServerState {
+        database_connection_pool,
+        ..
+    }): State<ServerState>,
+    Path(VersionPath {
+        crate_name,
+        version,
+    }): Path<VersionPath>,
+) -> Result<Json<AuthorsResponse>, Response> {
+    let mut connection = database_connection_pool
+        .acquire()
+        .await
+        .map_err(|_e| internal_server_error("couldn't connect to database"))?;
+    if !version_exists(&crate_name, &version, &mut *connection)
+        .await
+        .inspect_err(|e| eprintln!("Failed to check if version exists: {e}"))
+        .map_err(|_e| internal_server_error("couldn't check if version exists"))?
+    {
+        return Err((StatusCode::NOT_FOUND, "crate or version doesn't exist").into_response());
+    }
+    let authors = list_authors(&crate_name, &version, &mut *connection)
+        .await
+        .inspect_err(|e| eprintln!("Failed to get authors: {e}"))
+        .map_err(|_e| internal_server_error("couldn't get authors"))?;
+    Ok(Json(AuthorsResponse { authors }))
+}
+
+pub async fn version_info_handler(
+    State(ServerState {
+        database_connection_pool,
+        ..
+    }): State<ServerState>,
+    Path(VersionPath {
+        crate_name,
+        version,
+    }): Path<VersionPath>,
+) -> Result<Json<VersionInfoResponse>, Response> {
+    let mut connection = database_connection_pool
+        .acquire()
+        .await
+        .map_err(|_e| internal_server_error("couldn't connect to database"))?;
+    let state = get_version_state(&crate_name, &version, &mut *connection)
+        .await
+        .inspect_err(|e| eprintln!("Failed to get version: {e}"))
+        .map_err(|_e| internal_server_error("couldn't get version"))?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "crate or version doesn't exist").into_response())?;
+    let authors = list_authors(&crate_name, &version, &mut *connection)
+        .await
+        .inspect_err(|e| eprintln!("Failed to get authors: {e}"))
+        .map_err(|_e| internal_server_error("couldn't get authors"))?;
+    let author_count = count_authors(&crate_name, &version, &mut *connection)
+        .await
+        .inspect_err(|e| eprintln!("Failed to count authors: {e}"))
+        .map_err(|_e| internal_server_error("couldn't count authors"))?;
+    Ok(Json(VersionInfoResponse {
+        version: VersionInfo {
+            krate: crate_name,
+            num: version,
+            cksum: state.cksum,
+            yanked: state.yanked,
+            created_at: state.created_at,
+            downloads: state.downloads,
+            authors,
+            author_count,
+        },
+    }))
+}
+
+#[derive(Debug, Serialize)]
+pub struct VersionInfoResponse {
+    version: VersionInfo,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VersionInfo {
+    #[serde(rename = "crate")]
+    krate: CrateName,
+    num: Version,
+    cksum: String,
+    yanked: bool,
+    /// `null` for versions published before publish times were recorded
+    created_at: Option<DateTime<Utc>>,
+    downloads: i64,
+    /// As given in the manifest on publish
+    authors: Vec<String>,
+    author_count: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuthorsResponse {
+    authors: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FeaturesResponse {
+    features: BTreeMap<String, Vec<String>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChecksumResponse {
+    cksum: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BadgesResponse {
+    badges: Vec<Badge>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Badge {
+    badge_type: String,
+    attributes: serde_json::Value,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CrateInfo {
+    #[serde(rename = "crate")]
+    krate: CrateSummary,
+    /// Newest first
+    versions: Vec<Version>,
+    /// Versions with docs below `/docs/<crate>/<version>/`, newest first
+    documented_versions: Vec<Version>,
+}
+
+impl CrateInfo {
+    pub fn new(record: CrateRecord, mut documented_versions: Vec<Version>) -> Self {
+        let mut versions = record.versions.clone();
+        versions.sort_unstable_by(|a, b| b.cmp(a));
+        documented_versions.sort_unstable_by(|a, b| b.cmp(a));
+        Self {
+            krate: CrateSummary::from(record),
+            versions,
+            documented_versions,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+/// Crate-level information shared by the crate and search endpoints
+pub struct CrateSummary {
+    name: String,
+    max_version: Option<Version>,
+    description: String,
+    documentation: Option<String>,
+    homepage: Option<String>,
+    repository: Option<String>,
+    license: Option<String>,
+    deprecated: bool,
+    deprecation_message: Option<String>,
+    deprecation_replacement: Option<CrateName>,
+}
+
+impl From<CrateRecord> for CrateSummary {
+    fn from(record: CrateRecord) -> Self {
+        let (deprecation_message, deprecation_replacement) = record
+            .deprecation
+            .as_ref()
+            .map(|d| (d.message.clone(), d.replacement.clone()))
+            .unwrap_or_default();
+        Self {
+            max_version: record.versions.iter().max().cloned(),
+            name: record.name,
+            description: record.description,
+            documentation: record.documentation,
+            homepage: record.homepage,
+            repository: record.repository,
+            license: record.license,
+            deprecated: record.deprecation.is_some(),
+            deprecation_message,
+            deprecation_replacement,
+        }
+    }
+}
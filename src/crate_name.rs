@@ -1,8 +1,17 @@
 use std::{fmt::Display, hash::Hash, str::FromStr};
 
 use serde::{Deserialize, Serialize};
+use sqlx::{
+    encode::IsNull,
+    error::BoxDynError,
+    postgres::{PgArgumentBuffer, PgTypeInfo},
+    Encode, Postgres, Type,
+};
 use unicode_xid::UnicodeXID;
 
+/// Normalized names of the crates shipped with Rust, which resolve to the toolchain's copy
+const RESERVED_RUST_NAMES: &[&str] = &["alloc", "core", "proc_macro", "std", "test"];
+
 #[derive(Clone, Debug, Serialize)]
 /// Shares logic with cargo for validity of crate names
 ///
@@ -15,8 +24,62 @@ impl CrateName {
     pub fn original_str(&self) -> &str {
         &self.0
     }
-    pub fn normalized(&self) -> String {
-        self.0.replace('-', "_").to_lowercase()
+    pub fn normalized(&self) -> NormalizedCrateName {
+        NormalizedCrateName(self.0.replace('-', "_").to_lowercase())
+    }
+    /// Checks for names only new crates can't have, existing ones stay downloadable
+    pub fn ensure_publishable(&self, policy: CrateNamePolicy) -> Result<(), InvalidCrateName> {
+        if RESERVED_RUST_NAMES.contains(&self.normalized().as_str()) {
+            return Err(InvalidCrateName::ReservedRustName);
+        }
+        if policy == CrateNamePolicy::Ascii
+            && !self
+                .0
+                .chars()
+                .all(|ch| ch.is_ascii_alphanumeric() || ch == '-' || ch == '_')
+        {
+            return Err(InvalidCrateName::NotAscii);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+/// Which characters the names of newly published crates may use
+pub enum CrateNamePolicy {
+    #[default]
+    /// Everything [`CrateName`] accepts
+    Unicode,
+    /// ASCII letters, digits, `-` and `_`, so names can't imitate others with lookalike letters
+    Ascii,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// Lowercase crate name with `-` replaced by `_`, like `normalize_crate_name` in the database
+///
+/// Only [`CrateName::normalized`] creates it, so SQL parameters can't mix up the two forms.
+pub struct NormalizedCrateName(String);
+impl NormalizedCrateName {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+impl Display for NormalizedCrateName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+impl Type<Postgres> for NormalizedCrateName {
+    fn type_info() -> PgTypeInfo {
+        <&str as Type<Postgres>>::type_info()
+    }
+    fn compatible(ty: &PgTypeInfo) -> bool {
+        <&str as Type<Postgres>>::compatible(ty)
+    }
+}
+impl Encode<'_, Postgres> for NormalizedCrateName {
+    fn encode_by_ref(&self, buf: &mut PgArgumentBuffer) -> Result<IsNull, BoxDynError> {
+        <&str as Encode<Postgres>>::encode(self.as_str(), buf)
     }
 }
 impl PartialEq for CrateName {
@@ -48,6 +111,10 @@ impl Display for CrateName {
 impl FromStr for CrateName {
     type Err = InvalidCrateName;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // The index file name, checked first so `COM¹` isn't reported as an invalid letter.
+        // This covers every path component named after the crate: reserved names have no `-` or
+        // `_`, so the normalized name of the storage directory is reserved only if this one is,
+        // and index directories like `3` or `co` are never reserved.
         if is_reserved_file_name(&s.to_ascii_uppercase()) {
             return Err(InvalidCrateName::IsReservedFileName);
         }
@@ -90,6 +157,10 @@ pub enum InvalidCrateName {
     StartsWithDigit,
     FirstLetterNotUXID,
     LetterNotUXID,
+    /// Only checked on publish, see [`CrateName::ensure_publishable`]
+    ReservedRustName,
+    /// Only checked on publish with [`CrateNamePolicy::Ascii`]
+    NotAscii,
 }
 impl std::error::Error for InvalidCrateName {}
 impl std::fmt::Display for InvalidCrateName {
@@ -100,6 +171,12 @@ impl std::fmt::Display for InvalidCrateName {
             Self::StartsWithDigit => f.write_str("crate name starts with a digit"),
             Self::FirstLetterNotUXID => f.write_str("first letter is not unicode XID start or '_'"),
             Self::LetterNotUXID => f.write_str("characters after first must be unicode XID"),
+            Self::NotAscii => {
+                f.write_str("only ASCII letters, digits, '-' and '_' are allowed in new crate names")
+            }
+            Self::ReservedRustName => {
+                f.write_str("crate name belongs to a crate shipped with Rust, depending on it would be ambiguous")
+            }
         }
     }
 }
@@ -144,7 +221,71 @@ fn is_reserved_file_name(s: &str) -> bool {
 mod tests {
     use std::str::FromStr;
 
-    use crate::crate_name::{CrateName, InvalidCrateName};
+    use proptest::{prelude::*, string::string_regex};
+    use unicode_xid::UnicodeXID;
+
+    use crate::{
+        crate_name::{
+            is_reserved_file_name, CrateName, CrateNamePolicy, InvalidCrateName,
+            RESERVED_RUST_NAMES,
+        },
+        index::index_directories,
+    };
+
+    /// Accepted names satisfy the documented rules and keep their original spelling
+    fn check_accepted_name(s: &str) -> Result<(), TestCaseError> {
+        let Ok(name) = CrateName::from_str(s) else {
+            return Ok(());
+        };
+        let mut chars = s.chars();
+        let first = chars.next().unwrap();
+        prop_assert!(!first.is_ascii_digit());
+        prop_assert!(first == '_' || first.is_xid_start());
+        prop_assert!(chars.all(|ch| ch == '-' || ch.is_xid_continue()));
+        prop_assert!(!is_reserved_file_name(&s.to_ascii_uppercase()));
+        // Neither the index directories nor the storage directory have reserved names
+        let mut path_components = index_directories(s);
+        path_components.push(name.normalized().to_string());
+        for component in path_components {
+            prop_assert!(!is_reserved_file_name(&component.to_ascii_uppercase()));
+        }
+        prop_assert_eq!(name.original_str(), s);
+        let reparsed = CrateName::from_str(name.original_str()).map(|name| name.to_string());
+        prop_assert_eq!(reparsed, Ok(s.to_string()));
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn arbitrary_strings_only_pass_as_valid_names(
+            s in string_regex("(?s).{0,12}").unwrap()
+        ) {
+            check_accepted_name(&s)?;
+        }
+        #[test]
+        fn identifier_like_strings_only_pass_as_valid_names(
+            s in string_regex("[_\\p{XID_Start}0-9-][\\p{XID_Continue}\\-❤]{0,10}").unwrap()
+        ) {
+            check_accepted_name(&s)?;
+        }
+        #[test]
+        fn ascii_identifiers_are_accepted(
+            s in string_regex("[a-zA-Z_][a-zA-Z0-9_-]{3,20}").unwrap()
+        ) {
+            // At least 4 letters, so neither the name nor its index directories are reserved
+            prop_assert!(CrateName::from_str(&s).is_ok());
+            check_accepted_name(&s)?;
+        }
+        #[test]
+        fn reserved_file_names_are_rejected_in_any_case(
+            s in string_regex("(?i)con|prn|aux|nul|(com|lpt)[0-9¹²³]").unwrap()
+        ) {
+            prop_assert_eq!(
+                CrateName::from_str(&s),
+                Err(InvalidCrateName::IsReservedFileName)
+            );
+        }
+    }
 
     #[test]
     fn disallow_lowercase_aux() {
@@ -164,4 +305,98 @@ mod tests {
             Err(InvalidCrateName::FirstLetterNotUXID)
         );
     }
+    #[test]
+    fn disallow_leading_digit() {
+        assert_eq!(
+            CrateName::from_str("1abc"),
+            Err(InvalidCrateName::StartsWithDigit)
+        );
+    }
+    #[test]
+    fn disallow_emoji_after_first_letter() {
+        assert_eq!(
+            CrateName::from_str("abc❤️"),
+            Err(InvalidCrateName::LetterNotUXID)
+        );
+    }
+    #[test]
+    fn disallow_all_reserved_file_names() {
+        let numbered = ["COM", "LPT"].into_iter().flat_map(|prefix| {
+            [
+                "0", "1", "2", "3", "4", "5", "6", "7", "8", "9", "¹", "²", "³",
+            ]
+            .into_iter()
+            .map(move |suffix| format!("{prefix}{suffix}"))
+        });
+        for name in ["CON", "PRN", "AUX", "NUL"]
+            .into_iter()
+            .map(String::from)
+            .chain(numbered)
+        {
+            for name in [name.clone(), name.to_lowercase()] {
+                assert_eq!(
+                    CrateName::from_str(&name),
+                    Err(InvalidCrateName::IsReservedFileName),
+                    "{name}"
+                );
+            }
+        }
+    }
+    #[test]
+    fn allow_reserved_file_name_as_prefix() {
+        assert!(CrateName::from_str("con-fig").is_ok());
+        assert!(CrateName::from_str("com10").is_ok());
+    }
+    #[test]
+    fn allow_hyphens_and_underscores() {
+        assert!(CrateName::from_str("serde_json").is_ok());
+        assert!(CrateName::from_str("registry-server").is_ok());
+        assert!(CrateName::from_str("mixed-name_here").is_ok());
+    }
+    #[test]
+    fn allow_leading_underscore() {
+        assert!(CrateName::from_str("_private").is_ok());
+        assert!(CrateName::from_str("_").is_ok());
+    }
+    #[test]
+    fn allow_64_characters() {
+        assert!(CrateName::from_str(&"a".repeat(64)).is_ok());
+    }
+    #[test]
+    fn disallow_publishing_rust_crate_names() {
+        for name in RESERVED_RUST_NAMES.iter().chain(&["Std", "proc-macro"]) {
+            assert_eq!(
+                CrateName::from_str(name)
+                    .unwrap()
+                    .ensure_publishable(CrateNamePolicy::Unicode),
+                Err(InvalidCrateName::ReservedRustName),
+                "{name}"
+            );
+        }
+    }
+    #[test]
+    fn allow_rust_crate_names_as_prefix() {
+        assert_eq!(
+            CrateName::from_str("std-ext")
+                .unwrap()
+                .ensure_publishable(CrateNamePolicy::Unicode),
+            Ok(())
+        );
+    }
+    #[test]
+    fn homoglyph_names_depend_on_policy() {
+        // Cyrillic 'а' in place of the Latin 'a'
+        let name = CrateName::from_str("pаrser").unwrap();
+        assert_eq!(name.ensure_publishable(CrateNamePolicy::Unicode), Ok(()));
+        assert_eq!(
+            name.ensure_publishable(CrateNamePolicy::Ascii),
+            Err(InvalidCrateName::NotAscii)
+        );
+        assert_eq!(
+            CrateName::from_str("parser-2_x")
+                .unwrap()
+                .ensure_publishable(CrateNamePolicy::Ascii),
+            Ok(())
+        );
+    }
 }
@@ -0,0 +1,123 @@
+use std::collections::{BTreeSet, HashSet};
+
+use axum::{
+    extract::{Path, State},
+    response::Response,
+    Json,
+};
+use serde::Deserialize;
+
+use crate::{
+    auth::{ensure_crate_owner, AuthenticatedUser},
+    crate_info::CrateInfo,
+    crate_name::CrateName,
+    middleware::{bad_request, internal_server_error},
+    non_empty_strings::{Description, Keyword},
+    postgres::{
+        add_keywords, delete_category_entries, delete_keywords, get_bad_categories,
+        get_crate_record, get_documented_versions, insert_categories, update_crate_metadata,
+        CrateMetadataUpdate,
+    },
+    publish::url_errors,
+    ServerState,
+};
+
+#[derive(Debug, Deserialize)]
+/// Crate-level fields, omitted or `null` fields stay as they are
+pub struct PatchCrateBody {
+    description: Option<Description>,
+    homepage: Option<String>,
+    repository: Option<String>,
+    documentation: Option<String>,
+    /// Replaces all keywords of the crate
+    keywords: Option<HashSet<Keyword>>,
+    /// Replaces all categories of the crate, unknown categories are rejected
+    categories: Option<HashSet<String>>,
+}
+
+/// Updates crate-level metadata without publishing a new version
+pub async fn update_crate_handler(
+    State(ServerState {
+        database_connection_pool,
+        ..
+    }): State<ServerState>,
+    Path(crate_name): Path<CrateName>,
+    user: AuthenticatedUser,
+    Json(PatchCrateBody {
+        description,
+        homepage,
+        repository,
+        documentation,
+        keywords,
+        categories,
+    }): Json<PatchCrateBody>,
+) -> Result<Json<CrateInfo>, Response> {
+    let mut transaction = database_connection_pool
+        .begin()
+        .await
+        .map_err(|_e| internal_server_error("couldn't start transaction"))?;
+    ensure_crate_owner(&crate_name, &user, &mut transaction).await?;
+    let invalid_urls = url_errors(
+        homepage.as_deref(),
+        documentation.as_deref(),
+        repository.as_deref(),
+    );
+    if !invalid_urls.is_empty() {
+        return Err(bad_request(invalid_urls.join(", ")));
+    }
+    let update = CrateMetadataUpdate {
+        description,
+        homepage,
+        repository,
+        documentation,
+    };
+    update_crate_metadata(&crate_name, &update, &mut transaction)
+        .await
+        .inspect_err(|e| eprintln!("Failed to update crate: {e}"))
+        .map_err(|_e| internal_server_error("couldn't update crate"))?;
+    if let Some(keywords) = keywords {
+        delete_keywords(&crate_name, &mut transaction)
+            .await
+            .inspect_err(|e| eprintln!("Failed to delete keywords: {e}"))
+            .map_err(|_e| internal_server_error("couldn't remove old keywords"))?;
+        add_keywords(&crate_name, &keywords, &mut transaction)
+            .await
+            .inspect_err(|e| eprintln!("Failed to add keywords: {e}"))
+            .map_err(|_e| internal_server_error("couldn't add keywords"))?;
+    }
+    if let Some(categories) = categories {
+        let unknown_categories = get_bad_categories(&categories, &mut transaction)
+            .await
+            .inspect_err(|e| eprintln!("Failed to check categories: {e}"))
+            .map_err(|_e| internal_server_error("couldn't check categories"))?;
+        if !unknown_categories.is_empty() {
+            let unknown_categories = Vec::from_iter(BTreeSet::from_iter(unknown_categories));
+            return Err(bad_request(format!(
+                "unknown categories: {}",
+                unknown_categories.join(", ")
+            )));
+        }
+        delete_category_entries(&crate_name, &mut transaction)
+            .await
+            .inspect_err(|e| eprintln!("Failed to delete category entries: {e}"))
+            .map_err(|_e| internal_server_error("couldn't remove old categories"))?;
+        insert_categories(categories, &crate_name, &mut transaction)
+            .await
+            .inspect_err(|e| eprintln!("Failed to insert categories: {e}"))
+            .map_err(|_e| internal_server_error("couldn't add categories"))?;
+    }
+    let record = get_crate_record(&crate_name, &mut transaction)
+        .await
+        .inspect_err(|e| eprintln!("Failed to get crate: {e}"))
+        .map_err(|_e| internal_server_error("couldn't get crate"))?
+        .ok_or_else(|| internal_server_error("crate disappeared during update"))?;
+    let documented_versions = get_documented_versions(&crate_name, &mut transaction)
+        .await
+        .inspect_err(|e| eprintln!("Failed to get documented versions: {e}"))
+        .map_err(|_e| internal_server_error("couldn't get documented versions"))?;
+    transaction
+        .commit()
+        .await
+        .map_err(|_e| internal_server_error("committing to database failed"))?;
+    Ok(Json(CrateInfo::new(record, documented_versions)))
+}
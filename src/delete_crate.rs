@@ -0,0 +1,140 @@
+use axum::{
+    extract::{Extension, Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+
+use crate::{
+    audit::{record_failure, AuditAction, AuditContext, AuditEvent, AuditOutcome},
+    auth::Admin,
+    concurrency::index_busy,
+    crate_name::CrateName,
+    index::{read_index_entries, remove_crate_from_index},
+    middleware::internal_server_error,
+    postgres::{add_audit_event, add_pending_publish, delete_crate, DeletedCrate},
+    request_id::RequestId,
+    write_ahead_log::resolve_pending_publish,
+    ServerState,
+};
+
+/// Deletes the crate with every version, for abandoned test crates and accidental publishes
+pub async fn admin_delete_crate_handler(
+    _admin: Admin,
+    State(state): State<ServerState>,
+    Path(crate_name): Path<CrateName>,
+    Extension(request_id): Extension<RequestId>,
+) -> Result<Json<CrateDeleted>, Response> {
+    let audit = AuditContext {
+        actor: Some(String::from(Admin::AUDIT_ACTOR)),
+        request_id,
+    };
+    let result = remove_crate(&state, &crate_name, &audit).await;
+    if let Err(response) = &result {
+        record_failure(
+            AuditEvent {
+                context: &audit,
+                action: AuditAction::DeleteCrate,
+                crate_name: Some(&crate_name),
+                version: None,
+                outcome: AuditOutcome::from_status(response.status()),
+            },
+            &state.database_connection_pool,
+        )
+        .await;
+    }
+    let DeletedCrate { versions, bytes } = result?;
+    Ok(Json(CrateDeleted {
+        ok: true,
+        versions_deleted: versions,
+        bytes_freed: bytes,
+    }))
+}
+
+/// Every index line goes through the write-ahead log like a version deletion, so the crate
+/// files are deleted once the database commit went through and the lines come back if it didn't.
+async fn remove_crate(
+    ServerState {
+        registry,
+        database_connection_pool,
+        index_lock_timeout,
+        ..
+    }: &ServerState,
+    crate_name: &CrateName,
+    audit: &AuditContext,
+) -> Result<DeletedCrate, Response> {
+    let repository = registry
+        .index_repository
+        .lock_timeout(*index_lock_timeout)
+        .await
+        .ok_or_else(index_busy)?;
+    let mut transaction = database_connection_pool
+        .begin()
+        .await
+        .map_err(|_e| internal_server_error("couldn't start transaction"))?;
+    let deleted = delete_crate(crate_name, &mut transaction)
+        .await
+        .inspect_err(|e| eprintln!("Failed to delete crate: {e}"))
+        .map_err(|_e| internal_server_error("couldn't delete crate"))?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "crate doesn't exist").into_response())?;
+    let entries = read_index_entries(crate_name, &repository)
+        .await
+        .inspect_err(|e| eprintln!("Failed to read index: {e}"))
+        .map_err(|_e| internal_server_error("failed to read index"))?;
+    let mut pending = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let pending_id = add_pending_publish(&entry, &**database_connection_pool)
+            .await
+            .inspect_err(|e| eprintln!("Failed to record pending deletion: {e}"))
+            .map_err(|_e| internal_server_error("failed to record pending deletion"))?;
+        pending.push((pending_id, entry));
+    }
+    let result = async {
+        add_audit_event(
+            AuditEvent {
+                context: audit,
+                action: AuditAction::DeleteCrate,
+                crate_name: Some(crate_name),
+                version: None,
+                outcome: AuditOutcome::Success,
+            },
+            &mut *transaction,
+        )
+        .await
+        .inspect_err(|e| eprintln!("Failed to record audit event: {e}"))
+        .map_err(|_e| internal_server_error("failed to record audit event"))?;
+        if !pending.is_empty() {
+            let commit_message = format!("DELETE CRATE: [{}]", crate_name.original_str());
+            remove_crate_from_index(crate_name, &repository, &commit_message)
+                .await
+                .inspect_err(|e| eprintln!("Failed to remove crate from index: {e}"))
+                .map_err(|_e| internal_server_error("failed to remove crate from index"))?;
+        }
+        transaction
+            .commit()
+            .await
+            .map_err(|_e| internal_server_error("committing to database failed"))
+    }
+    .await;
+    drop(repository);
+    // Deletes the crate files on success and restores the index lines on failure
+    for (pending_id, entry) in &pending {
+        match resolve_pending_publish(*pending_id, entry, database_connection_pool, registry).await
+        {
+            Ok(resolution) if result.is_err() => {
+                eprintln!("Cleaned up failed deletion: {resolution:?}");
+            }
+            Ok(_) => {}
+            Err(e) => eprintln!("Failed to clean up deletion, retrying on restart: {e}"),
+        }
+    }
+    result.map(|()| deleted)
+}
+
+#[derive(Debug, Serialize)]
+pub struct CrateDeleted {
+    ok: bool,
+    versions_deleted: i64,
+    bytes_freed: i64,
+}
@@ -0,0 +1,362 @@
+use std::time::Duration;
+
+use axum::{
+    extract::{Extension, Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{TimeDelta, Utc};
+use semver::{Version, VersionReq};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgConnection, Postgres, Transaction};
+
+use crate::{
+    audit::{record_failure, AuditAction, AuditContext, AuditEvent, AuditOutcome},
+    auth::{ensure_crate_owner, Admin, AuthenticatedUser},
+    concurrency::index_busy,
+    crate_info::VersionPath,
+    crate_name::CrateName,
+    index::{read_index_entry, remove_from_index, IndexRepository},
+    middleware::internal_server_error,
+    postgres::{
+        add_audit_event, add_pending_publish, delete_version, get_reverse_dependency_reqs,
+        get_version_state, get_versions, VersionState,
+    },
+    request_id::RequestId,
+    write_ahead_log::resolve_pending_publish,
+    yank::set_yanked,
+    ServerState,
+};
+
+#[derive(Debug)]
+enum Removal {
+    Deleted {
+        crate_removed: bool,
+    },
+    /// Deleting wasn't allowed for the given reason
+    Yanked(String),
+}
+
+/// Deletes a version published within the grace period that nobody used yet, yanks it otherwise
+pub async fn delete_version_handler(
+    State(state): State<ServerState>,
+    Path(VersionPath {
+        crate_name,
+        version,
+    }): Path<VersionPath>,
+    user: AuthenticatedUser,
+    Extension(request_id): Extension<RequestId>,
+) -> Result<Json<DeleteVersionResponse>, Response> {
+    let audit = AuditContext {
+        actor: Some(user.login.clone()),
+        request_id,
+    };
+    let result = delete_or_yank(&state, &crate_name, &version, &user, &audit).await;
+    if let Err(response) = &result {
+        record_failure(
+            AuditEvent {
+                context: &audit,
+                action: AuditAction::DeleteVersion,
+                crate_name: Some(&crate_name),
+                version: Some(&version),
+                outcome: AuditOutcome::from_status(response.status()),
+            },
+            &state.database_connection_pool,
+        )
+        .await;
+    }
+    Ok(Json(match result? {
+        Removal::Deleted { crate_removed } => DeleteVersionResponse {
+            ok: true,
+            deleted: true,
+            yanked: false,
+            msg: if crate_removed {
+                format!("deleted {crate_name}@{version} and the crate, it had no other versions")
+            } else {
+                format!("deleted {crate_name}@{version}")
+            },
+        },
+        Removal::Yanked(reason) => DeleteVersionResponse {
+            ok: true,
+            deleted: false,
+            yanked: true,
+            msg: format!(
+                "{crate_name}@{version} can't be deleted because {reason}, yanked it instead"
+            ),
+        },
+    }))
+}
+
+async fn delete_or_yank(
+    state: &ServerState,
+    crate_name: &CrateName,
+    version: &Version,
+    user: &AuthenticatedUser,
+    audit: &AuditContext,
+) -> Result<Removal, Response> {
+    let mut transaction = state
+        .database_connection_pool
+        .begin()
+        .await
+        .map_err(|_e| internal_server_error("couldn't start transaction"))?;
+    ensure_crate_owner(crate_name, user, &mut transaction).await?;
+    let version_state = get_version_state(crate_name, version, &mut *transaction)
+        .await
+        .inspect_err(|e| eprintln!("Failed to get version: {e}"))
+        .map_err(|_e| internal_server_error("couldn't get version"))?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "version doesn't exist").into_response())?;
+    if let Some(reason) = deletion_blocker(
+        crate_name,
+        version,
+        &version_state,
+        state.delete_grace_period,
+        &mut transaction,
+    )
+    .await?
+    {
+        set_yanked(state, crate_name, version, true, audit, transaction).await?;
+        return Ok(Removal::Yanked(reason));
+    }
+    let commit_message = format!(
+        "DELETE CRATE: [{}] version: {version}",
+        crate_name.original_str()
+    );
+    let crate_removed = remove_version(
+        state,
+        crate_name,
+        version,
+        audit,
+        &commit_message,
+        transaction,
+    )
+    .await?;
+    Ok(Removal::Deleted { crate_removed })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AdminDeleteQuery {
+    /// Needed to delete the last version, which deletes the crate with it
+    #[serde(default)]
+    delete_crate: bool,
+}
+
+/// Deletes any version regardless of age, downloads and dependents, for leaked secrets and the like
+pub async fn admin_delete_version_handler(
+    _admin: Admin,
+    State(state): State<ServerState>,
+    Path(VersionPath {
+        crate_name,
+        version,
+    }): Path<VersionPath>,
+    Query(AdminDeleteQuery { delete_crate }): Query<AdminDeleteQuery>,
+    Extension(request_id): Extension<RequestId>,
+) -> Result<Json<DeleteVersionResponse>, Response> {
+    let audit = AuditContext {
+        actor: Some(String::from(Admin::AUDIT_ACTOR)),
+        request_id,
+    };
+    let result = force_delete(&state, &crate_name, &version, delete_crate, &audit).await;
+    if let Err(response) = &result {
+        record_failure(
+            AuditEvent {
+                context: &audit,
+                action: AuditAction::DeleteVersion,
+                crate_name: Some(&crate_name),
+                version: Some(&version),
+                outcome: AuditOutcome::from_status(response.status()),
+            },
+            &state.database_connection_pool,
+        )
+        .await;
+    }
+    let crate_removed = result?;
+    Ok(Json(DeleteVersionResponse {
+        ok: true,
+        deleted: true,
+        yanked: false,
+        msg: if crate_removed {
+            format!("deleted {crate_name}@{version} and the crate")
+        } else {
+            format!("deleted {crate_name}@{version}")
+        },
+    }))
+}
+
+async fn force_delete(
+    state: &ServerState,
+    crate_name: &CrateName,
+    version: &Version,
+    delete_crate: bool,
+    audit: &AuditContext,
+) -> Result<bool, Response> {
+    let mut transaction = state
+        .database_connection_pool
+        .begin()
+        .await
+        .map_err(|_e| internal_server_error("couldn't start transaction"))?;
+    let versions = get_versions(crate_name, &mut transaction)
+        .await
+        .inspect_err(|e| eprintln!("Failed to get versions: {e}"))
+        .map_err(|_e| internal_server_error("couldn't get versions"))?;
+    if !versions.iter().any(|existing| existing.vers == *version) {
+        return Err((StatusCode::NOT_FOUND, "crate or version doesn't exist").into_response());
+    }
+    if versions.len() == 1 && !delete_crate {
+        return Err((
+            StatusCode::CONFLICT,
+            format!(
+                "{version} is the only version of {crate_name}, \
+                pass delete_crate=true to delete the crate with it"
+            ),
+        )
+            .into_response());
+    }
+    let commit_message = format!(
+        "DELETE CRATE: [{}] version: {version} (removed by an administrator)",
+        crate_name.original_str()
+    );
+    remove_version(
+        state,
+        crate_name,
+        version,
+        audit,
+        &commit_message,
+        transaction,
+    )
+    .await
+}
+
+/// Why the version has to stay, `None` if it may be deleted
+async fn deletion_blocker(
+    crate_name: &CrateName,
+    version: &Version,
+    version_state: &VersionState,
+    grace_period: Duration,
+    exec: &mut PgConnection,
+) -> Result<Option<String>, Response> {
+    let grace_period = TimeDelta::from_std(grace_period).unwrap_or(TimeDelta::MAX);
+    if version_state
+        .created_at
+        .is_none_or(|created_at| Utc::now() - created_at > grace_period)
+    {
+        return Ok(Some(format!(
+            "it was published more than {} hours ago",
+            grace_period.num_hours()
+        )));
+    }
+    if version_state.downloads > 0 {
+        return Ok(Some(String::from("it has been downloaded")));
+    }
+    let reverse_dependency_reqs = get_reverse_dependency_reqs(crate_name, exec)
+        .await
+        .inspect_err(|e| eprintln!("Failed to get reverse dependencies: {e}"))
+        .map_err(|_e| internal_server_error("couldn't get reverse dependencies"))?;
+    // Requirements that don't parse anymore are assumed to match
+    if reverse_dependency_reqs
+        .iter()
+        .any(|req| VersionReq::parse(req).map_or(true, |req| req.matches(version)))
+    {
+        return Ok(Some(String::from("other crates depend on it")));
+    }
+    Ok(None)
+}
+
+/// Removes database rows, index line and crate file, returning whether the crate was removed too
+///
+/// The index line is removed in a new commit instead of rewriting history, so clones of the
+/// index can still fast-forward. The removal goes through the write-ahead log like a publish,
+/// with the database deciding whether index and crate file still have to go after a failure.
+async fn remove_version(
+    ServerState {
+        registry,
+        database_connection_pool,
+        index_lock_timeout,
+        ..
+    }: &ServerState,
+    crate_name: &CrateName,
+    version: &Version,
+    audit: &AuditContext,
+    commit_message: &str,
+    transaction: Transaction<'_, Postgres>,
+) -> Result<bool, Response> {
+    let repository = registry
+        .index_repository
+        .lock_timeout(*index_lock_timeout)
+        .await
+        .ok_or_else(index_busy)?;
+    let index_entry = read_index_entry(crate_name, version, &repository)
+        .await
+        .inspect_err(|e| eprintln!("Failed to read index: {e}"))
+        .map_err(|_e| internal_server_error("failed to read index"))?
+        .ok_or_else(|| internal_server_error("version is missing from the index"))?;
+    let pending_id = add_pending_publish(&index_entry, &**database_connection_pool)
+        .await
+        .inspect_err(|e| eprintln!("Failed to record pending deletion: {e}"))
+        .map_err(|_e| internal_server_error("failed to record pending deletion"))?;
+    let result = delete_rows_and_index_line(
+        crate_name,
+        version,
+        audit,
+        commit_message,
+        transaction,
+        &repository,
+    )
+    .await;
+    drop(repository);
+    // Deletes the crate file on success and restores the index line on failure
+    match resolve_pending_publish(pending_id, &index_entry, database_connection_pool, registry)
+        .await
+    {
+        Ok(resolution) if result.is_err() => {
+            eprintln!("Cleaned up failed deletion: {resolution:?}");
+        }
+        Ok(_) => {}
+        Err(e) => eprintln!("Failed to clean up deletion, retrying on restart: {e}"),
+    }
+    result
+}
+
+async fn delete_rows_and_index_line(
+    crate_name: &CrateName,
+    version: &Version,
+    audit: &AuditContext,
+    commit_message: &str,
+    mut transaction: Transaction<'_, Postgres>,
+    repository: &IndexRepository,
+) -> Result<bool, Response> {
+    let crate_removed = delete_version(crate_name, version, &mut transaction)
+        .await
+        .inspect_err(|e| eprintln!("Failed to delete version: {e}"))
+        .map_err(|_e| internal_server_error("couldn't delete version"))?;
+    add_audit_event(
+        AuditEvent {
+            context: audit,
+            action: AuditAction::DeleteVersion,
+            crate_name: Some(crate_name),
+            version: Some(version),
+            outcome: AuditOutcome::Success,
+        },
+        &mut *transaction,
+    )
+    .await
+    .inspect_err(|e| eprintln!("Failed to record audit event: {e}"))
+    .map_err(|_e| internal_server_error("failed to record audit event"))?;
+    remove_from_index(crate_name, version, repository, commit_message)
+        .await
+        .inspect_err(|e| eprintln!("Failed to remove version from index: {e}"))
+        .map_err(|_e| internal_server_error("failed to remove version from index"))?;
+    transaction
+        .commit()
+        .await
+        .map_err(|_e| internal_server_error("committing to database failed"))?;
+    Ok(crate_removed)
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeleteVersionResponse {
+    ok: bool,
+    deleted: bool,
+    yanked: bool,
+    msg: String,
+}
@@ -0,0 +1,139 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    crate_info::VersionPath,
+    index::{read_index_entry, IndexEntry},
+    middleware::internal_server_error,
+    postgres::get_version_deps,
+    publish::{DependencyKind, DependencyMetadata},
+    ServerState,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+/// Dependency of a version, as stored in `versions.deps` and returned by the API
+pub struct Dependency {
+    /// Name of the depended on crate, not the name it was renamed to
+    crate_id: String,
+    req: String,
+    optional: bool,
+    default_features: bool,
+    features: Vec<String>,
+    kind: DependencyKind,
+    target: Option<String>,
+}
+impl From<&DependencyMetadata> for Dependency {
+    fn from(dependency: &DependencyMetadata) -> Self {
+        Self {
+            crate_id: dependency.name.original_str().to_string(),
+            req: dependency.version_req.to_string(),
+            optional: dependency.optional,
+            default_features: dependency.default_features,
+            features: dependency
+                .features
+                .iter()
+                .map(ToString::to_string)
+                .collect(),
+            kind: dependency.kind,
+            target: dependency.target.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+/// The part of an index line needed for versions published before dependencies were stored
+struct IndexLine {
+    deps: Vec<IndexDependency>,
+}
+
+#[derive(Debug, Deserialize)]
+struct IndexDependency {
+    /// The name the dependency was renamed to if `package` is set
+    name: String,
+    req: String,
+    features: Vec<String>,
+    optional: bool,
+    default_features: bool,
+    target: Option<String>,
+    kind: Option<DependencyKind>,
+    package: Option<String>,
+}
+impl From<IndexDependency> for Dependency {
+    fn from(dependency: IndexDependency) -> Self {
+        Self {
+            crate_id: dependency.package.unwrap_or(dependency.name),
+            req: dependency.req,
+            optional: dependency.optional,
+            default_features: dependency.default_features,
+            features: dependency.features,
+            kind: dependency.kind.unwrap_or(DependencyKind::Normal),
+            target: dependency.target,
+        }
+    }
+}
+
+pub fn deps_from_index_entry(entry: &IndexEntry) -> Result<Vec<Dependency>, serde_json::Error> {
+    Ok(serde_json::from_str::<IndexLine>(entry.line())?
+        .deps
+        .into_iter()
+        .map(Dependency::from)
+        .collect())
+}
+
+pub async fn version_deps_handler(
+    State(state): State<ServerState>,
+    Path(VersionPath {
+        crate_name,
+        version,
+    }): Path<VersionPath>,
+) -> Result<Json<DependenciesResponse>, Response> {
+    let stored = get_version_deps(&crate_name, &version, &*state.database_connection_pool)
+        .await
+        .inspect_err(|e| eprintln!("Failed to get dependencies: {e}"))
+        .map_err(|_e| internal_server_error("couldn't get dependencies"))?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "crate or version doesn't exist").into_response())?;
+    let dependencies = match stored {
+        Some(dependencies) => dependencies,
+        None => {
+            let entry = read_index_entry(&crate_name, &version, state.index_repository())
+                .await
+                .inspect_err(|e| eprintln!("Failed to read index: {e}"))
+                .map_err(|_e| internal_server_error("failed to read index"))?
+                .ok_or_else(|| internal_server_error("version is missing from the index"))?;
+            deps_from_index_entry(&entry)
+                .inspect_err(|e| eprintln!("Failed to parse index line: {e}"))
+                .map_err(|_e| internal_server_error("invalid line in index"))?
+        }
+    };
+    Ok(Json(DependenciesResponse { dependencies }))
+}
+
+#[derive(Debug, Serialize)]
+pub struct DependenciesResponse {
+    dependencies: Vec<Dependency>,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{dependencies::deps_from_index_entry, index::IndexEntry};
+
+    #[test]
+    fn renamed_index_dependencies_use_the_package_name() {
+        let entry = IndexEntry::from_line(
+            "foo".parse().unwrap(),
+            "1.0.0".parse().unwrap(),
+            String::from(
+                r#"{"name":"foo","vers":"1.0.0","deps":[{"name":"renamed","req":"^1","features":[],"optional":false,"default_features":true,"target":null,"kind":"dev","registry":null,"package":"bar"}]}"#,
+            ),
+        );
+        let deps = deps_from_index_entry(&entry).unwrap();
+        assert_eq!(deps.len(), 1);
+        assert_eq!(deps[0].crate_id, "bar");
+        assert!(matches!(deps[0].kind, crate::publish::DependencyKind::Dev));
+    }
+}
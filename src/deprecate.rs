@@ -0,0 +1,74 @@
+use axum::{
+    extract::{Path, State},
+    response::Response,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    auth::{ensure_crate_owner, AuthenticatedUser},
+    crate_name::CrateName,
+    middleware::{bad_request, internal_server_error},
+    postgres::{crate_exists_exact, set_crate_deprecation, Deprecation},
+    ServerState,
+};
+
+#[derive(Debug, Deserialize)]
+/// Setting `deprecated` to `false` lifts an earlier deprecation
+pub struct DeprecateBody {
+    deprecated: bool,
+    message: Option<String>,
+    /// Suggested crate to use instead, has to exist in this registry
+    replacement: Option<CrateName>,
+}
+
+pub async fn deprecate_handler(
+    State(ServerState {
+        database_connection_pool,
+        ..
+    }): State<ServerState>,
+    Path(crate_name): Path<CrateName>,
+    user: AuthenticatedUser,
+    Json(DeprecateBody {
+        deprecated,
+        message,
+        replacement,
+    }): Json<DeprecateBody>,
+) -> Result<Json<DeprecateResponse>, Response> {
+    let mut transaction = database_connection_pool
+        .begin()
+        .await
+        .map_err(|_e| internal_server_error("couldn't start transaction"))?;
+    ensure_crate_owner(&crate_name, &user, &mut transaction).await?;
+    if let Some(replacement) = replacement.as_ref().filter(|_| deprecated) {
+        if *replacement == crate_name {
+            return Err(bad_request("a crate can't replace itself"));
+        }
+        if !crate_exists_exact(replacement, &mut transaction)
+            .await
+            .map_err(|_e| internal_server_error("couldn't check if crate exists"))?
+        {
+            return Err(bad_request(format!(
+                "replacement crate {replacement} doesn't exist"
+            )));
+        }
+    }
+    let deprecation = deprecated.then_some(Deprecation {
+        message,
+        replacement,
+    });
+    set_crate_deprecation(&crate_name, deprecation.as_ref(), &mut transaction)
+        .await
+        .inspect_err(|e| eprintln!("Failed to set deprecation: {e}"))
+        .map_err(|_e| internal_server_error("couldn't update crate"))?;
+    transaction
+        .commit()
+        .await
+        .map_err(|_e| internal_server_error("committing to database failed"))?;
+    Ok(Json(DeprecateResponse { ok: true }))
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeprecateResponse {
+    ok: bool,
+}
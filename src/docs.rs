@@ -0,0 +1,486 @@
+use std::{
+    fmt::Display,
+    io::ErrorKind,
+    path::{Component, Path as FilePath, PathBuf},
+};
+
+use axum::{
+    body::{to_bytes, Body, Bytes},
+    extract::{Extension, Path, State},
+    http::{
+        header::{CONTENT_TYPE, X_CONTENT_TYPE_OPTIONS},
+        StatusCode,
+    },
+    response::{IntoResponse, Response},
+    Json,
+};
+use flate2::read::GzDecoder;
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    fs::{create_dir_all, metadata, read, read_link, remove_dir_all, rename, symlink},
+    sync::Mutex,
+};
+
+use crate::{
+    audit::{record_failure, AuditAction, AuditContext, AuditEvent, AuditOutcome},
+    auth::{ensure_crate_owner, AuthenticatedUser},
+    crate_info::VersionPath,
+    crate_name::CrateName,
+    middleware::internal_server_error,
+    postgres::{add_audit_event, set_version_has_docs, version_exists},
+    request_id::RequestId,
+    version::without_build_metadata,
+    ServerState,
+};
+
+/// Where docs are unpacked if nothing else is configured
+pub const DEFAULT_DOCS_STORAGE_PATH: &str = "./target/test_filesystem/docs/";
+
+#[derive(Debug)]
+/// Unpacked rustdoc output, served below `/docs/<crate>/<version>/`
+///
+/// `<base path>/<normalized name>/<version>` is a symlink to the directory of the latest upload.
+/// Replacing docs swaps the link with one rename, so requests never see a half-unpacked tree.
+pub struct DocsStorage {
+    base_path: PathBuf,
+    /// Bytes of unpacked files per upload
+    max_size: u64,
+    /// Serializes replacements, so each one removes the directory it replaced
+    replacing: Mutex<()>,
+}
+impl DocsStorage {
+    pub fn new(base_path: PathBuf, max_size: u64) -> Self {
+        Self {
+            base_path,
+            max_size,
+            replacing: Mutex::new(()),
+        }
+    }
+    pub fn max_size(&self) -> u64 {
+        self.max_size
+    }
+    fn crate_directory_path(&self, crate_name: &CrateName) -> PathBuf {
+        self.base_path.join(crate_name.normalized().as_str())
+    }
+    fn version_path(&self, crate_name: &CrateName, version: &Version) -> PathBuf {
+        self.crate_directory_path(crate_name)
+            .join(without_build_metadata(version).to_string())
+    }
+    /// Unpacks a `.tar.gz` of the contents of `target/doc` as the version's docs
+    pub async fn replace(
+        &self,
+        crate_name: &CrateName,
+        version: &Version,
+        archive: Bytes,
+    ) -> Result<(), DocsError> {
+        let crate_directory = self.crate_directory_path(crate_name);
+        create_dir_all(&crate_directory)
+            .await
+            .map_err(DocsError::Io)?;
+        // Hidden, a version can't start with a dot
+        let upload_name = format!(
+            ".{}-{}",
+            without_build_metadata(version),
+            uuid::Uuid::new_v4().simple()
+        );
+        let upload_path = crate_directory.join(&upload_name);
+        let max_size = self.max_size;
+        let unpacked = tokio::task::spawn_blocking({
+            let upload_path = upload_path.clone();
+            move || unpack(&archive, &upload_path, max_size)
+        })
+        .await
+        .map_err(|e| DocsError::Io(e.into()))?;
+        if let Err(e) = unpacked {
+            remove_dir_all(&upload_path).await.ok();
+            return Err(e);
+        }
+        let _replacing = self.replacing.lock().await;
+        let version_path = self.version_path(crate_name, version);
+        let link_path = crate_directory.join(format!("{upload_name}.link"));
+        // Relative, the storage can be moved as a whole
+        symlink(&upload_name, &link_path)
+            .await
+            .map_err(DocsError::Io)?;
+        let replaced = read_link(&version_path).await.ok();
+        rename(&link_path, &version_path)
+            .await
+            .map_err(DocsError::Io)?;
+        if let Some(replaced) = replaced {
+            if let Err(e) = remove_dir_all(crate_directory.join(replaced)).await {
+                eprintln!("Failed to remove replaced docs of {crate_name} {version}: {e}");
+            }
+        }
+        Ok(())
+    }
+    /// The file at `path` of the version's docs, `None` if there is no such file
+    ///
+    /// Paths ending in `/` are directories and serve their `index.html`.
+    pub async fn read(
+        &self,
+        crate_name: &CrateName,
+        version: &Version,
+        path: &str,
+    ) -> Result<Option<Vec<u8>>, std::io::Error> {
+        let Some(relative) = docs_file_path(path) else {
+            return Ok(None);
+        };
+        let file_path = self.version_path(crate_name, version).join(relative);
+        match metadata(&file_path).await {
+            Ok(file) if file.is_file() => read(&file_path).await.map(Some),
+            Ok(_) => Ok(None),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+    /// Removing docs that don't exist is not an error
+    pub async fn delete(
+        &self,
+        crate_name: &CrateName,
+        version: &Version,
+    ) -> Result<(), std::io::Error> {
+        let _replacing = self.replacing.lock().await;
+        let version_path = self.version_path(crate_name, version);
+        let target = match read_link(&version_path).await {
+            Ok(target) => target,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        tokio::fs::remove_file(&version_path).await?;
+        remove_dir_all(self.crate_directory_path(crate_name).join(target)).await
+    }
+}
+
+/// Regular files and directories of the archive, links could point outside of the docs
+fn unpack(archive: &[u8], destination: &FilePath, max_size: u64) -> Result<(), DocsError> {
+    std::fs::create_dir_all(destination).map_err(DocsError::Io)?;
+    let mut archive = tar::Archive::new(GzDecoder::new(archive));
+    let mut unpacked_size = 0u64;
+    for entry in archive.entries().map_err(DocsError::InvalidArchive)? {
+        let mut entry = entry.map_err(DocsError::InvalidArchive)?;
+        let entry_type = entry.header().entry_type();
+        if !entry_type.is_file() && !entry_type.is_dir() {
+            continue;
+        }
+        unpacked_size += entry.size();
+        if unpacked_size > max_size {
+            return Err(DocsError::TooLarge { max_size });
+        }
+        // Paths leaving the destination are skipped by tar, they are rejected instead
+        if !entry
+            .unpack_in(destination)
+            .map_err(DocsError::InvalidArchive)?
+        {
+            let path = entry.path().map_err(DocsError::InvalidArchive)?;
+            return Err(DocsError::InvalidPath(path.into_owned()));
+        }
+    }
+    Ok(())
+}
+
+/// `path` relative to the version's docs, `None` if it would leave them
+fn docs_file_path(path: &str) -> Option<PathBuf> {
+    let mut relative = PathBuf::new();
+    for component in FilePath::new(path).components() {
+        match component {
+            Component::Normal(part) => relative.push(part),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    if path.is_empty() || path.ends_with('/') {
+        relative.push("index.html");
+    }
+    Some(relative)
+}
+
+/// Content type of a docs file, rustdoc only writes a handful of file types
+fn content_type(path: &FilePath) -> &'static str {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js") => "text/javascript; charset=utf-8",
+        Some("json") => "application/json",
+        Some("md") => "text/markdown; charset=utf-8",
+        Some("txt") => "text/plain; charset=utf-8",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("ico") => "image/x-icon",
+        Some("woff") => "font/woff",
+        Some("woff2") => "font/woff2",
+        Some("ttf") => "font/ttf",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Replaces the version's docs with a `.tar.gz` of the contents of `target/doc`
+///
+/// Built like `tar czf docs.tar.gz -C target/doc .`, only owners of the crate may upload.
+pub async fn upload_docs_handler(
+    State(state): State<ServerState>,
+    Path(VersionPath {
+        crate_name,
+        version,
+    }): Path<VersionPath>,
+    user: AuthenticatedUser,
+    Extension(request_id): Extension<RequestId>,
+    body: Body,
+) -> Result<Json<UploadDocsResponse>, Response> {
+    let audit = AuditContext {
+        actor: Some(user.login.clone()),
+        request_id,
+    };
+    let event = |outcome| AuditEvent {
+        context: &audit,
+        action: AuditAction::UploadDocs,
+        crate_name: Some(&crate_name),
+        version: Some(&version),
+        outcome,
+    };
+    let result = upload_docs(&state, &crate_name, &version, &user, body).await;
+    match &result {
+        Ok(()) => {
+            if let Err(e) = add_audit_event(
+                event(AuditOutcome::Success),
+                &*state.database_connection_pool,
+            )
+            .await
+            {
+                eprintln!("Failed to record audit event: {e}");
+            }
+        }
+        Err(response) => {
+            record_failure(
+                event(AuditOutcome::from_status(response.status())),
+                &state.database_connection_pool,
+            )
+            .await;
+        }
+    }
+    result.map(|()| Json(UploadDocsResponse { ok: true }))
+}
+
+async fn upload_docs(
+    state: &ServerState,
+    crate_name: &CrateName,
+    version: &Version,
+    user: &AuthenticatedUser,
+    body: Body,
+) -> Result<(), Response> {
+    let docs_storage = &state.registry.docs_storage;
+    let mut connection = state
+        .database_connection_pool
+        .acquire()
+        .await
+        .map_err(|_e| internal_server_error("couldn't connect to database"))?;
+    ensure_crate_owner(crate_name, user, &mut connection).await?;
+    if !version_exists(crate_name, version, &mut *connection)
+        .await
+        .inspect_err(|e| eprintln!("Failed to check if version exists: {e}"))
+        .map_err(|_e| internal_server_error("couldn't check if version exists"))?
+    {
+        return Err((StatusCode::NOT_FOUND, "version doesn't exist").into_response());
+    }
+    // Compressed docs are smaller than unpacked ones
+    let archive = to_bytes(body, docs_storage.max_size() as usize)
+        .await
+        .map_err(|_e| {
+            (
+                StatusCode::PAYLOAD_TOO_LARGE,
+                DocsError::TooLarge {
+                    max_size: docs_storage.max_size(),
+                }
+                .to_string(),
+            )
+                .into_response()
+        })?;
+    docs_storage
+        .replace(crate_name, version, archive)
+        .await
+        .map_err(|e| match e {
+            DocsError::Io(e) => {
+                eprintln!("Failed to store docs: {e}");
+                internal_server_error("couldn't store docs")
+            }
+            DocsError::TooLarge { .. } => {
+                (StatusCode::PAYLOAD_TOO_LARGE, e.to_string()).into_response()
+            }
+            DocsError::InvalidArchive(_) | DocsError::InvalidPath(_) => {
+                (StatusCode::BAD_REQUEST, e.to_string()).into_response()
+            }
+        })?;
+    set_version_has_docs(crate_name, version, true, &mut *connection)
+        .await
+        .inspect_err(|e| eprintln!("Failed to mark version as documented: {e}"))
+        .map_err(|_e| internal_server_error("couldn't mark version as documented"))?;
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+pub struct UploadDocsResponse {
+    ok: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DocsFilePath {
+    crate_name: CrateName,
+    version: Version,
+    path: String,
+}
+
+/// A file of the version's uploaded docs, like `/docs/foo/1.0.0/foo/index.html`
+pub async fn docs_file_handler(
+    State(state): State<ServerState>,
+    Path(DocsFilePath {
+        crate_name,
+        version,
+        path,
+    }): Path<DocsFilePath>,
+) -> Result<Response, Response> {
+    let file = state
+        .registry
+        .docs_storage
+        .read(&crate_name, &version, &path)
+        .await
+        .inspect_err(|e| eprintln!("Failed to read docs: {e}"))
+        .map_err(|_e| internal_server_error("couldn't read docs"))?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "no such docs file").into_response())?;
+    let content_type = docs_file_path(&path).map_or("application/octet-stream", |relative| {
+        content_type(&relative)
+    });
+    Ok((
+        [
+            (CONTENT_TYPE, content_type),
+            (X_CONTENT_TYPE_OPTIONS, "nosniff"),
+        ],
+        file,
+    )
+        .into_response())
+}
+
+#[derive(Debug)]
+pub enum DocsError {
+    Io(std::io::Error),
+    InvalidArchive(std::io::Error),
+    /// Entry that would be unpacked outside of the docs
+    InvalidPath(PathBuf),
+    TooLarge {
+        max_size: u64,
+    },
+}
+impl std::error::Error for DocsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(io) | Self::InvalidArchive(io) => Some(io),
+            Self::InvalidPath(_) | Self::TooLarge { .. } => None,
+        }
+    }
+}
+impl Display for DocsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(io) => write!(f, "failed to store docs: {io}"),
+            Self::InvalidArchive(io) => write!(f, "invalid docs archive: {io}"),
+            Self::InvalidPath(path) => {
+                write!(f, "docs archive contains invalid path {}", path.display())
+            }
+            Self::TooLarge { max_size } => write!(f, "docs are larger than {max_size} bytes"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use flate2::{write::GzEncoder, Compression};
+
+    use crate::docs::{content_type, docs_file_path, unpack, DocsError};
+
+    fn archive(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::fast()));
+        for (path, content) in files {
+            let mut header = tar::Header::new_gnu();
+            // Bypasses the path checks of `append_data`, archives can contain anything
+            header.as_old_mut().name[..path.len()].copy_from_slice(path.as_bytes());
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append(&header, content.as_bytes()).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    fn destination() -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "registry_server_docs_{}",
+            uuid::Uuid::new_v4().simple()
+        ))
+    }
+
+    #[test]
+    fn paths_stay_inside_the_docs() {
+        assert_eq!(
+            docs_file_path("foo/struct.Foo.html"),
+            Some(PathBuf::from("foo/struct.Foo.html"))
+        );
+        assert_eq!(
+            docs_file_path("./foo/"),
+            Some(PathBuf::from("foo/index.html"))
+        );
+        assert_eq!(docs_file_path(""), Some(PathBuf::from("index.html")));
+        for path in ["../foo", "foo/../../bar", "/etc/passwd"] {
+            assert_eq!(docs_file_path(path), None, "{path}");
+        }
+    }
+    #[test]
+    fn rustdoc_files_have_content_types() {
+        assert_eq!(
+            content_type(Path::new("foo/index.html")),
+            "text/html; charset=utf-8"
+        );
+        assert_eq!(
+            content_type(Path::new("static.files/main.js")),
+            "text/javascript; charset=utf-8"
+        );
+        assert_eq!(
+            content_type(Path::new("search-index.bin")),
+            "application/octet-stream"
+        );
+    }
+    #[test]
+    fn archives_are_unpacked() {
+        let destination = destination();
+        unpack(
+            &archive(&[("./foo/index.html", "<html>"), ("./foo/all.html", "")]),
+            &destination,
+            100,
+        )
+        .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(destination.join("foo/index.html")).unwrap(),
+            "<html>"
+        );
+        std::fs::remove_dir_all(destination).unwrap();
+    }
+    #[test]
+    fn escaping_paths_are_rejected() {
+        let destination = destination();
+        let result = unpack(&archive(&[("../escaped.html", "")]), &destination, 100);
+        assert!(matches!(result, Err(DocsError::InvalidPath(_))));
+        assert!(!destination.join("../escaped.html").exists());
+        std::fs::remove_dir_all(destination).unwrap();
+    }
+    #[test]
+    fn oversized_archives_are_rejected() {
+        let destination = destination();
+        let result = unpack(
+            &archive(&[("foo/a.html", "12345"), ("foo/b.html", "67890")]),
+            &destination,
+            8,
+        );
+        assert!(matches!(result, Err(DocsError::TooLarge { max_size: 8 })));
+        std::fs::remove_dir_all(destination).unwrap();
+    }
+}
@@ -3,7 +3,15 @@ use std::{fmt::Display, str::FromStr};
 use serde::{Deserialize, Serialize};
 use unicode_xid::UnicodeXID;
 
+/// Cargo rejects longer feature names
+const MAX_FEATURE_NAME_LENGTH: usize = 64;
+
 #[derive(Clone, Debug, Serialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// Name of a feature as declared in `[features]`, with cargo's character set
+///
+/// The first character is Unicode XID start, an ASCII digit or `_`, the others are
+/// Unicode XID continue, `-`, `+` or `.`. `default` is a name like any other here.
+/// The `dep:` prefix and `/` only appear in what a feature enables, see [`FeatureValue`].
 pub struct FeatureName(String);
 impl AsRef<str> for FeatureName {
     fn as_ref(&self) -> &str {
@@ -28,6 +36,10 @@ impl Display for FeatureName {
 impl FromStr for FeatureName {
     type Err = InvalidFeatureName;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let length = s.chars().count();
+        if length > MAX_FEATURE_NAME_LENGTH {
+            return Err(InvalidFeatureName::TooLong(length));
+        }
         let mut chars = s.chars();
         match chars.next() {
             None => return Err(InvalidFeatureName::Empty),
@@ -46,11 +58,57 @@ impl FromStr for FeatureName {
         Ok(Self(s.to_string()))
     }
 }
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// One entry in the list of what a feature enables
+pub enum FeatureValue<'a> {
+    /// Another feature, or an optional dependency through its implicit feature
+    Feature(&'a str),
+    /// `dep:name` enables the optional dependency without an implicit feature
+    Dependency(&'a str),
+    /// `name/feature` enables the dependency too, the weak `name?/feature` only if it's enabled anyway
+    DependencyFeature {
+        dependency: &'a str,
+        feature: &'a str,
+        weak: bool,
+    },
+}
+impl<'a> FeatureValue<'a> {
+    pub fn parse(value: &'a str) -> Self {
+        if let Some(dependency) = value.strip_prefix("dep:") {
+            return Self::Dependency(dependency);
+        }
+        let Some((dependency, feature)) = value.split_once('/') else {
+            return Self::Feature(value);
+        };
+        match dependency.strip_suffix('?') {
+            Some(dependency) => Self::DependencyFeature {
+                dependency,
+                feature,
+                weak: true,
+            },
+            None => Self::DependencyFeature {
+                dependency,
+                feature,
+                weak: false,
+            },
+        }
+    }
+    /// Syntax older cargo versions can't read, features using it go into `features2` of the index
+    pub fn needs_features2(self) -> bool {
+        matches!(
+            self,
+            Self::Dependency(_) | Self::DependencyFeature { weak: true, .. }
+        )
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
 pub enum InvalidFeatureName {
     Empty,
     InvalidStart,
     InvalidCharacter,
+    /// Length in characters
+    TooLong(usize),
 }
 impl std::error::Error for InvalidFeatureName {}
 impl Display for InvalidFeatureName {
@@ -58,7 +116,128 @@ impl Display for InvalidFeatureName {
         match self {
             Self::Empty => f.write_str("feature name is empty"),
             Self::InvalidStart => f.write_str("invalid first character. Must be Unicode XID start, digit, or an underscore"),
-            Self::InvalidCharacter => f.write_str("invalid non-start character. Must be Unicode XID continue, digit or '+', '-', ':' or '.'"),
+            Self::InvalidCharacter => f.write_str("invalid non-start character. Must be Unicode XID continue, '+', '-' or '.'"),
+            Self::TooLong(length) => write!(f, "feature name is {length} characters long, the maximum is {MAX_FEATURE_NAME_LENGTH}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use proptest::{prelude::*, string::string_regex};
+    use unicode_xid::UnicodeXID;
+
+    use crate::feature_name::{
+        FeatureName, FeatureValue, InvalidFeatureName, MAX_FEATURE_NAME_LENGTH,
+    };
+
+    /// Accepted names satisfy the documented rules and keep their original spelling
+    fn check_accepted_name(s: &str) -> Result<(), TestCaseError> {
+        let Ok(name) = FeatureName::from_str(s) else {
+            return Ok(());
+        };
+        prop_assert!((1..=MAX_FEATURE_NAME_LENGTH).contains(&s.chars().count()));
+        let mut chars = s.chars();
+        let first = chars.next().unwrap();
+        prop_assert!(first == '_' || first.is_ascii_digit() || first.is_xid_start());
+        prop_assert!(chars.all(|ch| matches!(ch, '-' | '+' | '.') || ch.is_xid_continue()));
+        prop_assert_eq!(name.as_ref(), s);
+        prop_assert_eq!(FeatureName::from_str(name.as_ref()), Ok(name.clone()));
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn arbitrary_strings_only_pass_as_valid_names(
+            s in string_regex("(?s).{0,12}").unwrap()
+        ) {
+            check_accepted_name(&s)?;
+        }
+        #[test]
+        fn identifier_like_strings_only_pass_as_valid_names(
+            s in string_regex("[_\\p{XID_Start}0-9:+-][\\p{XID_Continue}+.:/-]{0,70}").unwrap()
+        ) {
+            check_accepted_name(&s)?;
+        }
+        #[test]
+        fn ascii_identifiers_are_accepted(
+            s in string_regex("[a-zA-Z0-9_][a-zA-Z0-9_+.-]{0,63}").unwrap()
+        ) {
+            prop_assert!(FeatureName::from_str(&s).is_ok());
+            check_accepted_name(&s)?;
+        }
+    }
+
+    #[test]
+    fn allow_64_characters() {
+        assert!(FeatureName::from_str(&"a".repeat(64)).is_ok());
+    }
+    #[test]
+    fn disallow_65_characters() {
+        assert_eq!(
+            FeatureName::from_str(&"a".repeat(65)),
+            Err(InvalidFeatureName::TooLong(65))
+        );
+    }
+    #[test]
+    fn accepted_characters() {
+        for name in [
+            "default", "std", "1st", "_private", "a-b", "a+b", "a.b", "a_b", "a1", "ü", "änd",
+        ] {
+            assert!(FeatureName::from_str(name).is_ok(), "{name}");
+        }
+    }
+    #[test]
+    fn rejected_characters() {
+        for (name, error) in [
+            ("", InvalidFeatureName::Empty),
+            ("-a", InvalidFeatureName::InvalidStart),
+            ("+a", InvalidFeatureName::InvalidStart),
+            (".a", InvalidFeatureName::InvalidStart),
+            (":a", InvalidFeatureName::InvalidStart),
+            ("dep:serde", InvalidFeatureName::InvalidCharacter),
+            ("serde/derive", InvalidFeatureName::InvalidCharacter),
+            ("serde?/derive", InvalidFeatureName::InvalidCharacter),
+            ("a b", InvalidFeatureName::InvalidCharacter),
+            ("a,b", InvalidFeatureName::InvalidCharacter),
+            ("a=b", InvalidFeatureName::InvalidCharacter),
+        ] {
+            assert_eq!(FeatureName::from_str(name), Err(error), "{name}");
         }
     }
+    #[test]
+    fn colons_are_rejected_as_the_error_says() {
+        let error = FeatureName::from_str("a:b").unwrap_err();
+        assert_eq!(error, InvalidFeatureName::InvalidCharacter);
+        assert!(!error.to_string().contains("':'"), "{error}");
+    }
+    #[test]
+    fn dependency_syntax_is_parsed_in_values() {
+        assert_eq!(
+            FeatureValue::parse("dep:serde"),
+            FeatureValue::Dependency("serde")
+        );
+        assert_eq!(
+            FeatureValue::parse("default"),
+            FeatureValue::Feature("default")
+        );
+    }
+    #[test]
+    fn weak_dependency_features_need_features2() {
+        let weak = FeatureValue::parse("serde?/derive");
+        assert_eq!(
+            weak,
+            FeatureValue::DependencyFeature {
+                dependency: "serde",
+                feature: "derive",
+                weak: true
+            }
+        );
+        assert!(weak.needs_features2());
+        assert!(FeatureValue::parse("dep:serde").needs_features2());
+        assert!(!FeatureValue::parse("serde/derive").needs_features2());
+        assert!(!FeatureValue::parse("std").needs_features2());
+    }
 }
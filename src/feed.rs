@@ -0,0 +1,177 @@
+use std::fmt::Write;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+
+use crate::summary::RecentVersion;
+
+/// Feed-level fields of an Atom feed, the entries are the published versions
+pub struct FeedInfo<'a> {
+    pub title: &'a str,
+    /// URL the feed is served under, also its id
+    pub self_url: &'a str,
+    /// Root of this server, entries link to its crate endpoints
+    pub base_url: &'a str,
+}
+
+/// Renders an Atom feed, `versions` are expected newest first
+///
+/// The feed is as old as its newest entry, an empty feed is dated to the Unix epoch.
+pub fn atom_feed(info: &FeedInfo<'_>, versions: &[RecentVersion]) -> String {
+    let updated = versions
+        .first()
+        .map_or(DateTime::<Utc>::UNIX_EPOCH, |version| version.created_at);
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    xml.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    push_element(&mut xml, 1, "id", info.self_url);
+    push_element(&mut xml, 1, "title", info.title);
+    push_element(&mut xml, 1, "updated", &atom_date(updated));
+    let _ = writeln!(
+        xml,
+        "  <link rel=\"self\" href=\"{}\"/>",
+        escape(info.self_url)
+    );
+    for version in versions {
+        let crate_url = format!("{}/api/v1/crates/{}", info.base_url, version.krate);
+        xml.push_str("  <entry>\n");
+        push_element(
+            &mut xml,
+            2,
+            "id",
+            &format!("{crate_url}/{}", version.version),
+        );
+        push_element(
+            &mut xml,
+            2,
+            "title",
+            &format!("{} {}", version.krate, version.version),
+        );
+        push_element(&mut xml, 2, "updated", &atom_date(version.created_at));
+        xml.push_str("    <author>\n");
+        push_element(
+            &mut xml,
+            3,
+            "name",
+            version.publisher.as_deref().unwrap_or("unknown"),
+        );
+        xml.push_str("    </author>\n");
+        push_element(&mut xml, 2, "summary", &version.description);
+        let _ = writeln!(xml, "    <link href=\"{}\"/>", escape(&crate_url));
+        xml.push_str("  </entry>\n");
+    }
+    xml.push_str("</feed>\n");
+    xml
+}
+
+fn push_element(xml: &mut String, depth: usize, name: &str, text: &str) {
+    let _ = writeln!(
+        xml,
+        "{:indent$}<{name}>{}</{name}>",
+        "",
+        escape(text),
+        indent = depth * 2
+    );
+}
+
+fn atom_date(date: DateTime<Utc>) -> String {
+    date.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            ch => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use crate::{
+        feed::{atom_feed, FeedInfo},
+        summary::RecentVersion,
+    };
+
+    const INFO: FeedInfo<'static> = FeedInfo {
+        title: "Recent versions",
+        self_url: "http://127.0.0.1:8999/feed.xml",
+        base_url: "http://127.0.0.1:8999",
+    };
+
+    #[test]
+    fn empty_feed_snapshot() {
+        assert_eq!(
+            atom_feed(&INFO, &[]),
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <id>http://127.0.0.1:8999/feed.xml</id>
+  <title>Recent versions</title>
+  <updated>1970-01-01T00:00:00Z</updated>
+  <link rel="self" href="http://127.0.0.1:8999/feed.xml"/>
+</feed>
+"#
+        );
+    }
+    #[test]
+    fn feed_snapshot() {
+        let versions = [
+            RecentVersion {
+                krate: String::from("foo"),
+                version: "1.1.0-rc.1".parse().unwrap(),
+                description: String::from("Parses <foo> & \"bar\""),
+                created_at: Utc.with_ymd_and_hms(2024, 5, 2, 12, 30, 0).unwrap(),
+                publisher: None,
+                new_crate: false,
+                yanked: false,
+            },
+            RecentVersion {
+                krate: String::from("foo"),
+                version: "1.0.0".parse().unwrap(),
+                description: String::from("Parses foo"),
+                created_at: Utc.with_ymd_and_hms(2024, 5, 1, 8, 0, 0).unwrap(),
+                publisher: Some(String::from("alice")),
+                new_crate: true,
+                yanked: false,
+            },
+        ];
+        assert_eq!(
+            atom_feed(&INFO, &versions),
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <id>http://127.0.0.1:8999/feed.xml</id>
+  <title>Recent versions</title>
+  <updated>2024-05-02T12:30:00Z</updated>
+  <link rel="self" href="http://127.0.0.1:8999/feed.xml"/>
+  <entry>
+    <id>http://127.0.0.1:8999/api/v1/crates/foo/1.1.0-rc.1</id>
+    <title>foo 1.1.0-rc.1</title>
+    <updated>2024-05-02T12:30:00Z</updated>
+    <author>
+      <name>unknown</name>
+    </author>
+    <summary>Parses &lt;foo&gt; &amp; &quot;bar&quot;</summary>
+    <link href="http://127.0.0.1:8999/api/v1/crates/foo"/>
+  </entry>
+  <entry>
+    <id>http://127.0.0.1:8999/api/v1/crates/foo/1.0.0</id>
+    <title>foo 1.0.0</title>
+    <updated>2024-05-01T08:00:00Z</updated>
+    <author>
+      <name>alice</name>
+    </author>
+    <summary>Parses foo</summary>
+    <link href="http://127.0.0.1:8999/api/v1/crates/foo"/>
+  </entry>
+</feed>
+"#
+        );
+    }
+}
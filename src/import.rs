@@ -0,0 +1,245 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use tokio::fs::{read, read_dir};
+
+use crate::{
+    audit::AuditContext,
+    crate_name::NormalizedCrateName,
+    publish::{import_version, DependencyKind, ImportOutcome, Metadata},
+    request_id::RequestId,
+    ServerState,
+};
+
+/// Recorded as the actor of imported publishes
+const AUDIT_ACTOR: &str = "(import)";
+
+#[derive(Debug)]
+/// A `.crate` file with the publish metadata from the `.json` file next to it
+struct ImportedVersion {
+    source: PathBuf,
+    metadata: Metadata,
+    file_content: Vec<u8>,
+}
+
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub imported: usize,
+    /// Versions the registry already had
+    pub skipped: usize,
+    /// File or crate version with the reason it wasn't imported
+    pub failed: Vec<(String, String)>,
+}
+impl Display for ImportReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (what, reason) in &self.failed {
+            writeln!(f, "failed: {what}: {reason}")?;
+        }
+        write!(
+            f,
+            "{} imported, {} already present, {} failed",
+            self.imported,
+            self.skipped,
+            self.failed.len()
+        )
+    }
+}
+
+/// Publishes every `<file>.crate` in `directory` that has a `<file>.json` with the metadata cargo
+/// would have sent, crates before the crates depending on them
+///
+/// Versions already in the registry are skipped, so an interrupted import can be rerun.
+/// Imported crates have no owners.
+pub async fn import_directory(
+    state: &ServerState,
+    directory: &Path,
+) -> Result<ImportReport, std::io::Error> {
+    let mut report = ImportReport::default();
+    let mut versions = Vec::new();
+    let mut entries = read_dir(directory).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if path
+            .extension()
+            .is_none_or(|extension| extension != "crate")
+        {
+            continue;
+        }
+        match read_version(&path).await {
+            Ok(version) => versions.push(version),
+            Err(reason) => report.failed.push((path.display().to_string(), reason)),
+        }
+    }
+    let audit = AuditContext {
+        actor: Some(String::from(AUDIT_ACTOR)),
+        request_id: RequestId(uuid::Uuid::new_v4().to_string()),
+    };
+    for version in import_order(versions) {
+        let metadata = &version.metadata;
+        match import_version(state, &audit, metadata, &version.file_content).await {
+            Ok(ImportOutcome::Imported) => report.imported += 1,
+            Ok(ImportOutcome::AlreadyPresent) => report.skipped += 1,
+            Err(e) => report.failed.push((
+                format!(
+                    "{} {} ({})",
+                    metadata.name,
+                    metadata.vers,
+                    version.source.display()
+                ),
+                e.to_string(),
+            )),
+        }
+    }
+    Ok(report)
+}
+
+async fn read_version(path: &Path) -> Result<ImportedVersion, String> {
+    let file_content = read(path)
+        .await
+        .map_err(|e| format!("can't read crate file: {e}"))?;
+    let metadata_path = path.with_extension("json");
+    let metadata = read(&metadata_path)
+        .await
+        .map_err(|e| format!("can't read {}: {e}", metadata_path.display()))?;
+    let metadata = serde_json::from_slice(&metadata)
+        .map_err(|e| format!("invalid metadata in {}: {e}", metadata_path.display()))?;
+    Ok(ImportedVersion {
+        source: path.to_path_buf(),
+        metadata,
+        file_content,
+    })
+}
+
+/// Dependencies come before the crates depending on them, versions of a crate oldest first
+///
+/// Dev-dependencies are ignored, crates in a dependency cycle are imported by name.
+fn import_order(versions: Vec<ImportedVersion>) -> Vec<ImportedVersion> {
+    let mut by_crate = BTreeMap::<NormalizedCrateName, Vec<ImportedVersion>>::new();
+    for version in versions {
+        by_crate
+            .entry(version.metadata.name.normalized())
+            .or_default()
+            .push(version);
+    }
+    let mut remaining: BTreeMap<_, BTreeSet<_>> = by_crate
+        .iter()
+        .map(|(name, versions)| {
+            let dependencies = versions
+                .iter()
+                .flat_map(|version| &version.metadata.deps)
+                .filter(|dependency| !matches!(dependency.kind, DependencyKind::Dev))
+                .map(|dependency| dependency.name.normalized())
+                .filter(|dependency| dependency != name && by_crate.contains_key(dependency))
+                .collect();
+            (name.clone(), dependencies)
+        })
+        .collect();
+    let mut ordered = Vec::with_capacity(by_crate.len());
+    while !remaining.is_empty() {
+        let ready = remaining
+            .iter()
+            .find(|(_, dependencies)| dependencies.is_empty())
+            .or_else(|| remaining.first_key_value())
+            .map(|(name, _)| name.clone())
+            .expect("remaining isn't empty");
+        remaining.remove(&ready);
+        for dependencies in remaining.values_mut() {
+            dependencies.remove(&ready);
+        }
+        ordered.push(ready);
+    }
+    ordered
+        .into_iter()
+        .flat_map(|name| {
+            let mut versions = by_crate.remove(&name).unwrap_or_default();
+            versions.sort_by(|a, b| a.metadata.vers.cmp_precedence(&b.metadata.vers));
+            versions
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use serde_json::json;
+
+    use crate::import::{import_order, ImportedVersion};
+
+    fn version(name: &str, vers: &str, deps: &[(&str, &str)]) -> ImportedVersion {
+        let deps: Vec<_> = deps
+            .iter()
+            .map(|(dependency, kind)| {
+                json!({
+                    "name": dependency,
+                    "version_req": "^1",
+                    "features": [],
+                    "optional": false,
+                    "default_features": true,
+                    "target": null,
+                    "kind": kind,
+                    "registry": null,
+                    "explicit_name_in_toml": null,
+                })
+            })
+            .collect();
+        let metadata = json!({
+            "name": name,
+            "vers": vers,
+            "deps": deps,
+            "features": {},
+            "authors": [],
+            "description": "imported",
+            "documentation": null,
+            "homepage": null,
+            "readme": null,
+            "readme_file": null,
+            "keywords": [],
+            "categories": [],
+            "license": "MIT",
+            "license_file": null,
+            "repository": null,
+            "badges": {},
+            "links": null,
+            "rust_version": null,
+        });
+        ImportedVersion {
+            source: PathBuf::from(format!("{name}-{vers}.crate")),
+            metadata: serde_json::from_value(metadata).unwrap(),
+            file_content: Vec::new(),
+        }
+    }
+
+    fn names(versions: &[ImportedVersion]) -> Vec<String> {
+        versions
+            .iter()
+            .map(|version| format!("{} {}", version.metadata.name, version.metadata.vers))
+            .collect()
+    }
+
+    #[test]
+    fn dependencies_are_imported_first() {
+        let ordered = import_order(vec![
+            version("app", "1.0.0", &[("lib_b", "normal")]),
+            version("lib_b", "1.1.0", &[("Lib-A", "build")]),
+            version("lib_b", "1.0.0", &[]),
+            version("lib-a", "1.0.0", &[("app", "dev"), ("serde", "normal")]),
+        ]);
+        assert_eq!(
+            names(&ordered),
+            ["lib-a 1.0.0", "lib_b 1.0.0", "lib_b 1.1.0", "app 1.0.0"]
+        );
+    }
+    #[test]
+    fn cycles_are_broken_by_name() {
+        let ordered = import_order(vec![
+            version("b", "1.0.0", &[("a", "normal")]),
+            version("a", "1.0.0", &[("b", "normal")]),
+            version("c", "1.0.0", &[("a", "normal")]),
+        ]);
+        assert_eq!(names(&ordered), ["a 1.0.0", "b 1.0.0", "c 1.0.0"]);
+    }
+}
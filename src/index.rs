@@ -1,164 +1,725 @@
 use std::{
-    fmt::Display,
+    fmt::{Debug, Display},
+    io::ErrorKind,
     path::{Path, PathBuf},
+    sync::Arc,
 };
 
+use axum::async_trait;
+
+use semver::Version;
 use tokio::{
-    fs::{create_dir_all, OpenOptions},
+    fs::{create_dir_all, read_to_string, remove_file, write, OpenOptions},
     io::AsyncWriteExt,
     process::Command,
 };
 
-use crate::{publish::Metadata, read_only_mutex::ReadOnlyMutex};
-use json::{build_version_metadata, VersionMetadata};
+use crate::{crate_name::CrateName, publish::Metadata, version::without_build_metadata};
+use json::build_version_metadata;
+mod config;
 mod json;
 
-pub async fn add_file_to_index(
-    crate_metadata: &Metadata,
-    file_content: &[u8],
-    repository: &ReadOnlyMutex<PathBuf>,
-) -> Result<(), AddToIndexError> {
-    let version_metadata = build_version_metadata(crate_metadata, file_content);
-    let repository = repository.lock().await;
-    add_version_to_index_file(&version_metadata, &repository).await?;
+#[derive(Clone, Debug)]
+/// Serialized index line for one version of a crate
+pub struct IndexEntry {
+    pub(crate) name: CrateName,
+    pub(crate) vers: Version,
+    line: String,
+}
+impl IndexEntry {
+    pub fn new(crate_metadata: &Metadata, file_content: &[u8]) -> Result<Self, IndexError> {
+        let version_metadata = build_version_metadata(crate_metadata, file_content);
+        Ok(Self {
+            line: serde_json::to_string(&version_metadata).map_err(IndexError::SerializeJson)?,
+            name: version_metadata.name,
+            vers: version_metadata.vers,
+        })
+    }
+    /// Restores an entry from a line serialized earlier
+    pub fn from_line(name: CrateName, vers: Version, line: String) -> Self {
+        Self { name, vers, line }
+    }
+    pub fn line(&self) -> &str {
+        &self.line
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub enum CommitSigning {
+    #[default]
+    Unsigned,
+    /// Signs with the given key, or git's `user.signingkey` if `None`
+    Signed(Option<String>),
+}
+impl CommitSigning {
+    fn arg(&self) -> String {
+        match self {
+            Self::Unsigned => "--no-gpg-sign".to_string(),
+            Self::Signed(None) => "--gpg-sign".to_string(),
+            Self::Signed(Some(key)) => format!("--gpg-sign={key}"),
+        }
+    }
+}
+
+#[async_trait]
+/// Where the index files are kept and how changes to them are persisted
+///
+/// Files are named by their path relative to the index root, like `3/f/foo` or `config.json`.
+pub trait IndexBackend: Debug + Send + Sync {
+    /// Content of the file, `None` if there is none
+    async fn read(&self, file_path: &Path) -> Result<Option<String>, IndexError>;
+    /// Adds `line` and a line break to the end of the file, creating it if needed
+    async fn append(&self, file_path: &Path, line: &str) -> Result<(), IndexError>;
+    /// Replaces the content of the file, creating it if needed
+    async fn write(&self, file_path: &Path, content: &str) -> Result<(), IndexError>;
+    async fn remove(&self, file_path: &Path) -> Result<(), IndexError>;
+    /// Persists the change to `file_path`, which is already written or removed
+    async fn commit(&self, file_path: &Path, commit_message: &str) -> Result<(), IndexError>;
+    /// Finds out at startup whether commits will work
+    async fn check(&self) -> Result<(), IndexError> {
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+/// Index files below a directory, shared by the backends keeping the index on disk
+struct IndexDirectory {
+    root: PathBuf,
+}
+impl IndexDirectory {
+    async fn read(&self, file_path: &Path) -> Result<Option<String>, IndexError> {
+        match read_to_string(self.root.join(file_path)).await {
+            Ok(content) => Ok(Some(content)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(IndexError::ReadIndexFile(e)),
+        }
+    }
+    async fn append(&self, file_path: &Path, line: &str) -> Result<(), IndexError> {
+        let path = self.create_parent(file_path).await?;
+        let mut file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(path)
+            .await
+            .map_err(IndexError::OpenIndexFile)?;
+        file.write_all(line.as_bytes())
+            .await
+            .map_err(IndexError::WriteIndexFile)?;
+        file.write_all(b"\n")
+            .await
+            .map_err(IndexError::WriteIndexFile)
+    }
+    async fn write(&self, file_path: &Path, content: &str) -> Result<(), IndexError> {
+        let path = self.create_parent(file_path).await?;
+        write(path, content)
+            .await
+            .map_err(IndexError::WriteIndexFile)
+    }
+    async fn remove(&self, file_path: &Path) -> Result<(), IndexError> {
+        remove_file(self.root.join(file_path))
+            .await
+            .map_err(IndexError::WriteIndexFile)
+    }
+    /// Absolute path of the file, after creating the directories it's in
+    async fn create_parent(&self, file_path: &Path) -> Result<PathBuf, IndexError> {
+        let path = self.root.join(file_path);
+        create_dir_all(
+            path.parent()
+                .expect("an index file path shouldn't be parentless"),
+        )
+        .await
+        .map_err(IndexError::CreateDirectoryInIndex)?;
+        Ok(path)
+    }
+}
+
+#[derive(Clone, Debug)]
+/// Keeps the index in a git repository and commits every change
+pub struct GitIndexBackend {
+    directory: IndexDirectory,
+    signing: CommitSigning,
+}
+impl GitIndexBackend {
+    pub fn new(repository_path: PathBuf, signing: CommitSigning) -> Self {
+        Self {
+            directory: IndexDirectory {
+                root: repository_path,
+            },
+            signing,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+/// Only writes the files to a directory, for registries served through the sparse protocol alone
+pub struct SparseOnlyIndexBackend {
+    directory: IndexDirectory,
+}
+impl SparseOnlyIndexBackend {
+    pub fn new(index_path: PathBuf) -> Self {
+        Self {
+            directory: IndexDirectory { root: index_path },
+        }
+    }
+}
+
+#[async_trait]
+impl IndexBackend for SparseOnlyIndexBackend {
+    async fn read(&self, file_path: &Path) -> Result<Option<String>, IndexError> {
+        self.directory.read(file_path).await
+    }
+    async fn append(&self, file_path: &Path, line: &str) -> Result<(), IndexError> {
+        self.directory.append(file_path, line).await
+    }
+    async fn write(&self, file_path: &Path, content: &str) -> Result<(), IndexError> {
+        self.directory.write(file_path, content).await
+    }
+    async fn remove(&self, file_path: &Path) -> Result<(), IndexError> {
+        self.directory.remove(file_path).await
+    }
+    async fn commit(&self, _: &Path, _: &str) -> Result<(), IndexError> {
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+/// The index, stored and persisted by its backend
+pub struct IndexRepository {
+    backend: Arc<dyn IndexBackend>,
+}
+impl IndexRepository {
+    pub fn new(backend: Arc<dyn IndexBackend>) -> Self {
+        Self { backend }
+    }
+    pub async fn check_backend(&self) -> Result<(), IndexError> {
+        self.backend.check().await
+    }
+    /// Content of a file the caller knows to exist
+    async fn read_existing(&self, file_path: &Path) -> Result<String, IndexError> {
+        self.backend
+            .read(file_path)
+            .await?
+            .ok_or_else(|| IndexError::ReadIndexFile(ErrorKind::NotFound.into()))
+    }
+}
+
+/// Hash of the empty tree, which git knows without it being stored
+const EMPTY_TREE: &str = "4b825dc642cb6eb9a060e54bf8d69288fbee4904";
+
+/// Appends and commits the entry. The caller has to hold the repository lock.
+pub async fn append_to_index(
+    entry: &IndexEntry,
+    repository: &IndexRepository,
+) -> Result<(), IndexError> {
+    add_version_to_index_file(entry, repository).await?;
     let commit_message = format!(
         "ADD CRATE: [{}] version: {}",
-        version_metadata.name.original_str(),
-        version_metadata.vers
+        entry.name.original_str(),
+        entry.vers
+    );
+    commit_to_index(repository, &index_file_path(&entry.name), &commit_message).await
+}
+
+/// Removes the line of `vers` and commits with `commit_message`, deleting the file if it ends up empty.
+/// The caller has to hold the repository lock.
+pub async fn remove_from_index(
+    crate_name: &CrateName,
+    vers: &Version,
+    repository: &IndexRepository,
+    commit_message: &str,
+) -> Result<(), IndexError> {
+    let file_path = index_file_path(crate_name);
+    let content = repository.read_existing(&file_path).await?;
+    let mut remaining = String::new();
+    for line in content.lines() {
+        if line_version(line)? != *vers {
+            remaining.push_str(line);
+            remaining.push('\n');
+        }
+    }
+    if remaining.is_empty() {
+        repository.backend.remove(&file_path).await?;
+    } else {
+        repository.backend.write(&file_path, &remaining).await?;
+    }
+    commit_to_index(repository, &file_path, commit_message).await
+}
+
+/// Deletes the index file of the crate and commits with `commit_message`.
+/// The caller has to hold the repository lock.
+pub async fn remove_crate_from_index(
+    crate_name: &CrateName,
+    repository: &IndexRepository,
+    commit_message: &str,
+) -> Result<(), IndexError> {
+    let file_path = index_file_path(crate_name);
+    repository.backend.remove(&file_path).await?;
+    commit_to_index(repository, &file_path, commit_message).await
+}
+
+/// Whether the index file of the crate has a line for `vers`
+pub async fn index_contains_version(
+    crate_name: &CrateName,
+    vers: &Version,
+    repository: &IndexRepository,
+) -> Result<bool, IndexError> {
+    Ok(find_index_line(crate_name, vers, repository)
+        .await?
+        .is_some())
+}
+
+/// The current index line of `vers`, if there is one
+pub async fn read_index_entry(
+    crate_name: &CrateName,
+    vers: &Version,
+    repository: &IndexRepository,
+) -> Result<Option<IndexEntry>, IndexError> {
+    Ok(find_index_line(crate_name, vers, repository)
+        .await?
+        .map(|line| IndexEntry::from_line(crate_name.clone(), vers.clone(), line)))
+}
+
+/// Content of the crate's index file, `None` if it has none
+pub async fn read_index_file(
+    crate_name: &CrateName,
+    repository: &IndexRepository,
+) -> Result<Option<String>, IndexError> {
+    repository.backend.read(&index_file_path(crate_name)).await
+}
+
+/// All index lines of the crate, none if it has no index file
+pub async fn read_index_entries(
+    crate_name: &CrateName,
+    repository: &IndexRepository,
+) -> Result<Vec<IndexEntry>, IndexError> {
+    let Some(content) = read_index_file(crate_name, repository).await? else {
+        return Ok(Vec::new());
+    };
+    content
+        .lines()
+        .map(|line| {
+            Ok(IndexEntry::from_line(
+                crate_name.clone(),
+                line_version(line)?,
+                line.to_string(),
+            ))
+        })
+        .collect()
+}
+
+async fn find_index_line(
+    crate_name: &CrateName,
+    vers: &Version,
+    repository: &IndexRepository,
+) -> Result<Option<String>, IndexError> {
+    let Some(content) = read_index_file(crate_name, repository).await? else {
+        return Ok(None);
+    };
+    for line in content.lines() {
+        if line_version(line)? == *vers {
+            return Ok(Some(line.to_string()));
+        }
+    }
+    Ok(None)
+}
+
+/// Sets the `yanked` field in the line of `vers` and commits. The caller has to hold the repository lock.
+pub async fn set_yanked_in_index(
+    crate_name: &CrateName,
+    vers: &Version,
+    yanked: bool,
+    repository: &IndexRepository,
+) -> Result<(), IndexError> {
+    let file_path = index_file_path(crate_name);
+    let content = repository.read_existing(&file_path).await?;
+    let mut rewritten = String::with_capacity(content.len());
+    for line in content.lines() {
+        if line_version(line)? == *vers {
+            let mut version_metadata: serde_json::Value =
+                serde_json::from_str(line).map_err(IndexError::ParseIndexFile)?;
+            version_metadata["yanked"] = serde_json::Value::Bool(yanked);
+            rewritten.push_str(
+                &serde_json::to_string(&version_metadata).map_err(IndexError::SerializeJson)?,
+            );
+        } else {
+            rewritten.push_str(line);
+        }
+        rewritten.push('\n');
+    }
+    repository.backend.write(&file_path, &rewritten).await?;
+    let commit_message = format!(
+        "{} CRATE: [{}] version: {vers}",
+        if yanked { "YANK" } else { "UNYANK" },
+        crate_name.original_str()
     );
-    commit_to_index(
-        &repository,
-        &index_file_path(&version_metadata, &repository),
-        &commit_message,
-    )
-    .await
-    .unwrap();
-    Ok(())
+    commit_to_index(repository, &file_path, &commit_message).await
+}
+
+fn line_version(line: &str) -> Result<Version, IndexError> {
+    #[derive(serde::Deserialize)]
+    struct VersionOnly {
+        vers: Version,
+    }
+    serde_json::from_str::<VersionOnly>(line)
+        .map(|v| v.vers)
+        .map_err(IndexError::ParseIndexFile)
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+/// Versions of a crate that only one of the database and the index file knows
+///
+/// Left behind by a publish or deletion that failed between writing the two.
+pub struct IndexDrift {
+    pub missing_from_index: Vec<Version>,
+    pub missing_from_database: Vec<Version>,
 }
+impl IndexDrift {
+    /// Build metadata is ignored, the database doesn't store it
+    pub fn between(database: &[Version], index: &[IndexEntry]) -> Self {
+        let database = database
+            .iter()
+            .map(without_build_metadata)
+            .collect::<Vec<_>>();
+        let index = index
+            .iter()
+            .map(|entry| without_build_metadata(&entry.vers))
+            .collect::<Vec<_>>();
+        Self {
+            missing_from_index: database
+                .iter()
+                .filter(|vers| !index.contains(vers))
+                .cloned()
+                .collect(),
+            missing_from_database: index
+                .iter()
+                .filter(|vers| !database.contains(vers))
+                .cloned()
+                .collect(),
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.missing_from_index.is_empty() && self.missing_from_database.is_empty()
+    }
+}
+impl Display for IndexDrift {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let list = |versions: &[Version]| {
+            versions
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        match (
+            self.missing_from_index.is_empty(),
+            self.missing_from_database.is_empty(),
+        ) {
+            (true, true) => f.write_str("no drift"),
+            (false, true) => write!(f, "index is missing {}", list(&self.missing_from_index)),
+            (true, false) => write!(
+                f,
+                "database is missing {}",
+                list(&self.missing_from_database)
+            ),
+            (false, false) => write!(
+                f,
+                "index is missing {}, database is missing {}",
+                list(&self.missing_from_index),
+                list(&self.missing_from_database)
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+/// What a publish does if the crate's index file and database rows disagree
+pub enum IndexDriftPolicy {
+    /// Logs the difference and publishes anyway
+    #[default]
+    Warn,
+    /// Fails the publish until an operator repaired the crate
+    Reject,
+}
+
 #[derive(Debug)]
-pub enum AddToIndexError {
+pub enum IndexError {
     CreateDirectoryInIndex(std::io::Error),
     OpenIndexFile(std::io::Error),
+    ReadIndexFile(std::io::Error),
+    ParseIndexFile(serde_json::Error),
     SerializeJson(serde_json::Error),
     WriteIndexFile(std::io::Error),
     GitReset(std::io::Error),
     CanonicalizeFilePath(std::io::Error),
     GitAdd(std::io::Error),
     GitCommit(std::io::Error),
+    /// Stderr of a failed `git commit`, e.g. because signing isn't available
+    GitCommitFailed(String),
+    /// The index file already has a line for this version, e.g. from a retried publish
+    VersionAlreadyInIndex(Version),
+    /// `config.json` isn't valid JSON or misses the `dl` field
+    InvalidConfig(serde_json::Error),
 }
-impl std::error::Error for AddToIndexError {
+impl std::error::Error for IndexError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
             Self::OpenIndexFile(io)
+            | Self::ReadIndexFile(io)
             | Self::WriteIndexFile(io)
             | Self::GitReset(io)
             | Self::CanonicalizeFilePath(io)
             | Self::GitAdd(io)
             | Self::GitCommit(io)
             | Self::CreateDirectoryInIndex(io) => Some(io),
-            Self::SerializeJson(json) => Some(json),
+            Self::SerializeJson(json) | Self::ParseIndexFile(json) | Self::InvalidConfig(json) => {
+                Some(json)
+            }
+            Self::GitCommitFailed(_) | Self::VersionAlreadyInIndex(_) => None,
         }
     }
 }
-impl Display for AddToIndexError {
+impl Display for IndexError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::CreateDirectoryInIndex(io) => {
                 write!(f, "failed to create directory in index: {io}")
             }
             Self::OpenIndexFile(io) => write!(f, "failed to open index file: {io}"),
+            Self::ReadIndexFile(io) => write!(f, "failed to read index file: {io}"),
+            Self::ParseIndexFile(json) => write!(f, "invalid line in index file: {json}"),
             Self::SerializeJson(json) => write!(f, "failed to serialize json: {json}"),
             Self::WriteIndexFile(io) => write!(f, "failed to write to index file: {io}"),
             Self::GitReset(io) => write!(f, "failed to run \"git reset\": {io}"),
             Self::CanonicalizeFilePath(io) => write!(f, "failed to canonicalize file path: {io}"),
             Self::GitAdd(ga) => write!(f, "failed to run \"git add\": {ga}"),
             Self::GitCommit(commit) => write!(f, "failed to commit to index: {commit}"),
+            Self::GitCommitFailed(stderr) => write!(f, "git refused to commit to index: {stderr}"),
+            Self::VersionAlreadyInIndex(vers) => {
+                write!(f, "version {vers} is already in the index")
+            }
+            Self::InvalidConfig(json) => write!(f, "invalid config.json in index: {json}"),
         }
     }
 }
 
-fn index_file_path(index: &VersionMetadata, repository_path: &Path) -> PathBuf {
-    let name = index.name.original_str();
+/// Relative to the index root
+fn index_file_path(crate_name: &CrateName) -> PathBuf {
+    let name = crate_name.original_str();
+    let mut path = PathBuf::from_iter(index_directories(name));
+    path.push(name);
+    path
+}
+
+/// Directories below the repository root the index file of the crate named `name` is in
+pub fn index_directories(name: &str) -> Vec<String> {
     let mut chars = name.chars();
     let first_letter = chars.next().unwrap();
     let Some(second_letter) = chars.next() else {
-        return repository_path.join("1").join(name);
+        return vec![String::from("1")];
     };
     let Some(third_letter) = chars.next() else {
-        return repository_path.join("2").join(name);
+        return vec![String::from("2")];
     };
     let Some(fourth_letter) = chars.next() else {
-        return repository_path
-            .join("3")
-            .join(first_letter.to_string())
-            .join(name);
+        return vec![String::from("3"), first_letter.to_string()];
     };
-    repository_path
-        .join(format! {"{first_letter}{second_letter}"})
-        .join(format!("{third_letter}{fourth_letter}"))
-        .join(name)
+    vec![
+        format!("{first_letter}{second_letter}"),
+        format!("{third_letter}{fourth_letter}"),
+    ]
 }
 
+/// Refuses to add a second line for the same version, cargo can't read such an index file
 async fn add_version_to_index_file(
-    index: &VersionMetadata,
-    repository_path: &Path,
-) -> Result<(), AddToIndexError> {
-    let index_file_path = index_file_path(index, repository_path);
-    create_dir_all(
-        index_file_path
-            .parent()
-            .expect("an index file path shouldn't be parentless"),
-    )
-    .await
-    .map_err(AddToIndexError::CreateDirectoryInIndex)?;
-    let mut file = OpenOptions::new()
-        .append(true)
-        .create(true)
-        .open(index_file_path)
-        .await
-        .map_err(AddToIndexError::OpenIndexFile)?;
-    let json = serde_json::to_string(&index).map_err(AddToIndexError::SerializeJson)?;
-    file.write_all(json.as_bytes())
-        .await
-        .map_err(AddToIndexError::WriteIndexFile)?;
-    file.write_all(b"\n")
+    entry: &IndexEntry,
+    repository: &IndexRepository,
+) -> Result<(), IndexError> {
+    let index_file_path = index_file_path(&entry.name);
+    if let Some(content) = repository.backend.read(&index_file_path).await? {
+        for line in content.lines() {
+            // Cargo ignores build metadata when comparing versions
+            if line_version(line)?.cmp_precedence(&entry.vers).is_eq() {
+                return Err(IndexError::VersionAlreadyInIndex(entry.vers.clone()));
+            }
+        }
+    }
+    repository
+        .backend
+        .append(&index_file_path, &entry.line)
         .await
-        .map_err(AddToIndexError::WriteIndexFile)?;
-    Ok(())
 }
 
 async fn commit_to_index(
-    repository_path: &Path,
+    repository: &IndexRepository,
     file_path: &Path,
     commit_message: &str,
-) -> Result<(), AddToIndexError> {
-    Command::new("git")
-        .arg("reset")
-        .arg("-q")
-        .arg("HEAD")
-        .current_dir(repository_path)
-        .status()
-        .await
-        .map_err(AddToIndexError::GitReset)?;
-    Command::new("git")
-        .arg("add")
-        .arg(
+) -> Result<(), IndexError> {
+    repository.backend.commit(file_path, commit_message).await
+}
+
+#[async_trait]
+impl IndexBackend for GitIndexBackend {
+    async fn read(&self, file_path: &Path) -> Result<Option<String>, IndexError> {
+        self.directory.read(file_path).await
+    }
+    async fn append(&self, file_path: &Path, line: &str) -> Result<(), IndexError> {
+        self.directory.append(file_path, line).await
+    }
+    async fn write(&self, file_path: &Path, content: &str) -> Result<(), IndexError> {
+        self.directory.write(file_path, content).await
+    }
+    async fn remove(&self, file_path: &Path) -> Result<(), IndexError> {
+        self.directory.remove(file_path).await
+    }
+    async fn commit(&self, file_path: &Path, commit_message: &str) -> Result<(), IndexError> {
+        let repository_path = &self.directory.root;
+        let file_path = repository_path.join(file_path);
+        Command::new("git")
+            .arg("reset")
+            .arg("-q")
+            .arg("HEAD")
+            .current_dir(repository_path)
+            .status()
+            .await
+            .map_err(IndexError::GitReset)?;
+        // A removed file can't be canonicalized, its path is absolute already
+        let file_path = if file_path.exists() {
             file_path
                 .canonicalize()
-                .map_err(AddToIndexError::CanonicalizeFilePath)?,
-        )
-        .current_dir(repository_path)
-        .status()
-        .await
-        .map_err(AddToIndexError::GitAdd)?;
-    Command::new("git")
-        .arg("commit")
-        .arg("--no-gpg-sign")
-        .arg("-m")
-        .arg(commit_message)
-        .current_dir(repository_path)
-        .status()
-        .await
-        .map_err(AddToIndexError::GitCommit)?;
-    Ok(())
+                .map_err(IndexError::CanonicalizeFilePath)?
+        } else {
+            file_path
+        };
+        Command::new("git")
+            .arg("add")
+            .arg("--all")
+            .arg("--")
+            .arg(file_path)
+            .current_dir(repository_path)
+            .status()
+            .await
+            .map_err(IndexError::GitAdd)?;
+        let output = Command::new("git")
+            .arg("commit")
+            .arg(self.signing.arg())
+            .arg("-m")
+            .arg(commit_message)
+            .current_dir(repository_path)
+            .output()
+            .await
+            .map_err(IndexError::GitCommit)?;
+        if !output.status.success() {
+            return Err(IndexError::GitCommitFailed(
+                String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            ));
+        }
+        Ok(())
+    }
+    /// Signs a throwaway commit object to find out early if signing works, doesn't touch any ref
+    async fn check(&self) -> Result<(), IndexError> {
+        if let CommitSigning::Unsigned = self.signing {
+            return Ok(());
+        }
+        let output = Command::new("git")
+            .arg("commit-tree")
+            .arg(self.signing.arg())
+            .arg("-m")
+            .arg("signing check")
+            .arg(EMPTY_TREE)
+            .current_dir(&self.directory.root)
+            .output()
+            .await
+            .map_err(IndexError::GitCommit)?;
+        if !output.status.success() {
+            return Err(IndexError::GitCommitFailed(
+                String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        path::{Path, PathBuf},
+        sync::Arc,
+    };
+
+    use crate::{
+        index::{
+            add_version_to_index_file, index_file_path, IndexDrift, IndexEntry, IndexError,
+            IndexRepository,
+        },
+        test_util::InMemoryIndex,
+    };
+
+    fn path_for(name: &str) -> PathBuf {
+        index_file_path(&name.parse().unwrap())
+    }
+
+    #[test]
+    fn one_letter_name() {
+        assert_eq!(path_for("a"), Path::new("1/a"));
+    }
+    #[test]
+    fn two_letter_name() {
+        assert_eq!(path_for("ab"), Path::new("2/ab"));
+    }
+    #[test]
+    fn three_letter_name() {
+        assert_eq!(path_for("abc"), Path::new("3/a/abc"));
+    }
+    #[test]
+    fn four_letter_name() {
+        assert_eq!(path_for("abcd"), Path::new("ab/cd/abcd"));
+    }
+    #[test]
+    fn five_letter_name() {
+        assert_eq!(path_for("abcde"), Path::new("ab/cd/abcde"));
+    }
+    #[test]
+    fn drift_ignores_build_metadata() {
+        let entry = |vers: &str| {
+            IndexEntry::from_line(
+                "foo".parse().unwrap(),
+                vers.parse().unwrap(),
+                format!(r#"{{"name":"foo","vers":"{vers}"}}"#),
+            )
+        };
+        let database = ["1.0.0", "1.1.0"].map(|vers| vers.parse().unwrap());
+        let consistent = IndexDrift::between(&database, &[entry("1.0.0+build"), entry("1.1.0")]);
+        assert!(consistent.is_empty());
+        let drift = IndexDrift::between(&database, &[entry("1.0.0"), entry("2.0.0")]);
+        assert_eq!(
+            drift.to_string(),
+            "index is missing 1.1.0, database is missing 2.0.0"
+        );
+    }
+    #[tokio::test]
+    async fn appending_a_version_twice_is_rejected() {
+        let index = Arc::new(InMemoryIndex::default());
+        let repository = IndexRepository::new(index.clone());
+        let entry = |vers: &str| {
+            IndexEntry::from_line(
+                "foo".parse().unwrap(),
+                vers.parse().unwrap(),
+                format!(r#"{{"name":"foo","vers":"{vers}"}}"#),
+            )
+        };
+        add_version_to_index_file(&entry("1.0.0"), &repository)
+            .await
+            .unwrap();
+        let duplicate = add_version_to_index_file(&entry("1.0.0+build"), &repository).await;
+        let next = add_version_to_index_file(&entry("1.0.1"), &repository).await;
+        assert!(matches!(
+            duplicate,
+            Err(IndexError::VersionAlreadyInIndex(_))
+        ));
+        assert!(next.is_ok());
+        assert_eq!(
+            index.file(Path::new("3/f/foo")).unwrap(),
+            "{\"name\":\"foo\",\"vers\":\"1.0.0\"}\n{\"name\":\"foo\",\"vers\":\"1.0.1\"}\n"
+        );
+    }
 }
@@ -0,0 +1,111 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::index::{commit_to_index, IndexError, IndexRepository};
+
+const CONFIG_FILE_NAME: &str = "config.json";
+
+#[derive(Debug, Deserialize, Serialize)]
+/// The file in the index root telling cargo where to download and publish crates
+struct IndexConfig {
+    dl: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    api: Option<String>,
+}
+impl IndexConfig {
+    fn new(base_url: &str) -> Self {
+        Self {
+            dl: download_url(base_url),
+            api: Some(base_url.to_string()),
+        }
+    }
+    /// Fields that don't point at this server, which may still be right behind a proxy
+    fn warnings(&self, base_url: &str) -> Vec<String> {
+        let mut warnings = Vec::new();
+        let api = match &self.api {
+            Some(api) => {
+                if api.trim_end_matches('/') != base_url {
+                    warnings.push(format!(
+                        "{CONFIG_FILE_NAME} has api {api}, but the server listens on {base_url}"
+                    ));
+                }
+                api.trim_end_matches('/')
+            }
+            None => {
+                warnings.push(format!(
+                    "{CONFIG_FILE_NAME} has no api field, cargo can't publish to this registry"
+                ));
+                base_url
+            }
+        };
+        // Templates with markers like `{crate}` are taken as they are
+        let expected_dl = download_url(api);
+        if !self.dl.contains('{') && self.dl.trim_end_matches('/') != expected_dl {
+            warnings.push(format!(
+                "{CONFIG_FILE_NAME} has dl {}, but crates are downloaded from {expected_dl}",
+                self.dl
+            ));
+        }
+        warnings
+    }
+}
+
+/// Cargo appends `/{crate}/{version}/download`
+fn download_url(api: &str) -> String {
+    format!("{api}/api/v1/crates")
+}
+
+impl IndexRepository {
+    /// Content of `config.json`, which [`Self::ensure_config`] created at startup
+    pub async fn read_config(&self) -> Result<String, IndexError> {
+        self.read_existing(Path::new(CONFIG_FILE_NAME)).await
+    }
+    /// Creates `config.json` pointing at `base_url` if it's missing, otherwise checks it
+    ///
+    /// Returns warnings about fields that look wrong. An unparsable file is an error.
+    pub async fn ensure_config(&self, base_url: &str) -> Result<Vec<String>, IndexError> {
+        let path = Path::new(CONFIG_FILE_NAME);
+        match self.backend.read(path).await? {
+            Some(content) => Ok(serde_json::from_str::<IndexConfig>(&content)
+                .map_err(IndexError::InvalidConfig)?
+                .warnings(base_url)),
+            None => {
+                let mut content = serde_json::to_string_pretty(&IndexConfig::new(base_url))
+                    .map_err(IndexError::SerializeJson)?;
+                content.push('\n');
+                self.backend.write(path, &content).await?;
+                commit_to_index(self, path, &format!("ADD {CONFIG_FILE_NAME}")).await?;
+                Ok(Vec::new())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::index::config::IndexConfig;
+
+    const BASE_URL: &str = "http://127.0.0.1:8999";
+
+    #[test]
+    fn generated_config_has_no_warnings() {
+        assert!(IndexConfig::new(BASE_URL).warnings(BASE_URL).is_empty());
+    }
+    #[test]
+    fn download_templates_are_accepted() {
+        let config = IndexConfig {
+            dl: format!("{BASE_URL}/api/v1/crates/{{crate}}/{{version}}/download"),
+            api: Some(format!("{BASE_URL}/")),
+        };
+        assert!(config.warnings(BASE_URL).is_empty());
+    }
+    #[test]
+    fn missing_api_and_other_dl_are_warned_about() {
+        let config = IndexConfig {
+            dl: String::from("https://example.com/crates"),
+            api: None,
+        };
+        assert_eq!(config.warnings(BASE_URL).len(), 2);
+    }
+}
@@ -6,7 +6,7 @@ use sha2::{Digest, Sha256};
 
 use crate::{
     crate_name::CrateName,
-    feature_name::FeatureName,
+    feature_name::{FeatureName, FeatureValue},
     publish::{self, DependencyKind, Metadata, RustVersionReq},
 };
 
@@ -53,7 +53,15 @@ pub fn build_version_metadata(metadata: &Metadata, crate_file: &[u8]) -> Version
             },
         )
         .collect();
-    let features = metadata.features.clone();
+    let (features2, features) = metadata
+        .features
+        .clone()
+        .into_iter()
+        .partition(|(_, values)| {
+            values
+                .iter()
+                .any(|value| FeatureValue::parse(value).needs_features2())
+        });
     VersionMetadata {
         name,
         vers,
@@ -63,7 +71,7 @@ pub fn build_version_metadata(metadata: &Metadata, crate_file: &[u8]) -> Version
         yanked: false,
         links,
         v: 2,
-        features2: BTreeMap::new(),
+        features2,
         rust_version,
     }
 }
@@ -0,0 +1,271 @@
+use std::{collections::BTreeMap, fmt::Display};
+
+use semver::Version;
+use serde::Deserialize;
+
+use crate::{
+    crate_name::CrateName,
+    dependencies::{deps_from_index_entry, Dependency},
+    index::{read_index_entries, IndexEntry, IndexRepository},
+    postgres::{get_stored_versions, StoredVersion},
+    version::without_build_metadata,
+    ServerState,
+};
+
+#[derive(Debug, PartialEq, Eq)]
+/// How a version's index line disagrees with the database
+pub enum Discrepancy {
+    MissingFromIndex,
+    /// The index file has a line the database has no version for
+    MissingFromDatabase,
+    Cksum {
+        database: String,
+        index: String,
+    },
+    Yanked {
+        database: bool,
+        index: bool,
+    },
+    Deps,
+    /// The crate's index file or this line can't be read
+    Unreadable(String),
+}
+impl Display for Discrepancy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingFromIndex => f.write_str("missing from the index"),
+            Self::MissingFromDatabase => f.write_str("missing from the database"),
+            Self::Cksum { database, index } => {
+                write!(
+                    f,
+                    "cksum is {database} in the database, {index} in the index"
+                )
+            }
+            Self::Yanked { database, index } => {
+                write!(
+                    f,
+                    "yanked is {database} in the database, {index} in the index"
+                )
+            }
+            Self::Deps => f.write_str("dependencies differ"),
+            Self::Unreadable(reason) => write!(f, "index can't be read: {reason}"),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct IndexCheckReport {
+    /// Versions in the database
+    pub checked: usize,
+    /// Crate, version and what is wrong with it
+    pub discrepancies: Vec<(String, String, Discrepancy)>,
+}
+impl Display for IndexCheckReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (crate_name, vers, discrepancy) in &self.discrepancies {
+            writeln!(f, "{crate_name} {vers}: {discrepancy}")?;
+        }
+        write!(
+            f,
+            "{} versions checked, {} discrepancies",
+            self.checked,
+            self.discrepancies.len()
+        )
+    }
+}
+
+#[derive(Debug, Deserialize)]
+/// The fields of an index line the database also stores, besides the dependencies
+struct IndexLine {
+    cksum: String,
+    yanked: bool,
+}
+
+/// Compares the index line of every version in the database with its database row
+///
+/// Finds what a failure between a database commit and the index commit left behind.
+pub async fn check_index(state: &ServerState) -> Result<IndexCheckReport, sqlx::Error> {
+    let stored_versions = get_stored_versions(&*state.database_connection_pool).await?;
+    let mut crates = BTreeMap::<String, Vec<StoredVersion>>::new();
+    for version in stored_versions {
+        crates
+            .entry(version.crate_name.clone())
+            .or_default()
+            .push(version);
+    }
+    let mut report = IndexCheckReport::default();
+    for (crate_name, versions) in crates {
+        report.checked += versions.len();
+        let mut report_crate = |vers: String, discrepancy| {
+            report
+                .discrepancies
+                .push((crate_name.clone(), vers, discrepancy));
+        };
+        match read_crate_entries(&crate_name, state.index_repository()).await {
+            Ok(entries) => {
+                for (vers, discrepancy) in crate_discrepancies(&versions, &entries) {
+                    report_crate(vers, discrepancy);
+                }
+            }
+            Err(reason) => report_crate(String::from("*"), Discrepancy::Unreadable(reason)),
+        }
+    }
+    Ok(report)
+}
+
+async fn read_crate_entries(
+    crate_name: &str,
+    repository: &IndexRepository,
+) -> Result<Vec<IndexEntry>, String> {
+    let crate_name = crate_name
+        .parse::<CrateName>()
+        .map_err(|e| format!("invalid crate name: {e}"))?;
+    read_index_entries(&crate_name, repository)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Build metadata is ignored, the database doesn't store it
+fn crate_discrepancies(
+    versions: &[StoredVersion],
+    entries: &[IndexEntry],
+) -> Vec<(String, Discrepancy)> {
+    let mut discrepancies = Vec::new();
+    for version in versions {
+        let entry = version.vers.parse::<Version>().ok().and_then(|vers| {
+            entries
+                .iter()
+                .find(|entry| without_build_metadata(&entry.vers) == vers)
+        });
+        let Some(entry) = entry else {
+            discrepancies.push((version.vers.clone(), Discrepancy::MissingFromIndex));
+            continue;
+        };
+        discrepancies.extend(
+            version_discrepancies(version, entry)
+                .into_iter()
+                .map(|discrepancy| (version.vers.clone(), discrepancy)),
+        );
+    }
+    for entry in entries {
+        let vers = without_build_metadata(&entry.vers);
+        if !versions.iter().any(|version| {
+            version
+                .vers
+                .parse::<Version>()
+                .is_ok_and(|stored| stored == vers)
+        }) {
+            discrepancies.push((entry.vers.to_string(), Discrepancy::MissingFromDatabase));
+        }
+    }
+    discrepancies
+}
+
+fn version_discrepancies(version: &StoredVersion, entry: &IndexEntry) -> Vec<Discrepancy> {
+    let line = match serde_json::from_str::<IndexLine>(entry.line()) {
+        Ok(line) => line,
+        Err(e) => return vec![Discrepancy::Unreadable(e.to_string())],
+    };
+    let mut discrepancies = Vec::new();
+    if line.cksum != version.cksum {
+        discrepancies.push(Discrepancy::Cksum {
+            database: version.cksum.clone(),
+            index: line.cksum,
+        });
+    }
+    if line.yanked != version.yanked {
+        discrepancies.push(Discrepancy::Yanked {
+            database: version.yanked,
+            index: line.yanked,
+        });
+    }
+    if let Some(stored_deps) = &version.deps {
+        match deps_from_index_entry(entry) {
+            Ok(index_deps) if !same_deps(stored_deps, &index_deps) => {
+                discrepancies.push(Discrepancy::Deps);
+            }
+            Ok(_) => {}
+            Err(e) => discrepancies.push(Discrepancy::Unreadable(e.to_string())),
+        }
+    }
+    discrepancies
+}
+
+/// Ignores the order, the index lists them as published but nothing depends on it
+fn same_deps(stored: &[Dependency], index: &[Dependency]) -> bool {
+    stored.len() == index.len() && stored.iter().all(|dependency| index.contains(dependency))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        index::IndexEntry,
+        index_check::{crate_discrepancies, Discrepancy},
+        postgres::StoredVersion,
+    };
+
+    fn stored(vers: &str, cksum: &str, yanked: bool) -> StoredVersion {
+        StoredVersion {
+            crate_name: String::from("foo"),
+            vers: vers.to_string(),
+            cksum: cksum.to_string(),
+            yanked,
+            deps: Some(serde_json::from_str(
+                r#"[{"crate_id":"bar","req":"^1","optional":false,"default_features":true,"features":[],"kind":"normal","target":null}]"#,
+            ).unwrap()),
+        }
+    }
+    fn entry(vers: &str, cksum: &str, yanked: bool, dependency: &str) -> IndexEntry {
+        IndexEntry::from_line(
+            "foo".parse().unwrap(),
+            vers.parse().unwrap(),
+            format!(
+                r#"{{"name":"foo","vers":"{vers}","deps":[{{"name":"{dependency}","req":"^1","features":[],"optional":false,"default_features":true,"target":null,"kind":"normal","registry":null,"package":null}}],"cksum":"{cksum}","features":{{}},"yanked":{yanked},"links":null,"v":2}}"#
+            ),
+        )
+    }
+
+    #[test]
+    fn consistent_versions_have_no_discrepancies() {
+        let discrepancies = crate_discrepancies(
+            &[stored("1.0.0", "aa", false), stored("1.1.0", "bb", true)],
+            &[
+                entry("1.0.0+build", "aa", false, "bar"),
+                entry("1.1.0", "bb", true, "bar"),
+            ],
+        );
+        assert_eq!(discrepancies, []);
+    }
+    #[test]
+    fn differing_fields_are_reported() {
+        let discrepancies = crate_discrepancies(
+            &[stored("1.0.0", "aa", false), stored("1.1.0", "bb", false)],
+            &[
+                entry("1.0.0", "cc", true, "baz"),
+                entry("2.0.0", "dd", false, "bar"),
+            ],
+        );
+        assert_eq!(
+            discrepancies,
+            [
+                (
+                    String::from("1.0.0"),
+                    Discrepancy::Cksum {
+                        database: String::from("aa"),
+                        index: String::from("cc")
+                    }
+                ),
+                (
+                    String::from("1.0.0"),
+                    Discrepancy::Yanked {
+                        database: false,
+                        index: true
+                    }
+                ),
+                (String::from("1.0.0"), Discrepancy::Deps),
+                (String::from("1.1.0"), Discrepancy::MissingFromIndex),
+                (String::from("2.0.0"), Discrepancy::MissingFromDatabase),
+            ]
+        );
+    }
+}
@@ -0,0 +1,838 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
+
+use audit::audit_log_handler;
+use auth::refresh_token_handler;
+use axum::{
+    extract::{Path, State},
+    http::{
+        header::{CONTENT_LENGTH, CONTENT_TYPE, ETAG},
+        HeaderName, StatusCode,
+    },
+    response::{IntoResponse, Response},
+    routing::{delete, get, post, put},
+    Router,
+};
+use categories::{add_category_handler, delete_category_handler, list_categories_handler};
+use concurrency::{limit_concurrency, ConcurrencyLimit};
+use crate_archive::ArchivePolicy;
+use crate_file::{LocalCrateStorage, DEFAULT_CRATE_STORAGE_PATH};
+use crate_info::{
+    badges_handler, checksum_handler, crate_availability_handler, crate_info_handler,
+    index_entries_handler, links_owner_handler, total_downloads_handler, version_authors_handler,
+    version_download_count_handler, version_features_handler, version_info_handler,
+};
+use crate_name::{CrateName, CrateNamePolicy};
+use crate_update::update_crate_handler;
+use delete_crate::admin_delete_crate_handler;
+use delete_version::{admin_delete_version_handler, delete_version_handler};
+use dependencies::version_deps_handler;
+use deprecate::deprecate_handler;
+use docs::{docs_file_handler, upload_docs_handler, DocsStorage, DEFAULT_DOCS_STORAGE_PATH};
+use index::{
+    CommitSigning, GitIndexBackend, IndexBackend, IndexDriftPolicy, IndexRepository,
+    SparseOnlyIndexBackend,
+};
+use meta::{meta_handler, AuthRequirements, Features, Limits, ServerMeta};
+use metrics::metrics_handler;
+use middleware::{bad_gateway, internal_server_error};
+use owners::{
+    add_owners_handler, list_crate_invitations_handler, list_invitations_handler,
+    list_owners_handler, remove_owners_handler, reply_to_invitation_handler,
+    transfer_owners_handler, OwnerPolicy,
+};
+use publish::publish_handler;
+use rate_limit::{limit_rate, RateLimiter};
+use read_only_mutex::ReadOnlyMutex;
+use readme::version_readme_handler;
+use registry::RegistryConfig;
+use request_id::assign_request_id;
+use reserved_names::{
+    add_reserved_name_handler, delete_reserved_name_handler, list_reserved_names_handler,
+    ConfiguredReservedNames,
+};
+use search::{crates_by_category_handler, crates_by_keyword_handler, search_handler};
+use semver::Version;
+use serde::Deserialize;
+use sparse_index::sparse_index_handler;
+use sqlx::{
+    postgres::{PgConnectOptions, PgPoolOptions},
+    Pool, Postgres,
+};
+use storage_quota::usage_handler;
+use summary::{crate_feed_handler, feed_handler, stats_handler, summary_handler, StatsCache};
+use teams::{
+    add_team_members_handler, list_team_members_handler, put_team_handler,
+    remove_team_members_handler,
+};
+use tokio::net::TcpListener;
+use upstream::{purge_upstream_cache_handler, CacheStatus, Upstream, UpstreamError};
+use version::{PrereleasePolicy, WildcardDependencyPolicy};
+use webhooks::WebhookDispatcher;
+use write_ahead_log::recover_pending_publishes;
+use yank::{unyank_handler, yank_handler};
+
+mod audit;
+mod auth;
+mod author;
+mod categories;
+mod concurrency;
+mod crate_archive;
+mod crate_file;
+mod crate_info;
+mod crate_name;
+mod crate_update;
+mod delete_crate;
+mod delete_version;
+mod dependencies;
+mod deprecate;
+mod docs;
+mod feature_name;
+mod feed;
+mod import;
+mod index;
+mod index_check;
+mod meta;
+mod metrics;
+mod middleware;
+mod non_empty_strings;
+mod owners;
+mod postgres;
+mod publish;
+mod rate_limit;
+mod read_only_mutex;
+mod readme;
+mod registry;
+mod request_id;
+mod reserved_names;
+mod search;
+mod sparse_index;
+mod storage_quota;
+mod summary;
+mod teams;
+#[cfg(test)]
+mod test_util;
+mod upstream;
+mod version;
+mod webhooks;
+mod write_ahead_log;
+mod yank;
+
+/// Parser of cargo's publish body, public for the fuzz targets in `fuzz/`
+#[doc(hidden)]
+pub use publish::extract_request_body;
+
+const IP_ENV_VARIABLE: &str = "REGISTRY_SERVER_IP";
+const PORT_ENV_VARIABLE: &str = "REGISTRY_SERVER_PORT";
+const REPOSITORY_ENV_VARIABLE: &str = "REGISTRY_SERVER_REPOSITORY_PATH";
+/// Directory for crate files, `./target/test_filesystem/download_files/` by default
+const CRATE_STORAGE_PATH_VAR: &str = "REGISTRY_SERVER_CRATE_STORAGE_PATH";
+/// Directory for uploaded docs, `./target/test_filesystem/docs/` by default
+const DOCS_STORAGE_PATH_VAR: &str = "REGISTRY_SERVER_DOCS_STORAGE_PATH";
+/// Bytes of unpacked docs per upload, 256 MiB by default
+const MAX_DOCS_SIZE_VAR: &str = "REGISTRY_SERVER_MAX_DOCS_SIZE";
+const POSTGRES_CONNECTION_STRING_VAR: &str = "REGISTRY_SERVER_DATABASE_URL";
+/// Size of the database connection pool, 10 by default
+const DB_MAX_CONNECTIONS_VAR: &str = "REGISTRY_SERVER_DB_MAX_CONNECTIONS";
+/// Seconds to wait for a free database connection, 30 by default
+const DB_ACQUIRE_TIMEOUT_SECS_VAR: &str = "REGISTRY_SERVER_DB_ACQUIRE_TIMEOUT_SECS";
+/// Seconds after which Postgres cancels a statement, 30 by default, 0 disables it
+const DB_STATEMENT_TIMEOUT_SECS_VAR: &str = "REGISTRY_SERVER_DB_STATEMENT_TIMEOUT_SECS";
+const MAX_CONCURRENT_PUBLISHES_VAR: &str = "REGISTRY_SERVER_MAX_CONCURRENT_PUBLISHES";
+const PUBLISH_QUEUE_LENGTH_VAR: &str = "REGISTRY_SERVER_PUBLISH_QUEUE_LENGTH";
+const MAX_CONCURRENT_DOWNLOADS_VAR: &str = "REGISTRY_SERVER_MAX_CONCURRENT_DOWNLOADS";
+const DOWNLOAD_QUEUE_LENGTH_VAR: &str = "REGISTRY_SERVER_DOWNLOAD_QUEUE_LENGTH";
+/// Publishes per minute and client, rate limiting is off if unset
+const PUBLISH_RATE_LIMIT_VAR: &str = "REGISTRY_SERVER_PUBLISH_RATE_LIMIT";
+const PUBLISH_RATE_BURST_VAR: &str = "REGISTRY_SERVER_PUBLISH_RATE_BURST";
+/// `git` (default) commits every index change, `sparse-only` only writes the files
+const INDEX_BACKEND_VAR: &str = "REGISTRY_SERVER_INDEX_BACKEND";
+/// `warn` (default) logs publishes to crates whose index file and database rows disagree,
+/// `reject` fails them
+const INDEX_DRIFT_POLICY_VAR: &str = "REGISTRY_SERVER_INDEX_DRIFT_POLICY";
+/// `warn` (default) publishes dependencies like `*` or `>=0.1` with a warning, `reject` fails them
+const WILDCARD_DEPENDENCY_POLICY_VAR: &str = "REGISTRY_SERVER_WILDCARD_DEPENDENCY_POLICY";
+/// Whether to sign index commits, off by default
+const SIGN_INDEX_COMMITS_VAR: &str = "REGISTRY_SERVER_SIGN_INDEX_COMMITS";
+/// Key to sign index commits with, git's `user.signingkey` if unset
+const INDEX_SIGNING_KEY_VAR: &str = "REGISTRY_SERVER_INDEX_SIGNING_KEY";
+/// Rejects crates without a clean `.cargo_vcs_info.json`, off by default
+const REQUIRE_VCS_INFO_VAR: &str = "REGISTRY_SERVER_REQUIRE_VCS_INFO";
+/// `unicode` (default) allows any valid crate name on publish, `ascii` only ASCII ones
+const CRATE_NAME_POLICY_VAR: &str = "REGISTRY_SERVER_CRATE_NAME_POLICY";
+/// File or comma separated list of names new crates can't have, `acme-*` reserves a prefix
+const RESERVED_NAMES_VAR: &str = "REGISTRY_SERVER_RESERVED_NAMES";
+/// `allow` (default) accepts versions like `1.0.0-alpha.1`, `existing-crates` only for crates
+/// that already have a version and `deny` never, `true` and `false` mean `allow` and `deny`
+const ALLOW_PRERELEASE_VAR: &str = "REGISTRY_SERVER_ALLOW_PRERELEASE";
+/// Entries of the Atom feeds, 50 by default
+const FEED_ENTRIES_VAR: &str = "REGISTRY_SERVER_FEED_ENTRIES";
+/// Most authors a published version may list, 20 by default
+const MAX_AUTHORS_VAR: &str = "REGISTRY_SERVER_MAX_AUTHORS";
+/// Adds owners without an invitation, off by default
+const DIRECT_OWNER_ADD_VAR: &str = "REGISTRY_SERVER_DIRECT_OWNER_ADD";
+/// Days an owner invitation can be accepted
+const OWNER_INVITATION_DAYS_VAR: &str = "REGISTRY_SERVER_OWNER_INVITATION_DAYS";
+/// Days a refresh extends a token's expiry by, 90 by default
+const TOKEN_LIFETIME_DAYS_VAR: &str = "REGISTRY_SERVER_TOKEN_LIFETIME_DAYS";
+/// Whether pre-releases can be a crate's newest version, whose metadata the crate shows, on by default
+const PRERELEASES_COUNT_AS_NEWEST_VAR: &str = "REGISTRY_SERVER_PRERELEASES_COUNT_AS_NEWEST";
+/// Token for the admin API, which is disabled if unset
+const ADMIN_TOKEN_VAR: &str = "REGISTRY_SERVER_ADMIN_TOKEN";
+/// Path to a JSON file listing webhooks, no webhooks if unset
+const WEBHOOKS_CONFIG_VAR: &str = "REGISTRY_SERVER_WEBHOOKS_CONFIG";
+/// Hours after publishing in which owners can delete an unused version instead of yanking it
+const DELETE_GRACE_HOURS_VAR: &str = "REGISTRY_SERVER_DELETE_GRACE_HOURS";
+/// Seconds a request waits for the index lock before answering 503, 30 by default
+const INDEX_LOCK_TIMEOUT_SECS_VAR: &str = "REGISTRY_SERVER_INDEX_LOCK_TIMEOUT_SECS";
+/// Bytes of crate files each user may publish, unlimited if unset
+const STORAGE_QUOTA_BYTES_VAR: &str = "REGISTRY_SERVER_STORAGE_QUOTA_BYTES";
+/// Sparse index of a registry to proxy crates not published here from, like
+/// `https://index.crates.io`, proxying is off if unset
+const UPSTREAM_INDEX_URL_VAR: &str = "REGISTRY_SERVER_UPSTREAM_INDEX_URL";
+/// Directory for proxied files, `./target/test_filesystem/upstream_cache/` by default
+const UPSTREAM_CACHE_PATH_VAR: &str = "REGISTRY_SERVER_UPSTREAM_CACHE_PATH";
+/// Seconds to wait for the upstream before answering 502, 30 by default
+const UPSTREAM_TIMEOUT_SECS_VAR: &str = "REGISTRY_SERVER_UPSTREAM_TIMEOUT_SECS";
+/// Seconds a proxied index file is served from the cache, 300 by default
+const UPSTREAM_INDEX_TTL_SECS_VAR: &str = "REGISTRY_SERVER_UPSTREAM_INDEX_TTL_SECS";
+/// Bytes of proxied crate files to keep, unbounded if unset
+const UPSTREAM_CACHE_MAX_BYTES_VAR: &str = "REGISTRY_SERVER_UPSTREAM_CACHE_MAX_BYTES";
+
+#[derive(Clone, Debug)]
+/// Configuration and shared resources of the handlers
+pub struct ServerState {
+    registry: RegistryConfig,
+    database_connection_pool: Arc<Pool<Postgres>>,
+    publish_limit: Arc<ConcurrencyLimit>,
+    /// Per client, `None` if publishes aren't rate limited
+    publish_rate_limiter: Option<Arc<RateLimiter>>,
+    download_limit: Arc<ConcurrencyLimit>,
+    webhooks: Arc<WebhookDispatcher>,
+    /// SHA-256 hex digest of the admin token
+    admin_token_hash: Option<Arc<String>>,
+    archive_policy: ArchivePolicy,
+    name_policy: CrateNamePolicy,
+    reserved_names: Arc<ConfiguredReservedNames>,
+    max_authors: usize,
+    prerelease_policy: PrereleasePolicy,
+    prereleases_count_as_newest: bool,
+    owner_policy: OwnerPolicy,
+    delete_grace_period: Duration,
+    /// How far a refresh moves a token's expiry
+    token_lifetime: Duration,
+    index_lock_timeout: Duration,
+    index_drift_policy: IndexDriftPolicy,
+    wildcard_dependency_policy: WildcardDependencyPolicy,
+    /// Bytes of crate files per user
+    storage_quota: Option<u64>,
+    feed_entries: u32,
+    stats_cache: Arc<StatsCache>,
+    /// Where this server listens, for links to itself
+    base_url: Arc<String>,
+    meta: Arc<ServerMeta>,
+}
+
+impl ServerState {
+    /// The index repository, for handlers that only read from it
+    fn index_repository(&self) -> &IndexRepository {
+        self.registry.index_repository.get_unlocked()
+    }
+}
+
+/// Serves the registry, or runs the command given as argument
+pub async fn run() {
+    let state = state_from_env().await;
+    let mut args = std::env::args().skip(1);
+    match args.next().as_deref() {
+        None => {}
+        Some("import") => {
+            let Some(directory) = args.next() else {
+                panic!("usage: registry_server import <directory>");
+            };
+            let report = import::import_directory(&state, &PathBuf::from(directory))
+                .await
+                .unwrap_or_else(|e| panic!("can't read import directory: {e}"));
+            println!("{report}");
+            if !report.failed.is_empty() {
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some("--check-index") => {
+            let report = index_check::check_index(&state)
+                .await
+                .unwrap_or_else(|e| panic!("can't read versions from the database: {e}"));
+            println!("{report}");
+            if !report.discrepancies.is_empty() {
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some(command) => panic!("unknown command {command}, expected import or --check-index"),
+    }
+    let pool = state.database_connection_pool.clone();
+    tokio::spawn(async move {
+        loop {
+            auth::cleanup_expired_tokens(pool.clone()).await;
+            tokio::time::sleep(TOKEN_CLEANUP_INTERVAL).await;
+        }
+    });
+    let tcp_connector = TcpListener::bind(listen_address()).await.unwrap();
+    axum::serve(
+        tcp_connector,
+        router(state).into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .unwrap()
+}
+
+fn listen_address() -> SocketAddr {
+    let ip_from_env: IpAddr = std::env::var(IP_ENV_VARIABLE).unwrap().parse().unwrap();
+    let port_from_env: u16 = std::env::var(PORT_ENV_VARIABLE).unwrap().parse().unwrap();
+    SocketAddr::from((ip_from_env, port_from_env))
+}
+
+/// Reads the configuration from the environment and prepares database, index and storage
+///
+/// Unfinished publishes are resolved before it returns. Panics on invalid configuration.
+pub async fn state_from_env() -> ServerState {
+    let database_url_from_env = std::env::var(POSTGRES_CONNECTION_STRING_VAR).unwrap();
+    let connect_options = PgConnectOptions::from_str(&database_url_from_env)
+        .unwrap()
+        .options([(
+            "statement_timeout",
+            format!("{}s", env_or_default(DB_STATEMENT_TIMEOUT_SECS_VAR, 30u64)),
+        )]);
+    let database_connection_pool = Arc::new(
+        PgPoolOptions::new()
+            .max_connections(env_or_default(DB_MAX_CONNECTIONS_VAR, 10))
+            .acquire_timeout(Duration::from_secs(env_or_default(
+                DB_ACQUIRE_TIMEOUT_SECS_VAR,
+                30,
+            )))
+            .connect_lazy_with(connect_options),
+    );
+    let git_repository_from_env = std::env::var(REPOSITORY_ENV_VARIABLE).unwrap();
+    let git_repository_path = PathBuf::from(git_repository_from_env)
+        .canonicalize()
+        .unwrap();
+    let max_concurrent_publishes = env_or_default(MAX_CONCURRENT_PUBLISHES_VAR, 4);
+    let max_concurrent_downloads = env_or_default(MAX_CONCURRENT_DOWNLOADS_VAR, 256);
+    let publish_limit = Arc::new(ConcurrencyLimit::new(
+        max_concurrent_publishes,
+        env_or_default(PUBLISH_QUEUE_LENGTH_VAR, 8),
+    ));
+    let download_limit = Arc::new(ConcurrencyLimit::new(
+        max_concurrent_downloads,
+        env_or_default(DOWNLOAD_QUEUE_LENGTH_VAR, 1024),
+    ));
+    let publish_rate_limit = env_optional::<u32>(PUBLISH_RATE_LIMIT_VAR).map(|per_minute| {
+        (
+            per_minute,
+            env_or_default(PUBLISH_RATE_BURST_VAR, per_minute),
+        )
+    });
+    let publish_rate_limiter =
+        publish_rate_limit.map(|(per_minute, burst)| Arc::new(RateLimiter::new(per_minute, burst)));
+    let sign_index_commits = env_or_default(SIGN_INDEX_COMMITS_VAR, false);
+    let signing = if sign_index_commits {
+        CommitSigning::Signed(std::env::var(INDEX_SIGNING_KEY_VAR).ok())
+    } else {
+        CommitSigning::Unsigned
+    };
+    let (index_backend, git_index): (Arc<dyn IndexBackend>, bool) =
+        match std::env::var(INDEX_BACKEND_VAR).as_deref() {
+            Err(_) | Ok("git") => (
+                Arc::new(GitIndexBackend::new(git_repository_path, signing)),
+                true,
+            ),
+            Ok("sparse-only") if sign_index_commits => {
+                panic!("{SIGN_INDEX_COMMITS_VAR} needs the git index backend")
+            }
+            Ok("sparse-only") => (
+                Arc::new(SparseOnlyIndexBackend::new(git_repository_path)),
+                false,
+            ),
+            Ok(_) => panic!("invalid value for {INDEX_BACKEND_VAR}, expected git or sparse-only"),
+        };
+    let index_repository = IndexRepository::new(index_backend);
+    if let Err(e) = index_repository.check_backend().await {
+        panic!("index backend can't be used: {e}");
+    }
+    let base_url = format!("http://{}", listen_address());
+    match index_repository.ensure_config(&base_url).await {
+        Ok(warnings) => {
+            for warning in warnings {
+                eprintln!("Warning: {warning}");
+            }
+        }
+        Err(e) => panic!("index config can't be used: {e}"),
+    }
+    let crate_storage_path = std::env::var(CRATE_STORAGE_PATH_VAR)
+        .unwrap_or_else(|_| String::from(DEFAULT_CRATE_STORAGE_PATH));
+    let docs_storage_path = std::env::var(DOCS_STORAGE_PATH_VAR)
+        .unwrap_or_else(|_| String::from(DEFAULT_DOCS_STORAGE_PATH));
+    let max_docs_size = env_or_default(MAX_DOCS_SIZE_VAR, 256 * 1024 * 1024);
+    let upstream = std::env::var(UPSTREAM_INDEX_URL_VAR).ok().map(|index_url| {
+        let cache_path = std::env::var(UPSTREAM_CACHE_PATH_VAR)
+            .unwrap_or_else(|_| String::from("./target/test_filesystem/upstream_cache/"));
+        let upstream = Upstream::new(
+            &index_url,
+            PathBuf::from(cache_path),
+            Duration::from_secs(env_or_default(UPSTREAM_TIMEOUT_SECS_VAR, 30)),
+            Duration::from_secs(env_or_default(UPSTREAM_INDEX_TTL_SECS_VAR, 300)),
+            env_optional(UPSTREAM_CACHE_MAX_BYTES_VAR),
+        )
+        .unwrap_or_else(|e| panic!("upstream can't be used: {e}"));
+        Arc::new(upstream)
+    });
+    let registry = RegistryConfig {
+        crate_storage: Arc::new(LocalCrateStorage::new(PathBuf::from(crate_storage_path))),
+        docs_storage: Arc::new(DocsStorage::new(
+            PathBuf::from(docs_storage_path),
+            max_docs_size,
+        )),
+        index_repository: Arc::new(ReadOnlyMutex::new(index_repository)),
+        upstream,
+    };
+    recover_pending_publishes(&database_connection_pool, &registry)
+        .await
+        .expect("failed to recover unfinished publishes");
+    let webhooks = match std::env::var(WEBHOOKS_CONFIG_VAR) {
+        Ok(path) => WebhookDispatcher::from_config_file(&PathBuf::from(path)).unwrap(),
+        Err(_) => WebhookDispatcher::default(),
+    };
+    let admin_token_hash = std::env::var(ADMIN_TOKEN_VAR)
+        .ok()
+        .map(|token| Arc::new(auth::hash_token(&token)));
+    let archive_policy = ArchivePolicy {
+        require_vcs_info: env_or_default(REQUIRE_VCS_INFO_VAR, false),
+    };
+    let name_policy = match std::env::var(CRATE_NAME_POLICY_VAR).as_deref() {
+        Err(_) | Ok("unicode") => CrateNamePolicy::Unicode,
+        Ok("ascii") => CrateNamePolicy::Ascii,
+        Ok(_) => panic!("invalid value for {CRATE_NAME_POLICY_VAR}, expected unicode or ascii"),
+    };
+    let reserved_names = match std::env::var(RESERVED_NAMES_VAR) {
+        Ok(value) => ConfiguredReservedNames::from_config(&value)
+            .unwrap_or_else(|e| panic!("invalid value for {RESERVED_NAMES_VAR}: {e}")),
+        Err(_) => ConfiguredReservedNames::default(),
+    };
+    let max_authors = env_or_default(MAX_AUTHORS_VAR, 20);
+    let prerelease_policy = match std::env::var(ALLOW_PRERELEASE_VAR).as_deref() {
+        Err(_) | Ok("allow" | "true") => PrereleasePolicy::Allow,
+        Ok("existing-crates") => PrereleasePolicy::ExistingCrates,
+        Ok("deny" | "false") => PrereleasePolicy::Deny,
+        Ok(_) => panic!(
+            "invalid value for {ALLOW_PRERELEASE_VAR}, expected allow, existing-crates or deny"
+        ),
+    };
+    let owner_policy = OwnerPolicy {
+        direct_add: env_or_default(DIRECT_OWNER_ADD_VAR, false),
+        invitation_valid_for: Duration::from_secs(
+            env_or_default(OWNER_INVITATION_DAYS_VAR, 30u64) * 24 * 60 * 60,
+        ),
+    };
+    let delete_grace_period =
+        Duration::from_secs(env_or_default(DELETE_GRACE_HOURS_VAR, 72u64) * 60 * 60);
+    let token_lifetime =
+        Duration::from_secs(env_or_default(TOKEN_LIFETIME_DAYS_VAR, 90u64) * 24 * 60 * 60);
+    let index_lock_timeout =
+        Duration::from_secs(env_or_default(INDEX_LOCK_TIMEOUT_SECS_VAR, 30u64));
+    let index_drift_policy = match std::env::var(INDEX_DRIFT_POLICY_VAR).as_deref() {
+        Err(_) | Ok("warn") => IndexDriftPolicy::Warn,
+        Ok("reject") => IndexDriftPolicy::Reject,
+        Ok(_) => panic!("invalid value for {INDEX_DRIFT_POLICY_VAR}, expected warn or reject"),
+    };
+    let wildcard_dependency_policy = match std::env::var(WILDCARD_DEPENDENCY_POLICY_VAR).as_deref()
+    {
+        Err(_) | Ok("warn") => WildcardDependencyPolicy::Warn,
+        Ok("reject") => WildcardDependencyPolicy::Reject,
+        Ok(_) => {
+            panic!("invalid value for {WILDCARD_DEPENDENCY_POLICY_VAR}, expected warn or reject")
+        }
+    };
+    let storage_quota = env_optional::<u64>(STORAGE_QUOTA_BYTES_VAR);
+    let feed_entries = env_or_default(FEED_ENTRIES_VAR, 50);
+    let meta = ServerMeta {
+        version: env!("CARGO_PKG_VERSION"),
+        features: Features {
+            sparse_index: true,
+            git_index,
+            search: true,
+            yank: true,
+            deprecation: true,
+            badges: true,
+            checksum: true,
+            webhooks: !webhooks.is_empty(),
+            signed_index_commits: sign_index_commits,
+            admin_api: admin_token_hash.is_some(),
+            require_vcs_info: archive_policy.require_vcs_info,
+            ascii_crate_names: name_policy == CrateNamePolicy::Ascii,
+            prerelease_versions: prerelease_policy != PrereleasePolicy::Deny,
+            prerelease_new_crates: prerelease_policy == PrereleasePolicy::Allow,
+            owner_invitations: !owner_policy.direct_add,
+            teams: admin_token_hash.is_some(),
+            upstream_proxy: registry.upstream.is_some(),
+            wildcard_dependencies: wildcard_dependency_policy == WildcardDependencyPolicy::Warn,
+        },
+        auth: AuthRequirements {
+            publish: true,
+            download: false,
+        },
+        limits: Limits {
+            max_upload_size: None,
+            max_concurrent_publishes,
+            max_concurrent_downloads,
+            publish_rate_limit: publish_rate_limit.map(|(per_minute, _)| per_minute),
+            publish_rate_burst: publish_rate_limit.map(|(_, burst)| burst),
+            max_search_results_per_page: search::MAX_PER_PAGE,
+            max_readme_size: publish::MAX_README_SIZE,
+            max_docs_size,
+            storage_quota_bytes: storage_quota,
+            max_authors,
+            feed_entries,
+            upstream_cache_bytes: registry
+                .upstream
+                .as_ref()
+                .and_then(|upstream| upstream.crate_cache_limit()),
+        },
+    };
+    ServerState {
+        registry,
+        database_connection_pool,
+        publish_limit,
+        publish_rate_limiter,
+        download_limit,
+        webhooks: Arc::new(webhooks),
+        admin_token_hash,
+        archive_policy,
+        name_policy,
+        reserved_names: Arc::new(reserved_names),
+        max_authors,
+        prerelease_policy,
+        prereleases_count_as_newest: env_or_default(PRERELEASES_COUNT_AS_NEWEST_VAR, true),
+        owner_policy,
+        delete_grace_period,
+        token_lifetime,
+        index_lock_timeout,
+        index_drift_policy,
+        wildcard_dependency_policy,
+        storage_quota,
+        feed_entries,
+        stats_cache: Arc::default(),
+        base_url: Arc::new(base_url),
+        meta: Arc::new(meta),
+    }
+}
+
+/// Every endpoint of the registry, clients' addresses are needed for rate limiting
+pub fn router(state: ServerState) -> Router {
+    let mut publish_route = put(publish_handler).layer(axum::middleware::from_fn_with_state(
+        state.publish_limit.clone(),
+        limit_concurrency,
+    ));
+    if let Some(limiter) = state.publish_rate_limiter.clone() {
+        publish_route = publish_route.layer(axum::middleware::from_fn_with_state(
+            (limiter, state.database_connection_pool.clone()),
+            limit_rate,
+        ));
+    }
+    let download_limit = state.download_limit.clone();
+    Router::new()
+        .route("/api/v1/crates", get(search_handler))
+        .route("/api/v1/crates/new", publish_route)
+        .route(
+            "/api/v1/categories/:category/crates",
+            get(crates_by_category_handler),
+        )
+        .route(
+            "/api/v1/keywords/:keyword/crates",
+            get(crates_by_keyword_handler),
+        )
+        .route(
+            "/api/v1/crates/:crate_name",
+            get(crate_info_handler).patch(update_crate_handler),
+        )
+        .route(
+            "/api/v1/crates/:crate_name/available",
+            get(crate_availability_handler),
+        )
+        .route("/api/v1/crates/:crate_name/badges", get(badges_handler))
+        .route(
+            "/api/v1/crates/:crate_name/downloads/total",
+            get(total_downloads_handler),
+        )
+        .route(
+            "/api/v1/crates/:crate_name/index",
+            get(index_entries_handler),
+        )
+        .route(
+            "/api/v1/crates/:crate_name/owners",
+            get(list_owners_handler)
+                .put(add_owners_handler)
+                .delete(remove_owners_handler),
+        )
+        .route(
+            "/api/v1/crates/:crate_name/owner-invitations",
+            get(list_crate_invitations_handler),
+        )
+        .route(
+            "/api/v1/crates/:crate_name/deprecate",
+            put(deprecate_handler),
+        )
+        .route(
+            "/api/v1/crates/:crate_name/:version",
+            get(version_info_handler).delete(delete_version_handler),
+        )
+        .route(
+            "/api/v1/crates/:crate_name/:version/yank",
+            delete(yank_handler),
+        )
+        .route(
+            "/api/v1/crates/:crate_name/:version/unyank",
+            put(unyank_handler),
+        )
+        .route(
+            "/api/v1/crates/:crate_name/:version/download",
+            get(download_handler).head(download_head_handler).layer(
+                axum::middleware::from_fn_with_state(download_limit, limit_concurrency),
+            ),
+        )
+        .route(
+            "/api/v1/crates/:crate_name/:version/download-count",
+            get(version_download_count_handler),
+        )
+        .route(
+            "/api/v1/crates/:crate_name/:version/checksum",
+            get(checksum_handler),
+        )
+        .route(
+            "/api/v1/crates/:crate_name/:version/authors",
+            get(version_authors_handler),
+        )
+        .route(
+            "/api/v1/crates/:crate_name/:version/features",
+            get(version_features_handler),
+        )
+        .route(
+            "/api/v1/crates/:crate_name/:version/dependencies",
+            get(version_deps_handler),
+        )
+        .route(
+            "/api/v1/crates/:crate_name/:version/readme",
+            get(version_readme_handler),
+        )
+        .route(
+            "/api/v1/crates/:crate_name/:version/docs",
+            put(upload_docs_handler),
+        )
+        .route(
+            "/api/v1/me/crate_owner_invitations",
+            get(list_invitations_handler),
+        )
+        .route(
+            "/api/v1/me/crate_owner_invitations/:crate_id",
+            put(reply_to_invitation_handler),
+        )
+        .route("/api/v1/links/:links", get(links_owner_handler))
+        .route("/api/v1/me/usage", get(usage_handler))
+        .route("/api/v1/tokens/refresh", post(refresh_token_handler))
+        .route("/api/v1/summary", get(summary_handler))
+        .route("/api/v1/summary/stats", get(stats_handler))
+        .route("/api/v1/admin/audit", get(audit_log_handler))
+        .route(
+            "/api/v1/admin/crates/:crate_name/owners",
+            put(transfer_owners_handler),
+        )
+        .route(
+            "/api/v1/admin/categories",
+            get(list_categories_handler).post(add_category_handler),
+        )
+        .route(
+            "/api/v1/admin/categories/:category_id",
+            delete(delete_category_handler),
+        )
+        .route(
+            "/api/v1/admin/crates/:crate_name",
+            delete(admin_delete_crate_handler),
+        )
+        .route(
+            "/api/v1/admin/crates/:crate_name/:version",
+            delete(admin_delete_version_handler),
+        )
+        .route(
+            "/api/v1/admin/reserved_names",
+            get(list_reserved_names_handler).post(add_reserved_name_handler),
+        )
+        .route(
+            "/api/v1/admin/reserved_names/:reservation_id",
+            delete(delete_reserved_name_handler),
+        )
+        .route("/api/v1/admin/teams/:team", put(put_team_handler))
+        .route(
+            "/api/v1/admin/upstream_cache",
+            delete(purge_upstream_cache_handler),
+        )
+        .route(
+            "/api/v1/admin/teams/:team/members",
+            get(list_team_members_handler)
+                .put(add_team_members_handler)
+                .delete(remove_team_members_handler),
+        )
+        .route("/api/v1/meta", get(meta_handler))
+        .route("/index/*path", get(sparse_index_handler))
+        .route("/metrics", get(metrics_handler))
+        .route("/feed.xml", get(feed_handler))
+        .route("/crates/:crate_name/feed.xml", get(crate_feed_handler))
+        .route("/docs/:crate_name/:version/*path", get(docs_file_handler))
+        .fallback(middleware::not_found)
+        .layer(axum::middleware::from_fn(
+            middleware::convert_errors_to_json,
+        ))
+        .layer(axum::middleware::from_fn(assign_request_id))
+        .with_state(state)
+}
+
+/// Panics if the variable is set but can't be parsed
+fn env_optional<T: std::str::FromStr>(variable: &str) -> Option<T> {
+    std::env::var(variable).ok().map(|value| {
+        value
+            .parse()
+            .unwrap_or_else(|_| panic!("invalid value for {variable}"))
+    })
+}
+
+/// Panics if the variable is set but can't be parsed
+fn env_or_default<T: std::str::FromStr>(variable: &str, default: T) -> T {
+    env_optional(variable).unwrap_or(default)
+}
+
+/// How often tokens past their expiry are deleted
+const TOKEN_CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Tells whether a proxied crate file came from the upstream cache
+const CACHE_HEADER: &str = "x-cache";
+
+#[derive(Debug, Deserialize)]
+struct DownloadPath {
+    crate_name: CrateName,
+    version: Version,
+}
+
+/// Counts the download, a failure to count doesn't fail the download
+///
+/// Crates this registry doesn't know are proxied from the upstream if one is configured,
+/// their downloads aren't counted.
+async fn download_handler(
+    State(ServerState {
+        database_connection_pool,
+        registry,
+        ..
+    }): State<ServerState>,
+    Path(DownloadPath {
+        crate_name,
+        version,
+    }): Path<DownloadPath>,
+) -> Result<Response, Response> {
+    let not_found = || (StatusCode::NOT_FOUND, "crate or version doesn't exist").into_response();
+    let file = match registry.crate_storage.get(&crate_name, &version).await {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let (file, cache_status) =
+                download_from_upstream(&registry, &crate_name, &version, &database_connection_pool)
+                    .await?
+                    .ok_or_else(not_found)?;
+            return Ok(([(CACHE_HEADER, cache_status.as_str())], file).into_response());
+        }
+        Err(_e) => return Err(internal_server_error("couldn't get crate file for you")),
+    };
+    if let Err(e) =
+        postgres::record_download(&crate_name, &version, &*database_connection_pool).await
+    {
+        eprintln!("Failed to count download: {e}");
+    }
+    let etag = crate_file_etag(&crate_name, &version, &database_connection_pool).await;
+    Ok((etag, file).into_response())
+}
+
+/// Headers of [`download_handler`] without reading the crate file, the download isn't counted
+async fn download_head_handler(
+    State(state): State<ServerState>,
+    Path(path): Path<DownloadPath>,
+) -> Result<Response, Response> {
+    let size = match state
+        .registry
+        .crate_storage
+        .size(&path.crate_name, &path.version)
+        .await
+    {
+        Ok(size) => size,
+        // Proxied crates have to be fetched to know their size, their body is dropped
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return download_handler(State(state), Path(path)).await
+        }
+        Err(_e) => return Err(internal_server_error("couldn't get crate file for you")),
+    };
+    let etag = crate_file_etag(
+        &path.crate_name,
+        &path.version,
+        &state.database_connection_pool,
+    )
+    .await;
+    Ok((
+        [
+            (CONTENT_TYPE, String::from("application/octet-stream")),
+            (CONTENT_LENGTH, size.to_string()),
+        ],
+        etag,
+        (),
+    )
+        .into_response())
+}
+
+/// The checksum of a crate file published here as `ETag`, none if it can't be looked up
+async fn crate_file_etag(
+    crate_name: &CrateName,
+    version: &Version,
+    database_connection_pool: &Pool<Postgres>,
+) -> Option<[(HeaderName, String); 1]> {
+    let cksum = postgres::get_version_cksum(crate_name, version, database_connection_pool)
+        .await
+        .inspect_err(|e| eprintln!("Failed to get checksum: {e}"))
+        .ok()??;
+    Some([(ETAG, format!("\"{cksum}\""))])
+}
+
+/// `None` without an upstream or if the crate is published here, which shadows the upstream
+async fn download_from_upstream(
+    registry: &RegistryConfig,
+    crate_name: &CrateName,
+    version: &Version,
+    database_connection_pool: &Pool<Postgres>,
+) -> Result<Option<(Vec<u8>, CacheStatus)>, Response> {
+    let Some(upstream) = &registry.upstream else {
+        return Ok(None);
+    };
+    let local_name = postgres::get_original_crate_name(crate_name, database_connection_pool)
+        .await
+        .inspect_err(|e| eprintln!("Failed to look up crate: {e}"))
+        .map_err(|_e| internal_server_error("couldn't look up crate"))?;
+    if local_name.is_some() {
+        return Ok(None);
+    }
+    upstream
+        .crate_file(crate_name, version)
+        .await
+        .inspect_err(|e| eprintln!("Failed to fetch crate file from upstream: {e}"))
+        .map_err(|e| match e {
+            UpstreamError::Cache(_) => internal_server_error("couldn't get crate file for you"),
+            e => bad_gateway(e.to_string()),
+        })
+}
@@ -0,0 +1,78 @@
+use axum::{extract::State, Json};
+use serde::Serialize;
+
+use crate::ServerState;
+
+#[derive(Clone, Debug, Serialize)]
+/// What this server supports, built once from the configuration at startup
+pub struct ServerMeta {
+    pub version: &'static str,
+    pub features: Features,
+    pub auth: AuthRequirements,
+    pub limits: Limits,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Features {
+    /// The index is also served over cargo's sparse protocol below `/index/`
+    pub sparse_index: bool,
+    /// Index changes are committed to git, not the case for the sparse-only backend
+    pub git_index: bool,
+    pub search: bool,
+    pub yank: bool,
+    pub deprecation: bool,
+    pub badges: bool,
+    pub checksum: bool,
+    pub webhooks: bool,
+    pub signed_index_commits: bool,
+    pub admin_api: bool,
+    /// Crates have to be published from a clean git checkout
+    pub require_vcs_info: bool,
+    /// New crate names may only use ASCII letters, digits, `-` and `_`
+    pub ascii_crate_names: bool,
+    /// Versions like `1.0.0-alpha.1` can be published
+    pub prerelease_versions: bool,
+    /// The first version of a crate can be a pre-release
+    pub prerelease_new_crates: bool,
+    /// New owners have to accept an invitation
+    pub owner_invitations: bool,
+    /// Teams can own crates, they are managed through the admin API
+    pub teams: bool,
+    /// Crates not published here are proxied from an upstream registry
+    pub upstream_proxy: bool,
+    /// Dependencies without an upper version bound like `*` can be published
+    pub wildcard_dependencies: bool,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct AuthRequirements {
+    pub publish: bool,
+    pub download: bool,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Limits {
+    /// Bytes of a publish request body, `null` if unlimited
+    pub max_upload_size: Option<usize>,
+    pub max_concurrent_publishes: usize,
+    pub max_concurrent_downloads: usize,
+    /// Publishes per minute and client, `null` if not rate limited
+    pub publish_rate_limit: Option<u32>,
+    pub publish_rate_burst: Option<u32>,
+    pub max_search_results_per_page: u32,
+    /// Bytes of a packaged README that is still served
+    pub max_readme_size: u64,
+    /// Bytes of unpacked docs per upload
+    pub max_docs_size: u64,
+    /// Bytes of crate files each user may publish, `null` if unlimited
+    pub storage_quota_bytes: Option<u64>,
+    pub max_authors: usize,
+    /// Entries of the Atom feeds
+    pub feed_entries: u32,
+    /// Bytes of proxied crate files kept, `null` if unbounded or proxying is off
+    pub upstream_cache_bytes: Option<u64>,
+}
+
+pub async fn meta_handler(State(ServerState { meta, .. }): State<ServerState>) -> Json<ServerMeta> {
+    Json(ServerMeta::clone(&meta))
+}
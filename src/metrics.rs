@@ -0,0 +1,51 @@
+use axum::{extract::State, http::header::CONTENT_TYPE, response::IntoResponse};
+
+use crate::ServerState;
+
+/// Prometheus text exposition of the server's gauges
+pub async fn metrics_handler(
+    State(ServerState {
+        publish_limit,
+        download_limit,
+        registry,
+        ..
+    }): State<ServerState>,
+) -> impl IntoResponse {
+    let mut body = format!(
+        "# HELP registry_server_publishes_in_flight Publish requests currently being processed.
+# TYPE registry_server_publishes_in_flight gauge
+registry_server_publishes_in_flight {}
+# HELP registry_server_publishes_queued Publish requests waiting for a free slot.
+# TYPE registry_server_publishes_queued gauge
+registry_server_publishes_queued {}
+# HELP registry_server_downloads_in_flight Download requests currently being processed.
+# TYPE registry_server_downloads_in_flight gauge
+registry_server_downloads_in_flight {}
+# HELP registry_server_downloads_queued Download requests waiting for a free slot.
+# TYPE registry_server_downloads_queued gauge
+registry_server_downloads_queued {}
+# HELP registry_server_index_locked Whether the index repository is currently being written to.
+# TYPE registry_server_index_locked gauge
+registry_server_index_locked {}
+",
+        publish_limit.in_flight(),
+        publish_limit.queued(),
+        download_limit.in_flight(),
+        download_limit.queued(),
+        u8::from(registry.index_repository.try_lock().is_none()),
+    );
+    if let Some(upstream) = &registry.upstream {
+        body.push_str(&format!(
+            "# HELP registry_server_upstream_cache_hits_total Proxied downloads served from the cache.
+# TYPE registry_server_upstream_cache_hits_total counter
+registry_server_upstream_cache_hits_total {}
+# HELP registry_server_upstream_cache_misses_total Proxied downloads fetched from the upstream.
+# TYPE registry_server_upstream_cache_misses_total counter
+registry_server_upstream_cache_misses_total {}
+",
+            upstream.cache_hits(),
+            upstream.cache_misses(),
+        ));
+    }
+    ([(CONTENT_TYPE, "text/plain; version=0.0.4")], body)
+}
@@ -23,6 +23,14 @@ impl ApiErrorResponse {
     pub fn push_error(&mut self, error: impl Into<String>) {
         self.errors.push(ApiError {
             detail: error.into(),
+            code: None,
+        });
+    }
+    /// Error with a stable `code` for clients to match on instead of the wording of `detail`
+    pub fn push_error_with_code(&mut self, error: impl Into<String>, code: &'static str) {
+        self.errors.push(ApiError {
+            detail: error.into(),
+            code: Some(code),
         });
     }
     pub fn new() -> Self {
@@ -47,6 +55,28 @@ impl IntoResponse for ApiErrorResponse {
 /// Component of a multi-error cargo response
 pub struct ApiError {
     detail: String,
+    /// Machine readable kind of the error, cargo only shows `detail`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<&'static str>,
+}
+
+pub fn internal_server_error(s: impl Into<String>) -> Response {
+    (StatusCode::INTERNAL_SERVER_ERROR, s.into()).into_response()
+}
+
+pub fn bad_request(s: impl Into<String>) -> Response {
+    (StatusCode::BAD_REQUEST, s.into()).into_response()
+}
+
+pub fn bad_gateway(s: impl Into<String>) -> Response {
+    (StatusCode::BAD_GATEWAY, s.into()).into_response()
+}
+
+/// Fallback for paths no route matches, axum's own 404 has no body
+pub async fn not_found() -> Response {
+    let mut errors = ApiErrorResponse::new();
+    errors.push_error("not found");
+    (StatusCode::NOT_FOUND, errors).into_response()
 }
 
 pub async fn convert_errors_to_json(request: Request, next: Next) -> Response {
@@ -57,7 +87,7 @@ pub async fn convert_errors_to_json(request: Request, next: Next) -> Response {
     }
 
     let content_type = response.headers().get(CONTENT_TYPE);
-    if content_type.is_none_or(|ct| ct != "text/plain; charset=utf-8") {
+    if content_type.is_some_and(|ct| ct.as_bytes().starts_with(b"application/json")) {
         return response;
     }
 
@@ -66,14 +96,19 @@ pub async fn convert_errors_to_json(request: Request, next: Next) -> Response {
     parts.headers.remove(CONTENT_TYPE);
     parts.headers.remove(CONTENT_LENGTH);
 
-    let Ok(bytes) = axum::body::to_bytes(body, usize::MAX).await else {
-        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
-    };
-    let Ok(text) = std::str::from_utf8(&bytes) else {
-        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    // Rejections like axum's 405 have no body, cargo would show no reason at all
+    let bytes = axum::body::to_bytes(body, usize::MAX)
+        .await
+        .unwrap_or_default();
+    let detail = match std::str::from_utf8(&bytes) {
+        Ok(text) if !text.trim().is_empty() => text.to_string(),
+        _ => status
+            .canonical_reason()
+            .unwrap_or("request failed")
+            .to_lowercase(),
     };
 
     let mut errors = ApiErrorResponse::new();
-    errors.push_error(text);
+    errors.push_error(detail);
     (parts, errors).into_response()
 }
@@ -56,6 +56,12 @@ impl Display for IsEmpty {
 }
 non_empty_string!(Description);
 non_empty_string!(Keyword);
+impl Keyword {
+    /// Keywords are matched regardless of case
+    pub fn normalized(&self) -> String {
+        self.0.trim().to_lowercase()
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,692 @@
+use std::time::Duration;
+
+use axum::{
+    extract::{Extension, Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgConnection, Pool, Postgres};
+
+use crate::{
+    audit::{record_failure, AuditAction, AuditContext, AuditEvent, AuditOutcome},
+    auth::{ensure_crate_owner, Admin, AuthenticatedUser, UserId},
+    crate_name::CrateName,
+    middleware::{bad_request, internal_server_error},
+    postgres::{
+        add_audit_event, add_owner, add_team_owner, crate_exists_exact,
+        get_crate_owner_invitations, get_owner_invitations, get_owners, get_team_id_by_login,
+        get_user_id_by_login, get_users_by_email, is_crate_owner, is_team_member,
+        remove_all_owners, remove_owner, remove_team_owner, take_owner_invitation,
+        upsert_owner_invitation,
+    },
+    request_id::RequestId,
+    teams::is_team_login,
+    ServerState,
+};
+
+#[derive(Clone, Copy, Debug)]
+/// How `cargo owner --add` takes effect
+pub struct OwnerPolicy {
+    /// Adds owners right away instead of inviting them, meant for small teams
+    pub direct_add: bool,
+    /// How long invitations can be accepted
+    pub invitation_valid_for: Duration,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum OwnerChange {
+    Add,
+    Invite,
+    Remove,
+}
+impl OwnerChange {
+    fn audit_action(self) -> AuditAction {
+        match self {
+            Self::Add => AuditAction::AddOwner,
+            Self::Invite => AuditAction::InviteOwner,
+            Self::Remove => AuditAction::RemoveOwner,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+/// Body cargo sends for `cargo owner --add` and `--remove`
+pub struct OwnersBody {
+    /// Logins of the users or teams to add or remove, users can also be given by email
+    users: Vec<String>,
+}
+
+pub async fn list_owners_handler(
+    State(ServerState {
+        database_connection_pool,
+        ..
+    }): State<ServerState>,
+    Path(crate_name): Path<CrateName>,
+) -> Result<Json<OwnersResponse>, Response> {
+    let mut connection = database_connection_pool
+        .acquire()
+        .await
+        .map_err(|_e| internal_server_error("couldn't connect to database"))?;
+    if !crate_exists_exact(&crate_name, &mut connection)
+        .await
+        .map_err(|_e| internal_server_error("couldn't check if crate exists"))?
+    {
+        return Err((StatusCode::NOT_FOUND, "crate doesn't exist").into_response());
+    }
+    let owners = get_owners(&crate_name, &mut connection)
+        .await
+        .inspect_err(|e| eprintln!("Failed to get owners: {e}"))
+        .map_err(|_e| internal_server_error("couldn't get owners"))?;
+    Ok(Json(OwnersResponse { users: owners }))
+}
+
+/// Invites the users, or adds them right away if [`OwnerPolicy::direct_add`] is set
+pub async fn add_owners_handler(
+    State(ServerState {
+        database_connection_pool,
+        owner_policy,
+        ..
+    }): State<ServerState>,
+    Path(crate_name): Path<CrateName>,
+    user: AuthenticatedUser,
+    Extension(request_id): Extension<RequestId>,
+    Json(OwnersBody { users }): Json<OwnersBody>,
+) -> Result<Json<OwnersChanged>, Response> {
+    let change = if owner_policy.direct_add {
+        OwnerChange::Add
+    } else {
+        OwnerChange::Invite
+    };
+    let logins = change_owners(
+        &database_connection_pool,
+        &crate_name,
+        user,
+        request_id,
+        &users,
+        change,
+        owner_policy,
+    )
+    .await?;
+    let msg = match (change, logins.as_slice()) {
+        (OwnerChange::Invite, [login]) => format!("user {login} has been invited to the crate"),
+        (OwnerChange::Invite, _) => {
+            format!("users {} have been invited to the crate", logins.join(", "))
+        }
+        _ => format!(
+            "{} added as owner(s) of crate {crate_name}",
+            logins.join(", ")
+        ),
+    };
+    Ok(Json(OwnersChanged { ok: true, msg }))
+}
+
+pub async fn remove_owners_handler(
+    State(ServerState {
+        database_connection_pool,
+        owner_policy,
+        ..
+    }): State<ServerState>,
+    Path(crate_name): Path<CrateName>,
+    user: AuthenticatedUser,
+    Extension(request_id): Extension<RequestId>,
+    Json(OwnersBody { users }): Json<OwnersBody>,
+) -> Result<Json<OwnersChanged>, Response> {
+    let logins = change_owners(
+        &database_connection_pool,
+        &crate_name,
+        user,
+        request_id,
+        &users,
+        OwnerChange::Remove,
+        owner_policy,
+    )
+    .await?;
+    Ok(Json(OwnersChanged {
+        ok: true,
+        msg: format!(
+            "{} removed from the owners of crate {crate_name}",
+            logins.join(", ")
+        ),
+    }))
+}
+
+/// Applies the change for all `logins` in one transaction, recording the attempt in the audit log
+///
+/// Returns the logins of the changed users and teams, in the order they were given.
+async fn change_owners(
+    database_connection_pool: &Pool<Postgres>,
+    crate_name: &CrateName,
+    user: AuthenticatedUser,
+    request_id: RequestId,
+    logins: &[String],
+    change: OwnerChange,
+    policy: OwnerPolicy,
+) -> Result<Vec<String>, Response> {
+    let audit = AuditContext {
+        actor: Some(user.login.clone()),
+        request_id,
+    };
+    let result = apply_owner_change(
+        database_connection_pool,
+        crate_name,
+        &user,
+        logins,
+        change,
+        policy,
+        &audit,
+    )
+    .await;
+    if let Err(response) = &result {
+        record_failure(
+            AuditEvent {
+                context: &audit,
+                action: change.audit_action(),
+                crate_name: Some(crate_name),
+                version: None,
+                outcome: AuditOutcome::from_status(response.status()),
+            },
+            database_connection_pool,
+        )
+        .await;
+    }
+    result
+}
+
+async fn apply_owner_change(
+    database_connection_pool: &Pool<Postgres>,
+    crate_name: &CrateName,
+    user: &AuthenticatedUser,
+    logins: &[String],
+    change: OwnerChange,
+    policy: OwnerPolicy,
+    audit: &AuditContext,
+) -> Result<Vec<String>, Response> {
+    if logins.is_empty() {
+        return Err(bad_request("no users given"));
+    }
+    let mut transaction = database_connection_pool
+        .begin()
+        .await
+        .map_err(|_e| internal_server_error("couldn't start transaction"))?;
+    ensure_crate_owner(crate_name, user, &mut transaction).await?;
+    // Members of owning teams may publish, but only users owning the crate manage its owners
+    if !is_crate_owner(crate_name, user.user_id, &mut transaction)
+        .await
+        .inspect_err(|e| eprintln!("Failed to check ownership: {e}"))
+        .map_err(|_e| internal_server_error("couldn't check crate ownership"))?
+    {
+        return Err((
+            StatusCode::FORBIDDEN,
+            format!(
+                "only users owning crate {crate_name} can change its owners, team members can't"
+            ),
+        )
+            .into_response());
+    }
+    let mut changed = Vec::with_capacity(logins.len());
+    for login in logins {
+        if is_team_login(login) {
+            change_team_owner(crate_name, login, user, change, &mut transaction).await?;
+            changed.push(login.clone());
+            continue;
+        }
+        let (user_id, user_login) = find_user(login, &mut transaction).await?;
+        changed.push(user_login);
+        match change {
+            OwnerChange::Add => add_owner(crate_name, user_id, &mut transaction).await,
+            OwnerChange::Invite => {
+                invite_owner(crate_name, user_id, user.user_id, policy, &mut transaction).await
+            }
+            OwnerChange::Remove => remove_owner(crate_name, user_id, &mut transaction).await,
+        }
+        .inspect_err(|e| eprintln!("Failed to change owners: {e}"))
+        .map_err(|_e| internal_server_error("couldn't change owners"))?;
+    }
+    if !get_owners(crate_name, &mut transaction)
+        .await
+        .inspect_err(|e| eprintln!("Failed to get owners: {e}"))
+        .map_err(|_e| internal_server_error("couldn't get owners"))?
+        .iter()
+        .any(|owner| owner.kind == OwnerKind::User)
+    {
+        return Err(bad_request("a crate needs at least one user as owner"));
+    }
+    add_audit_event(
+        AuditEvent {
+            context: audit,
+            action: change.audit_action(),
+            crate_name: Some(crate_name),
+            version: None,
+            outcome: AuditOutcome::Success,
+        },
+        &mut *transaction,
+    )
+    .await
+    .inspect_err(|e| eprintln!("Failed to record audit event: {e}"))
+    .map_err(|_e| internal_server_error("failed to record audit event"))?;
+    transaction
+        .commit()
+        .await
+        .map_err(|_e| internal_server_error("committing to database failed"))?;
+    Ok(changed)
+}
+
+/// Looks `identifier` up as login first, then as email
+async fn find_user(
+    identifier: &str,
+    exec: &mut PgConnection,
+) -> Result<(UserId, String), Response> {
+    if let Some(user_id) = get_user_id_by_login(identifier, &mut *exec)
+        .await
+        .inspect_err(|e| eprintln!("Failed to look up user: {e}"))
+        .map_err(|_e| internal_server_error("couldn't look up user"))?
+    {
+        return Ok((user_id, identifier.to_owned()));
+    }
+    let mut users = get_users_by_email(identifier, exec)
+        .await
+        .inspect_err(|e| eprintln!("Failed to look up user: {e}"))
+        .map_err(|_e| internal_server_error("couldn't look up user"))?;
+    match users.len() {
+        0 => Err((
+            StatusCode::NOT_FOUND,
+            format!("user '{identifier}' not found"),
+        )
+            .into_response()),
+        1 => Ok(users.remove(0)),
+        _ => Err(bad_request(format!(
+            "several users have the email {identifier}, give a login instead"
+        ))),
+    }
+}
+
+/// Teams are added without an invitation, only by their own members
+async fn change_team_owner(
+    crate_name: &CrateName,
+    login: &str,
+    user: &AuthenticatedUser,
+    change: OwnerChange,
+    exec: &mut PgConnection,
+) -> Result<(), Response> {
+    let team_id = get_team_id_by_login(login, &mut *exec)
+        .await
+        .inspect_err(|e| eprintln!("Failed to look up team: {e}"))
+        .map_err(|_e| internal_server_error("couldn't look up team"))?
+        .ok_or_else(|| bad_request(format!("unknown team: {login}")))?;
+    match change {
+        OwnerChange::Add | OwnerChange::Invite => {
+            if !is_team_member(team_id, user.user_id, &mut *exec)
+                .await
+                .inspect_err(|e| eprintln!("Failed to check team membership: {e}"))
+                .map_err(|_e| internal_server_error("couldn't check team membership"))?
+            {
+                return Err((
+                    StatusCode::FORBIDDEN,
+                    format!("only members of team {login} can add it as owner"),
+                )
+                    .into_response());
+            }
+            add_team_owner(crate_name, team_id, exec).await
+        }
+        OwnerChange::Remove => remove_team_owner(crate_name, team_id, exec).await,
+    }
+    .inspect_err(|e| eprintln!("Failed to change owners: {e}"))
+    .map_err(|_e| internal_server_error("couldn't change owners"))
+}
+
+/// Existing owners aren't invited again
+async fn invite_owner(
+    crate_name: &CrateName,
+    invitee: UserId,
+    inviter: UserId,
+    policy: OwnerPolicy,
+    exec: &mut PgConnection,
+) -> Result<(), sqlx::Error> {
+    if is_crate_owner(crate_name, invitee, &mut *exec).await? {
+        return Ok(());
+    }
+    upsert_owner_invitation(
+        crate_name,
+        invitee,
+        inviter,
+        policy.invitation_valid_for,
+        exec,
+    )
+    .await
+}
+
+/// Replaces all owners of the crate, users and teams, with the given users
+///
+/// Meant for crates whose owners left and can't hand them over themselves.
+pub async fn transfer_owners_handler(
+    _admin: Admin,
+    State(ServerState {
+        database_connection_pool,
+        ..
+    }): State<ServerState>,
+    Path(crate_name): Path<CrateName>,
+    Extension(request_id): Extension<RequestId>,
+    Json(OwnersBody { users }): Json<OwnersBody>,
+) -> Result<Json<OwnersTransferred>, Response> {
+    let audit = AuditContext {
+        actor: Some(String::from(Admin::AUDIT_ACTOR)),
+        request_id,
+    };
+    let result = replace_owners(&database_connection_pool, &crate_name, &users, &audit).await;
+    if let Err(response) = &result {
+        record_failure(
+            AuditEvent {
+                context: &audit,
+                action: AuditAction::TransferOwners,
+                crate_name: Some(&crate_name),
+                version: None,
+                outcome: AuditOutcome::from_status(response.status()),
+            },
+            &database_connection_pool,
+        )
+        .await;
+    }
+    let (before, after) = result?;
+    Ok(Json(OwnersTransferred {
+        ok: true,
+        before,
+        after,
+    }))
+}
+
+/// Returns the owners before and after
+async fn replace_owners(
+    database_connection_pool: &Pool<Postgres>,
+    crate_name: &CrateName,
+    logins: &[String],
+    audit: &AuditContext,
+) -> Result<(Vec<Owner>, Vec<Owner>), Response> {
+    if logins.is_empty() {
+        return Err(bad_request("a crate needs at least one owner"));
+    }
+    let mut transaction = database_connection_pool
+        .begin()
+        .await
+        .map_err(|_e| internal_server_error("couldn't start transaction"))?;
+    if !crate_exists_exact(crate_name, &mut transaction)
+        .await
+        .map_err(|_e| internal_server_error("couldn't check if crate exists"))?
+    {
+        return Err((StatusCode::NOT_FOUND, "crate doesn't exist").into_response());
+    }
+    let mut user_ids = Vec::with_capacity(logins.len());
+    let mut unknown = Vec::new();
+    for login in logins {
+        match get_user_id_by_login(login, &mut transaction)
+            .await
+            .inspect_err(|e| eprintln!("Failed to look up user: {e}"))
+            .map_err(|_e| internal_server_error("couldn't look up user"))?
+        {
+            Some(user_id) => user_ids.push(user_id),
+            None => unknown.push(login.as_str()),
+        }
+    }
+    if !unknown.is_empty() {
+        return Err(bad_request(format!(
+            "unknown users: {}",
+            unknown.join(", ")
+        )));
+    }
+    let before = get_owners(crate_name, &mut transaction)
+        .await
+        .inspect_err(|e| eprintln!("Failed to get owners: {e}"))
+        .map_err(|_e| internal_server_error("couldn't get owners"))?;
+    remove_all_owners(crate_name, &mut transaction)
+        .await
+        .inspect_err(|e| eprintln!("Failed to remove owners: {e}"))
+        .map_err(|_e| internal_server_error("couldn't change owners"))?;
+    for user_id in user_ids {
+        add_owner(crate_name, user_id, &mut transaction)
+            .await
+            .inspect_err(|e| eprintln!("Failed to add owner: {e}"))
+            .map_err(|_e| internal_server_error("couldn't change owners"))?;
+    }
+    let after = get_owners(crate_name, &mut transaction)
+        .await
+        .inspect_err(|e| eprintln!("Failed to get owners: {e}"))
+        .map_err(|_e| internal_server_error("couldn't get owners"))?;
+    add_audit_event(
+        AuditEvent {
+            context: audit,
+            action: AuditAction::TransferOwners,
+            crate_name: Some(crate_name),
+            version: None,
+            outcome: AuditOutcome::Success,
+        },
+        &mut *transaction,
+    )
+    .await
+    .inspect_err(|e| eprintln!("Failed to record audit event: {e}"))
+    .map_err(|_e| internal_server_error("failed to record audit event"))?;
+    transaction
+        .commit()
+        .await
+        .map_err(|_e| internal_server_error("committing to database failed"))?;
+    Ok((before, after))
+}
+
+/// Pending invitations of the authenticated user
+pub async fn list_invitations_handler(
+    State(ServerState {
+        database_connection_pool,
+        ..
+    }): State<ServerState>,
+    user: AuthenticatedUser,
+) -> Result<Json<InvitationsResponse>, Response> {
+    let mut connection = database_connection_pool
+        .acquire()
+        .await
+        .map_err(|_e| internal_server_error("couldn't connect to database"))?;
+    let invitations = get_owner_invitations(user.user_id, &mut connection)
+        .await
+        .inspect_err(|e| eprintln!("Failed to get invitations: {e}"))
+        .map_err(|_e| internal_server_error("couldn't get invitations"))?;
+    Ok(Json(InvitationsResponse {
+        crate_owner_invitations: invitations
+            .into_iter()
+            .map(|invitation| Invitation {
+                invitee_id: user.user_id.0,
+                inviter_id: invitation.inviter_id.0,
+                invited_by_username: invitation.inviter_login,
+                crate_id: invitation.crate_id,
+                crate_name: invitation.crate_name,
+                created_at: invitation.created_at,
+                expires_at: invitation.expires_at,
+            })
+            .collect(),
+    }))
+}
+
+/// Invitations to the crate nobody answered yet, so owners can follow a transfer
+pub async fn list_crate_invitations_handler(
+    State(ServerState {
+        database_connection_pool,
+        ..
+    }): State<ServerState>,
+    Path(crate_name): Path<CrateName>,
+    user: AuthenticatedUser,
+) -> Result<Json<CrateInvitationsResponse>, Response> {
+    let mut connection = database_connection_pool
+        .acquire()
+        .await
+        .map_err(|_e| internal_server_error("couldn't connect to database"))?;
+    ensure_crate_owner(&crate_name, &user, &mut connection).await?;
+    let invitations = get_crate_owner_invitations(&crate_name, &mut connection)
+        .await
+        .inspect_err(|e| eprintln!("Failed to get invitations: {e}"))
+        .map_err(|_e| internal_server_error("couldn't get invitations"))?;
+    Ok(Json(CrateInvitationsResponse {
+        crate_owner_invitations: invitations
+            .into_iter()
+            .map(|invitation| CrateInvitation {
+                invitee_id: invitation.invitee_id.0,
+                invitee_username: invitation.invitee_login,
+                inviter_id: invitation.inviter_id.0,
+                invited_by_username: invitation.inviter_login,
+                created_at: invitation.created_at,
+                expires_at: invitation.expires_at,
+            })
+            .collect(),
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InvitationReplyBody {
+    crate_owner_invite: InvitationReply,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct InvitationReply {
+    crate_id: i32,
+    accepted: bool,
+}
+
+/// Accepting makes the user an owner, declining only drops the invitation
+pub async fn reply_to_invitation_handler(
+    State(ServerState {
+        database_connection_pool,
+        ..
+    }): State<ServerState>,
+    Path(crate_id): Path<i32>,
+    user: AuthenticatedUser,
+    Extension(request_id): Extension<RequestId>,
+    Json(InvitationReplyBody { crate_owner_invite }): Json<InvitationReplyBody>,
+) -> Result<Json<InvitationReplyResponse>, Response> {
+    if crate_owner_invite.crate_id != crate_id {
+        return Err(bad_request("crate id in body doesn't match the path"));
+    }
+    let mut transaction = database_connection_pool
+        .begin()
+        .await
+        .map_err(|_e| internal_server_error("couldn't start transaction"))?;
+    let invitation = take_owner_invitation(crate_id, user.user_id, &mut transaction)
+        .await
+        .inspect_err(|e| eprintln!("Failed to get invitation: {e}"))
+        .map_err(|_e| internal_server_error("couldn't get invitation"))?
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                "no pending invitation for this crate",
+            )
+                .into_response()
+        })?;
+    if invitation.expired {
+        return Err((
+            StatusCode::GONE,
+            "the invitation has expired, ask an owner to invite you again",
+        )
+            .into_response());
+    }
+    if crate_owner_invite.accepted {
+        let crate_name: CrateName = invitation
+            .crate_name
+            .parse()
+            .map_err(|_e| internal_server_error("invalid crate name in database"))?;
+        add_owner(&crate_name, user.user_id, &mut transaction)
+            .await
+            .inspect_err(|e| eprintln!("Failed to add owner: {e}"))
+            .map_err(|_e| internal_server_error("couldn't add owner"))?;
+        let audit = AuditContext {
+            actor: Some(user.login),
+            request_id,
+        };
+        add_audit_event(
+            AuditEvent {
+                context: &audit,
+                action: AuditAction::AddOwner,
+                crate_name: Some(&crate_name),
+                version: None,
+                outcome: AuditOutcome::Success,
+            },
+            &mut *transaction,
+        )
+        .await
+        .inspect_err(|e| eprintln!("Failed to record audit event: {e}"))
+        .map_err(|_e| internal_server_error("failed to record audit event"))?;
+    }
+    transaction
+        .commit()
+        .await
+        .map_err(|_e| internal_server_error("committing to database failed"))?;
+    Ok(Json(InvitationReplyResponse {
+        crate_owner_invitation: crate_owner_invite,
+    }))
+}
+
+#[derive(Debug, Serialize)]
+pub struct InvitationsResponse {
+    crate_owner_invitations: Vec<Invitation>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Invitation {
+    invitee_id: i32,
+    inviter_id: i32,
+    invited_by_username: String,
+    crate_id: i32,
+    crate_name: String,
+    created_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CrateInvitationsResponse {
+    crate_owner_invitations: Vec<CrateInvitation>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CrateInvitation {
+    invitee_id: i32,
+    invitee_username: String,
+    inviter_id: i32,
+    invited_by_username: String,
+    created_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InvitationReplyResponse {
+    crate_owner_invitation: InvitationReply,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OwnersResponse {
+    users: Vec<Owner>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Owner {
+    /// User id or team id, depending on `kind`
+    pub id: i32,
+    pub login: String,
+    pub name: Option<String>,
+    pub kind: OwnerKind,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OwnerKind {
+    User,
+    Team,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OwnersTransferred {
+    ok: bool,
+    before: Vec<Owner>,
+    after: Vec<Owner>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OwnersChanged {
+    ok: bool,
+    msg: String,
+}
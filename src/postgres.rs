@@ -1,8 +1,28 @@
-use std::collections::HashSet;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    fmt::Display,
+    time::Duration,
+};
 
-use sqlx::{Executor, PgConnection, Postgres};
+use chrono::{DateTime, Utc};
+use sqlx::{types::Json, Executor, PgConnection, Postgres};
 
-use crate::{crate_name::CrateName, publish::Metadata};
+use crate::{
+    audit::{AuditEvent, AuditOutcome, AuditRecord},
+    auth::{AuthenticatedUser, TokenId, UserId},
+    author::Author,
+    categories::Category,
+    crate_name::CrateName,
+    dependencies::Dependency,
+    index::IndexEntry,
+    non_empty_strings::{Description, Keyword},
+    owners::{Owner, OwnerKind},
+    publish::Metadata,
+    reserved_names::{ReservedName, ReservedPattern},
+    search::{CrateSort, SearchFilter},
+    summary::{RecentVersion, RegistryStats},
+    version::without_build_metadata,
+};
 
 pub async fn crate_exists_exact(
     crate_name: &CrateName,
@@ -26,7 +46,7 @@ pub async fn crate_exists_or_normalized(
     let res_normalized = sqlx::query!(
         "SELECT EXISTS(SELECT crate_id, original_name FROM crates
         WHERE normalize_crate_name(original_name) = $1)",
-        crate_name.normalized()
+        crate_name.normalized() as _
     )
     .fetch_one(&mut *exec)
     .await?;
@@ -36,6 +56,36 @@ pub async fn crate_exists_or_normalized(
         Ok(CrateExists::No)
     }
 }
+/// Name the crate was published under, found by its normalized name
+pub async fn get_original_crate_name(
+    crate_name: &CrateName,
+    exec: impl Executor<'_, Database = Postgres>,
+) -> Result<Option<String>, sqlx::Error> {
+    let res = sqlx::query!(
+        "SELECT original_name FROM crates WHERE normalize_crate_name(original_name) = $1",
+        crate_name.normalized() as _
+    )
+    .fetch_optional(exec)
+    .await?;
+    Ok(res.map(|row| row.original_name))
+}
+/// Crate that claimed the `links` value first
+pub async fn get_links_owner(
+    links: &str,
+    exec: impl Executor<'_, Database = Postgres>,
+) -> Result<Option<String>, sqlx::Error> {
+    let res = sqlx::query!(
+        "SELECT crates.original_name FROM crates
+        JOIN versions ON versions.crate = crates.crate_id
+        WHERE versions.links = $1
+        ORDER BY versions.created_at ASC NULLS FIRST
+        LIMIT 1",
+        links
+    )
+    .fetch_optional(exec)
+    .await?;
+    Ok(res.map(|row| row.original_name))
+}
 pub async fn add_crate(
     metadata: &Metadata,
     exec: impl Executor<'_, Database = Postgres>,
@@ -62,15 +112,21 @@ pub async fn add_crate(
     .await?;
     Ok(())
 }
-pub async fn add_keywords(metadata: &Metadata, exec: &mut PgConnection) -> Result<(), sqlx::Error> {
+/// Stores the keywords normalized, so spellings differing in case are one keyword
+pub async fn add_keywords(
+    crate_name: &CrateName,
+    keywords: &HashSet<Keyword>,
+    exec: &mut PgConnection,
+) -> Result<(), sqlx::Error> {
     sqlx::query!(
         "INSERT INTO keywords (crate_id, keyword)
         VALUES ((SELECT crate_id FROM crates WHERE original_name = $1), unnest($2::TEXT[]))",
-        metadata.name.original_str(),
-        &metadata
-            .keywords
+        crate_name.original_str(),
+        &keywords
             .iter()
-            .map(|x| x.to_string())
+            .map(Keyword::normalized)
+            .collect::<BTreeSet<_>>()
+            .into_iter()
             .collect::<Vec<_>>(),
     )
     .execute(&mut *exec)
@@ -110,7 +166,7 @@ pub async fn insert_categories(
     Ok(())
 }
 pub async fn get_bad_categories(
-    metadata: &Metadata,
+    categories: &HashSet<String>,
     exec: &mut PgConnection,
 ) -> Result<HashSet<String>, sqlx::Error> {
     sqlx::query!(
@@ -118,7 +174,7 @@ pub async fn get_bad_categories(
         FROM unnest($1::TEXT[]) AS category
         LEFT JOIN valid_categories ON valid_categories.category_name = category
         WHERE valid_categories.category_name IS NULL",
-        &metadata.categories.iter().cloned().collect::<Vec<_>>()
+        &categories.iter().cloned().collect::<Vec<_>>()
     )
     .fetch_all(exec)
     .await
@@ -129,6 +185,84 @@ pub async fn get_bad_categories(
             .collect()
     })
 }
+/// All valid categories with the number of crates in them, ordered by name
+pub async fn get_categories(exec: &mut PgConnection) -> Result<Vec<Category>, sqlx::Error> {
+    Ok(sqlx::query!(
+        r#"SELECT valid_categories.category_id, valid_categories.category_name,
+            COUNT(crate_categories.crate_id) AS "crates!"
+        FROM valid_categories
+        LEFT JOIN crate_categories ON crate_categories.category_id = valid_categories.category_id
+        GROUP BY valid_categories.category_id
+        ORDER BY valid_categories.category_name"#
+    )
+    .fetch_all(exec)
+    .await?
+    .into_iter()
+    .map(|record| Category {
+        id: record.category_id,
+        category: record.category_name,
+        crates: record.crates,
+    })
+    .collect())
+}
+/// Returns the id of the new category, `None` if it already exists
+pub async fn add_category(
+    category: &str,
+    exec: &mut PgConnection,
+) -> Result<Option<i32>, sqlx::Error> {
+    Ok(sqlx::query!(
+        "INSERT INTO valid_categories (category_name) VALUES ($1)
+        ON CONFLICT DO NOTHING
+        RETURNING category_id",
+        category
+    )
+    .fetch_optional(exec)
+    .await?
+    .map(|record| record.category_id))
+}
+/// Number of crates in the category, `None` if it doesn't exist
+///
+/// Locks the category until the end of the transaction, so no publish can add crates to it.
+pub async fn lock_category_for_deletion(
+    category_id: i32,
+    exec: &mut PgConnection,
+) -> Result<Option<i64>, sqlx::Error> {
+    if sqlx::query!(
+        "SELECT category_id FROM valid_categories WHERE category_id = $1 FOR UPDATE",
+        category_id
+    )
+    .fetch_optional(&mut *exec)
+    .await?
+    .is_none()
+    {
+        return Ok(None);
+    }
+    Ok(Some(
+        sqlx::query!(
+            r#"SELECT COUNT(*) AS "crates!" FROM crate_categories WHERE category_id = $1"#,
+            category_id
+        )
+        .fetch_one(exec)
+        .await?
+        .crates,
+    ))
+}
+/// Removes the category, taking it off every crate in it
+pub async fn delete_category(category_id: i32, exec: &mut PgConnection) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM crate_categories WHERE category_id = $1",
+        category_id
+    )
+    .execute(&mut *exec)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM valid_categories WHERE category_id = $1",
+        category_id
+    )
+    .execute(exec)
+    .await?;
+    Ok(())
+}
 pub async fn delete_category_entries(
     crate_name: &CrateName,
     exec: &mut PgConnection,
@@ -144,60 +278,167 @@ pub async fn delete_category_entries(
     .await?;
     Ok(())
 }
+/// Replaces all badges of the crate
+pub async fn set_badges(
+    crate_name: &CrateName,
+    badges: &BTreeMap<&String, &BTreeMap<String, String>>,
+    exec: &mut PgConnection,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM crate_badges
+        WHERE crate_id
+        IN (SELECT crate_id FROM crates WHERE original_name = $1)",
+        crate_name.original_str()
+    )
+    .execute(&mut *exec)
+    .await?;
+    let (badge_types, badge_params): (Vec<String>, Vec<serde_json::Value>) = badges
+        .iter()
+        .map(|(badge_type, params)| ((*badge_type).clone(), serde_json::json!(params)))
+        .unzip();
+    sqlx::query!(
+        "INSERT INTO crate_badges (crate_id, badge_type, badge_params)
+        SELECT crates.crate_id, badge.badge_type, badge.badge_params
+        FROM crates, unnest($1::TEXT[], $2::JSONB[]) AS badge(badge_type, badge_params)
+        WHERE crates.original_name = $3",
+        &badge_types,
+        &badge_params,
+        crate_name.original_str()
+    )
+    .execute(exec)
+    .await?;
+    Ok(())
+}
+/// `None` if the crate doesn't exist
+pub async fn get_badges(
+    crate_name: &CrateName,
+    exec: &mut PgConnection,
+) -> Result<Option<Vec<(String, serde_json::Value)>>, sqlx::Error> {
+    if !crate_exists_exact(crate_name, &mut *exec).await? {
+        return Ok(None);
+    }
+    Ok(Some(
+        sqlx::query!(
+            "SELECT badge_type, badge_params
+            FROM crate_badges
+            JOIN crates ON crates.crate_id = crate_badges.crate_id
+            WHERE crates.original_name = $1
+            ORDER BY badge_type",
+            crate_name.original_str()
+        )
+        .fetch_all(exec)
+        .await?
+        .into_iter()
+        .map(|record| (record.badge_type, record.badge_params))
+        .collect(),
+    ))
+}
+/// `published_by` is `None` for unauthenticated publishes
 pub async fn add_version(
     metadata: &Metadata,
     cksum: &str,
+    file_size: i64,
+    published_by: Option<UserId>,
+    readme: Option<&str>,
     exec: &mut PgConnection,
 ) -> Result<(), sqlx::Error> {
     sqlx::query!(
-        "INSERT INTO versions (crate, vers, cksum, links, rust_version)
-        SELECT crates.crate_id, $1, $2, $3, $4
+        "INSERT INTO versions (crate, vers, cksum, links, rust_version, published_by, file_size, deps, readme)
+        SELECT crates.crate_id, $1, $2, $3, $4, $5, $6, $7, $8
         FROM crates
-        WHERE crates.original_name = $5",
-        metadata.vers.to_string(),
+        WHERE crates.original_name = $9",
+        without_build_metadata(&metadata.vers).to_string(),
         cksum,
         metadata.links,
         metadata.rust_version.as_ref().map(|rv| rv.to_string()),
+        published_by.map(|UserId(id)| id),
+        file_size,
+        Json(metadata.deps.iter().map(Dependency::from).collect::<Vec<_>>()) as _,
+        readme,
+        metadata.name.original_str()
+    )
+    .execute(&mut *exec)
+    .await?;
+    // Same transaction, so the same time as the version's created_at
+    sqlx::query!(
+        "UPDATE crates SET updated_at = NOW() WHERE original_name = $1",
         metadata.name.original_str()
     )
     .execute(&mut *exec)
     .await?;
     // features2 is empty
-    for (feature, feature_deps) in &metadata.features {
-        sqlx::query!(
-            "INSERT INTO version_features (crate_id, crate_version, feature_name)
-            SELECT crates.crate_id, $1, $2
-            FROM crates
-            WHERE crates.original_name = $3",
-            metadata.vers.to_string(),
-            feature.as_ref(),
-            metadata.name.original_str()
-        )
-        .execute(&mut *exec)
-        .await?;
-        for dependency_name in feature_deps {
-            sqlx::query!(
-                "INSERT INTO feature_dependencies (crate_id, crate_version, feature_name, dependency_name)
-                SELECT crates.crate_id, $1, $2, $3
-                FROM crates
-                WHERE crates.original_name = $4",
-                metadata.vers.to_string(),
-                feature.as_ref(),
-                dependency_name,
-                metadata.name.original_str(),
-            )
-            .execute(&mut *exec)
-            .await?;
-        }
-    }
-    for author in &metadata.authors {
+    // One statement per table instead of one per row, the ordinality keeps rows in manifest order
+    let feature_names: Vec<&str> = metadata.features.keys().map(AsRef::as_ref).collect();
+    sqlx::query!(
+        "INSERT INTO version_features (crate_id, crate_version, feature_name)
+        SELECT crates.crate_id, $1, feature.name
+        FROM crates, unnest($2::text[]) WITH ORDINALITY AS feature(name, position)
+        WHERE crates.original_name = $3
+        ORDER BY feature.position",
+        without_build_metadata(&metadata.vers).to_string(),
+        &feature_names as &[&str],
+        metadata.name.original_str()
+    )
+    .execute(&mut *exec)
+    .await?;
+    let (dependency_features, dependency_names): (Vec<&str>, Vec<&str>) = metadata
+        .features
+        .iter()
+        .flat_map(|(feature, feature_deps)| {
+            feature_deps
+                .iter()
+                .map(move |dependency_name| (feature.as_ref(), dependency_name.as_str()))
+        })
+        .unzip();
+    sqlx::query!(
+        "INSERT INTO feature_dependencies (crate_id, crate_version, feature_name, dependency_name, position)
+        SELECT crates.crate_id, $1, dependency.feature_name, dependency.name, dependency.position
+        FROM crates, unnest($2::text[], $3::text[])
+            WITH ORDINALITY AS dependency(feature_name, name, position)
+        WHERE crates.original_name = $4",
+        without_build_metadata(&metadata.vers).to_string(),
+        &dependency_features as &[&str],
+        &dependency_names as &[&str],
+        metadata.name.original_str(),
+    )
+    .execute(&mut *exec)
+    .await?;
+    let parsed_authors: Vec<Author> = metadata
+        .authors
+        .iter()
+        .map(|author| Author::parse(author))
+        .collect();
+    sqlx::query!(
+        "INSERT INTO version_authors (crate_id, version, author, author_name, author_email)
+        SELECT crates.crate_id, $1, author.author, author.name, author.email
+        FROM crates, unnest($2::text[], $3::text[], $4::text[])
+            WITH ORDINALITY AS author(author, name, email, position)
+        WHERE crates.original_name = $5
+        ORDER BY author.position",
+        without_build_metadata(&metadata.vers).to_string(),
+        &metadata.authors,
+        &parsed_authors
+            .iter()
+            .map(|author| author.name)
+            .collect::<Vec<_>>() as &[Option<&str>],
+        &parsed_authors
+            .iter()
+            .map(|author| author.email)
+            .collect::<Vec<_>>() as &[Option<&str>],
+        metadata.name.original_str(),
+    )
+    .execute(&mut *exec)
+    .await?;
+    // Dependencies on other registries can't be reverse dependencies here
+    for dependency in metadata.deps.iter().filter(|dep| dep.registry.is_none()) {
         sqlx::query!(
-            "INSERT INTO version_authors (crate_id, version, author)
-            SELECT crates.crate_id, $1, $2
+            "INSERT INTO version_dependencies (crate_id, vers, dependency_name, version_req)
+            SELECT crates.crate_id, $1, $2, $3
             FROM crates
-            WHERE crates.original_name = $3",
-            metadata.vers.to_string(),
-            author,
+            WHERE crates.original_name = $4",
+            without_build_metadata(&metadata.vers).to_string(),
+            dependency.name.original_str(),
+            dependency.version_req.to_string(),
             metadata.name.original_str(),
         )
         .execute(&mut *exec)
@@ -205,12 +446,155 @@ pub async fn add_version(
     }
     Ok(())
 }
+pub async fn version_exists(
+    crate_name: &CrateName,
+    version: &semver::Version,
+    exec: impl Executor<'_, Database = Postgres>,
+) -> Result<bool, sqlx::Error> {
+    let res = sqlx::query!(
+        "SELECT EXISTS(SELECT 1 FROM versions
+        JOIN crates ON versions.crate = crates.crate_id
+        WHERE crates.original_name = $1 AND versions.vers = $2)",
+        crate_name.original_str(),
+        without_build_metadata(version).to_string()
+    )
+    .fetch_one(exec)
+    .await?;
+    Ok(res.exists.unwrap())
+}
+pub async fn get_version_cksum(
+    crate_name: &CrateName,
+    version: &semver::Version,
+    exec: impl Executor<'_, Database = Postgres>,
+) -> Result<Option<String>, sqlx::Error> {
+    Ok(sqlx::query!(
+        "SELECT cksum FROM versions
+        JOIN crates ON versions.crate = crates.crate_id
+        WHERE crates.original_name = $1 AND versions.vers = $2",
+        crate_name.original_str(),
+        without_build_metadata(version).to_string()
+    )
+    .fetch_optional(exec)
+    .await?
+    .map(|res| res.cksum))
+}
+/// `None` if the version doesn't exist, `Some(None)` if its dependencies weren't stored
+pub async fn get_version_deps(
+    crate_name: &CrateName,
+    version: &semver::Version,
+    exec: impl Executor<'_, Database = Postgres>,
+) -> Result<Option<Option<Vec<Dependency>>>, sqlx::Error> {
+    Ok(sqlx::query!(
+        r#"SELECT deps AS "deps: Json<Vec<Dependency>>" FROM versions
+        JOIN crates ON versions.crate = crates.crate_id
+        WHERE crates.original_name = $1 AND versions.vers = $2"#,
+        crate_name.original_str(),
+        without_build_metadata(version).to_string()
+    )
+    .fetch_optional(exec)
+    .await?
+    .map(|record| record.deps.map(|Json(deps)| deps)))
+}
+/// `None` if the version doesn't exist, `Some(None)` if it has no stored README
+pub async fn get_version_readme(
+    crate_name: &CrateName,
+    version: &semver::Version,
+    exec: impl Executor<'_, Database = Postgres>,
+) -> Result<Option<Option<String>>, sqlx::Error> {
+    Ok(sqlx::query!(
+        "SELECT versions.readme FROM versions
+        JOIN crates ON versions.crate = crates.crate_id
+        WHERE crates.original_name = $1 AND versions.vers = $2",
+        crate_name.original_str(),
+        without_build_metadata(version).to_string()
+    )
+    .fetch_optional(exec)
+    .await?
+    .map(|record| record.readme))
+}
+#[derive(Debug)]
+/// The parts of a version its index line has to agree with
+pub struct StoredVersion {
+    pub crate_name: String,
+    pub vers: String,
+    pub cksum: String,
+    pub yanked: bool,
+    /// `None` for versions published before dependencies were stored
+    pub deps: Option<Vec<Dependency>>,
+}
+/// Every version in the registry, grouped by crate in publish order
+pub async fn get_stored_versions(
+    exec: impl Executor<'_, Database = Postgres>,
+) -> Result<Vec<StoredVersion>, sqlx::Error> {
+    Ok(sqlx::query!(
+        r#"SELECT crates.original_name, versions.vers, versions.cksum, versions.yanked,
+        versions.deps AS "deps: Json<Vec<Dependency>>"
+        FROM versions
+        JOIN crates ON versions.crate = crates.crate_id
+        ORDER BY crates.original_name, versions.created_at NULLS FIRST, versions.vers"#
+    )
+    .fetch_all(exec)
+    .await?
+    .into_iter()
+    .map(|record| StoredVersion {
+        crate_name: record.original_name,
+        vers: record.vers,
+        cksum: record.cksum,
+        yanked: record.yanked,
+        deps: record.deps.map(|Json(deps)| deps),
+    })
+    .collect())
+}
+/// Features of the version with the features they enable, `None` if the version doesn't exist
+pub async fn get_version_features(
+    crate_name: &CrateName,
+    version: &semver::Version,
+    exec: impl Executor<'_, Database = Postgres>,
+) -> Result<Option<BTreeMap<String, Vec<String>>>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"SELECT version_features.feature_name AS "feature_name?",
+            feature_dependencies.dependency_name AS "dependency_name?"
+        FROM versions
+        JOIN crates ON versions.crate = crates.crate_id
+        LEFT JOIN version_features
+            ON version_features.crate_id = versions.crate
+            AND version_features.crate_version = versions.vers
+        LEFT JOIN feature_dependencies
+            ON feature_dependencies.crate_id = version_features.crate_id
+            AND feature_dependencies.crate_version = version_features.crate_version
+            AND feature_dependencies.feature_name = version_features.feature_name
+        WHERE crates.original_name = $1 AND versions.vers = $2
+        ORDER BY feature_dependencies.position"#,
+        crate_name.original_str(),
+        without_build_metadata(version).to_string()
+    )
+    .fetch_all(exec)
+    .await?;
+    if rows.is_empty() {
+        return Ok(None);
+    }
+    let mut features = BTreeMap::<String, Vec<String>>::new();
+    for row in rows {
+        let Some(feature_name) = row.feature_name else {
+            continue;
+        };
+        let enabled = features.entry(feature_name).or_default();
+        enabled.extend(row.dependency_name);
+    }
+    Ok(Some(features))
+}
+#[derive(Clone, Debug)]
+pub struct ExistingVersion {
+    pub vers: semver::Version,
+    pub yanked: bool,
+}
+/// Fails with [`sqlx::Error::Decode`] if a stored version doesn't parse
 pub async fn get_versions(
     crate_name: &CrateName,
     exec: &mut PgConnection,
-) -> Result<Vec<semver::Version>, sqlx::Error> {
-    Ok(sqlx::query!(
-        "SELECT vers
+) -> Result<Vec<ExistingVersion>, sqlx::Error> {
+    sqlx::query!(
+        "SELECT vers, yanked
         FROM versions
         JOIN crates
         ON versions.crate = crates.crate_id
@@ -220,13 +604,1780 @@ pub async fn get_versions(
     .fetch_all(exec)
     .await?
     .into_iter()
-    .map(|x| {
-        x.vers
-            .parse()
-            .expect("hope all the database contents are valid")
+    .map(|record| {
+        Ok(ExistingVersion {
+            vers: parse_stored_version(crate_name, &record.vers)?,
+            yanked: record.yanked,
+        })
     })
+    .collect()
+}
+/// A decode error naming the row, so one corrupted version doesn't panic every request
+fn parse_stored_version(
+    crate_name: impl Display,
+    vers: &str,
+) -> Result<semver::Version, sqlx::Error> {
+    vers.parse().map_err(|e| {
+        sqlx::Error::Decode(
+            format!("invalid version {vers:?} of crate {crate_name} in database: {e}").into(),
+        )
+    })
+}
+
+/// Versions with uploaded docs
+pub async fn get_documented_versions(
+    crate_name: &CrateName,
+    exec: &mut PgConnection,
+) -> Result<Vec<semver::Version>, sqlx::Error> {
+    sqlx::query!(
+        "SELECT vers
+        FROM versions
+        JOIN crates
+        ON versions.crate = crates.crate_id
+        WHERE crates.original_name = $1 AND versions.has_docs",
+        crate_name.original_str()
+    )
+    .fetch_all(exec)
+    .await?
+    .into_iter()
+    .map(|record| parse_stored_version(crate_name, &record.vers))
+    .collect()
+}
+
+#[derive(Clone, Debug)]
+pub struct VersionState {
+    pub cksum: String,
+    /// Unknown for versions published before publish times were recorded
+    pub created_at: Option<DateTime<Utc>>,
+    pub yanked: bool,
+    pub downloads: i64,
+}
+pub async fn get_version_state(
+    crate_name: &CrateName,
+    version: &semver::Version,
+    exec: impl Executor<'_, Database = Postgres>,
+) -> Result<Option<VersionState>, sqlx::Error> {
+    Ok(sqlx::query!(
+        r#"SELECT versions.cksum, versions.created_at, versions.yanked,
+            (SELECT COALESCE(SUM(count), 0) FROM version_downloads
+            WHERE version_downloads.crate_id = versions.crate
+            AND version_downloads.vers = versions.vers)::BIGINT AS "downloads!"
+        FROM versions
+        JOIN crates ON versions.crate = crates.crate_id
+        WHERE crates.original_name = $1 AND versions.vers = $2"#,
+        crate_name.original_str(),
+        without_build_metadata(version).to_string()
+    )
+    .fetch_optional(exec)
+    .await?
+    .map(|record| VersionState {
+        cksum: record.cksum,
+        created_at: record.created_at,
+        yanked: record.yanked,
+        downloads: record.downloads,
+    }))
+}
+/// Downloads of every version of the crate, `None` if the crate doesn't exist
+pub async fn get_total_downloads(
+    crate_name: &CrateName,
+    exec: impl Executor<'_, Database = Postgres>,
+) -> Result<Option<i64>, sqlx::Error> {
+    Ok(sqlx::query!(
+        r#"SELECT (SELECT COALESCE(SUM(count), 0) FROM version_downloads
+            WHERE version_downloads.crate_id = crates.crate_id)::BIGINT AS "total!"
+        FROM crates
+        WHERE crates.original_name = $1"#,
+        crate_name.original_str()
+    )
+    .fetch_optional(exec)
+    .await?
+    .map(|record| record.total))
+}
+/// Downloads of the version on all days, `None` if it doesn't exist
+pub async fn get_version_download_count(
+    crate_name: &CrateName,
+    version: &semver::Version,
+    exec: impl Executor<'_, Database = Postgres>,
+) -> Result<Option<i64>, sqlx::Error> {
+    Ok(sqlx::query!(
+        r#"SELECT (SELECT COALESCE(SUM(count), 0) FROM version_downloads
+            WHERE version_downloads.crate_id = versions.crate
+            AND version_downloads.vers = versions.vers)::BIGINT AS "downloads!"
+        FROM versions
+        JOIN crates ON versions.crate = crates.crate_id
+        WHERE crates.original_name = $1 AND versions.vers = $2"#,
+        crate_name.original_str(),
+        without_build_metadata(version).to_string()
+    )
+    .fetch_optional(exec)
+    .await?
+    .map(|record| record.downloads))
+}
+pub async fn count_authors(
+    crate_name: &CrateName,
+    version: &semver::Version,
+    exec: impl Executor<'_, Database = Postgres>,
+) -> Result<i64, sqlx::Error> {
+    Ok(sqlx::query!(
+        r#"SELECT COUNT(*) AS "count!" FROM version_authors
+        JOIN crates ON version_authors.crate_id = crates.crate_id
+        WHERE crates.original_name = $1 AND version_authors.version = $2"#,
+        crate_name.original_str(),
+        without_build_metadata(version).to_string()
+    )
+    .fetch_one(exec)
+    .await?
+    .count)
+}
+pub async fn list_authors(
+    crate_name: &CrateName,
+    version: &semver::Version,
+    exec: impl Executor<'_, Database = Postgres>,
+) -> Result<Vec<String>, sqlx::Error> {
+    Ok(sqlx::query!(
+        "SELECT author FROM version_authors
+        JOIN crates ON version_authors.crate_id = crates.crate_id
+        WHERE crates.original_name = $1 AND version_authors.version = $2",
+        crate_name.original_str(),
+        without_build_metadata(version).to_string()
+    )
+    .fetch_all(exec)
+    .await?
+    .into_iter()
+    .map(|record| record.author)
+    .collect())
+}
+/// Returns false if the version doesn't exist
+pub async fn set_version_yanked(
+    crate_name: &CrateName,
+    version: &semver::Version,
+    yanked: bool,
+    exec: impl Executor<'_, Database = Postgres>,
+) -> Result<bool, sqlx::Error> {
+    let res = sqlx::query!(
+        "UPDATE versions SET yanked = $1
+        FROM crates
+        WHERE versions.crate = crates.crate_id
+        AND crates.original_name = $2 AND versions.vers = $3",
+        yanked,
+        crate_name.original_str(),
+        without_build_metadata(version).to_string()
+    )
+    .execute(exec)
+    .await?;
+    Ok(res.rows_affected() > 0)
+}
+pub async fn set_version_has_docs(
+    crate_name: &CrateName,
+    version: &semver::Version,
+    has_docs: bool,
+    exec: impl Executor<'_, Database = Postgres>,
+) -> Result<bool, sqlx::Error> {
+    let res = sqlx::query!(
+        "UPDATE versions SET has_docs = $1
+        FROM crates
+        WHERE versions.crate = crates.crate_id
+        AND crates.original_name = $2 AND versions.vers = $3",
+        has_docs,
+        crate_name.original_str(),
+        without_build_metadata(version).to_string()
+    )
+    .execute(exec)
+    .await?;
+    Ok(res.rows_affected() > 0)
+}
+/// Errors are the caller's business, a missing version is simply not counted
+pub async fn record_download(
+    crate_name: &CrateName,
+    version: &semver::Version,
+    exec: impl Executor<'_, Database = Postgres>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "WITH counted AS (
+            INSERT INTO version_downloads (crate_id, vers, count)
+            SELECT versions.crate, versions.vers, 1
+            FROM versions
+            JOIN crates ON versions.crate = crates.crate_id
+            WHERE crates.original_name = $1 AND versions.vers = $2
+            ON CONFLICT (crate_id, vers, date)
+            DO UPDATE SET count = version_downloads.count + 1
+            RETURNING crate_id
+        )
+        UPDATE crates SET downloads = crates.downloads + 1
+        FROM counted WHERE crates.crate_id = counted.crate_id",
+        crate_name.original_str(),
+        without_build_metadata(version).to_string()
+    )
+    .execute(exec)
+    .await?;
+    Ok(())
+}
+/// Version requirements other crates' versions have on this crate
+pub async fn get_reverse_dependency_reqs(
+    crate_name: &CrateName,
+    exec: impl Executor<'_, Database = Postgres>,
+) -> Result<Vec<String>, sqlx::Error> {
+    Ok(sqlx::query!(
+        "SELECT DISTINCT version_dependencies.version_req
+        FROM version_dependencies
+        JOIN crates ON version_dependencies.crate_id = crates.crate_id
+        WHERE normalize_crate_name(version_dependencies.dependency_name) = $1
+        AND crates.original_name <> $2",
+        crate_name.normalized() as _,
+        crate_name.original_str()
+    )
+    .fetch_all(exec)
+    .await?
+    .into_iter()
+    .map(|record| record.version_req)
     .collect())
 }
+/// Removes the version with everything attached to it, and the crate if it was the last version
+///
+/// Returns whether the crate was removed.
+pub async fn delete_version(
+    crate_name: &CrateName,
+    version: &semver::Version,
+    exec: &mut PgConnection,
+) -> Result<bool, sqlx::Error> {
+    let crate_id = sqlx::query!(
+        "SELECT crate_id FROM crates WHERE original_name = $1",
+        crate_name.original_str()
+    )
+    .fetch_one(&mut *exec)
+    .await?
+    .crate_id;
+    let vers = without_build_metadata(version).to_string();
+    // Frees the storage of the publisher
+    sqlx::query!(
+        "UPDATE user_storage SET used_bytes = GREATEST(used_bytes - versions.file_size, 0)
+        FROM versions
+        WHERE versions.crate = $1 AND versions.vers = $2
+        AND user_storage.user_id = versions.published_by",
+        crate_id,
+        vers
+    )
+    .execute(&mut *exec)
+    .await?;
+    // Features, authors, downloads and dependencies are deleted with it
+    sqlx::query!(
+        "DELETE FROM versions WHERE crate = $1 AND vers = $2",
+        crate_id,
+        vers
+    )
+    .execute(&mut *exec)
+    .await?;
+    let versions_left = sqlx::query!(
+        "SELECT EXISTS(SELECT 1 FROM versions WHERE crate = $1)",
+        crate_id
+    )
+    .fetch_one(&mut *exec)
+    .await?
+    .exists
+    .unwrap();
+    if versions_left {
+        // The deleted version's downloads are gone and it may have been the newest
+        sqlx::query!(
+            "UPDATE crates SET
+                downloads = (SELECT COALESCE(SUM(count), 0) FROM version_downloads
+                    WHERE version_downloads.crate_id = $1),
+                updated_at = (SELECT MAX(created_at) FROM versions WHERE versions.crate = $1)
+            WHERE crate_id = $1",
+            crate_id
+        )
+        .execute(&mut *exec)
+        .await?;
+        return Ok(false);
+    }
+    sqlx::query!("DELETE FROM crates WHERE crate_id = $1", crate_id)
+        .execute(&mut *exec)
+        .await?;
+    Ok(true)
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct DeletedCrate {
+    pub versions: i64,
+    /// Versions published before file sizes were recorded count as 0 bytes
+    pub bytes: i64,
+}
+/// Removes the crate with all its versions, `None` if it doesn't exist
+pub async fn delete_crate(
+    crate_name: &CrateName,
+    exec: &mut PgConnection,
+) -> Result<Option<DeletedCrate>, sqlx::Error> {
+    let Some(crate_id) = sqlx::query!(
+        "SELECT crate_id FROM crates WHERE original_name = $1 FOR UPDATE",
+        crate_name.original_str()
+    )
+    .fetch_optional(&mut *exec)
+    .await?
+    .map(|record| record.crate_id) else {
+        return Ok(None);
+    };
+    let removed = sqlx::query!(
+        r#"SELECT COUNT(*) AS "versions!", COALESCE(SUM(file_size), 0)::BIGINT AS "bytes!"
+        FROM versions WHERE crate = $1"#,
+        crate_id
+    )
+    .fetch_one(&mut *exec)
+    .await?;
+    // Frees the storage of every publisher
+    sqlx::query!(
+        "UPDATE user_storage SET used_bytes = GREATEST(used_bytes - published.bytes, 0)
+        FROM (SELECT published_by, SUM(file_size) AS bytes FROM versions
+            WHERE crate = $1 GROUP BY published_by) AS published
+        WHERE user_storage.user_id = published.published_by",
+        crate_id
+    )
+    .execute(&mut *exec)
+    .await?;
+    // Everything else attached to the crate is deleted with it
+    sqlx::query!("DELETE FROM crates WHERE crate_id = $1", crate_id)
+        .execute(&mut *exec)
+        .await?;
+    Ok(Some(DeletedCrate {
+        versions: removed.versions,
+        bytes: removed.bytes,
+    }))
+}
+
+pub async fn add_pending_publish(
+    entry: &IndexEntry,
+    exec: impl Executor<'_, Database = Postgres>,
+) -> Result<PendingPublishId, sqlx::Error> {
+    let record = sqlx::query!(
+        "INSERT INTO pending_publishes (crate_name, vers, index_line)
+        VALUES ($1, $2, $3)
+        RETURNING pending_id",
+        entry.name.original_str(),
+        entry.vers.to_string(),
+        entry.line()
+    )
+    .fetch_one(exec)
+    .await?;
+    Ok(PendingPublishId(record.pending_id))
+}
+pub async fn delete_pending_publish(
+    id: PendingPublishId,
+    exec: impl Executor<'_, Database = Postgres>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!("DELETE FROM pending_publishes WHERE pending_id = $1", id.0)
+        .execute(exec)
+        .await?;
+    Ok(())
+}
+/// Oldest first
+///
+/// Rows with an invalid crate name or version are logged and skipped, so they can't keep the
+/// server from starting.
+pub async fn get_pending_publishes(
+    exec: impl Executor<'_, Database = Postgres>,
+) -> Result<Vec<(PendingPublishId, IndexEntry)>, sqlx::Error> {
+    Ok(sqlx::query!(
+        "SELECT pending_id, crate_name, vers, index_line
+        FROM pending_publishes
+        ORDER BY pending_id"
+    )
+    .fetch_all(exec)
+    .await?
+    .into_iter()
+    .filter_map(|record| {
+        let (Ok(name), Ok(vers)) = (record.crate_name.parse(), record.vers.parse()) else {
+            eprintln!(
+                "Skipping pending publish {} with invalid crate {:?} version {:?}",
+                record.pending_id, record.crate_name, record.vers
+            );
+            return None;
+        };
+        Some((
+            PendingPublishId(record.pending_id),
+            IndexEntry::from_line(name, vers, record.index_line),
+        ))
+    })
+    .collect())
+}
+
+pub async fn add_audit_event(
+    event: AuditEvent<'_>,
+    exec: impl Executor<'_, Database = Postgres>,
+) -> Result<(), sqlx::Error> {
+    let (outcome, error_category) = match event.outcome {
+        AuditOutcome::Success => ("success", None),
+        AuditOutcome::Failure(category) => ("failure", Some(category)),
+    };
+    sqlx::query!(
+        "INSERT INTO audit_events
+        (actor, action, crate_name, version, request_id, outcome, error_category)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        event.context.actor,
+        event.action.as_str(),
+        event.crate_name.map(CrateName::original_str),
+        event.version.map(ToString::to_string),
+        event.context.request_id.0,
+        outcome,
+        error_category,
+    )
+    .execute(exec)
+    .await?;
+    Ok(())
+}
+/// Newest first, filtered by exact crate name and start time. Returns the page and total count.
+pub async fn get_audit_events(
+    crate_name: Option<&str>,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+    limit: i64,
+    offset: i64,
+    exec: impl Executor<'_, Database = Postgres>,
+) -> Result<(Vec<AuditRecord>, i64), sqlx::Error> {
+    let records = sqlx::query!(
+        r#"SELECT event_id, occurred_at, actor, action, crate_name, version,
+        request_id, outcome, error_category, COUNT(*) OVER () AS "total!"
+        FROM audit_events
+        WHERE ($1::TEXT IS NULL OR crate_name = $1)
+        AND ($2::TIMESTAMPTZ IS NULL OR occurred_at >= $2)
+        AND ($3::TIMESTAMPTZ IS NULL OR occurred_at < $3)
+        ORDER BY occurred_at DESC, event_id DESC
+        LIMIT $4 OFFSET $5"#,
+        crate_name,
+        since,
+        until,
+        limit,
+        offset
+    )
+    .fetch_all(exec)
+    .await?;
+    let total = records.first().map_or(0, |record| record.total);
+    Ok((
+        records
+            .into_iter()
+            .map(|record| AuditRecord {
+                event_id: record.event_id,
+                occurred_at: record.occurred_at,
+                actor: record.actor,
+                action: record.action,
+                crate_name: record.crate_name,
+                version: record.version,
+                request_id: record.request_id,
+                outcome: record.outcome,
+                error_category: record.error_category,
+            })
+            .collect(),
+        total,
+    ))
+}
+/// Other crates whose normalized name is at most `max_distance` edits away
+pub async fn get_similar_crate_names(
+    crate_name: &CrateName,
+    max_distance: i32,
+    exec: impl Executor<'_, Database = Postgres>,
+) -> Result<Vec<String>, sqlx::Error> {
+    Ok(sqlx::query!(
+        "SELECT original_name
+        FROM crates
+        WHERE levenshtein(normalize_crate_name(original_name), $1) <= $2
+        AND normalize_crate_name(original_name) != $1
+        ORDER BY original_name",
+        crate_name.normalized() as _,
+        max_distance
+    )
+    .fetch_all(exec)
+    .await?
+    .into_iter()
+    .map(|record| record.original_name)
+    .collect())
+}
+pub async fn get_user_by_token_hash(
+    token_hash: &str,
+    exec: &mut PgConnection,
+) -> Result<Option<AuthenticatedUser>, sqlx::Error> {
+    Ok(sqlx::query!(
+        "SELECT users.user_id, users.login, tokens.token_id
+        FROM tokens
+        JOIN users ON users.user_id = tokens.user_id
+        WHERE tokens.token_hash = $1
+        AND (tokens.expires_at IS NULL OR tokens.expires_at > NOW())",
+        token_hash
+    )
+    .fetch_optional(exec)
+    .await?
+    .map(|record| AuthenticatedUser {
+        user_id: UserId(record.user_id),
+        login: record.login,
+        token_id: TokenId(record.token_id),
+    }))
+}
+/// Returns how many tokens were deleted
+pub async fn delete_expired_tokens(
+    exec: impl Executor<'_, Database = Postgres>,
+) -> Result<u64, sqlx::Error> {
+    Ok(
+        sqlx::query!("DELETE FROM tokens WHERE expires_at IS NOT NULL AND expires_at < NOW()")
+            .execute(exec)
+            .await?
+            .rows_affected(),
+    )
+}
+pub async fn set_token_last_used(
+    TokenId(token_id): TokenId,
+    exec: impl Executor<'_, Database = Postgres>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE tokens SET last_used_at = NOW() WHERE token_id = $1",
+        token_id
+    )
+    .execute(exec)
+    .await?;
+    Ok(())
+}
+#[derive(Clone, Copy, Debug)]
+pub struct TokenExpiry {
+    pub token_id: TokenId,
+    /// `None` for tokens that never expire
+    pub expires_at: Option<DateTime<Utc>>,
+    pub expired: bool,
+    pub self_refresh: bool,
+}
+/// Locks the token's row until the transaction ends, `None` if no token has the hash
+pub async fn get_token_expiry(
+    token_hash: &str,
+    exec: &mut PgConnection,
+) -> Result<Option<TokenExpiry>, sqlx::Error> {
+    Ok(sqlx::query!(
+        r#"SELECT token_id, expires_at, COALESCE(expires_at <= NOW(), FALSE) AS "expired!",
+        self_refresh
+        FROM tokens
+        WHERE token_hash = $1
+        FOR UPDATE"#,
+        token_hash
+    )
+    .fetch_optional(exec)
+    .await?
+    .map(|record| TokenExpiry {
+        token_id: TokenId(record.token_id),
+        expires_at: record.expires_at,
+        expired: record.expired,
+        self_refresh: record.self_refresh,
+    }))
+}
+/// Returns the new expiry, `lifetime` from now
+pub async fn extend_token_expiry(
+    TokenId(token_id): TokenId,
+    lifetime: Duration,
+    exec: &mut PgConnection,
+) -> Result<DateTime<Utc>, sqlx::Error> {
+    Ok(sqlx::query!(
+        r#"UPDATE tokens SET expires_at = NOW() + $2::BIGINT * INTERVAL '1 second'
+        WHERE token_id = $1
+        RETURNING expires_at AS "expires_at!""#,
+        token_id,
+        i64::try_from(lifetime.as_secs()).unwrap_or(i64::MAX)
+    )
+    .fetch_one(exec)
+    .await?
+    .expires_at)
+}
+pub async fn is_crate_owner(
+    crate_name: &CrateName,
+    user_id: UserId,
+    exec: &mut PgConnection,
+) -> Result<bool, sqlx::Error> {
+    let res = sqlx::query!(
+        "SELECT EXISTS(SELECT 1 FROM crate_owners
+        JOIN crates ON crates.crate_id = crate_owners.crate_id
+        WHERE crates.original_name = $1 AND crate_owners.user_id = $2)",
+        crate_name.original_str(),
+        user_id.0
+    )
+    .fetch_one(exec)
+    .await?;
+    Ok(res.exists.unwrap())
+}
+/// Ownership through a team counts as well, in a single query
+pub async fn is_crate_owner_or_team_member(
+    crate_name: &CrateName,
+    user_id: UserId,
+    exec: &mut PgConnection,
+) -> Result<bool, sqlx::Error> {
+    let res = sqlx::query!(
+        "SELECT EXISTS(SELECT 1 FROM crate_owners
+        JOIN crates ON crates.crate_id = crate_owners.crate_id
+        LEFT JOIN team_members ON team_members.team_id = crate_owners.team_id
+            AND team_members.user_id = $2
+        WHERE crates.original_name = $1
+        AND (crate_owners.user_id = $2 OR team_members.user_id IS NOT NULL))",
+        crate_name.original_str(),
+        user_id.0
+    )
+    .fetch_one(exec)
+    .await?;
+    Ok(res.exists.unwrap())
+}
+pub async fn get_user_id_by_login(
+    login: &str,
+    exec: &mut PgConnection,
+) -> Result<Option<UserId>, sqlx::Error> {
+    Ok(
+        sqlx::query!("SELECT user_id FROM users WHERE login = $1", login)
+            .fetch_optional(exec)
+            .await?
+            .map(|record| UserId(record.user_id)),
+    )
+}
+/// Ids and logins of the users with the email, compared case-insensitively
+pub async fn get_users_by_email(
+    email: &str,
+    exec: &mut PgConnection,
+) -> Result<Vec<(UserId, String)>, sqlx::Error> {
+    Ok(sqlx::query!(
+        "SELECT user_id, login FROM users WHERE lower(email) = lower($1) ORDER BY user_id",
+        email
+    )
+    .fetch_all(exec)
+    .await?
+    .into_iter()
+    .map(|record| (UserId(record.user_id), record.login))
+    .collect())
+}
+/// Users and teams owning the crate, ordered by login
+pub async fn get_owners(
+    crate_name: &CrateName,
+    exec: &mut PgConnection,
+) -> Result<Vec<Owner>, sqlx::Error> {
+    Ok(sqlx::query!(
+        r#"SELECT users.user_id AS "id!", users.login AS "login!", NULL AS name, FALSE AS "is_team!"
+        FROM crate_owners
+        JOIN crates ON crates.crate_id = crate_owners.crate_id
+        JOIN users ON users.user_id = crate_owners.user_id
+        WHERE crates.original_name = $1
+        UNION ALL
+        SELECT teams.team_id, teams.login, teams.name, TRUE
+        FROM crate_owners
+        JOIN crates ON crates.crate_id = crate_owners.crate_id
+        JOIN teams ON teams.team_id = crate_owners.team_id
+        WHERE crates.original_name = $1
+        ORDER BY 2"#,
+        crate_name.original_str()
+    )
+    .fetch_all(exec)
+    .await?
+    .into_iter()
+    .map(|record| Owner {
+        id: record.id,
+        login: record.login,
+        name: record.name,
+        kind: if record.is_team {
+            OwnerKind::Team
+        } else {
+            OwnerKind::User
+        },
+    })
+    .collect())
+}
+/// Adding an existing owner again is a no-op
+pub async fn add_owner(
+    crate_name: &CrateName,
+    user_id: UserId,
+    exec: &mut PgConnection,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "INSERT INTO crate_owners (crate_id, user_id)
+        SELECT crate_id, $1 FROM crates WHERE original_name = $2
+        ON CONFLICT DO NOTHING",
+        user_id.0,
+        crate_name.original_str()
+    )
+    .execute(exec)
+    .await?;
+    Ok(())
+}
+pub async fn remove_owner(
+    crate_name: &CrateName,
+    user_id: UserId,
+    exec: &mut PgConnection,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM crate_owners
+        USING crates
+        WHERE crates.crate_id = crate_owners.crate_id
+        AND crates.original_name = $1 AND crate_owners.user_id = $2",
+        crate_name.original_str(),
+        user_id.0
+    )
+    .execute(exec)
+    .await?;
+    Ok(())
+}
+/// Removes users and teams alike
+pub async fn remove_all_owners(
+    crate_name: &CrateName,
+    exec: &mut PgConnection,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM crate_owners
+        USING crates
+        WHERE crates.crate_id = crate_owners.crate_id
+        AND crates.original_name = $1",
+        crate_name.original_str()
+    )
+    .execute(exec)
+    .await?;
+    Ok(())
+}
+/// Adding an owning team again is a no-op
+pub async fn add_team_owner(
+    crate_name: &CrateName,
+    team_id: TeamId,
+    exec: &mut PgConnection,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "INSERT INTO crate_owners (crate_id, team_id)
+        SELECT crate_id, $1 FROM crates WHERE original_name = $2
+        ON CONFLICT DO NOTHING",
+        team_id.0,
+        crate_name.original_str()
+    )
+    .execute(exec)
+    .await?;
+    Ok(())
+}
+pub async fn remove_team_owner(
+    crate_name: &CrateName,
+    team_id: TeamId,
+    exec: &mut PgConnection,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM crate_owners
+        USING crates
+        WHERE crates.crate_id = crate_owners.crate_id
+        AND crates.original_name = $1 AND crate_owners.team_id = $2",
+        crate_name.original_str(),
+        team_id.0
+    )
+    .execute(exec)
+    .await?;
+    Ok(())
+}
+pub async fn get_team_id_by_login(
+    login: &str,
+    exec: &mut PgConnection,
+) -> Result<Option<TeamId>, sqlx::Error> {
+    Ok(
+        sqlx::query!("SELECT team_id FROM teams WHERE login = $1", login)
+            .fetch_optional(exec)
+            .await?
+            .map(|record| TeamId(record.team_id)),
+    )
+}
+/// Creates the team, or updates its name if it exists
+pub async fn upsert_team(
+    login: &str,
+    name: Option<&str>,
+    exec: &mut PgConnection,
+) -> Result<TeamId, sqlx::Error> {
+    Ok(TeamId(
+        sqlx::query!(
+            "INSERT INTO teams (login, name) VALUES ($1, $2)
+            ON CONFLICT (login) DO UPDATE SET name = EXCLUDED.name
+            RETURNING team_id",
+            login,
+            name
+        )
+        .fetch_one(exec)
+        .await?
+        .team_id,
+    ))
+}
+pub async fn is_team_member(
+    team_id: TeamId,
+    user_id: UserId,
+    exec: &mut PgConnection,
+) -> Result<bool, sqlx::Error> {
+    let res = sqlx::query!(
+        "SELECT EXISTS(SELECT 1 FROM team_members WHERE team_id = $1 AND user_id = $2)",
+        team_id.0,
+        user_id.0
+    )
+    .fetch_one(exec)
+    .await?;
+    Ok(res.exists.unwrap())
+}
+/// Members of the team as `(user id, login)`, ordered by login
+pub async fn get_team_members(
+    team_id: TeamId,
+    exec: &mut PgConnection,
+) -> Result<Vec<(UserId, String)>, sqlx::Error> {
+    Ok(sqlx::query!(
+        "SELECT users.user_id, users.login
+        FROM team_members
+        JOIN users ON users.user_id = team_members.user_id
+        WHERE team_members.team_id = $1
+        ORDER BY users.login",
+        team_id.0
+    )
+    .fetch_all(exec)
+    .await?
+    .into_iter()
+    .map(|record| (UserId(record.user_id), record.login))
+    .collect())
+}
+/// Adding an existing member again is a no-op
+pub async fn add_team_member(
+    team_id: TeamId,
+    user_id: UserId,
+    exec: &mut PgConnection,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "INSERT INTO team_members (team_id, user_id) VALUES ($1, $2)
+        ON CONFLICT DO NOTHING",
+        team_id.0,
+        user_id.0
+    )
+    .execute(exec)
+    .await?;
+    Ok(())
+}
+pub async fn remove_team_member(
+    team_id: TeamId,
+    user_id: UserId,
+    exec: &mut PgConnection,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM team_members WHERE team_id = $1 AND user_id = $2",
+        team_id.0,
+        user_id.0
+    )
+    .execute(exec)
+    .await?;
+    Ok(())
+}
+/// Whether a reservation matching the name blocks the user from publishing it,
+/// anonymous users are blocked by every reservation
+pub async fn is_name_reserved_for(
+    crate_name: &CrateName,
+    user_id: Option<UserId>,
+    exec: &mut PgConnection,
+) -> Result<bool, sqlx::Error> {
+    Ok(sqlx::query!(
+        r#"SELECT EXISTS(
+            SELECT 1 FROM reserved_names
+            WHERE (name = $1 OR (is_prefix AND starts_with($1, name)))
+            AND NOT EXISTS(
+                SELECT 1 FROM reserved_name_users
+                WHERE reserved_name_users.reservation_id = reserved_names.reservation_id
+                AND reserved_name_users.user_id = $2
+            )
+        ) AS "reserved!""#,
+        crate_name.normalized() as _,
+        user_id.map(|user_id| user_id.0) as _
+    )
+    .fetch_one(exec)
+    .await?
+    .reserved)
+}
+/// Returns the id of the new reservation, `None` if the pattern is already reserved
+pub async fn add_reserved_name(
+    pattern: &ReservedPattern,
+    exec: &mut PgConnection,
+) -> Result<Option<i32>, sqlx::Error> {
+    Ok(sqlx::query!(
+        "INSERT INTO reserved_names (name, is_prefix) VALUES ($1, $2)
+        ON CONFLICT DO NOTHING
+        RETURNING reservation_id",
+        pattern.name.as_str(),
+        pattern.is_prefix
+    )
+    .fetch_optional(exec)
+    .await?
+    .map(|record| record.reservation_id))
+}
+pub async fn add_reserved_name_user(
+    reservation_id: i32,
+    user_id: UserId,
+    exec: &mut PgConnection,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "INSERT INTO reserved_name_users (reservation_id, user_id) VALUES ($1, $2)
+        ON CONFLICT DO NOTHING",
+        reservation_id,
+        user_id.0
+    )
+    .execute(exec)
+    .await?;
+    Ok(())
+}
+/// All reservations with the logins allowed to publish them, ordered by name
+pub async fn get_reserved_names(exec: &mut PgConnection) -> Result<Vec<ReservedName>, sqlx::Error> {
+    Ok(sqlx::query!(
+        r#"SELECT reserved_names.reservation_id, reserved_names.name, reserved_names.is_prefix,
+            reserved_names.created_at,
+            COALESCE(
+                array_agg(users.login ORDER BY users.login) FILTER (WHERE users.login IS NOT NULL),
+                '{}'
+            ) AS "allowed_users!"
+        FROM reserved_names
+        LEFT JOIN reserved_name_users
+            ON reserved_name_users.reservation_id = reserved_names.reservation_id
+        LEFT JOIN users ON users.user_id = reserved_name_users.user_id
+        GROUP BY reserved_names.reservation_id
+        ORDER BY reserved_names.name, reserved_names.is_prefix"#
+    )
+    .fetch_all(exec)
+    .await?
+    .into_iter()
+    .map(|record| ReservedName {
+        id: record.reservation_id,
+        pattern: if record.is_prefix {
+            format!("{}*", record.name)
+        } else {
+            record.name
+        },
+        allowed_users: record.allowed_users,
+        created_at: record.created_at,
+    })
+    .collect())
+}
+/// Returns whether the reservation existed
+pub async fn delete_reserved_name(
+    reservation_id: i32,
+    exec: &mut PgConnection,
+) -> Result<bool, sqlx::Error> {
+    Ok(sqlx::query!(
+        "DELETE FROM reserved_names WHERE reservation_id = $1",
+        reservation_id
+    )
+    .execute(exec)
+    .await?
+    .rows_affected()
+        > 0)
+}
+/// Adds to the storage the user uses, returning the new total
+///
+/// The row stays locked until the transaction ends, so concurrent publishes of the user wait.
+pub async fn add_storage_usage(
+    user_id: UserId,
+    bytes: i64,
+    exec: &mut PgConnection,
+) -> Result<i64, sqlx::Error> {
+    Ok(sqlx::query!(
+        "INSERT INTO user_storage (user_id, used_bytes) VALUES ($1, $2)
+        ON CONFLICT (user_id) DO UPDATE SET used_bytes = user_storage.used_bytes + EXCLUDED.used_bytes
+        RETURNING used_bytes",
+        user_id.0,
+        bytes
+    )
+    .fetch_one(exec)
+    .await?
+    .used_bytes)
+}
+pub async fn get_storage_usage(
+    user_id: UserId,
+    exec: impl Executor<'_, Database = Postgres>,
+) -> Result<i64, sqlx::Error> {
+    Ok(sqlx::query!(
+        "SELECT used_bytes FROM user_storage WHERE user_id = $1",
+        user_id.0
+    )
+    .fetch_optional(exec)
+    .await?
+    .map_or(0, |record| record.used_bytes))
+}
+/// Creates the invitation, or renews it if the user was invited before
+pub async fn upsert_owner_invitation(
+    crate_name: &CrateName,
+    invitee: UserId,
+    inviter: UserId,
+    valid_for: Duration,
+    exec: &mut PgConnection,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "INSERT INTO crate_owner_invitations (crate_id, invitee_id, inviter_id, expires_at)
+        SELECT crate_id, $1, $2, NOW() + $3::BIGINT * INTERVAL '1 second'
+        FROM crates WHERE original_name = $4
+        ON CONFLICT (crate_id, invitee_id) DO UPDATE
+        SET inviter_id = EXCLUDED.inviter_id,
+            created_at = NOW(),
+            expires_at = EXCLUDED.expires_at",
+        invitee.0,
+        inviter.0,
+        i64::try_from(valid_for.as_secs()).unwrap_or(i64::MAX),
+        crate_name.original_str()
+    )
+    .execute(exec)
+    .await?;
+    Ok(())
+}
+#[derive(Clone, Debug)]
+pub struct OwnerInvitation {
+    pub crate_id: i32,
+    pub crate_name: String,
+    pub inviter_id: UserId,
+    pub inviter_login: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+/// Unexpired invitations of `invitee`, oldest first
+pub async fn get_owner_invitations(
+    invitee: UserId,
+    exec: &mut PgConnection,
+) -> Result<Vec<OwnerInvitation>, sqlx::Error> {
+    Ok(sqlx::query!(
+        "SELECT crates.crate_id, crates.original_name, users.user_id, users.login,
+        crate_owner_invitations.created_at, crate_owner_invitations.expires_at
+        FROM crate_owner_invitations
+        JOIN crates ON crates.crate_id = crate_owner_invitations.crate_id
+        JOIN users ON users.user_id = crate_owner_invitations.inviter_id
+        WHERE crate_owner_invitations.invitee_id = $1
+        AND crate_owner_invitations.expires_at > NOW()
+        ORDER BY crate_owner_invitations.created_at",
+        invitee.0
+    )
+    .fetch_all(exec)
+    .await?
+    .into_iter()
+    .map(|record| OwnerInvitation {
+        crate_id: record.crate_id,
+        crate_name: record.original_name,
+        inviter_id: UserId(record.user_id),
+        inviter_login: record.login,
+        created_at: record.created_at,
+        expires_at: record.expires_at,
+    })
+    .collect())
+}
+#[derive(Clone, Debug)]
+pub struct PendingInvitation {
+    pub invitee_id: UserId,
+    pub invitee_login: String,
+    pub inviter_id: UserId,
+    pub inviter_login: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+/// Unexpired invitations to own the crate, oldest first
+pub async fn get_crate_owner_invitations(
+    crate_name: &CrateName,
+    exec: &mut PgConnection,
+) -> Result<Vec<PendingInvitation>, sqlx::Error> {
+    Ok(sqlx::query!(
+        r#"SELECT invitees.user_id AS invitee_id, invitees.login AS invitee_login,
+        inviters.user_id AS inviter_id, inviters.login AS inviter_login,
+        crate_owner_invitations.created_at, crate_owner_invitations.expires_at
+        FROM crate_owner_invitations
+        JOIN crates ON crates.crate_id = crate_owner_invitations.crate_id
+        JOIN users AS invitees ON invitees.user_id = crate_owner_invitations.invitee_id
+        JOIN users AS inviters ON inviters.user_id = crate_owner_invitations.inviter_id
+        WHERE crates.original_name = $1
+        AND crate_owner_invitations.expires_at > NOW()
+        ORDER BY crate_owner_invitations.created_at"#,
+        crate_name.original_str()
+    )
+    .fetch_all(exec)
+    .await?
+    .into_iter()
+    .map(|record| PendingInvitation {
+        invitee_id: UserId(record.invitee_id),
+        invitee_login: record.invitee_login,
+        inviter_id: UserId(record.inviter_id),
+        inviter_login: record.inviter_login,
+        created_at: record.created_at,
+        expires_at: record.expires_at,
+    })
+    .collect())
+}
+#[derive(Clone, Debug)]
+pub struct TakenInvitation {
+    pub crate_name: String,
+    pub expired: bool,
+}
+/// Deletes the invitation of `invitee` for the crate, `None` if there was none
+pub async fn take_owner_invitation(
+    crate_id: i32,
+    invitee: UserId,
+    exec: &mut PgConnection,
+) -> Result<Option<TakenInvitation>, sqlx::Error> {
+    Ok(sqlx::query!(
+        "DELETE FROM crate_owner_invitations
+        USING crates
+        WHERE crates.crate_id = crate_owner_invitations.crate_id
+        AND crate_owner_invitations.crate_id = $1 AND crate_owner_invitations.invitee_id = $2
+        RETURNING crates.original_name, crate_owner_invitations.expires_at <= NOW() AS \"expired!\"",
+        crate_id,
+        invitee.0
+    )
+    .fetch_optional(exec)
+    .await?
+    .map(|record| TakenInvitation {
+        crate_name: record.original_name,
+        expired: record.expired,
+    }))
+}
+pub async fn set_crate_deprecation(
+    crate_name: &CrateName,
+    deprecation: Option<&Deprecation>,
+    exec: &mut PgConnection,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE crates
+        SET deprecated = $1, deprecation_message = $2, deprecation_replacement = $3
+        WHERE original_name = $4",
+        deprecation.is_some(),
+        deprecation.and_then(|d| d.message.as_deref()),
+        deprecation.and_then(|d| d.replacement.as_ref().map(CrateName::original_str)),
+        crate_name.original_str()
+    )
+    .execute(exec)
+    .await?;
+    Ok(())
+}
+#[derive(Clone, Debug, Default)]
+/// Crate-level fields to overwrite, `None` keeps the current value
+pub struct CrateMetadataUpdate {
+    pub description: Option<Description>,
+    pub homepage: Option<String>,
+    pub repository: Option<String>,
+    pub documentation: Option<String>,
+}
+pub async fn update_crate_metadata(
+    crate_name: &CrateName,
+    update: &CrateMetadataUpdate,
+    exec: &mut PgConnection,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE crates
+        SET description = COALESCE($1, description),
+            homepage = COALESCE($2, homepage),
+            repository = COALESCE($3, repository),
+            documentation = COALESCE($4, documentation)
+        WHERE original_name = $5",
+        update.description.as_deref(),
+        update.homepage,
+        update.repository,
+        update.documentation,
+        crate_name.original_str()
+    )
+    .execute(exec)
+    .await?;
+    Ok(())
+}
+pub async fn get_crate_record(
+    crate_name: &CrateName,
+    exec: &mut PgConnection,
+) -> Result<Option<CrateRecord>, sqlx::Error> {
+    let Some(record) = sqlx::query!(
+        "SELECT original_name, description, documentation, homepage, repository,
+        license, deprecated, deprecation_message, deprecation_replacement
+        FROM crates
+        WHERE original_name = $1",
+        crate_name.original_str()
+    )
+    .fetch_optional(&mut *exec)
+    .await?
+    else {
+        return Ok(None);
+    };
+    let versions = get_versions(crate_name, exec)
+        .await?
+        .into_iter()
+        .map(|version| version.vers)
+        .collect();
+    Ok(Some(CrateRecord {
+        name: record.original_name,
+        description: record.description,
+        documentation: record.documentation,
+        homepage: record.homepage,
+        repository: record.repository,
+        license: record.license,
+        deprecation: deprecation_from_columns(
+            record.deprecated,
+            record.deprecation_message,
+            record.deprecation_replacement,
+        ),
+        versions,
+    }))
+}
+/// Crates matching the words of `query`, ordered by relevance, then downloads and name
+///
+/// Uses the `crates_search_vector` index. Returns the requested page and the total amount of
+/// matches, `None` if the query has no searchable words, like a query of only stop words.
+pub async fn full_text_search_crates(
+    query: &str,
+    filter: &SearchFilter<'_>,
+    limit: i64,
+    offset: i64,
+    exec: &mut PgConnection,
+) -> Result<Option<(Vec<CrateRecord>, i64)>, sqlx::Error> {
+    let searchable = sqlx::query!(
+        r#"SELECT numnode(websearch_to_tsquery('english', $1)) > 0 AS "searchable!""#,
+        query
+    )
+    .fetch_one(&mut *exec)
+    .await?
+    .searchable;
+    if !searchable {
+        return Ok(None);
+    }
+    let rows = sqlx::query_as!(
+        CrateListRow,
+        r#"SELECT crates.original_name, crates.description, crates.documentation,
+        crates.homepage, crates.repository, crates.license, crates.deprecated,
+        crates.deprecation_message, crates.deprecation_replacement,
+        ARRAY_AGG(versions.vers) AS "versions!", crates.downloads, crates.updated_at,
+        COUNT(*) OVER () AS "total!"
+        FROM crates
+        JOIN versions ON versions.crate = crates.crate_id
+        WHERE crates.search_vector @@ websearch_to_tsquery('english', $1)
+        AND ($4::TEXT IS NULL OR crates.crate_id IN (
+            SELECT crate_categories.crate_id FROM crate_categories
+            JOIN valid_categories
+            ON valid_categories.category_id = crate_categories.category_id
+            WHERE valid_categories.category_name = $4))
+        AND ($5::TEXT IS NULL OR crates.crate_id IN (
+            SELECT crate_id FROM keywords WHERE keyword = $5))
+        GROUP BY crates.crate_id
+        ORDER BY ts_rank(crates.search_vector, websearch_to_tsquery('english', $1)) DESC,
+            crates.downloads DESC, crates.original_name
+        LIMIT $2 OFFSET $3"#,
+        query,
+        limit,
+        offset,
+        filter.category,
+        filter.keyword
+    )
+    .fetch_all(exec)
+    .await?;
+    Ok(Some(CrateListRow::into_page(rows)?))
+}
+/// Crates named similarly to `query` other than `excluded`, most similar first
+///
+/// Names are compared by trigrams after normalization, how similar they have to be is pg_trgm's
+/// `similarity_threshold`, 0.3 unless configured for the database. Fails without pg_trgm, see
+/// [`is_missing_extension`].
+pub async fn fuzzy_search_crates(
+    query: &str,
+    filter: &SearchFilter<'_>,
+    excluded: &[String],
+    limit: i64,
+    offset: i64,
+    exec: &mut PgConnection,
+) -> Result<(Vec<CrateRecord>, i64), sqlx::Error> {
+    let rows = sqlx::query_as!(
+        CrateListRow,
+        r#"SELECT crates.original_name, crates.description, crates.documentation,
+        crates.homepage, crates.repository, crates.license, crates.deprecated,
+        crates.deprecation_message, crates.deprecation_replacement,
+        ARRAY_AGG(versions.vers) AS "versions!", crates.downloads, crates.updated_at,
+        COUNT(*) OVER () AS "total!"
+        FROM crates
+        JOIN versions ON versions.crate = crates.crate_id
+        WHERE normalize_crate_name(crates.original_name) % $1
+        AND crates.original_name != ALL($2)
+        AND ($5::TEXT IS NULL OR crates.crate_id IN (
+            SELECT crate_categories.crate_id FROM crate_categories
+            JOIN valid_categories
+            ON valid_categories.category_id = crate_categories.category_id
+            WHERE valid_categories.category_name = $5))
+        AND ($6::TEXT IS NULL OR crates.crate_id IN (
+            SELECT crate_id FROM keywords WHERE keyword = $6))
+        GROUP BY crates.crate_id
+        ORDER BY similarity(normalize_crate_name(crates.original_name), $1) DESC,
+            crates.downloads DESC, crates.original_name
+        LIMIT $3 OFFSET $4"#,
+        query.replace('-', "_").to_lowercase(),
+        excluded,
+        limit,
+        offset,
+        filter.category,
+        filter.keyword
+    )
+    .fetch_all(exec)
+    .await?;
+    CrateListRow::into_page(rows)
+}
+/// Whether `error` comes from a function or operator class of an extension that isn't installed
+pub fn is_missing_extension(error: &sqlx::Error) -> bool {
+    // undefined_function and undefined_object
+    error
+        .as_database_error()
+        .and_then(|error| error.code())
+        .is_some_and(|code| code == "42883" || code == "42704")
+}
+/// Crates whose name or description contains `query`, ordered by name
+///
+/// Returns the requested page and the total amount of matches.
+pub async fn search_crates(
+    query: &str,
+    filter: &SearchFilter<'_>,
+    limit: i64,
+    offset: i64,
+    exec: &mut PgConnection,
+) -> Result<(Vec<CrateRecord>, i64), sqlx::Error> {
+    let rows = sqlx::query_as!(
+        CrateListRow,
+        r#"SELECT crates.original_name, crates.description, crates.documentation,
+        crates.homepage, crates.repository, crates.license, crates.deprecated,
+        crates.deprecation_message, crates.deprecation_replacement,
+        ARRAY_AGG(versions.vers) AS "versions!", crates.downloads, crates.updated_at,
+        COUNT(*) OVER () AS "total!"
+        FROM crates
+        JOIN versions ON versions.crate = crates.crate_id
+        WHERE (normalize_crate_name(crates.original_name) LIKE '%' || $1 || '%'
+        OR crates.description ILIKE '%' || $2 || '%')
+        AND ($5::TEXT IS NULL OR crates.crate_id IN (
+            SELECT crate_categories.crate_id FROM crate_categories
+            JOIN valid_categories
+            ON valid_categories.category_id = crate_categories.category_id
+            WHERE valid_categories.category_name = $5))
+        AND ($6::TEXT IS NULL OR crates.crate_id IN (
+            SELECT crate_id FROM keywords WHERE keyword = $6))
+        GROUP BY crates.crate_id
+        ORDER BY crates.original_name
+        LIMIT $3 OFFSET $4"#,
+        escape_like_pattern(&query.replace('-', "_").to_lowercase()),
+        escape_like_pattern(query),
+        limit,
+        offset,
+        filter.category,
+        filter.keyword
+    )
+    .fetch_all(exec)
+    .await?;
+    CrateListRow::into_page(rows)
+}
+
+/// Search results sorted by `sort`, an empty query lists every crate
+///
+/// The page is taken from the `crates_downloads`, `crates_updated_at` or name index before the
+/// versions are joined, ties are sorted by name. Relevance needs a full-text search, see
+/// [`full_text_search_crates`], these substring matches are all equally relevant and sorted by name.
+pub async fn get_sorted_crates(
+    query: &str,
+    filter: &SearchFilter<'_>,
+    sort: CrateSort,
+    limit: i64,
+    offset: i64,
+    exec: impl Executor<'_, Database = Postgres>,
+) -> Result<(Vec<RankedCrateRecord>, i64), sqlx::Error> {
+    let name_pattern = escape_like_pattern(&query.replace('-', "_").to_lowercase());
+    let description_pattern = escape_like_pattern(query);
+    let rows = match sort {
+        CrateSort::Downloads => {
+            sqlx::query_as!(
+                CrateListRow,
+                r#"WITH page AS (
+                    SELECT crate_id FROM crates
+                    WHERE ($1 = '' OR normalize_crate_name(original_name) LIKE '%' || $2 || '%'
+                    OR description ILIKE '%' || $3 || '%')
+                    AND ($6::TEXT IS NULL OR crates.crate_id IN (
+                        SELECT crate_categories.crate_id FROM crate_categories
+                        JOIN valid_categories
+                        ON valid_categories.category_id = crate_categories.category_id
+                        WHERE valid_categories.category_name = $6))
+                    AND ($7::TEXT IS NULL OR crates.crate_id IN (
+                        SELECT crate_id FROM keywords WHERE keyword = $7))
+                    ORDER BY downloads DESC, original_name
+                    LIMIT $4 OFFSET $5
+                )
+                SELECT crates.original_name, crates.description, crates.documentation,
+                crates.homepage, crates.repository, crates.license, crates.deprecated,
+                crates.deprecation_message, crates.deprecation_replacement,
+                ARRAY_AGG(versions.vers) AS "versions!", crates.downloads, crates.updated_at,
+                (SELECT COUNT(*) FROM crates
+                    WHERE ($1 = '' OR normalize_crate_name(original_name) LIKE '%' || $2 || '%'
+                    OR description ILIKE '%' || $3 || '%')
+                    AND ($6::TEXT IS NULL OR crates.crate_id IN (
+                        SELECT crate_categories.crate_id FROM crate_categories
+                        JOIN valid_categories
+                        ON valid_categories.category_id = crate_categories.category_id
+                        WHERE valid_categories.category_name = $6))
+                    AND ($7::TEXT IS NULL OR crates.crate_id IN (
+                        SELECT crate_id FROM keywords WHERE keyword = $7))) AS "total!"
+                FROM page
+                JOIN crates ON crates.crate_id = page.crate_id
+                JOIN versions ON versions.crate = crates.crate_id
+                GROUP BY crates.crate_id
+                ORDER BY crates.downloads DESC, crates.original_name"#,
+                query,
+                name_pattern,
+                description_pattern,
+                limit,
+                offset,
+                filter.category,
+                filter.keyword
+            )
+            .fetch_all(exec)
+            .await?
+        }
+        CrateSort::RecentUpdates => {
+            sqlx::query_as!(
+                CrateListRow,
+                r#"WITH page AS (
+                    SELECT crate_id FROM crates
+                    WHERE ($1 = '' OR normalize_crate_name(original_name) LIKE '%' || $2 || '%'
+                    OR description ILIKE '%' || $3 || '%')
+                    AND ($6::TEXT IS NULL OR crates.crate_id IN (
+                        SELECT crate_categories.crate_id FROM crate_categories
+                        JOIN valid_categories
+                        ON valid_categories.category_id = crate_categories.category_id
+                        WHERE valid_categories.category_name = $6))
+                    AND ($7::TEXT IS NULL OR crates.crate_id IN (
+                        SELECT crate_id FROM keywords WHERE keyword = $7))
+                    ORDER BY updated_at DESC NULLS LAST, original_name
+                    LIMIT $4 OFFSET $5
+                )
+                SELECT crates.original_name, crates.description, crates.documentation,
+                crates.homepage, crates.repository, crates.license, crates.deprecated,
+                crates.deprecation_message, crates.deprecation_replacement,
+                ARRAY_AGG(versions.vers) AS "versions!", crates.downloads, crates.updated_at,
+                (SELECT COUNT(*) FROM crates
+                    WHERE ($1 = '' OR normalize_crate_name(original_name) LIKE '%' || $2 || '%'
+                    OR description ILIKE '%' || $3 || '%')
+                    AND ($6::TEXT IS NULL OR crates.crate_id IN (
+                        SELECT crate_categories.crate_id FROM crate_categories
+                        JOIN valid_categories
+                        ON valid_categories.category_id = crate_categories.category_id
+                        WHERE valid_categories.category_name = $6))
+                    AND ($7::TEXT IS NULL OR crates.crate_id IN (
+                        SELECT crate_id FROM keywords WHERE keyword = $7))) AS "total!"
+                FROM page
+                JOIN crates ON crates.crate_id = page.crate_id
+                JOIN versions ON versions.crate = crates.crate_id
+                GROUP BY crates.crate_id
+                ORDER BY crates.updated_at DESC NULLS LAST, crates.original_name"#,
+                query,
+                name_pattern,
+                description_pattern,
+                limit,
+                offset,
+                filter.category,
+                filter.keyword
+            )
+            .fetch_all(exec)
+            .await?
+        }
+        CrateSort::Relevance | CrateSort::Alphabetical => {
+            sqlx::query_as!(
+                CrateListRow,
+                r#"WITH page AS (
+                    SELECT crate_id FROM crates
+                    WHERE ($1 = '' OR normalize_crate_name(original_name) LIKE '%' || $2 || '%'
+                    OR description ILIKE '%' || $3 || '%')
+                    AND ($6::TEXT IS NULL OR crates.crate_id IN (
+                        SELECT crate_categories.crate_id FROM crate_categories
+                        JOIN valid_categories
+                        ON valid_categories.category_id = crate_categories.category_id
+                        WHERE valid_categories.category_name = $6))
+                    AND ($7::TEXT IS NULL OR crates.crate_id IN (
+                        SELECT crate_id FROM keywords WHERE keyword = $7))
+                    ORDER BY original_name
+                    LIMIT $4 OFFSET $5
+                )
+                SELECT crates.original_name, crates.description, crates.documentation,
+                crates.homepage, crates.repository, crates.license, crates.deprecated,
+                crates.deprecation_message, crates.deprecation_replacement,
+                ARRAY_AGG(versions.vers) AS "versions!", crates.downloads, crates.updated_at,
+                (SELECT COUNT(*) FROM crates
+                    WHERE ($1 = '' OR normalize_crate_name(original_name) LIKE '%' || $2 || '%'
+                    OR description ILIKE '%' || $3 || '%')
+                    AND ($6::TEXT IS NULL OR crates.crate_id IN (
+                        SELECT crate_categories.crate_id FROM crate_categories
+                        JOIN valid_categories
+                        ON valid_categories.category_id = crate_categories.category_id
+                        WHERE valid_categories.category_name = $6))
+                    AND ($7::TEXT IS NULL OR crates.crate_id IN (
+                        SELECT crate_id FROM keywords WHERE keyword = $7))) AS "total!"
+                FROM page
+                JOIN crates ON crates.crate_id = page.crate_id
+                JOIN versions ON versions.crate = crates.crate_id
+                GROUP BY crates.crate_id
+                ORDER BY crates.original_name"#,
+                query,
+                name_pattern,
+                description_pattern,
+                limit,
+                offset,
+                filter.category,
+                filter.keyword
+            )
+            .fetch_all(exec)
+            .await?
+        }
+    };
+    CrateListRow::into_ranked_page(rows)
+}
+
+/// Crates tagged with `category`, `None` if the category doesn't exist
+///
+/// `page` starts at 1.
+pub async fn get_crates_by_category(
+    category: &str,
+    page: u32,
+    per_page: u32,
+    exec: &mut PgConnection,
+) -> Result<Option<(Vec<CrateRecord>, i64)>, sqlx::Error> {
+    let Some(category) = sqlx::query!(
+        "SELECT category_id FROM valid_categories WHERE category_name = $1",
+        category
+    )
+    .fetch_optional(&mut *exec)
+    .await?
+    else {
+        return Ok(None);
+    };
+    let offset = i64::from(page.max(1) - 1) * i64::from(per_page);
+    let rows = sqlx::query_as!(
+        CrateListRow,
+        r#"SELECT crates.original_name, crates.description, crates.documentation,
+        crates.homepage, crates.repository, crates.license, crates.deprecated,
+        crates.deprecation_message, crates.deprecation_replacement,
+        ARRAY_AGG(versions.vers) AS "versions!", crates.downloads, crates.updated_at,
+        COUNT(*) OVER () AS "total!"
+        FROM crate_categories
+        JOIN crates ON crates.crate_id = crate_categories.crate_id
+        JOIN versions ON versions.crate = crates.crate_id
+        WHERE crate_categories.category_id = $1
+        GROUP BY crates.crate_id
+        ORDER BY crates.original_name
+        LIMIT $2 OFFSET $3"#,
+        category.category_id,
+        i64::from(per_page),
+        offset
+    )
+    .fetch_all(exec)
+    .await?;
+    Ok(Some(CrateListRow::into_page(rows)?))
+}
+
+/// Crates with the keyword, `None` if no crate uses it
+///
+/// `page` starts at 1.
+pub async fn get_crates_by_keyword(
+    normalized_keyword: &str,
+    page: u32,
+    per_page: u32,
+    exec: &mut PgConnection,
+) -> Result<Option<(Vec<CrateRecord>, i64)>, sqlx::Error> {
+    let used = sqlx::query!(
+        r#"SELECT EXISTS(SELECT 1 FROM keywords WHERE keyword = $1) AS "used!""#,
+        normalized_keyword
+    )
+    .fetch_one(&mut *exec)
+    .await?
+    .used;
+    if !used {
+        return Ok(None);
+    }
+    let offset = i64::from(page.max(1) - 1) * i64::from(per_page);
+    let rows = sqlx::query_as!(
+        CrateListRow,
+        r#"SELECT crates.original_name, crates.description, crates.documentation,
+        crates.homepage, crates.repository, crates.license, crates.deprecated,
+        crates.deprecation_message, crates.deprecation_replacement,
+        ARRAY_AGG(versions.vers) AS "versions!", crates.downloads, crates.updated_at,
+        COUNT(*) OVER () AS "total!"
+        FROM crates
+        JOIN versions ON versions.crate = crates.crate_id
+        WHERE crates.crate_id IN (
+            SELECT crate_id FROM keywords WHERE keyword = $1
+        )
+        GROUP BY crates.crate_id
+        ORDER BY crates.original_name
+        LIMIT $2 OFFSET $3"#,
+        normalized_keyword,
+        i64::from(per_page),
+        offset
+    )
+    .fetch_all(exec)
+    .await?;
+    Ok(Some(CrateListRow::into_page(rows)?))
+}
+
+/// Versions by publish time, newest first, reads only the newest entries of `versions_created_at`
+///
+/// Only versions of `crate_name` if given.
+pub async fn get_recent_versions(
+    limit: i64,
+    crate_name: Option<&CrateName>,
+    exec: impl Executor<'_, Database = Postgres>,
+) -> Result<Vec<RecentVersion>, sqlx::Error> {
+    sqlx::query!(
+        r#"SELECT crates.original_name, versions.vers, crates.description,
+            versions.created_at AS "created_at!", users.login AS "publisher?", versions.yanked,
+            NOT EXISTS(
+                SELECT 1 FROM versions AS earlier
+                WHERE earlier.crate = versions.crate
+                AND (earlier.created_at IS NULL OR earlier.created_at < versions.created_at)
+            ) AS "new_crate!"
+        FROM versions
+        JOIN crates ON crates.crate_id = versions.crate
+        LEFT JOIN users ON users.user_id = versions.published_by
+        WHERE versions.created_at IS NOT NULL
+        AND ($2::TEXT IS NULL OR normalize_crate_name(crates.original_name) = $2)
+        ORDER BY versions.created_at DESC
+        LIMIT $1"#,
+        limit,
+        crate_name.map(CrateName::normalized) as _
+    )
+    .fetch_all(exec)
+    .await?
+    .into_iter()
+    .map(|record| {
+        Ok(RecentVersion {
+            version: parse_stored_version(&record.original_name, &record.vers)?,
+            krate: record.original_name,
+            description: record.description,
+            created_at: record.created_at,
+            publisher: record.publisher,
+            new_crate: record.new_crate,
+            yanked: record.yanked,
+        })
+    })
+    .collect()
+}
+
+/// Totals over the whole registry, reads every version row
+pub async fn get_registry_stats(
+    exec: impl Executor<'_, Database = Postgres>,
+) -> Result<RegistryStats, sqlx::Error> {
+    let record = sqlx::query!(
+        r#"SELECT
+            (SELECT COUNT(*) FROM crates) AS "crates!",
+            COUNT(*) AS "versions!",
+            COALESCE(SUM(file_size), 0)::BIGINT AS "stored_bytes!",
+            (SELECT COALESCE(SUM(count), 0) FROM version_downloads)::BIGINT AS "downloads!",
+            COUNT(*) FILTER (WHERE created_at > NOW() - INTERVAL '1 day') AS "publishes_last_day!",
+            COUNT(*) FILTER (WHERE created_at > NOW() - INTERVAL '7 days') AS "publishes_last_week!"
+        FROM versions"#
+    )
+    .fetch_one(exec)
+    .await?;
+    Ok(RegistryStats {
+        crates: record.crates,
+        versions: record.versions,
+        stored_bytes: record.stored_bytes,
+        downloads: record.downloads,
+        publishes_last_day: record.publishes_last_day,
+        publishes_last_week: record.publishes_last_week,
+    })
+}
+
+/// Row of a paginated crate listing, `total` counts the rows of all pages
+struct CrateListRow {
+    original_name: String,
+    description: String,
+    documentation: Option<String>,
+    homepage: Option<String>,
+    repository: Option<String>,
+    license: Option<String>,
+    deprecated: bool,
+    deprecation_message: Option<String>,
+    deprecation_replacement: Option<String>,
+    versions: Vec<String>,
+    downloads: i64,
+    updated_at: Option<DateTime<Utc>>,
+    total: i64,
+}
+impl CrateListRow {
+    fn into_page(rows: Vec<Self>) -> Result<(Vec<CrateRecord>, i64), sqlx::Error> {
+        let total = rows.first().map_or(0, |row| row.total);
+        let crates = rows
+            .into_iter()
+            .map(Self::into_record)
+            .collect::<Result<_, _>>()?;
+        Ok((crates, total))
+    }
+    fn into_ranked_page(rows: Vec<Self>) -> Result<(Vec<RankedCrateRecord>, i64), sqlx::Error> {
+        let total = rows.first().map_or(0, |row| row.total);
+        let crates = rows
+            .into_iter()
+            .map(|row| {
+                Ok(RankedCrateRecord {
+                    downloads: row.downloads,
+                    updated_at: row.updated_at,
+                    record: row.into_record()?,
+                })
+            })
+            .collect::<Result<_, sqlx::Error>>()?;
+        Ok((crates, total))
+    }
+    fn into_record(self) -> Result<CrateRecord, sqlx::Error> {
+        let versions = self
+            .versions
+            .iter()
+            .map(|vers| parse_stored_version(&self.original_name, vers))
+            .collect::<Result<_, _>>()?;
+        Ok(CrateRecord {
+            name: self.original_name,
+            description: self.description,
+            documentation: self.documentation,
+            homepage: self.homepage,
+            repository: self.repository,
+            license: self.license,
+            deprecation: deprecation_from_columns(
+                self.deprecated,
+                self.deprecation_message,
+                self.deprecation_replacement,
+            ),
+            versions,
+        })
+    }
+}
+
+/// Crate of a sorted listing with the values it can be sorted by
+pub struct RankedCrateRecord {
+    pub record: CrateRecord,
+    /// Of all versions still in the registry
+    pub downloads: i64,
+    /// Publish time of the newest version
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+fn deprecation_from_columns(
+    deprecated: bool,
+    message: Option<String>,
+    replacement: Option<String>,
+) -> Option<Deprecation> {
+    deprecated.then(|| Deprecation {
+        message,
+        replacement: replacement.and_then(|name| name.parse().ok()),
+    })
+}
+
+fn escape_like_pattern(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+#[derive(Clone, Debug)]
+/// Crate-level data, as stored on first publish
+pub struct CrateRecord {
+    pub name: String,
+    pub description: String,
+    pub documentation: Option<String>,
+    pub homepage: Option<String>,
+    pub repository: Option<String>,
+    pub license: Option<String>,
+    pub deprecation: Option<Deprecation>,
+    pub versions: Vec<semver::Version>,
+}
+
+#[derive(Clone, Debug)]
+/// Marks a whole crate as no longer maintained. Downloads keep working.
+pub struct Deprecation {
+    pub message: Option<String>,
+    /// Crate in this registry users should switch to
+    pub replacement: Option<CrateName>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// Row in the `pending_publishes` write-ahead log
+pub struct PendingPublishId(i32);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// Database id of a team in the `teams` table
+pub struct TeamId(i32);
 
 #[derive(Clone, Copy, Debug)]
 pub enum CrateExists {
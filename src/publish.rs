@@ -1,71 +1,333 @@
 use std::{
-    collections::{BTreeMap, HashSet},
+    collections::{BTreeMap, BTreeSet, HashSet},
     fmt::{Display, Formatter, Result as FmtResult},
+    str::FromStr,
 };
 
 use axum::{
-    body::{to_bytes, Body},
-    extract::State,
-    http::StatusCode,
+    body::{to_bytes, Body, Bytes},
+    extract::{Extension, State},
+    http::{
+        header::{CONTENT_LENGTH, IF_MATCH},
+        HeaderMap, StatusCode,
+    },
     response::{IntoResponse, Response},
     Json,
 };
+use cargo_platform::Platform;
 use semver::{Version, VersionReq};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
-use sqlx::{Postgres, Transaction};
+use sqlx::{Pool, Postgres, Transaction};
 
 use crate::{
-    crate_file::create_crate_file,
+    audit::{record_failure, AuditAction, AuditContext, AuditEvent, AuditOutcome},
+    auth::{ensure_crate_owner, AuthenticatedUser, UserId},
+    concurrency::index_busy,
+    crate_archive::{missing_files, read_packaged_file, validate_crate_archive, ArchivePolicy},
+    crate_file::CrateStorage,
     crate_name::CrateName,
-    feature_name::FeatureName,
-    index::add_file_to_index,
+    feature_name::{FeatureName, FeatureValue},
+    index::{
+        append_to_index, read_index_entries, IndexDrift, IndexDriftPolicy, IndexEntry,
+        IndexRepository,
+    },
+    middleware::ApiErrorResponse,
     non_empty_strings::{Description, Keyword},
     postgres::{
-        add_crate, add_keywords, add_version, crate_exists_or_normalized, delete_category_entries,
-        delete_keywords, get_bad_categories, get_versions, insert_categories, CrateExists,
+        add_audit_event, add_crate, add_keywords, add_owner, add_pending_publish, add_version,
+        crate_exists_or_normalized, delete_category_entries, delete_keywords,
+        delete_pending_publish, get_bad_categories, get_similar_crate_names, get_versions,
+        insert_categories, is_name_reserved_for, set_badges, CrateExists, ExistingVersion,
     },
+    read_only_mutex::ReadOnlyGuard,
+    registry::RegistryConfig,
+    request_id::RequestId,
+    storage_quota::charge_storage,
+    version::{has_upper_bound, is_newest_available, PrereleasePolicy, WildcardDependencyPolicy},
+    webhooks::{WebhookEvent, WebhookEventKind, WebhookLinks},
+    write_ahead_log::resolve_pending_publish,
     ServerState,
 };
 
+/// Badge types cargo documented for the `[badges]` manifest section
+const KNOWN_BADGE_TYPES: &[&str] = &[
+    "appveyor",
+    "azure-devops",
+    "bitbucket-pipelines",
+    "circle-ci",
+    "cirrus-ci",
+    "codecov",
+    "coveralls",
+    "gitlab",
+    "is-it-maintained-issue-resolution",
+    "is-it-maintained-open-issues",
+    "maintenance",
+    "travis-ci",
+];
+
+/// Edit distance up to which a new crate name counts as similar to an existing one
+const MAX_SIMILAR_NAME_DISTANCE: i32 = 2;
+/// Bytes of a README that is still stored and served
+pub const MAX_README_SIZE: u64 = 1024 * 1024;
+
+/// Publishes a version, cargo's `PUT /api/v1/crates/new`
+///
+/// Release pipelines can send `If-Match` with the max version they expect the crate to have,
+/// like `If-Match: "1.2.3"` (quotes are optional). `If-Match: *` only requires the crate to
+/// exist. If the crate's current max version differs, including yanked and pre-release versions,
+/// the publish is rejected with 412 Precondition Failed.
 pub async fn publish_handler(
-    State(ServerState {
-        database_connection_pool,
-        git_repository_path,
-    }): State<ServerState>,
+    State(state): State<ServerState>,
+    user: Result<AuthenticatedUser, Response>,
+    Extension(request_id): Extension<RequestId>,
+    headers: HeaderMap,
     body: Body,
-) -> Result<Json<SuccessfulPublish>, Response> {
-    let mut other_warnings = Vec::new();
+) -> Result<Json<SuccessfulPublish>, PublishError> {
+    let audit = AuditContext {
+        actor: user.as_ref().ok().map(|user| user.login.clone()),
+        request_id,
+    };
+    let failure = match read_body(&headers, body).await {
+        Ok(body_bytes) => match extract_request_body(&body_bytes) {
+            Ok((crate_metadata, file_content)) => {
+                let result = match max_version_precondition(&headers) {
+                    Ok(precondition) => {
+                        publish_crate(
+                            &state,
+                            user,
+                            &audit,
+                            &crate_metadata,
+                            file_content,
+                            precondition.as_ref(),
+                        )
+                        .await
+                    }
+                    Err(message) => Err(PublishError::ValidationFailed(vec![message.into()])),
+                };
+                if let Err(error) = &result {
+                    record_failure(
+                        AuditEvent {
+                            context: &audit,
+                            action: AuditAction::Publish,
+                            crate_name: Some(&crate_metadata.name),
+                            version: Some(&crate_metadata.vers),
+                            outcome: AuditOutcome::from_status(error.status_code()),
+                        },
+                        &state.database_connection_pool,
+                    )
+                    .await;
+                }
+                return result;
+            }
+            Err(e) => e.into(),
+        },
+        Err(error) => error,
+    };
+    record_failure(
+        AuditEvent {
+            context: &audit,
+            action: AuditAction::Publish,
+            crate_name: None,
+            version: None,
+            outcome: AuditOutcome::from_status(failure.status_code()),
+        },
+        &state.database_connection_pool,
+    )
+    .await;
+    Err(failure)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// `If-Match` of a publish
+enum MaxVersionPrecondition {
+    /// `*`, any existing crate
+    Exists,
+    /// The crate's current max version, build metadata is ignored
+    MaxVersion(Version),
+}
+impl MaxVersionPrecondition {
+    /// Error message if `max_version` doesn't satisfy the precondition
+    fn check(&self, max_version: Option<&Version>) -> Result<(), String> {
+        match (self, max_version) {
+            (Self::Exists, _) => Ok(()),
+            (Self::MaxVersion(expected), Some(max)) if expected.cmp_precedence(max).is_eq() => {
+                Ok(())
+            }
+            (Self::MaxVersion(expected), Some(max)) => Err(format!(
+                "max version of the crate is {max}, If-Match expected {expected}"
+            )),
+            (Self::MaxVersion(expected), None) => Err(format!(
+                "crate has no versions, If-Match expected {expected}"
+            )),
+        }
+    }
+}
+
+const INVALID_IF_MATCH: &str = "If-Match has to be * or a version like \"1.2.3\"";
+
+fn max_version_precondition(
+    headers: &HeaderMap,
+) -> Result<Option<MaxVersionPrecondition>, &'static str> {
+    let Some(value) = headers.get(IF_MATCH) else {
+        return Ok(None);
+    };
+    let value = value.to_str().map_err(|_e| INVALID_IF_MATCH)?.trim();
+    if value == "*" {
+        return Ok(Some(MaxVersionPrecondition::Exists));
+    }
+    let unquoted = value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .unwrap_or(value);
+    let version = unquoted.parse().map_err(|_e| INVALID_IF_MATCH)?;
+    Ok(Some(MaxVersionPrecondition::MaxVersion(version)))
+}
+
+async fn read_body(headers: &HeaderMap, body: Body) -> Result<Bytes, PublishError> {
     let body_bytes = to_bytes(body, usize::MAX)
         .await
-        .map_err(|_| (StatusCode::PAYLOAD_TOO_LARGE, "payload too large").into_response())?;
-    let (crate_metadata, file_content) =
-        extract_request_body(&body_bytes).map_err(IntoResponse::into_response)?;
+        .map_err(|_| PublishError::PayloadTooLarge)?;
+    if let Some(content_length) = headers
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok()?.parse::<usize>().ok())
+        .filter(|content_length| *content_length != body_bytes.len())
+    {
+        return Err(BodyError::LengthMismatch {
+            expected: content_length,
+            actual: body_bytes.len(),
+        }
+        .into());
+    }
+    Ok(body_bytes)
+}
+
+async fn publish_crate(
+    ServerState {
+        database_connection_pool,
+        registry,
+        webhooks,
+        archive_policy,
+        storage_quota,
+        index_lock_timeout,
+        name_policy,
+        reserved_names,
+        max_authors,
+        prerelease_policy,
+        index_drift_policy,
+        wildcard_dependency_policy,
+        prereleases_count_as_newest,
+        ..
+    }: &ServerState,
+    user: Result<AuthenticatedUser, Response>,
+    audit: &AuditContext,
+    crate_metadata: &Metadata,
+    file_content: &[u8],
+    precondition: Option<&MaxVersionPrecondition>,
+) -> Result<Json<SuccessfulPublish>, PublishError> {
+    let user = user?;
+    let is_prerelease = !crate_metadata.vers.pre.is_empty();
+    let mut other_warnings = validate_publish(
+        crate_metadata,
+        file_content,
+        *prerelease_policy,
+        *max_authors,
+        *archive_policy,
+        *wildcard_dependency_policy,
+    )
+    .map_err(PublishError::ValidationFailed)?;
+    // Taken before any row is locked, like yanks and deletions do, so they can't deadlock
+    let repository = registry
+        .index_repository
+        .lock_timeout(*index_lock_timeout)
+        .await
+        .ok_or_else(index_busy)?;
     let mut transaction = database_connection_pool
         .begin()
         .await
-        .map_err(|_e| internal_server_error("couldn't start transaction"))?;
+        .map_err(|_e| PublishError::Internal("couldn't start transaction".into()))?;
+    if is_name_reserved_for(&crate_metadata.name, Some(user.user_id), &mut transaction)
+        .await
+        .inspect_err(|e| eprintln!("Failed to check reserved names: {e}"))
+        .map_err(|_e| PublishError::Internal("couldn't check reserved names".into()))?
+    {
+        return Err(PublishError::Forbidden(format!(
+            "crate name {} is reserved",
+            crate_metadata.name
+        )));
+    }
+    // Only new crates have to follow the name rules, existing ones keep getting versions when
+    // the rules get stricter
+    let name_error = crate_metadata.name.ensure_publishable(*name_policy).err();
     let publish_kind = match crate_exists_or_normalized(&crate_metadata.name, &mut transaction)
         .await
         .inspect_err(|e| eprintln!("Failed to check if crate exists: {e}"))
-        .map_err(|_e| internal_server_error("couldn't check if crate exists"))?
+        .map_err(|_e| PublishError::Internal("couldn't check if crate exists".into()))?
     {
         CrateExists::NoButNormalized => {
-            return Err(bad_request(
+            return Err(PublishError::Conflict(String::from(
                 "Crate exists under different -_ usage or capitalization",
+            )))
+        }
+        CrateExists::No if reserved_names.contains(&crate_metadata.name) => {
+            return Err(PublishError::Forbidden(format!(
+                "crate name {} is reserved",
+                crate_metadata.name
+            )))
+        }
+        CrateExists::No if name_error.is_some() => {
+            return Err(PublishError::ValidationFailed(
+                name_error.iter().map(ToString::to_string).collect(),
             ))
         }
+        CrateExists::No if precondition.is_some() => {
+            return Err(PublishError::PreconditionFailed(String::from(
+                "crate doesn't exist yet, If-Match can't be met",
+            )))
+        }
+        CrateExists::No if is_prerelease && *prerelease_policy != PrereleasePolicy::Allow => {
+            return Err(PublishError::ValidationFailed(vec![String::from(
+                "the first version of a crate can't be a pre-release on this registry",
+            )]))
+        }
         // Add crate to database, assign new owner
         CrateExists::No => PublishKind::NewCrate,
-        // Check if person is owner, if newer version update crate data
-        // TODO Check if it's a newer version
+        // Only owners may publish, if newer version update crate data
         CrateExists::Yes => {
-            let max = get_versions(&crate_metadata.name, &mut transaction)
+            ensure_crate_owner(&crate_metadata.name, &user, &mut transaction).await?;
+            let existing_versions = get_versions(&crate_metadata.name, &mut transaction)
                 .await
-                .map_err(|_e| internal_server_error("cannot get versions of crate"))?
-                .into_iter()
-                .max();
-            if max.is_none_or(|max| max < crate_metadata.vers) {
+                .map_err(versions_unavailable)?;
+            let versions: Vec<Version> = existing_versions
+                .iter()
+                .map(|existing| existing.vers.clone())
+                .collect();
+            if let Some(precondition) = precondition {
+                precondition
+                    .check(versions.iter().max())
+                    .map_err(PublishError::PreconditionFailed)?;
+            }
+            // Versions differing only in build metadata share index line and crate file
+            if let Some(existing) = versions
+                .iter()
+                .find(|vers| vers.cmp_precedence(&crate_metadata.vers).is_eq())
+            {
+                return Err(PublishError::Conflict(format!(
+                    "crate version {existing} is already uploaded"
+                )));
+            }
+            check_index_drift(
+                &crate_metadata.name,
+                &versions,
+                &repository,
+                *index_drift_policy,
+            )
+            .await?;
+            if is_newest_available(
+                &crate_metadata.vers,
+                &available_versions(&existing_versions),
+                *prereleases_count_as_newest,
+            ) {
                 PublishKind::NewVersionForExistingCrate
             } else {
                 PublishKind::OldVersionForExistingCrate
@@ -73,61 +335,609 @@ pub async fn publish_handler(
         }
     };
 
-    let mut invalid_categories = Vec::new();
+    let (invalid_categories, invalid_badges) =
+        update_crate_data(publish_kind, crate_metadata, &mut transaction).await?;
+    match publish_kind {
+        PublishKind::NewCrate => {
+            add_owner(&crate_metadata.name, user.user_id, &mut transaction)
+                .await
+                .inspect_err(|e| eprintln!("Adding owner failed: {e}"))
+                .map_err(|_e| PublishError::Internal("adding crate owner failed".into()))?;
+        }
+        PublishKind::NewVersionForExistingCrate => {}
+        PublishKind::OldVersionForExistingCrate => {
+            other_warnings.push(String::from("Newer version for this crate is already in the registry. Categories and keywords will not be overwritten."));
+        }
+    };
+    charge_storage(
+        user.user_id,
+        file_content.len(),
+        *storage_quota,
+        &mut transaction,
+    )
+    .await?;
+    let cksum = commit_version(
+        crate_metadata,
+        file_content,
+        Some(user.user_id),
+        audit,
+        transaction,
+        repository,
+        database_connection_pool,
+        registry,
+    )
+    .await?;
+    webhooks.notify(&WebhookEvent {
+        event: WebhookEventKind::Publish,
+        links: WebhookLinks::new(&crate_metadata.name, &crate_metadata.vers),
+        krate: crate_metadata.name.clone(),
+        version: crate_metadata.vers.clone(),
+        cksum: cksum.clone(),
+        publisher: Some(user.login),
+    });
+    if let PublishKind::NewCrate = publish_kind {
+        // Only a hint against typosquatting, the publish already went through
+        match get_similar_crate_names(
+            &crate_metadata.name,
+            MAX_SIMILAR_NAME_DISTANCE,
+            &**database_connection_pool,
+        )
+        .await
+        {
+            Ok(similar) => other_warnings.extend(
+                similar
+                    .into_iter()
+                    .map(|name| format!("name is similar to existing crate: {name}")),
+            ),
+            Err(e) => eprintln!("Failed to look up similar crate names: {e}"),
+        }
+    }
+    Ok(Json(SuccessfulPublish {
+        warnings: PublishWarnings {
+            invalid_categories,
+            invalid_badges,
+            other: other_warnings,
+        },
+        published: PublishedVersion {
+            name: crate_metadata.name.clone(),
+            vers: crate_metadata.vers.clone(),
+            cksum,
+        },
+    }))
+}
+
+/// Outcome of importing a version from another registry
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImportOutcome {
+    Imported,
+    /// A version differing at most in build metadata is already in the registry
+    AlreadyPresent,
+}
+
+/// Publishes a version taken from another registry, without owners or authorization
+///
+/// The crate archive is validated, registry policies for new publishes like reserved names,
+/// pre-release rules and storage quotas don't apply. No webhooks are notified.
+pub async fn import_version(
+    ServerState {
+        database_connection_pool,
+        registry,
+        archive_policy,
+        index_lock_timeout,
+        prereleases_count_as_newest,
+        ..
+    }: &ServerState,
+    audit: &AuditContext,
+    crate_metadata: &Metadata,
+    file_content: &[u8],
+) -> Result<ImportOutcome, PublishError> {
+    validate_crate_archive(file_content, *archive_policy)
+        .map_err(|e| PublishError::ValidationFailed(vec![e.to_string()]))?;
+    let repository = registry
+        .index_repository
+        .lock_timeout(*index_lock_timeout)
+        .await
+        .ok_or_else(index_busy)?;
+    let mut transaction = database_connection_pool
+        .begin()
+        .await
+        .map_err(|_e| PublishError::Internal("couldn't start transaction".into()))?;
+    let publish_kind = match crate_exists_or_normalized(&crate_metadata.name, &mut transaction)
+        .await
+        .inspect_err(|e| eprintln!("Failed to check if crate exists: {e}"))
+        .map_err(|_e| PublishError::Internal("couldn't check if crate exists".into()))?
+    {
+        CrateExists::NoButNormalized => {
+            return Err(PublishError::Conflict(String::from(
+                "Crate exists under different -_ usage or capitalization",
+            )))
+        }
+        CrateExists::No => PublishKind::NewCrate,
+        CrateExists::Yes => {
+            let existing_versions = get_versions(&crate_metadata.name, &mut transaction)
+                .await
+                .map_err(versions_unavailable)?;
+            if existing_versions
+                .iter()
+                .any(|existing| existing.vers.cmp_precedence(&crate_metadata.vers).is_eq())
+            {
+                return Ok(ImportOutcome::AlreadyPresent);
+            }
+            if is_newest_available(
+                &crate_metadata.vers,
+                &available_versions(&existing_versions),
+                *prereleases_count_as_newest,
+            ) {
+                PublishKind::NewVersionForExistingCrate
+            } else {
+                PublishKind::OldVersionForExistingCrate
+            }
+        }
+    };
+    update_crate_data(publish_kind, crate_metadata, &mut transaction).await?;
+    commit_version(
+        crate_metadata,
+        file_content,
+        None,
+        audit,
+        transaction,
+        repository,
+        database_connection_pool,
+        registry,
+    )
+    .await?;
+    Ok(ImportOutcome::Imported)
+}
+
+/// Yanked versions don't keep a lower version from becoming the newest
+fn available_versions(existing_versions: &[ExistingVersion]) -> Vec<&Version> {
+    existing_versions
+        .iter()
+        .filter(|existing| !existing.yanked)
+        .map(|existing| &existing.vers)
+        .collect()
+}
+
+/// Names corrupted version rows in the response, so operators can fix them
+fn versions_unavailable(error: sqlx::Error) -> PublishError {
+    eprintln!("Failed to get versions: {error}");
+    match error {
+        sqlx::Error::Decode(e) => {
+            PublishError::Internal(format!("cannot get versions of crate: {e}"))
+        }
+        _ => PublishError::Internal("cannot get versions of crate".into()),
+    }
+}
+
+/// Adds or refreshes the crate-level rows, only the newest version sets keywords, categories
+/// and badges
+///
+/// Returns the categories and badges that weren't accepted.
+async fn update_crate_data(
+    publish_kind: PublishKind,
+    crate_metadata: &Metadata,
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<(Vec<String>, Vec<String>), PublishError> {
     match publish_kind {
         // Clean adding of new crate possible
         PublishKind::NewCrate => {
-            add_crate(&crate_metadata, &mut *transaction)
+            add_crate(crate_metadata, &mut **transaction)
                 .await
-                .map_err(|_e| internal_server_error("adding crate to db failed"))?;
-            invalid_categories
-                .extend(add_keywords_and_categories(&crate_metadata, &mut transaction).await?);
+                .map_err(|_e| PublishError::Internal("adding crate to db failed".into()))?;
         }
         // Old categories need to be deleted before
         PublishKind::NewVersionForExistingCrate => {
-            delete_keywords(&crate_metadata.name, &mut transaction)
+            delete_keywords(&crate_metadata.name, transaction)
                 .await
                 .inspect_err(|e| eprintln!("Deleting keywords failed: {e}"))
-                .map_err(|_e| internal_server_error("removing old keywords failed"))?;
-            delete_category_entries(&crate_metadata.name, &mut transaction)
+                .map_err(|_e| PublishError::Internal("removing old keywords failed".into()))?;
+            delete_category_entries(&crate_metadata.name, transaction)
                 .await
                 .inspect_err(|e| eprintln!("Deleting category entries failed: {e}"))
-                .map_err(|_e| internal_server_error("removing old categories failed"))?;
-            invalid_categories
-                .extend(add_keywords_and_categories(&crate_metadata, &mut transaction).await?);
+                .map_err(|_e| PublishError::Internal("removing old categories failed".into()))?;
         }
         // Categories and keywords are ignored
-        PublishKind::OldVersionForExistingCrate => {
-            other_warnings.push(String::from("Newer version for this crate is already in the registry. Categories and keywords will not be overwritten."));
-        }
+        PublishKind::OldVersionForExistingCrate => return Ok((Vec::new(), Vec::new())),
+    }
+    let invalid_categories = add_keywords_and_categories(crate_metadata, transaction)
+        .await?
+        .into_iter()
+        .collect();
+    let invalid_badges = replace_badges(crate_metadata, transaction).await?;
+    Ok((invalid_categories, invalid_badges))
+}
+
+/// Stores the version through the write-ahead log, returns the checksum of the crate file
+///
+/// Index line and crate file are removed again if the transaction doesn't commit.
+#[expect(clippy::too_many_arguments)]
+async fn commit_version(
+    crate_metadata: &Metadata,
+    file_content: &[u8],
+    published_by: Option<UserId>,
+    audit: &AuditContext,
+    transaction: Transaction<'_, Postgres>,
+    repository: ReadOnlyGuard<'_, IndexRepository>,
+    database_connection_pool: &Pool<Postgres>,
+    registry: &RegistryConfig,
+) -> Result<String, PublishError> {
+    let index_entry = IndexEntry::new(crate_metadata, file_content)
+        .inspect_err(|e| eprintln!("Failed to build index entry: {e}"))
+        .map_err(|_e| PublishError::Internal("failed to build index entry".into()))?;
+    let pending_id = add_pending_publish(&index_entry, database_connection_pool)
+        .await
+        .inspect_err(|e| eprintln!("Failed to record pending publish: {e}"))
+        .map_err(|_e| PublishError::Internal("failed to record pending publish".into()))?;
+    let cksum = hash_file_content(file_content);
+    let audit_event = AuditEvent {
+        context: audit,
+        action: AuditAction::Publish,
+        crate_name: Some(&crate_metadata.name),
+        version: Some(&crate_metadata.vers),
+        outcome: AuditOutcome::Success,
     };
-    create_crate_file(
+    let result = store_version(
+        crate_metadata,
         file_content,
-        crate_metadata.vers.clone(),
-        &crate_metadata.name,
+        &cksum,
+        published_by,
+        &index_entry,
+        audit_event,
+        transaction,
+        registry.crate_storage.as_ref(),
+        &repository,
+    )
+    .await;
+    drop(repository);
+    if let Err(response) = result {
+        match resolve_pending_publish(pending_id, &index_entry, database_connection_pool, registry)
+            .await
+        {
+            Ok(resolution) => eprintln!("Cleaned up failed publish: {resolution:?}"),
+            Err(e) => eprintln!("Failed to clean up failed publish, retrying on restart: {e}"),
+        }
+        return Err(response);
+    }
+    if let Err(e) = delete_pending_publish(pending_id, database_connection_pool).await {
+        eprintln!("Failed to remove finished publish from the write-ahead log: {e}");
+    }
+    Ok(cksum)
+}
+
+/// Writes crate file, version rows and index line, committing the transaction last
+#[expect(clippy::too_many_arguments)]
+async fn store_version(
+    crate_metadata: &Metadata,
+    file_content: &[u8],
+    cksum: &str,
+    published_by: Option<UserId>,
+    index_entry: &IndexEntry,
+    audit_event: AuditEvent<'_>,
+    mut transaction: Transaction<'_, Postgres>,
+    crate_storage: &dyn CrateStorage,
+    repository: &IndexRepository,
+) -> Result<(), PublishError> {
+    add_version(
+        crate_metadata,
+        cksum,
+        file_content.len() as i64,
+        published_by,
+        packaged_readme(crate_metadata, file_content).as_deref(),
+        &mut transaction,
     )
     .await
-    .map_err(|e| internal_server_error(e.to_string()))?;
-    let cksum = hash_file_content(file_content);
-    add_version(&crate_metadata, &cksum, &mut transaction)
+    .inspect_err(|e| eprintln!("failed to add crate version to db: {e}"))
+    .map_err(|_e| PublishError::Internal("failed to add crate version to database".into()))?;
+    write_version_files(
+        crate_metadata,
+        file_content,
+        index_entry,
+        crate_storage,
+        repository,
+    )
+    .await?;
+    add_audit_event(audit_event, &mut *transaction)
         .await
-        .inspect_err(|e| eprintln!("failed to add crate version to db: {e}"))
-        .map_err(|_e| internal_server_error("failed to add crate version to database"))?;
-    if let Err(e) = add_file_to_index(&crate_metadata, file_content, &git_repository_path).await {
-        eprintln!("Failed to add file to index: {e}");
-        return Err(internal_server_error("failed to add file to index"));
-    };
+        .inspect_err(|e| eprintln!("Failed to record audit event: {e}"))
+        .map_err(|_e| PublishError::Internal("failed to record audit event".into()))?;
     transaction
         .commit()
         .await
-        .map_err(|_e| internal_server_error("committing to database failed"))?;
-    Ok(Json(SuccessfulPublish {
-        warnings: PublishWarnings {
-            invalid_categories,
-            invalid_badges: Vec::new(),
-            other: other_warnings,
-        },
-    }))
+        .map_err(|_e| PublishError::Internal("committing to database failed".into()))
+}
+
+/// The README named by `readme_file` as packaged, `None` if there is none or it can't be stored
+fn packaged_readme(metadata: &Metadata, file_content: &[u8]) -> Option<String> {
+    let path = metadata.readme_file.as_deref()?;
+    let readme = read_packaged_file(file_content, path, MAX_README_SIZE)
+        .inspect_err(|e| eprintln!("Failed to read README: {e}"))
+        .ok()??;
+    match String::from_utf8(readme) {
+        // Postgres text can't contain NUL
+        Ok(readme) if !readme.contains('\0') => Some(readme),
+        _ => {
+            eprintln!(
+                "Warning: README {path} of {} {} isn't text",
+                metadata.name, metadata.vers
+            );
+            None
+        }
+    }
+}
+
+/// Stores the crate file and appends the index line, the parts of a publish outside the database
+async fn write_version_files(
+    crate_metadata: &Metadata,
+    file_content: &[u8],
+    index_entry: &IndexEntry,
+    crate_storage: &dyn CrateStorage,
+    repository: &IndexRepository,
+) -> Result<(), PublishError> {
+    crate_storage
+        .store(&crate_metadata.name, &crate_metadata.vers, file_content)
+        .await
+        .map_err(|e| PublishError::Internal(e.to_string()))?;
+    if let Err(e) = append_to_index(index_entry, repository).await {
+        eprintln!("Failed to add file to index: {e}");
+        return Err(PublishError::Internal("failed to add file to index".into()));
+    };
+    Ok(())
+}
+
+/// Every check of a publish that doesn't need the database, returns warnings or all errors
+///
+/// The checks are independent, so a publish learns about all of its problems at once.
+fn validate_publish(
+    metadata: &Metadata,
+    file_content: &[u8],
+    prerelease_policy: PrereleasePolicy,
+    max_authors: usize,
+    archive_policy: ArchivePolicy,
+    wildcard_dependency_policy: WildcardDependencyPolicy,
+) -> Result<Vec<String>, Vec<String>> {
+    let mut warnings = Vec::new();
+    let mut errors = Vec::new();
+    if !metadata.vers.pre.is_empty() && prerelease_policy == PrereleasePolicy::Deny {
+        errors.push(String::from(
+            "pre-release versions are not permitted on this registry",
+        ));
+    }
+    if metadata.authors.len() > max_authors {
+        errors.push(format!(
+            "{} authors listed, at most {max_authors} are allowed",
+            metadata.authors.len()
+        ));
+    }
+    if let Err(e) = validate_crate_archive(file_content, archive_policy) {
+        errors.push(e.to_string());
+    }
+    errors.extend(url_errors(
+        metadata.homepage.as_deref(),
+        metadata.documentation.as_deref(),
+        metadata.repository.as_deref(),
+    ));
+    for check in [
+        weak_dependency_feature_warnings(metadata),
+        dependency_target_warnings(metadata),
+        wildcard_dependency_warnings(metadata, wildcard_dependency_policy),
+    ] {
+        match check {
+            Ok(check_warnings) => warnings.extend(check_warnings),
+            Err(check_errors) => errors.extend(check_errors),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    warnings.extend(metadata_warnings(metadata));
+    warnings.extend(missing_file_warnings(metadata, file_content));
+    Ok(warnings)
+}
+
+/// Links shown for the crate have to be `http` or `https` URLs, like on crates.io
+pub fn url_errors(
+    homepage: Option<&str>,
+    documentation: Option<&str>,
+    repository: Option<&str>,
+) -> Vec<String> {
+    [
+        ("homepage", homepage),
+        ("documentation", documentation),
+        ("repository", repository),
+    ]
+    .into_iter()
+    .filter_map(|(field, url)| Some((field, url?)))
+    .filter(|(_, url)| {
+        reqwest::Url::parse(url).map_or(true, |url| !matches!(url.scheme(), "http" | "https"))
+    })
+    .map(|(field, url)| format!("{field} {url} is not an http or https URL"))
+    .collect()
+}
+
+/// Dependencies accepting any future version, like `*` or `>=0.1`
+///
+/// They are warned about, or errors with [`WildcardDependencyPolicy::Reject`].
+fn wildcard_dependency_warnings(
+    metadata: &Metadata,
+    policy: WildcardDependencyPolicy,
+) -> Result<Vec<String>, Vec<String>> {
+    let problems = metadata
+        .deps
+        .iter()
+        .filter(|dependency| !has_upper_bound(&dependency.version_req))
+        .map(|dependency| {
+            format!(
+                "dependency {} has no upper bound in its version requirement {}",
+                dependency.name, dependency.version_req
+            )
+        })
+        .collect::<Vec<_>>();
+    match policy {
+        WildcardDependencyPolicy::Reject if !problems.is_empty() => Err(problems),
+        _ => Ok(problems),
+    }
+}
+
+/// Parses the `target` of each dependency like cargo does
+///
+/// Targets cargo can't parse are errors, they would break the crate's index file for it.
+/// Cfg values cargo ignores when selecting dependencies, like `cfg(test)`, get a warning.
+fn dependency_target_warnings(metadata: &Metadata) -> Result<Vec<String>, Vec<String>> {
+    let mut warnings = Vec::new();
+    let mut errors = Vec::new();
+    for dependency in &metadata.deps {
+        let Some(target) = &dependency.target else {
+            continue;
+        };
+        // A bare target name only has to consist of valid characters, even none
+        if target.is_empty() {
+            errors.push(format!(
+                "dependency {} has an empty target",
+                dependency.name
+            ));
+            continue;
+        }
+        match Platform::from_str(target) {
+            Ok(platform) => {
+                let mut cfg_warnings = Vec::new();
+                platform.check_cfg_attributes(&mut cfg_warnings);
+                warnings.extend(
+                    cfg_warnings
+                        .into_iter()
+                        .map(|warning| format!("dependency {}: {warning}", dependency.name)),
+                );
+            }
+            Err(e) => errors.push(format!("dependency {}: {e}", dependency.name)),
+        }
+    }
+    if errors.is_empty() {
+        Ok(warnings)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Checks the dependencies named in `dep?/feature` values of the features
+///
+/// An undeclared dependency is an error. A non-optional one only gets a warning,
+/// `dep?/feature` enables the feature like `dep/feature` then.
+fn weak_dependency_feature_warnings(metadata: &Metadata) -> Result<Vec<String>, Vec<String>> {
+    let mut warnings = Vec::new();
+    let mut errors = Vec::new();
+    for (feature_name, values) in &metadata.features {
+        for value in values {
+            let FeatureValue::DependencyFeature {
+                dependency,
+                weak: true,
+                ..
+            } = FeatureValue::parse(value)
+            else {
+                continue;
+            };
+            // The same dependency can be declared once per kind and target
+            let mut declarations = metadata
+                .deps
+                .iter()
+                .filter(|dep| {
+                    dep.explicit_name_in_toml
+                        .as_ref()
+                        .unwrap_or(&dep.name)
+                        .original_str()
+                        == dependency
+                })
+                .peekable();
+            if declarations.peek().is_none() {
+                errors.push(format!(
+                    "feature {feature_name} includes {value}, but {dependency} is not a dependency"
+                ));
+            } else if !declarations.any(|dep| dep.optional) {
+                warnings.push(format!(
+                    "feature {feature_name} includes {value}, but {dependency} is not optional"
+                ));
+            }
+        }
+    }
+    if errors.is_empty() {
+        Ok(warnings)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Soft warnings about the metadata, like cargo's own nudges
+fn metadata_warnings(metadata: &Metadata) -> Vec<String> {
+    let mut warnings = Vec::new();
+    if metadata.license.is_none() && metadata.license_file.is_none() {
+        warnings.push(String::from("crate published without a license"));
+    }
+    let mut spellings = BTreeMap::<String, BTreeSet<&str>>::new();
+    for keyword in &metadata.keywords {
+        spellings
+            .entry(keyword.normalized())
+            .or_default()
+            .insert(keyword.as_ref());
+    }
+    for (normalized, spellings) in spellings {
+        if spellings.len() > 1 {
+            warnings.push(format!(
+                "keywords {} are all stored as {normalized}",
+                spellings.into_iter().collect::<Vec<_>>().join(", ")
+            ));
+        }
+    }
+    warnings
+}
+
+/// Warns about `readme_file` and `license_file` paths the archive doesn't contain
+fn missing_file_warnings(metadata: &Metadata, file_content: &[u8]) -> Vec<String> {
+    let referenced = [
+        ("readme_file", metadata.readme_file.as_deref()),
+        ("license_file", metadata.license_file.as_deref()),
+    ];
+    let paths: Vec<&str> = referenced.iter().filter_map(|(_, path)| *path).collect();
+    if paths.is_empty() {
+        return Vec::new();
+    }
+    let missing = match missing_files(file_content, &paths) {
+        Ok(missing) => missing,
+        Err(e) => {
+            eprintln!("Failed to check files referenced by the metadata: {e}");
+            return Vec::new();
+        }
+    };
+    referenced
+        .iter()
+        .filter_map(|(field, path)| Some((field, (*path)?)))
+        .filter(|(_, path)| missing.contains(path))
+        .map(|(field, path)| format!("{field} {path} is missing from the crate archive"))
+        .collect()
+}
+
+/// Compares the versions in the database with the crate's index file before a line is added
+///
+/// The caller has to hold the repository lock, so no other request is between its two writes.
+async fn check_index_drift(
+    crate_name: &CrateName,
+    database_versions: &[Version],
+    repository: &IndexRepository,
+    policy: IndexDriftPolicy,
+) -> Result<(), PublishError> {
+    let entries = read_index_entries(crate_name, repository)
+        .await
+        .inspect_err(|e| eprintln!("Failed to read index: {e}"))
+        .map_err(|_e| PublishError::Internal("failed to read index".into()))?;
+    let drift = IndexDrift::between(database_versions, &entries);
+    if drift.is_empty() {
+        return Ok(());
+    }
+    eprintln!("Index and database disagree about {crate_name}: {drift}");
+    match policy {
+        IndexDriftPolicy::Warn => Ok(()),
+        IndexDriftPolicy::Reject => Err(PublishError::Internal(format!(
+            "index and database disagree about the versions of {crate_name}, \
+            publishing is blocked until an administrator repaired it"
+        ))),
+    }
 }
 
 fn hash_file_content(file: &[u8]) -> String {
@@ -140,10 +950,10 @@ fn hash_file_content(file: &[u8]) -> String {
 async fn add_keywords_and_categories(
     metadata: &Metadata,
     transaction: &mut Transaction<'_, Postgres>,
-) -> Result<HashSet<String>, Response> {
-    let invalid_categories = get_bad_categories(metadata, transaction)
+) -> Result<HashSet<String>, PublishError> {
+    let invalid_categories = get_bad_categories(&metadata.categories, transaction)
         .await
-        .map_err(|_e| internal_server_error("Failed to check categories"))?;
+        .map_err(|_e| PublishError::Internal("Failed to check categories".into()))?;
     insert_categories(
         metadata
             .categories
@@ -154,25 +964,43 @@ async fn add_keywords_and_categories(
         transaction,
     )
     .await
-    .map_err(|_e| internal_server_error("Failed to insert categories"))?;
-    add_keywords(metadata, transaction)
+    .map_err(|_e| PublishError::Internal("Failed to insert categories".into()))?;
+    add_keywords(&metadata.name, &metadata.keywords, transaction)
         .await
         .inspect_err(|e| eprintln!("Couldn't insert keywords: {e}"))
-        .map_err(|_e| internal_server_error("Couldn't add keywords"))?;
+        .map_err(|_e| PublishError::Internal("Couldn't add keywords".into()))?;
     Ok(invalid_categories)
 }
 
-fn internal_server_error(s: impl Into<String>) -> Response {
-    (StatusCode::INTERNAL_SERVER_ERROR, s.into()).into_response()
-}
-
-fn bad_request(s: impl Into<String>) -> Response {
-    (StatusCode::BAD_REQUEST, s.into()).into_response()
+/// Stores the badges of known types, returning the unknown ones
+async fn replace_badges(
+    metadata: &Metadata,
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<Vec<String>, PublishError> {
+    let (known, unknown): (BTreeMap<_, _>, BTreeMap<_, _>) = metadata
+        .badges
+        .iter()
+        .partition(|(badge_type, _)| KNOWN_BADGE_TYPES.contains(&badge_type.as_str()));
+    set_badges(&metadata.name, &known, transaction)
+        .await
+        .inspect_err(|e| eprintln!("Couldn't store badges: {e}"))
+        .map_err(|_e| PublishError::Internal("Couldn't store badges".into()))?;
+    Ok(unknown.into_keys().cloned().collect())
 }
 
 #[derive(Debug, Serialize)]
 pub struct SuccessfulPublish {
     warnings: PublishWarnings,
+    /// Not part of cargo's response, which ignores it, for scripts logging what they published
+    published: PublishedVersion,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PublishedVersion {
+    name: CrateName,
+    vers: Version,
+    /// Same as in the index and the database
+    cksum: String,
 }
 
 #[derive(Clone, Debug, Default, Serialize)]
@@ -190,7 +1018,10 @@ enum PublishKind {
     OldVersionForExistingCrate,
 }
 
-fn extract_request_body(bytes: &[u8]) -> Result<(Metadata, &[u8]), BodyError> {
+/// Splits cargo's publish body into metadata and crate file
+///
+/// Both are prefixed with their length as little-endian `u32`, malformed input is an error.
+pub fn extract_request_body(bytes: &[u8]) -> Result<(Metadata, &[u8]), BodyError> {
     let (metadata_length_bytes, rest) = bytes
         .split_first_chunk::<4>()
         .ok_or(BodyError::UnexpectedEOF)?;
@@ -201,8 +1032,12 @@ fn extract_request_body(bytes: &[u8]) -> Result<(Metadata, &[u8]), BodyError> {
     let (file_length_bytes, file_content) = request_body_rest
         .split_first_chunk::<4>()
         .ok_or(BodyError::UnexpectedEOF)?;
-    if (u32::from_le_bytes(*file_length_bytes) as usize) < file_content.len() {
-        return Err(BodyError::UnexpectedEOF);
+    let file_length = u32::from_le_bytes(*file_length_bytes) as usize;
+    if file_length != file_content.len() {
+        return Err(BodyError::LengthMismatch {
+            expected: 4 + metadata_length + 4 + file_length,
+            actual: bytes.len(),
+        });
     }
     let metadata =
         serde_json::from_slice::<Metadata>(metadata_bytes).map_err(BodyError::InvalidMetadata)?;
@@ -210,29 +1045,122 @@ fn extract_request_body(bytes: &[u8]) -> Result<(Metadata, &[u8]), BodyError> {
     Ok((metadata, file_content))
 }
 
+#[derive(Debug)]
+/// Why a publish was rejected
+pub enum PublishError {
+    NotFound,
+    Unauthorized,
+    Forbidden(String),
+    Conflict(String),
+    PayloadTooLarge,
+    /// `If-Match` doesn't match the crate's max version
+    PreconditionFailed(String),
+    /// The body isn't in cargo's publish format
+    InvalidBody(BodyError),
+    /// Each problem is reported as its own error to cargo
+    ValidationFailed(Vec<String>),
+    Internal(String),
+    /// Rejection of a check shared with other endpoints, passed on unchanged
+    Rejected(Response),
+}
+impl PublishError {
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::Unauthorized => StatusCode::UNAUTHORIZED,
+            Self::Forbidden(_) => StatusCode::FORBIDDEN,
+            Self::Conflict(_) => StatusCode::CONFLICT,
+            Self::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::PreconditionFailed(_) => StatusCode::PRECONDITION_FAILED,
+            Self::InvalidBody(_) | Self::ValidationFailed(_) => StatusCode::BAD_REQUEST,
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::Rejected(response) => response.status(),
+        }
+    }
+}
+impl IntoResponse for PublishError {
+    fn into_response(self) -> Response {
+        let status = self.status_code();
+        let mut errors = ApiErrorResponse::new();
+        match self {
+            Self::NotFound => errors.push_error("crate doesn't exist"),
+            Self::Unauthorized => errors.push_error("missing authorization token"),
+            Self::PayloadTooLarge => errors.push_error("payload too large"),
+            Self::Forbidden(message)
+            | Self::Conflict(message)
+            | Self::PreconditionFailed(message)
+            | Self::Internal(message) => errors.push_error(message),
+            Self::InvalidBody(error) => {
+                errors.push_error_with_code(error.to_string(), error.code())
+            }
+            Self::ValidationFailed(messages) => errors.extend(messages),
+            Self::Rejected(response) => return response,
+        }
+        (status, errors).into_response()
+    }
+}
+impl Display for PublishError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::NotFound => write!(f, "crate doesn't exist"),
+            Self::Unauthorized => write!(f, "missing authorization token"),
+            Self::PayloadTooLarge => write!(f, "payload too large"),
+            Self::Forbidden(message)
+            | Self::Conflict(message)
+            | Self::PreconditionFailed(message)
+            | Self::Internal(message) => write!(f, "{message}"),
+            Self::InvalidBody(error) => write!(f, "{error}"),
+            Self::ValidationFailed(messages) => write!(f, "{}", messages.join(", ")),
+            Self::Rejected(response) => write!(f, "rejected with {}", response.status()),
+        }
+    }
+}
+/// Authentication and ownership checks answer with responses, their fixed messages become variants
+impl From<Response> for PublishError {
+    fn from(response: Response) -> Self {
+        match response.status() {
+            StatusCode::NOT_FOUND => Self::NotFound,
+            StatusCode::UNAUTHORIZED => Self::Unauthorized,
+            _ => Self::Rejected(response),
+        }
+    }
+}
+impl From<BodyError> for PublishError {
+    fn from(error: BodyError) -> Self {
+        Self::InvalidBody(error)
+    }
+}
+
 #[derive(Debug)]
 pub enum BodyError {
     UnexpectedEOF,
     InvalidMetadata(serde_json::Error),
+    /// Body size doesn't match the length prefixes or the `Content-Length` header
+    LengthMismatch {
+        expected: usize,
+        actual: usize,
+    },
 }
 impl BodyError {
-    fn status_code(&self) -> StatusCode {
+    /// Stable `code` of the error in the JSON response
+    pub fn code(&self) -> &'static str {
         match self {
-            Self::UnexpectedEOF | Self::InvalidMetadata(_) => StatusCode::BAD_REQUEST,
+            Self::UnexpectedEOF => "unexpected_eof",
+            Self::InvalidMetadata(_) => "invalid_metadata",
+            Self::LengthMismatch { .. } => "length_mismatch",
         }
     }
 }
-impl IntoResponse for BodyError {
-    fn into_response(self) -> axum::response::Response {
-        (self.status_code(), self.to_string()).into_response()
-    }
-}
 impl std::error::Error for BodyError {}
 impl Display for BodyError {
     fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
         match self {
             Self::UnexpectedEOF => f.write_str("Unexpected end of data stream."),
             Self::InvalidMetadata(e) => write!(f, "Invalid metadata: {e}"),
+            Self::LengthMismatch { expected, actual } => write!(
+                f,
+                "Body length {actual} doesn't match the declared length {expected}."
+            ),
         }
     }
 }
@@ -259,7 +1187,6 @@ pub struct Metadata {
     /// FILE WITH CONTENT of the license
     pub(crate) license_file: Option<String>,
     pub(crate) repository: Option<String>,
-    #[expect(dead_code)]
     pub(crate) badges: BTreeMap<String, BTreeMap<String, String>>,
     pub(crate) links: Option<String>,
     pub(crate) rust_version: Option<RustVersionReq>,
@@ -276,7 +1203,7 @@ pub struct DependencyMetadata {
     pub(crate) registry: Option<String>,
     pub(crate) explicit_name_in_toml: Option<CrateName>,
 }
-#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(rename_all = "lowercase")]
 pub enum DependencyKind {
     Dev,
@@ -315,3 +1242,362 @@ impl Display for RustVersionReq {
         self.0.fmt(f)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{path::Path, sync::Arc};
+
+    use axum::{
+        http::{header::IF_MATCH, HeaderMap, HeaderValue, StatusCode},
+        response::{IntoResponse, Response},
+    };
+    use serde_json::json;
+
+    use crate::{
+        index::{IndexEntry, IndexRepository},
+        publish::{
+            dependency_target_warnings, extract_request_body, hash_file_content,
+            max_version_precondition, metadata_warnings, url_errors,
+            weak_dependency_feature_warnings, wildcard_dependency_warnings, write_version_files,
+            BodyError, MaxVersionPrecondition, Metadata, PublishError,
+        },
+        test_util::{InMemoryIndex, InMemoryStorage},
+        version::WildcardDependencyPolicy,
+    };
+
+    fn framed(metadata: &[u8], declared_file_length: u32, file: &[u8]) -> Vec<u8> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&(metadata.len() as u32).to_le_bytes());
+        body.extend_from_slice(metadata);
+        body.extend_from_slice(&declared_file_length.to_le_bytes());
+        body.extend_from_slice(file);
+        body
+    }
+
+    #[test]
+    fn if_match_is_checked_against_the_max_version() {
+        let precondition = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(IF_MATCH, HeaderValue::from_str(value).unwrap());
+            max_version_precondition(&headers)
+        };
+        assert_eq!(max_version_precondition(&HeaderMap::new()).unwrap(), None);
+        assert_eq!(
+            precondition("*").unwrap(),
+            Some(MaxVersionPrecondition::Exists)
+        );
+        let quoted = precondition("\"1.2.3\"").unwrap().unwrap();
+        assert_eq!(precondition("1.2.3").unwrap().unwrap(), quoted);
+        assert!(precondition("latest").is_err());
+        let max = "1.2.3+build.5".parse().unwrap();
+        assert!(quoted.check(Some(&max)).is_ok());
+        let newer = "1.3.0-rc.1".parse().unwrap();
+        assert!(quoted.check(Some(&newer)).is_err());
+        assert!(quoted.check(None).is_err());
+        assert!(MaxVersionPrecondition::Exists.check(None).is_ok());
+    }
+    #[test]
+    fn truncated_file_is_rejected() {
+        let body = framed(b"{}", 10, b"short");
+        assert!(matches!(
+            extract_request_body(&body),
+            Err(BodyError::LengthMismatch {
+                expected: 20,
+                actual: 15
+            })
+        ));
+    }
+    #[test]
+    fn trailing_bytes_are_rejected() {
+        let body = framed(b"{}", 2, b"too long");
+        assert!(matches!(
+            extract_request_body(&body),
+            Err(BodyError::LengthMismatch {
+                expected: 12,
+                actual: 18
+            })
+        ));
+    }
+
+    #[test]
+    fn malformed_bodies_are_errors() {
+        // Seeds of the extract_request_body fuzz target
+        let mut only_metadata = 2u32.to_le_bytes().to_vec();
+        only_metadata.extend_from_slice(b"{}");
+        for body in [
+            Vec::new(),
+            vec![0; 4],
+            framed(b"{}", 0, b"")[..5].to_vec(),
+            only_metadata,
+        ] {
+            assert!(
+                matches!(extract_request_body(&body), Err(BodyError::UnexpectedEOF)),
+                "{body:?}"
+            );
+        }
+        let mut declared_too_long = u32::MAX.to_le_bytes().to_vec();
+        declared_too_long.extend_from_slice(b"{}");
+        assert!(matches!(
+            extract_request_body(&declared_too_long),
+            Err(BodyError::UnexpectedEOF)
+        ));
+        let metadata = serde_json::to_vec(&json!({"name": "foo"})).unwrap();
+        let trailing_garbage = [metadata.as_slice(), b"x"].concat();
+        assert!(matches!(
+            extract_request_body(&framed(&trailing_garbage, 0, b"")),
+            Err(BodyError::InvalidMetadata(_))
+        ));
+    }
+
+    fn metadata(license: Option<&str>, license_file: Option<&str>) -> Metadata {
+        serde_json::from_value(json!({
+            "name": "foo",
+            "vers": "1.0.0",
+            "deps": [],
+            "features": {},
+            "authors": [],
+            "description": "foo",
+            "keywords": [],
+            "categories": [],
+            "badges": {},
+            "license": license,
+            "license_file": license_file,
+        }))
+        .unwrap()
+    }
+
+    /// Depends on `serde`, optionally unless `serde_optional` is false, and has feature `json`
+    fn metadata_with_json_feature(serde_optional: bool, json_feature: &str) -> Metadata {
+        serde_json::from_value(json!({
+            "name": "foo",
+            "vers": "1.0.0",
+            "deps": [{
+                "name": "serde",
+                "version_req": "^1",
+                "features": [],
+                "optional": serde_optional,
+                "default_features": true,
+                "target": null,
+                "kind": "normal",
+                "registry": null,
+                "explicit_name_in_toml": null,
+            }],
+            "features": {"json": [json_feature]},
+            "authors": [],
+            "description": "foo",
+            "keywords": [],
+            "categories": [],
+            "badges": {},
+        }))
+        .unwrap()
+    }
+
+    fn metadata_with_serde_target(target: &str) -> Metadata {
+        let mut metadata = metadata_with_json_feature(true, "serde?/std");
+        metadata.deps[0].target = Some(target.to_string());
+        metadata
+    }
+
+    fn metadata_with_serde_requirement(version_req: &str) -> Metadata {
+        let mut metadata = metadata_with_json_feature(true, "serde?/std");
+        metadata.deps[0].version_req = version_req.parse().unwrap();
+        metadata
+    }
+
+    #[test]
+    fn links_have_to_be_web_urls() {
+        let mut metadata = metadata_with_json_feature(true, "serde?/std");
+        metadata.homepage = Some(String::from("https://example.com"));
+        metadata.repository = Some(String::from("http://git.example.com/foo"));
+        let errors = |metadata: &Metadata| {
+            url_errors(
+                metadata.homepage.as_deref(),
+                metadata.documentation.as_deref(),
+                metadata.repository.as_deref(),
+            )
+        };
+        assert!(errors(&metadata).is_empty());
+        metadata.homepage = Some(String::from("example.com"));
+        metadata.documentation = Some(String::from("ftp://example.com/docs"));
+        assert_eq!(errors(&metadata).len(), 2);
+    }
+    #[test]
+    fn unbounded_requirements_are_warned_about() {
+        for version_req in ["*", ">=0.0.0"] {
+            let metadata = metadata_with_serde_requirement(version_req);
+            let warnings =
+                wildcard_dependency_warnings(&metadata, WildcardDependencyPolicy::Warn).unwrap();
+            assert_eq!(warnings.len(), 1, "{version_req}");
+            let errors = wildcard_dependency_warnings(&metadata, WildcardDependencyPolicy::Reject)
+                .expect_err(version_req);
+            assert_eq!(errors, warnings);
+        }
+    }
+    #[test]
+    fn bounded_requirements_are_accepted() {
+        let metadata = metadata_with_serde_requirement("^1.2");
+        for policy in [
+            WildcardDependencyPolicy::Warn,
+            WildcardDependencyPolicy::Reject,
+        ] {
+            assert_eq!(
+                wildcard_dependency_warnings(&metadata, policy),
+                Ok(Vec::new())
+            );
+        }
+    }
+    #[test]
+    fn cfg_and_triple_targets_are_accepted() {
+        for target in [
+            "cfg(unix)",
+            "cfg(all(target_os = \"linux\", not(target_env = \"musl\")))",
+            "x86_64-unknown-linux-gnu",
+        ] {
+            assert_eq!(
+                dependency_target_warnings(&metadata_with_serde_target(target)),
+                Ok(Vec::new()),
+                "{target}"
+            );
+        }
+    }
+    #[test]
+    fn malformed_targets_are_rejected() {
+        for target in [
+            "cfg(unix",
+            "cfg(unix,,)",
+            "linux(x86)",
+            "x86 64",
+            "",
+            "cfg()garbage",
+        ] {
+            let errors =
+                dependency_target_warnings(&metadata_with_serde_target(target)).expect_err(target);
+            assert_eq!(errors.len(), 1);
+        }
+    }
+    #[test]
+    fn cfg_values_cargo_ignores_are_warned_about() {
+        let warnings =
+            dependency_target_warnings(&metadata_with_serde_target("cfg(debug_assertions)"))
+                .unwrap();
+        assert_eq!(warnings.len(), 1);
+    }
+    #[test]
+    fn weak_feature_of_optional_dependency_is_accepted() {
+        assert_eq!(
+            weak_dependency_feature_warnings(&metadata_with_json_feature(true, "serde?/std")),
+            Ok(Vec::new())
+        );
+    }
+    #[test]
+    fn weak_feature_of_required_dependency_is_warned_about() {
+        let warnings =
+            weak_dependency_feature_warnings(&metadata_with_json_feature(false, "serde?/std"))
+                .unwrap();
+        assert_eq!(warnings.len(), 1);
+    }
+    #[test]
+    fn weak_feature_of_undeclared_dependency_is_rejected() {
+        let errors =
+            weak_dependency_feature_warnings(&metadata_with_json_feature(true, "serde_json?/std"))
+                .unwrap_err();
+        assert_eq!(
+            errors,
+            ["feature json includes serde_json?/std, but serde_json is not a dependency"]
+        );
+    }
+    #[test]
+    fn missing_license_is_warned_about() {
+        assert_eq!(
+            metadata_warnings(&metadata(None, None)),
+            ["crate published without a license"]
+        );
+    }
+    #[test]
+    fn keywords_differing_in_case_are_warned_about() {
+        let mut metadata = metadata(Some("MIT"), None);
+        metadata.keywords = ["HTTP", "http", "Http", "web"]
+            .into_iter()
+            .map(|keyword| keyword.parse().unwrap())
+            .collect();
+        assert_eq!(
+            metadata_warnings(&metadata),
+            ["keywords HTTP, Http, http are all stored as http"]
+        );
+    }
+    #[test]
+    fn license_or_license_file_is_enough() {
+        assert!(metadata_warnings(&metadata(Some("MIT"), None)).is_empty());
+        assert!(metadata_warnings(&metadata(None, Some("LICENSE"))).is_empty());
+    }
+    #[tokio::test]
+    async fn body_errors_have_codes() {
+        let error = PublishError::from(BodyError::UnexpectedEOF);
+        assert_eq!(error.status_code(), StatusCode::BAD_REQUEST);
+        let response = error.into_response();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            body,
+            json!({"errors": [{"detail": "Unexpected end of data stream.", "code": "unexpected_eof"}]})
+        );
+        let invalid_metadata = extract_request_body(b"\x02\0\0\0{}\0\0\0\0").unwrap_err();
+        assert_eq!(invalid_metadata.code(), "invalid_metadata");
+    }
+    #[test]
+    fn shared_rejections_keep_their_status() {
+        let rejection = |status: StatusCode| -> Response { (status, "message").into_response() };
+        assert!(matches!(
+            PublishError::from(rejection(StatusCode::UNAUTHORIZED)),
+            PublishError::Unauthorized
+        ));
+        assert!(matches!(
+            PublishError::from(rejection(StatusCode::NOT_FOUND)),
+            PublishError::NotFound
+        ));
+        let forbidden = PublishError::from(rejection(StatusCode::FORBIDDEN));
+        assert_eq!(forbidden.status_code(), StatusCode::FORBIDDEN);
+        assert_eq!(forbidden.into_response().status(), StatusCode::FORBIDDEN);
+    }
+    #[tokio::test]
+    async fn published_version_lands_in_storage_and_index() {
+        let metadata_json = serde_json::to_vec(&json!({
+            "name": "foo",
+            "vers": "1.0.0",
+            "deps": [],
+            "features": {},
+            "authors": [],
+            "description": "foo",
+            "keywords": [],
+            "categories": [],
+            "badges": {},
+            "license": "MIT",
+        }))
+        .unwrap();
+        let archive = b"not a real archive";
+        let body = framed(&metadata_json, archive.len() as u32, archive);
+        let (crate_metadata, file_content) = extract_request_body(&body).unwrap();
+        let entry = IndexEntry::new(&crate_metadata, file_content).unwrap();
+        let storage = InMemoryStorage::default();
+        let index = Arc::new(InMemoryIndex::default());
+        let repository = IndexRepository::new(index.clone());
+        write_version_files(&crate_metadata, file_content, &entry, &storage, &repository)
+            .await
+            .unwrap();
+        assert_eq!(
+            storage.get_file(&crate_metadata.name, &crate_metadata.vers),
+            Some(archive.to_vec())
+        );
+        let index_file = index.file(Path::new("3/f/foo")).unwrap();
+        let line: serde_json::Value = serde_json::from_str(index_file.trim_end()).unwrap();
+        assert_eq!(line["vers"], "1.0.0");
+        assert_eq!(line["cksum"], hash_file_content(archive));
+        let commits = index.commits();
+        assert_eq!(commits.len(), 1);
+        assert_eq!(commits[0].file_path, Path::new("3/f/foo"));
+        assert_eq!(commits[0].content.as_ref(), Some(&index_file));
+    }
+}
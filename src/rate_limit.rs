@@ -0,0 +1,258 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header::RETRY_AFTER, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use sqlx::{Pool, Postgres};
+
+use crate::{
+    auth::{hash_token, token_from_headers},
+    postgres::get_user_by_token_hash,
+};
+
+/// How often buckets that refilled completely are dropped from the map
+const EVICTION_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+/// Who a request is accounted to
+pub enum RateLimitKey {
+    /// SHA-256 hex digest of a token that authenticated
+    Token(String),
+    /// Requests without a token known to be valid are limited per client address
+    Ip(IpAddr),
+}
+
+#[derive(Debug)]
+/// Token bucket per client, allowing `per_minute` requests on average with bursts up to `burst`
+pub struct RateLimiter {
+    per_second: f64,
+    burst: f64,
+    state: Mutex<RateLimiterState>,
+}
+
+#[derive(Debug)]
+struct RateLimiterState {
+    buckets: HashMap<RateLimitKey, Bucket>,
+    last_eviction: Instant,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(per_minute: u32, burst: u32) -> Self {
+        Self::new_at(per_minute, burst, Instant::now())
+    }
+    fn new_at(per_minute: u32, burst: u32, now: Instant) -> Self {
+        Self {
+            per_second: f64::from(per_minute.max(1)) / 60.0,
+            burst: f64::from(burst.max(1)),
+            state: Mutex::new(RateLimiterState {
+                buckets: HashMap::new(),
+                last_eviction: now,
+            }),
+        }
+    }
+    /// Takes one token from the bucket of `key`
+    ///
+    /// Returns how long to wait for the next token if the bucket is empty.
+    pub fn check(&self, key: RateLimitKey) -> Result<(), Duration> {
+        self.check_at(key, Instant::now())
+    }
+    fn check_at(&self, key: RateLimitKey, now: Instant) -> Result<(), Duration> {
+        let mut state = self.state.lock().expect("rate limiter lock poisoned");
+        if now.duration_since(state.last_eviction) >= EVICTION_INTERVAL {
+            state
+                .buckets
+                .retain(|_, bucket| self.refilled(*bucket, now).tokens < self.burst);
+            state.last_eviction = now;
+        }
+        let bucket = state.buckets.entry(key).or_insert(Bucket {
+            tokens: self.burst,
+            last_refill: now,
+        });
+        *bucket = self.refilled(*bucket, now);
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / self.per_second,
+            ))
+        }
+    }
+    /// Whether `key` has a bucket, for tokens: whether it authenticated since its bucket was
+    /// last evicted
+    pub fn tracks(&self, key: &RateLimitKey) -> bool {
+        self.state
+            .lock()
+            .expect("rate limiter lock poisoned")
+            .buckets
+            .contains_key(key)
+    }
+    /// Gives `key` a full bucket if it has none yet
+    pub fn track(&self, key: RateLimitKey) {
+        self.track_at(key, Instant::now());
+    }
+    fn track_at(&self, key: RateLimitKey, now: Instant) {
+        self.state
+            .lock()
+            .expect("rate limiter lock poisoned")
+            .buckets
+            .entry(key)
+            .or_insert(Bucket {
+                tokens: self.burst,
+                last_refill: now,
+            });
+    }
+    fn refilled(&self, bucket: Bucket, now: Instant) -> Bucket {
+        let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+        Bucket {
+            tokens: (bucket.tokens + elapsed * self.per_second).min(self.burst),
+            last_refill: now,
+        }
+    }
+    #[cfg(test)]
+    fn tracked_clients(&self) -> usize {
+        self.state.lock().unwrap().buckets.len()
+    }
+}
+
+/// Requests with a token that authenticated before are limited per token, all others per address
+///
+/// A token only gets its own bucket after it was found in the database. Made-up tokens share the
+/// bucket of their address, and are stopped before they are looked up.
+pub async fn limit_rate(
+    State((limiter, database_connection_pool)): State<(Arc<RateLimiter>, Arc<Pool<Postgres>>)>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let token_key =
+        token_from_headers(request.headers()).map(|token| RateLimitKey::Token(hash_token(token)));
+    let result = match token_key {
+        Some(token_key) if limiter.tracks(&token_key) => limiter.check(token_key),
+        token_key => {
+            let Some(ConnectInfo(address)) = request.extensions().get::<ConnectInfo<SocketAddr>>()
+            else {
+                return next.run(request).await;
+            };
+            let result = limiter.check(RateLimitKey::Ip(address.ip()));
+            if let (Ok(()), Some(token_key)) = (result, token_key) {
+                if token_authenticates(&token_key, &database_connection_pool).await {
+                    limiter.track(token_key);
+                }
+            }
+            result
+        }
+    };
+    if let Err(wait) = result {
+        return (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(RETRY_AFTER, (wait.as_secs_f64().ceil() as u64).to_string())],
+            "too many publish requests, slow down",
+        )
+            .into_response();
+    }
+    next.run(request).await
+}
+
+async fn token_authenticates(token_key: &RateLimitKey, pool: &Pool<Postgres>) -> bool {
+    let RateLimitKey::Token(token_hash) = token_key else {
+        return false;
+    };
+    let mut connection = match pool.acquire().await {
+        Ok(connection) => connection,
+        Err(e) => {
+            eprintln!("Failed to connect for rate limiting: {e}");
+            return false;
+        }
+    };
+    get_user_by_token_hash(token_hash, &mut connection)
+        .await
+        .inspect_err(|e| eprintln!("Failed to look up token for rate limiting: {e}"))
+        .is_ok_and(|user| user.is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{IpAddr, Ipv4Addr},
+        time::{Duration, Instant},
+    };
+
+    use crate::rate_limit::{RateLimitKey, RateLimiter};
+
+    fn token(s: &str) -> RateLimitKey {
+        RateLimitKey::Token(s.to_string())
+    }
+
+    #[test]
+    fn burst_then_refill() {
+        let start = Instant::now();
+        let limiter = RateLimiter::new_at(6, 2, start);
+        assert!(limiter.check_at(token("a"), start).is_ok());
+        assert!(limiter.check_at(token("a"), start).is_ok());
+        let wait = limiter.check_at(token("a"), start).unwrap_err();
+        assert_eq!(wait, Duration::from_secs(10));
+        assert!(limiter
+            .check_at(token("a"), start + Duration::from_secs(5))
+            .is_err());
+        assert!(limiter
+            .check_at(token("a"), start + Duration::from_secs(10))
+            .is_ok());
+    }
+    #[test]
+    fn keys_are_independent() {
+        let start = Instant::now();
+        let limiter = RateLimiter::new_at(1, 1, start);
+        assert!(limiter.check_at(token("a"), start).is_ok());
+        assert!(limiter.check_at(token("a"), start).is_err());
+        assert!(limiter.check_at(token("b"), start).is_ok());
+        let ip = RateLimitKey::Ip(IpAddr::V4(Ipv4Addr::LOCALHOST));
+        assert!(limiter.check_at(ip, start).is_ok());
+    }
+    #[test]
+    fn refill_is_capped_at_burst() {
+        let start = Instant::now();
+        let limiter = RateLimiter::new_at(60, 2, start);
+        let later = start + Duration::from_secs(3600);
+        assert!(limiter.check_at(token("a"), later).is_ok());
+        assert!(limiter.check_at(token("a"), later).is_ok());
+        assert!(limiter.check_at(token("a"), later).is_err());
+    }
+    #[test]
+    fn tracked_tokens_start_with_a_full_bucket() {
+        let start = Instant::now();
+        let limiter = RateLimiter::new_at(1, 2, start);
+        assert!(!limiter.tracks(&token("a")));
+        limiter.track_at(token("a"), start);
+        assert!(limiter.tracks(&token("a")));
+        assert!(limiter.check_at(token("a"), start).is_ok());
+        // Tracking again doesn't refill
+        limiter.track_at(token("a"), start);
+        assert!(limiter.check_at(token("a"), start).is_ok());
+        assert!(limiter.check_at(token("a"), start).is_err());
+    }
+    #[test]
+    fn full_buckets_are_evicted() {
+        let start = Instant::now();
+        let limiter = RateLimiter::new_at(60, 1, start);
+        assert!(limiter.check_at(token("a"), start).is_ok());
+        assert_eq!(limiter.tracked_clients(), 1);
+        assert!(limiter
+            .check_at(token("b"), start + Duration::from_secs(61))
+            .is_ok());
+        assert_eq!(limiter.tracked_clients(), 1);
+    }
+}
@@ -1,25 +1,79 @@
-use std::ops::Deref;
+use std::{ops::Deref, time::Duration};
 
 use tokio::sync::{Mutex, MutexGuard};
 
 #[derive(Debug)]
 /// Type of Mutex that only allows read-only access to the inner item
-pub struct ReadOnlyMutex<T>(Mutex<T>);
+///
+/// The lock serializes whatever the item stands for (like git operations on a repository).
+/// Since the item itself never changes, it can also be read without taking the lock.
+pub struct ReadOnlyMutex<T> {
+    value: T,
+    lock: Mutex<()>,
+}
 
 impl<T> ReadOnlyMutex<T> {
     pub fn new(path: T) -> Self {
-        Self(Mutex::new(path))
+        Self {
+            value: path,
+            lock: Mutex::new(()),
+        }
     }
     pub async fn lock(&self) -> ReadOnlyGuard<'_, T> {
-        ReadOnlyGuard(self.0.lock().await)
+        ReadOnlyGuard {
+            value: &self.value,
+            _guard: self.lock.lock().await,
+        }
+    }
+    /// [`Self::lock`] giving up after `timeout`, `None` if the lock wasn't acquired in time
+    pub async fn lock_timeout(&self, timeout: Duration) -> Option<ReadOnlyGuard<'_, T>> {
+        tokio::time::timeout(timeout, self.lock()).await.ok()
+    }
+    /// Non-blocking [`Self::lock`], `None` if the lock is currently held
+    pub fn try_lock(&self) -> Option<ReadOnlyGuard<'_, T>> {
+        Some(ReadOnlyGuard {
+            value: &self.value,
+            _guard: self.lock.try_lock().ok()?,
+        })
+    }
+    /// Reads the item without waiting for the lock
+    ///
+    /// Only for callers that don't touch what the lock protects.
+    pub fn get_unlocked(&self) -> &T {
+        &self.value
     }
 }
 
-pub struct ReadOnlyGuard<'m, T>(MutexGuard<'m, T>);
+pub struct ReadOnlyGuard<'m, T> {
+    value: &'m T,
+    _guard: MutexGuard<'m, ()>,
+}
 
 impl<'m, T> Deref for ReadOnlyGuard<'m, T> {
     type Target = T;
     fn deref(&self) -> &Self::Target {
-        &self.0
+        self.value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::read_only_mutex::ReadOnlyMutex;
+
+    #[tokio::test]
+    async fn lock_timeout_gives_up_while_held() {
+        let mutex = ReadOnlyMutex::new(());
+        let guard = mutex.lock().await;
+        assert!(mutex
+            .lock_timeout(Duration::from_millis(10))
+            .await
+            .is_none());
+        drop(guard);
+        assert!(mutex
+            .lock_timeout(Duration::from_millis(10))
+            .await
+            .is_some());
     }
 }
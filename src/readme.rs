@@ -0,0 +1,27 @@
+use axum::{
+    extract::{Path, State},
+    http::{header::CONTENT_TYPE, StatusCode},
+    response::{IntoResponse, Response},
+};
+
+use crate::{
+    crate_info::VersionPath, middleware::internal_server_error, postgres::get_version_readme,
+    ServerState,
+};
+
+/// The README packaged in the version's crate file, as markdown
+pub async fn version_readme_handler(
+    State(state): State<ServerState>,
+    Path(VersionPath {
+        crate_name,
+        version,
+    }): Path<VersionPath>,
+) -> Result<Response, Response> {
+    let readme = get_version_readme(&crate_name, &version, &*state.database_connection_pool)
+        .await
+        .inspect_err(|e| eprintln!("Failed to get README: {e}"))
+        .map_err(|_e| internal_server_error("couldn't get README"))?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "crate or version doesn't exist").into_response())?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "version has no README").into_response())?;
+    Ok(([(CONTENT_TYPE, "text/markdown; charset=utf-8")], readme).into_response())
+}
@@ -0,0 +1,17 @@
+use std::sync::Arc;
+
+use crate::{
+    crate_file::CrateStorage, docs::DocsStorage, index::IndexRepository,
+    read_only_mutex::ReadOnlyMutex, upstream::Upstream,
+};
+
+#[derive(Clone, Debug)]
+/// Where crate files and the index are kept, built in `main` from the environment
+pub struct RegistryConfig {
+    pub crate_storage: Arc<dyn CrateStorage>,
+    pub docs_storage: Arc<DocsStorage>,
+    /// The lock serializes index changes, whichever backend persists them
+    pub index_repository: Arc<ReadOnlyMutex<IndexRepository>>,
+    /// Serves crates that weren't published here, off unless configured
+    pub upstream: Option<Arc<Upstream>>,
+}
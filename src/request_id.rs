@@ -0,0 +1,33 @@
+use axum::{
+    extract::Request,
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use uuid::Uuid;
+
+const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+/// Longer incoming ids are replaced instead of being stored
+const MAX_REQUEST_ID_LENGTH: usize = 64;
+
+#[derive(Clone, Debug)]
+/// Identifies a request in logs and audit events
+pub struct RequestId(pub String);
+
+/// Keeps an incoming `X-Request-Id` or assigns a new one, echoing it in the response
+pub async fn assign_request_id(mut request: Request, next: Next) -> Response {
+    let request_id = request
+        .headers()
+        .get(&REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| !id.is_empty() && id.len() <= MAX_REQUEST_ID_LENGTH)
+        .map_or_else(|| Uuid::new_v4().to_string(), str::to_string);
+    request
+        .extensions_mut()
+        .insert(RequestId(request_id.clone()));
+    let mut response = next.run(request).await;
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
@@ -0,0 +1,284 @@
+use std::{fmt::Display, str::FromStr};
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    auth::Admin,
+    crate_name::{CrateName, InvalidCrateName, NormalizedCrateName},
+    middleware::{bad_request, internal_server_error},
+    postgres::{
+        add_reserved_name, add_reserved_name_user, delete_reserved_name, get_reserved_names,
+        get_user_id_by_login,
+    },
+    ServerState,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// A crate name, or a name prefix when written with a trailing `*` like `acme-*`
+///
+/// Stored normalized, so `Acme_*` reserves the same names as `acme-*`.
+pub struct ReservedPattern {
+    pub name: NormalizedCrateName,
+    pub is_prefix: bool,
+}
+impl FromStr for ReservedPattern {
+    type Err = InvalidCrateName;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, is_prefix) = match s.strip_suffix('*') {
+            Some(prefix) => (prefix, true),
+            None => (s, false),
+        };
+        Ok(Self {
+            name: name.parse::<CrateName>()?.normalized(),
+            is_prefix,
+        })
+    }
+}
+impl Display for ReservedPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)?;
+        if self.is_prefix {
+            f.write_str("*")?;
+        }
+        Ok(())
+    }
+}
+
+impl ReservedPattern {
+    pub fn matches(&self, crate_name: &CrateName) -> bool {
+        let normalized = crate_name.normalized();
+        if self.is_prefix {
+            normalized.as_str().starts_with(self.name.as_str())
+        } else {
+            normalized == self.name
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+/// Names reserved by the operator in the configuration, only new crates are checked against them
+///
+/// Unlike reservations made through the admin API no user is exempt.
+pub struct ConfiguredReservedNames {
+    patterns: Vec<ReservedPattern>,
+}
+impl ConfiguredReservedNames {
+    /// Reads the file at `value` if there is one, otherwise takes `value` as a comma separated list
+    ///
+    /// Files list one pattern per line, empty lines and lines starting with `#` are skipped.
+    pub fn from_config(value: &str) -> Result<Self, ReservedNamesConfigError> {
+        let path = std::path::Path::new(value);
+        if path.is_file() {
+            let content = std::fs::read_to_string(path).map_err(ReservedNamesConfigError::Read)?;
+            Self::parse(
+                content
+                    .lines()
+                    .filter(|line| !line.trim_start().starts_with('#')),
+            )
+        } else {
+            Self::parse(value.split(','))
+        }
+    }
+    fn parse<'a>(entries: impl Iterator<Item = &'a str>) -> Result<Self, ReservedNamesConfigError> {
+        let patterns = entries
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                entry
+                    .parse()
+                    .map_err(|e| ReservedNamesConfigError::InvalidPattern(entry.to_string(), e))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { patterns })
+    }
+    pub fn contains(&self, crate_name: &CrateName) -> bool {
+        self.patterns
+            .iter()
+            .any(|pattern| pattern.matches(crate_name))
+    }
+}
+
+#[derive(Debug)]
+pub enum ReservedNamesConfigError {
+    Read(std::io::Error),
+    InvalidPattern(String, InvalidCrateName),
+}
+impl std::error::Error for ReservedNamesConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Read(io) => Some(io),
+            Self::InvalidPattern(_, name) => Some(name),
+        }
+    }
+}
+impl Display for ReservedNamesConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Read(io) => write!(f, "failed to read reserved names: {io}"),
+            Self::InvalidPattern(pattern, name) => {
+                write!(f, "invalid reserved name {pattern}: {name}")
+            }
+        }
+    }
+}
+
+pub async fn list_reserved_names_handler(
+    _admin: Admin,
+    State(ServerState {
+        database_connection_pool,
+        ..
+    }): State<ServerState>,
+) -> Result<Json<ReservedNamesResponse>, Response> {
+    let mut connection = database_connection_pool
+        .acquire()
+        .await
+        .map_err(|_e| internal_server_error("couldn't connect to database"))?;
+    let reserved_names = get_reserved_names(&mut connection)
+        .await
+        .inspect_err(|e| eprintln!("Failed to get reserved names: {e}"))
+        .map_err(|_e| internal_server_error("couldn't get reserved names"))?;
+    Ok(Json(ReservedNamesResponse { reserved_names }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReservationBody {
+    pattern: String,
+    /// Logins of the users that may still publish matching crates
+    #[serde(default)]
+    allowed_users: Vec<String>,
+}
+
+pub async fn add_reserved_name_handler(
+    _admin: Admin,
+    State(ServerState {
+        database_connection_pool,
+        ..
+    }): State<ServerState>,
+    Json(ReservationBody {
+        pattern,
+        allowed_users,
+    }): Json<ReservationBody>,
+) -> Result<Json<ReservationsChanged>, Response> {
+    let pattern = pattern
+        .parse::<ReservedPattern>()
+        .map_err(|e| bad_request(format!("invalid pattern: {e}")))?;
+    let mut transaction = database_connection_pool
+        .begin()
+        .await
+        .map_err(|_e| internal_server_error("couldn't start transaction"))?;
+    let reservation_id = add_reserved_name(&pattern, &mut transaction)
+        .await
+        .inspect_err(|e| eprintln!("Failed to reserve name: {e}"))
+        .map_err(|_e| internal_server_error("couldn't reserve name"))?
+        .ok_or_else(|| {
+            (
+                StatusCode::CONFLICT,
+                format!("{pattern} is already reserved"),
+            )
+                .into_response()
+        })?;
+    for login in &allowed_users {
+        let user_id = get_user_id_by_login(login, &mut transaction)
+            .await
+            .inspect_err(|e| eprintln!("Failed to look up user: {e}"))
+            .map_err(|_e| internal_server_error("couldn't look up user"))?
+            .ok_or_else(|| bad_request(format!("unknown user: {login}")))?;
+        add_reserved_name_user(reservation_id, user_id, &mut transaction)
+            .await
+            .inspect_err(|e| eprintln!("Failed to allow user: {e}"))
+            .map_err(|_e| internal_server_error("couldn't allow user"))?;
+    }
+    transaction
+        .commit()
+        .await
+        .map_err(|_e| internal_server_error("committing to database failed"))?;
+    Ok(Json(ReservationsChanged {
+        ok: true,
+        msg: format!("reserved {pattern}"),
+    }))
+}
+
+pub async fn delete_reserved_name_handler(
+    _admin: Admin,
+    State(ServerState {
+        database_connection_pool,
+        ..
+    }): State<ServerState>,
+    Path(reservation_id): Path<i32>,
+) -> Result<Json<ReservationsChanged>, Response> {
+    let mut connection = database_connection_pool
+        .acquire()
+        .await
+        .map_err(|_e| internal_server_error("couldn't connect to database"))?;
+    if !delete_reserved_name(reservation_id, &mut connection)
+        .await
+        .inspect_err(|e| eprintln!("Failed to delete reservation: {e}"))
+        .map_err(|_e| internal_server_error("couldn't delete reservation"))?
+    {
+        return Err((StatusCode::NOT_FOUND, "reservation doesn't exist").into_response());
+    }
+    Ok(Json(ReservationsChanged {
+        ok: true,
+        msg: format!("reservation {reservation_id} deleted"),
+    }))
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ReservedName {
+    pub id: i32,
+    pub pattern: String,
+    pub allowed_users: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReservedNamesResponse {
+    reserved_names: Vec<ReservedName>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReservationsChanged {
+    ok: bool,
+    msg: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::reserved_names::{ConfiguredReservedNames, ReservedPattern};
+
+    #[test]
+    fn patterns_are_normalized() {
+        let exact = "Acme-Core".parse::<ReservedPattern>().unwrap();
+        assert!(!exact.is_prefix);
+        assert_eq!(exact.to_string(), "acme_core");
+        let prefix = "acme-*".parse::<ReservedPattern>().unwrap();
+        assert!(prefix.is_prefix);
+        assert_eq!(prefix.to_string(), "acme_*");
+    }
+    #[test]
+    fn only_a_trailing_star_is_a_prefix() {
+        assert!("*".parse::<ReservedPattern>().is_err());
+        assert!("ac*me".parse::<ReservedPattern>().is_err());
+    }
+    #[test]
+    fn configured_names_block_every_spelling() {
+        let reserved = ConfiguredReservedNames::from_config("std, acme-*,").unwrap();
+        for name in ["std", "Std", "STD", "Acme_Core", "acme-utils"] {
+            assert!(reserved.contains(&name.parse().unwrap()), "{name}");
+        }
+        for name in ["stdx", "my-std", "acme"] {
+            assert!(!reserved.contains(&name.parse().unwrap()), "{name}");
+        }
+    }
+    #[test]
+    fn invalid_configured_names_are_rejected() {
+        assert!(ConfiguredReservedNames::from_config("std,1abc").is_err());
+    }
+}
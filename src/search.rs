@@ -0,0 +1,312 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgConnection;
+
+use crate::{
+    crate_info::CrateSummary,
+    middleware::internal_server_error,
+    non_empty_strings::Keyword,
+    postgres::{
+        full_text_search_crates, fuzzy_search_crates, get_crates_by_category,
+        get_crates_by_keyword, get_sorted_crates, is_missing_extension, search_crates, CrateRecord,
+        RankedCrateRecord,
+    },
+    ServerState,
+};
+
+/// Cargo asks for 10 results unless `--limit` is given
+const DEFAULT_PER_PAGE: u32 = 10;
+pub const MAX_PER_PAGE: u32 = 100;
+/// Single words shorter than this are matched as substrings, full-text search needs whole words
+const MIN_FULL_TEXT_WORD_LENGTH: usize = 4;
+
+#[derive(Debug, Deserialize)]
+pub struct SearchQuery {
+    #[serde(default)]
+    q: String,
+    /// Only crates in this category
+    category: Option<String>,
+    /// Only crates with this keyword
+    keyword: Option<Keyword>,
+    /// Relevance if not given
+    sort: Option<CrateSort>,
+    per_page: Option<u32>,
+    page: Option<u32>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CrateSort {
+    /// Best full-text matches first, near-matches of the name after all matches
+    Relevance,
+    /// Most downloaded first
+    Downloads,
+    /// Most recently published version first, crates without publish times last
+    #[serde(alias = "recent")]
+    RecentUpdates,
+    /// By name
+    Alphabetical,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+/// Restrictions of a search besides its query, bound as parameters of the search queries
+pub struct SearchFilter<'a> {
+    /// Name of a category
+    pub category: Option<&'a str>,
+    /// Normalized keyword
+    pub keyword: Option<&'a str>,
+}
+
+pub async fn search_handler(
+    State(ServerState {
+        database_connection_pool,
+        ..
+    }): State<ServerState>,
+    Query(SearchQuery {
+        q,
+        category,
+        keyword,
+        sort,
+        per_page,
+        page,
+    }): Query<SearchQuery>,
+) -> Result<Response, Response> {
+    let keyword = keyword.map(|keyword| keyword.normalized());
+    let filter = SearchFilter {
+        category: category.as_deref(),
+        keyword: keyword.as_deref(),
+    };
+    let per_page = per_page.unwrap_or(DEFAULT_PER_PAGE).clamp(1, MAX_PER_PAGE);
+    let offset = u64::from(page.unwrap_or(1).max(1) - 1) * u64::from(per_page);
+    let mut connection = database_connection_pool.acquire().await.map_err(|_e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "couldn't connect to database",
+        )
+            .into_response()
+    })?;
+    if let Some(sort) = sort.filter(|sort| !matches!(sort, CrateSort::Relevance)) {
+        let (crates, total) = get_sorted_crates(
+            q.trim(),
+            &filter,
+            sort,
+            per_page.into(),
+            offset as i64,
+            &mut *connection,
+        )
+        .await
+        .inspect_err(|e| eprintln!("Failed to list sorted crates: {e}"))
+        .map_err(|_e| internal_server_error("search failed"))?;
+        return Ok(Json(SearchResponse {
+            crates: crates.into_iter().map(RankedCrateSummary::from).collect(),
+            meta: SearchMeta { total },
+        })
+        .into_response());
+    }
+    let q = q.trim();
+    let offset = offset as i64;
+    let (mut crates, mut total) =
+        matching_crates(q, &filter, per_page.into(), offset, &mut connection).await?;
+    // Near-matches follow the matches, so only when those fit on the first page
+    if is_single_word(q) && crates.len() < per_page as usize {
+        let (first_page, matches) = if offset == 0 {
+            (crate_names(&crates), total)
+        } else {
+            let (first_page, matches) =
+                matching_crates(q, &filter, per_page.into(), 0, &mut connection).await?;
+            (crate_names(&first_page), matches)
+        };
+        if matches < i64::from(per_page) {
+            let near_matches = fuzzy_search_crates(
+                q,
+                &filter,
+                &first_page,
+                i64::from(per_page) - crates.len() as i64,
+                (offset - matches).max(0),
+                &mut connection,
+            )
+            .await;
+            match near_matches {
+                Ok((near_matches, near_match_total)) => {
+                    crates.extend(near_matches);
+                    total = matches + near_match_total;
+                }
+                Err(e) if is_missing_extension(&e) => {
+                    eprintln!("Warning: skipping similar crate names, is pg_trgm installed? {e}");
+                }
+                Err(e) => {
+                    eprintln!("Failed to search similar crate names: {e}");
+                    return Err(internal_server_error("search failed"));
+                }
+            }
+        }
+    }
+    Ok(Json(SearchResponse {
+        crates: crates.into_iter().map(CrateSummary::from).collect(),
+        meta: SearchMeta { total },
+    })
+    .into_response())
+}
+
+/// Full-text search, or a substring search of name and description
+async fn matching_crates(
+    q: &str,
+    filter: &SearchFilter<'_>,
+    limit: i64,
+    offset: i64,
+    connection: &mut PgConnection,
+) -> Result<(Vec<CrateRecord>, i64), Response> {
+    let full_text_results = if uses_substring_search(q) {
+        None
+    } else {
+        full_text_search_crates(q, filter, limit, offset, connection)
+            .await
+            .inspect_err(|e| eprintln!("Failed to search crates: {e}"))
+            .map_err(|_e| internal_server_error("search failed"))?
+    };
+    match full_text_results {
+        Some(results) => Ok(results),
+        None => search_crates(q, filter, limit, offset, connection)
+            .await
+            .inspect_err(|e| eprintln!("Failed to search crates: {e}"))
+            .map_err(|_e| internal_server_error("search failed")),
+    }
+}
+
+fn crate_names(crates: &[CrateRecord]) -> Vec<String> {
+    crates.iter().map(|record| record.name.clone()).collect()
+}
+
+/// Only a lone word can be a misspelled name, other queries may use search operators
+fn is_single_word(query: &str) -> bool {
+    !query.is_empty() && !query.contains(char::is_whitespace)
+}
+
+/// Empty queries list every crate, short single words are likely the start of a name
+fn uses_substring_search(query: &str) -> bool {
+    let mut words = query.split_whitespace();
+    match (words.next(), words.next()) {
+        (None, _) => true,
+        (Some(word), None) => word.chars().count() < MIN_FULL_TEXT_WORD_LENGTH,
+        (Some(_), Some(_)) => false,
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PageQuery {
+    per_page: Option<u32>,
+    page: Option<u32>,
+}
+
+/// Lists the crates in a category, in the shape of search results
+pub async fn crates_by_category_handler(
+    State(ServerState {
+        database_connection_pool,
+        ..
+    }): State<ServerState>,
+    Path(category): Path<String>,
+    Query(PageQuery { per_page, page }): Query<PageQuery>,
+) -> Result<Json<SearchResponse>, Response> {
+    let per_page = per_page.unwrap_or(DEFAULT_PER_PAGE).clamp(1, MAX_PER_PAGE);
+    let mut connection = database_connection_pool
+        .acquire()
+        .await
+        .map_err(|_e| internal_server_error("couldn't connect to database"))?;
+    let (crates, total) =
+        get_crates_by_category(&category, page.unwrap_or(1), per_page, &mut connection)
+            .await
+            .inspect_err(|e| eprintln!("Failed to list crates in category: {e}"))
+            .map_err(|_e| internal_server_error("couldn't list crates in category"))?
+            .ok_or_else(|| (StatusCode::NOT_FOUND, "category doesn't exist").into_response())?;
+    Ok(Json(SearchResponse {
+        crates: crates.into_iter().map(CrateSummary::from).collect(),
+        meta: SearchMeta { total },
+    }))
+}
+
+/// Lists the crates with a keyword, in the shape of search results
+pub async fn crates_by_keyword_handler(
+    State(ServerState {
+        database_connection_pool,
+        ..
+    }): State<ServerState>,
+    Path(keyword): Path<Keyword>,
+    Query(PageQuery { per_page, page }): Query<PageQuery>,
+) -> Result<Json<SearchResponse>, Response> {
+    let per_page = per_page.unwrap_or(DEFAULT_PER_PAGE).clamp(1, MAX_PER_PAGE);
+    let mut connection = database_connection_pool
+        .acquire()
+        .await
+        .map_err(|_e| internal_server_error("couldn't connect to database"))?;
+    let (crates, total) = get_crates_by_keyword(
+        &keyword.normalized(),
+        page.unwrap_or(1),
+        per_page,
+        &mut connection,
+    )
+    .await
+    .inspect_err(|e| eprintln!("Failed to list crates with keyword: {e}"))
+    .map_err(|_e| internal_server_error("couldn't list crates with keyword"))?
+    .ok_or_else(|| (StatusCode::NOT_FOUND, "no crate uses this keyword").into_response())?;
+    Ok(Json(SearchResponse {
+        crates: crates.into_iter().map(CrateSummary::from).collect(),
+        meta: SearchMeta { total },
+    }))
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchResponse<T = CrateSummary> {
+    crates: Vec<T>,
+    meta: SearchMeta,
+}
+
+#[derive(Debug, Serialize)]
+/// Search result of a sorted listing, with the values it can be sorted by
+pub struct RankedCrateSummary {
+    #[serde(flatten)]
+    summary: CrateSummary,
+    downloads: i64,
+    updated_at: Option<DateTime<Utc>>,
+}
+impl From<RankedCrateRecord> for RankedCrateSummary {
+    fn from(ranked: RankedCrateRecord) -> Self {
+        Self {
+            summary: CrateSummary::from(ranked.record),
+            downloads: ranked.downloads,
+            updated_at: ranked.updated_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchMeta {
+    total: i64,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::search::{is_single_word, uses_substring_search};
+
+    #[test]
+    fn short_single_words_are_substring_searches() {
+        assert!(uses_substring_search(""));
+        assert!(uses_substring_search("tls"));
+        assert!(uses_substring_search("ürl"));
+        assert!(!uses_substring_search("http"));
+        assert!(!uses_substring_search("io ui"));
+        assert!(!uses_substring_search("http client"));
+    }
+    #[test]
+    fn only_single_words_get_similar_names() {
+        assert!(is_single_word("tokoi"));
+        assert!(is_single_word("serde-json"));
+        assert!(!is_single_word(""));
+        assert!(!is_single_word("http -client"));
+    }
+}
@@ -0,0 +1,70 @@
+use axum::{
+    extract::{Path, State},
+    http::{header::CONTENT_TYPE, StatusCode},
+    response::{IntoResponse, Response},
+};
+
+use crate::{
+    crate_name::CrateName,
+    index::read_index_file,
+    middleware::{bad_gateway, internal_server_error},
+    postgres::get_original_crate_name,
+    upstream::sparse_index_path,
+    ServerState,
+};
+
+/// Serves the index over cargo's sparse protocol
+///
+/// Crates published here shadow upstream crates of the same normalized name, only crates this
+/// registry doesn't know are looked up upstream.
+pub async fn sparse_index_handler(
+    State(state): State<ServerState>,
+    Path(path): Path<String>,
+) -> Result<Response, Response> {
+    if path == "config.json" {
+        let config = state
+            .registry
+            .index_repository
+            .get_unlocked()
+            .read_config()
+            .await
+            .inspect_err(|e| eprintln!("Failed to read index config: {e}"))
+            .map_err(|_e| internal_server_error("failed to read index config"))?;
+        return Ok(([(CONTENT_TYPE, "application/json")], config).into_response());
+    }
+    let not_found = || (StatusCode::NOT_FOUND, "crate doesn't exist").into_response();
+    let crate_name = path
+        .rsplit('/')
+        .next()
+        .and_then(|name| name.parse::<CrateName>().ok())
+        .filter(|crate_name| sparse_index_path(crate_name) == path)
+        .ok_or_else(not_found)?;
+    let original_name = get_original_crate_name(&crate_name, &*state.database_connection_pool)
+        .await
+        .inspect_err(|e| eprintln!("Failed to look up crate: {e}"))
+        .map_err(|_e| internal_server_error("couldn't look up crate"))?;
+    let content = match original_name {
+        Some(original_name) if original_name.to_lowercase() == crate_name.original_str() => {
+            let original_name = original_name
+                .parse::<CrateName>()
+                .map_err(|_e| internal_server_error("invalid crate name in database"))?;
+            read_index_file(&original_name, state.index_repository())
+                .await
+                .inspect_err(|e| eprintln!("Failed to read index: {e}"))
+                .map_err(|_e| internal_server_error("failed to read index"))?
+        }
+        // Differs only in `-` and `_`, cargo asks for the other spelling next
+        Some(_) => None,
+        None => match &state.registry.upstream {
+            Some(upstream) => upstream
+                .index_file(&crate_name)
+                .await
+                .inspect_err(|e| eprintln!("Failed to fetch index file from upstream: {e}"))
+                .map_err(|e| bad_gateway(e.to_string()))?,
+            None => None,
+        },
+    };
+    content
+        .map(IntoResponse::into_response)
+        .ok_or_else(not_found)
+}
@@ -0,0 +1,59 @@
+use axum::{extract::State, response::Response, Json};
+use serde::Serialize;
+use sqlx::PgConnection;
+
+use crate::{
+    auth::{AuthenticatedUser, UserId},
+    middleware::internal_server_error,
+    postgres::{add_storage_usage, get_storage_usage},
+    publish::PublishError,
+    ServerState,
+};
+
+/// Adds the crate file to the storage the publisher uses, failing if that exceeds the quota
+///
+/// Has to run in the publish transaction, so a failed publish doesn't count.
+pub async fn charge_storage(
+    user_id: UserId,
+    file_size: usize,
+    quota: Option<u64>,
+    exec: &mut PgConnection,
+) -> Result<(), PublishError> {
+    let used_bytes = add_storage_usage(user_id, file_size as i64, exec)
+        .await
+        .inspect_err(|e| eprintln!("Failed to update storage usage: {e}"))
+        .map_err(|_e| PublishError::Internal("couldn't update storage usage".into()))?;
+    match quota {
+        Some(quota) if used_bytes as u64 > quota => Err(PublishError::Forbidden(format!(
+            "storage quota exceeded: {} of {quota} bytes used, the upload needs {file_size} more",
+            used_bytes as u64 - file_size as u64
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Storage used by crate files the user published, yanked versions included
+pub async fn usage_handler(
+    State(ServerState {
+        database_connection_pool,
+        storage_quota,
+        ..
+    }): State<ServerState>,
+    user: AuthenticatedUser,
+) -> Result<Json<UsageResponse>, Response> {
+    let used_bytes = get_storage_usage(user.user_id, &*database_connection_pool)
+        .await
+        .inspect_err(|e| eprintln!("Failed to get storage usage: {e}"))
+        .map_err(|_e| internal_server_error("couldn't get storage usage"))?;
+    Ok(Json(UsageResponse {
+        used_bytes,
+        limit_bytes: storage_quota,
+    }))
+}
+
+#[derive(Debug, Serialize)]
+pub struct UsageResponse {
+    used_bytes: i64,
+    /// `null` if unlimited
+    limit_bytes: Option<u64>,
+}
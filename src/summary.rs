@@ -0,0 +1,279 @@
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{
+        header::{
+            CACHE_CONTROL, CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+        },
+        HeaderMap, StatusCode,
+    },
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use crate::{
+    crate_name::CrateName,
+    feed::{atom_feed, FeedInfo},
+    middleware::internal_server_error,
+    postgres::{get_original_crate_name, get_recent_versions, get_registry_stats},
+    ServerState,
+};
+
+const DEFAULT_LIMIT: u32 = 10;
+const MAX_LIMIT: u32 = 100;
+
+#[derive(Debug, Deserialize)]
+pub struct SummaryQuery {
+    limit: Option<u32>,
+}
+
+/// The most recently published versions, newest first
+pub async fn summary_handler(
+    State(ServerState {
+        database_connection_pool,
+        ..
+    }): State<ServerState>,
+    Query(SummaryQuery { limit }): Query<SummaryQuery>,
+) -> Result<Json<SummaryResponse>, Response> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let recent_versions = get_recent_versions(limit.into(), None, &*database_connection_pool)
+        .await
+        .inspect_err(|e| eprintln!("Failed to get recent versions: {e}"))
+        .map_err(|_e| internal_server_error("couldn't get recent versions"))?;
+    Ok(Json(SummaryResponse { recent_versions }))
+}
+
+/// Atom feed of the most recently published versions
+pub async fn feed_handler(
+    State(state): State<ServerState>,
+    headers: HeaderMap,
+) -> Result<Response, Response> {
+    let versions = get_recent_versions(
+        state.feed_entries.into(),
+        None,
+        &*state.database_connection_pool,
+    )
+    .await
+    .inspect_err(|e| eprintln!("Failed to get recent versions: {e}"))
+    .map_err(|_e| internal_server_error("couldn't get recent versions"))?;
+    let info = FeedInfo {
+        title: "Recently published versions",
+        self_url: &format!("{}/feed.xml", state.base_url),
+        base_url: &state.base_url,
+    };
+    Ok(feed_response(&info, &versions, &headers))
+}
+
+/// Atom feed of the most recently published versions of one crate
+pub async fn crate_feed_handler(
+    State(state): State<ServerState>,
+    Path(crate_name): Path<CrateName>,
+    headers: HeaderMap,
+) -> Result<Response, Response> {
+    let original_name = get_original_crate_name(&crate_name, &*state.database_connection_pool)
+        .await
+        .inspect_err(|e| eprintln!("Failed to look up crate: {e}"))
+        .map_err(|_e| internal_server_error("couldn't look up crate"))?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "crate doesn't exist").into_response())?;
+    let versions = get_recent_versions(
+        state.feed_entries.into(),
+        Some(&crate_name),
+        &*state.database_connection_pool,
+    )
+    .await
+    .inspect_err(|e| eprintln!("Failed to get recent versions: {e}"))
+    .map_err(|_e| internal_server_error("couldn't get recent versions"))?;
+    let info = FeedInfo {
+        title: &format!("Recently published versions of {original_name}"),
+        self_url: &format!("{}/crates/{original_name}/feed.xml", state.base_url),
+        base_url: &state.base_url,
+    };
+    Ok(feed_response(&info, &versions, &headers))
+}
+
+/// Answers 304 if the client has the newest entry already, feed readers poll often
+fn feed_response(info: &FeedInfo<'_>, versions: &[RecentVersion], headers: &HeaderMap) -> Response {
+    let newest = versions.first().map(|version| version.created_at);
+    let etag = match newest {
+        Some(newest) => format!("\"{}-{}\"", newest.timestamp_micros(), versions.len()),
+        None => String::from("\"empty\""),
+    };
+    let last_modified = newest
+        .unwrap_or(DateTime::<Utc>::UNIX_EPOCH)
+        .format("%a, %d %b %Y %H:%M:%S GMT")
+        .to_string();
+    let not_modified = match headers.get(IF_NONE_MATCH) {
+        Some(if_none_match) => if_none_match.to_str().is_ok_and(|tags| {
+            tags.split(',')
+                .any(|tag| tag.trim() == etag || tag.trim() == "*")
+        }),
+        None => headers
+            .get(IF_MODIFIED_SINCE)
+            .and_then(|since| since.to_str().ok())
+            .and_then(|since| DateTime::parse_from_rfc2822(since).ok())
+            .is_some_and(|since| {
+                newest.is_none_or(|newest| newest.timestamp() <= since.timestamp())
+            }),
+    };
+    let cache_headers = [
+        (ETAG, etag),
+        (LAST_MODIFIED, last_modified),
+        (CACHE_CONTROL, String::from("no-cache")),
+    ];
+    if not_modified {
+        return (StatusCode::NOT_MODIFIED, cache_headers).into_response();
+    }
+    (
+        cache_headers,
+        [(CONTENT_TYPE, "application/atom+xml; charset=utf-8")],
+        atom_feed(info, versions),
+    )
+        .into_response()
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct RecentVersion {
+    #[serde(rename = "crate")]
+    pub krate: String,
+    pub version: Version,
+    pub description: String,
+    pub created_at: DateTime<Utc>,
+    /// Login of the publisher, `None` for unauthenticated publishes
+    pub publisher: Option<String>,
+    /// Whether this was the first version of the crate
+    pub new_crate: bool,
+    pub yanked: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SummaryResponse {
+    recent_versions: Vec<RecentVersion>,
+}
+
+/// How long statistics are served from memory before the database is asked again
+const STATS_TTL: Duration = Duration::from_secs(60);
+
+/// Totals of the whole registry, refreshed at most once per [`STATS_TTL`]
+pub async fn stats_handler(
+    State(ServerState {
+        database_connection_pool,
+        stats_cache,
+        ..
+    }): State<ServerState>,
+) -> Result<Json<RegistryStats>, Response> {
+    let stats = stats_cache
+        .get_or_refresh(|| get_registry_stats(&*database_connection_pool))
+        .await
+        .inspect_err(|e| eprintln!("Failed to get registry statistics: {e}"))
+        .map_err(|_e| internal_server_error("couldn't get registry statistics"))?;
+    Ok(Json(stats))
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct RegistryStats {
+    pub crates: i64,
+    pub versions: i64,
+    /// Bytes of all stored crate files
+    pub stored_bytes: i64,
+    pub downloads: i64,
+    /// Versions published in the last 24 hours
+    pub publishes_last_day: i64,
+    /// Versions published in the last 7 days
+    pub publishes_last_week: i64,
+}
+
+#[derive(Debug)]
+/// Keeps the last statistics, concurrent requests after expiry wait for one refresh
+pub struct StatsCache {
+    ttl: Duration,
+    cached: Mutex<Option<(Instant, RegistryStats)>>,
+}
+impl Default for StatsCache {
+    fn default() -> Self {
+        Self::new(STATS_TTL)
+    }
+}
+impl StatsCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            cached: Mutex::new(None),
+        }
+    }
+    /// A failed refresh isn't cached, the next request tries again
+    pub async fn get_or_refresh<F, Fut, E>(&self, refresh: F) -> Result<RegistryStats, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<RegistryStats, E>>,
+    {
+        let mut cached = self.cached.lock().await;
+        if let Some((fetched_at, stats)) = &*cached {
+            if fetched_at.elapsed() < self.ttl {
+                return Ok(stats.clone());
+            }
+        }
+        let stats = refresh().await?;
+        *cached = Some((Instant::now(), stats.clone()));
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::Infallible, time::Duration};
+
+    use crate::summary::{RegistryStats, StatsCache};
+
+    fn stats(crates: i64) -> RegistryStats {
+        RegistryStats {
+            crates,
+            ..RegistryStats::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn stats_are_cached_until_the_ttl_passed() {
+        let cache = StatsCache::new(Duration::from_secs(60));
+        let first = cache
+            .get_or_refresh(|| async { Ok::<_, Infallible>(stats(1)) })
+            .await
+            .unwrap();
+        let second = cache
+            .get_or_refresh(|| async { Ok::<_, Infallible>(stats(2)) })
+            .await
+            .unwrap();
+        assert_eq!(first, stats(1));
+        assert_eq!(second, stats(1));
+        let expired = StatsCache::new(Duration::ZERO);
+        expired
+            .get_or_refresh(|| async { Ok::<_, Infallible>(stats(1)) })
+            .await
+            .unwrap();
+        let refreshed = expired
+            .get_or_refresh(|| async { Ok::<_, Infallible>(stats(2)) })
+            .await
+            .unwrap();
+        assert_eq!(refreshed, stats(2));
+    }
+    #[tokio::test]
+    async fn failed_refreshes_are_not_cached() {
+        let cache = StatsCache::new(Duration::from_secs(60));
+        assert!(cache
+            .get_or_refresh(|| async { Err::<RegistryStats, _>("database is down") })
+            .await
+            .is_err());
+        let stats_after = cache
+            .get_or_refresh(|| async { Ok::<_, &str>(stats(3)) })
+            .await
+            .unwrap();
+        assert_eq!(stats_after, stats(3));
+    }
+}
@@ -0,0 +1,196 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::PgConnection;
+
+use crate::{
+    auth::Admin,
+    middleware::{bad_request, internal_server_error},
+    postgres::{
+        add_team_member, get_team_id_by_login, get_team_members, get_user_id_by_login,
+        remove_team_member, upsert_team, TeamId,
+    },
+    ServerState,
+};
+
+/// Team logins have `:`-separated, non-empty parts like `github:org:team`, user logins have none
+pub fn is_team_login(login: &str) -> bool {
+    login.contains(':') && login.split(':').all(|part| !part.is_empty())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TeamBody {
+    /// Display name
+    #[serde(default)]
+    name: Option<String>,
+}
+
+/// Creates the team, or renames it if it exists
+pub async fn put_team_handler(
+    _admin: Admin,
+    State(ServerState {
+        database_connection_pool,
+        ..
+    }): State<ServerState>,
+    Path(team): Path<String>,
+    Json(TeamBody { name }): Json<TeamBody>,
+) -> Result<Json<TeamsChanged>, Response> {
+    if !is_team_login(&team) {
+        return Err(bad_request(
+            "team logins need at least two parts separated by ':', like github:org:team",
+        ));
+    }
+    let mut connection = database_connection_pool
+        .acquire()
+        .await
+        .map_err(|_e| internal_server_error("couldn't connect to database"))?;
+    upsert_team(&team, name.as_deref(), &mut connection)
+        .await
+        .inspect_err(|e| eprintln!("Failed to store team: {e}"))
+        .map_err(|_e| internal_server_error("couldn't store team"))?;
+    Ok(Json(TeamsChanged {
+        ok: true,
+        msg: format!("team {team} saved"),
+    }))
+}
+
+pub async fn list_team_members_handler(
+    _admin: Admin,
+    State(ServerState {
+        database_connection_pool,
+        ..
+    }): State<ServerState>,
+    Path(team): Path<String>,
+) -> Result<Json<TeamMembersResponse>, Response> {
+    let mut connection = database_connection_pool
+        .acquire()
+        .await
+        .map_err(|_e| internal_server_error("couldn't connect to database"))?;
+    let team_id = existing_team(&team, &mut connection).await?;
+    let members = get_team_members(team_id, &mut connection)
+        .await
+        .inspect_err(|e| eprintln!("Failed to get team members: {e}"))
+        .map_err(|_e| internal_server_error("couldn't get team members"))?;
+    Ok(Json(TeamMembersResponse {
+        users: members
+            .into_iter()
+            .map(|(user_id, login)| TeamMember {
+                id: user_id.0,
+                login,
+            })
+            .collect(),
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TeamMembersBody {
+    /// Logins of the users to add or remove
+    users: Vec<String>,
+}
+
+pub async fn add_team_members_handler(
+    _admin: Admin,
+    State(state): State<ServerState>,
+    Path(team): Path<String>,
+    Json(TeamMembersBody { users }): Json<TeamMembersBody>,
+) -> Result<Json<TeamsChanged>, Response> {
+    change_members(&state, &team, &users, true).await?;
+    Ok(Json(TeamsChanged {
+        ok: true,
+        msg: format!("{} added to team {team}", users.join(", ")),
+    }))
+}
+
+pub async fn remove_team_members_handler(
+    _admin: Admin,
+    State(state): State<ServerState>,
+    Path(team): Path<String>,
+    Json(TeamMembersBody { users }): Json<TeamMembersBody>,
+) -> Result<Json<TeamsChanged>, Response> {
+    change_members(&state, &team, &users, false).await?;
+    Ok(Json(TeamsChanged {
+        ok: true,
+        msg: format!("{} removed from team {team}", users.join(", ")),
+    }))
+}
+
+/// Applies the change for all `logins` in one transaction
+async fn change_members(
+    ServerState {
+        database_connection_pool,
+        ..
+    }: &ServerState,
+    team: &str,
+    logins: &[String],
+    add: bool,
+) -> Result<(), Response> {
+    if logins.is_empty() {
+        return Err(bad_request("no users given"));
+    }
+    let mut transaction = database_connection_pool
+        .begin()
+        .await
+        .map_err(|_e| internal_server_error("couldn't start transaction"))?;
+    let team_id = existing_team(team, &mut transaction).await?;
+    for login in logins {
+        let user_id = get_user_id_by_login(login, &mut transaction)
+            .await
+            .inspect_err(|e| eprintln!("Failed to look up user: {e}"))
+            .map_err(|_e| internal_server_error("couldn't look up user"))?
+            .ok_or_else(|| bad_request(format!("unknown user: {login}")))?;
+        if add {
+            add_team_member(team_id, user_id, &mut transaction).await
+        } else {
+            remove_team_member(team_id, user_id, &mut transaction).await
+        }
+        .inspect_err(|e| eprintln!("Failed to change team members: {e}"))
+        .map_err(|_e| internal_server_error("couldn't change team members"))?;
+    }
+    transaction
+        .commit()
+        .await
+        .map_err(|_e| internal_server_error("committing to database failed"))
+}
+
+async fn existing_team(team: &str, exec: &mut PgConnection) -> Result<TeamId, Response> {
+    get_team_id_by_login(team, exec)
+        .await
+        .inspect_err(|e| eprintln!("Failed to look up team: {e}"))
+        .map_err(|_e| internal_server_error("couldn't look up team"))?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "team doesn't exist").into_response())
+}
+
+#[derive(Debug, Serialize)]
+pub struct TeamMembersResponse {
+    users: Vec<TeamMember>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TeamMember {
+    id: i32,
+    login: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TeamsChanged {
+    ok: bool,
+    msg: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::teams::is_team_login;
+
+    #[test]
+    fn team_logins_have_colon_separated_parts() {
+        assert!(is_team_login("github:org:backend-team"));
+        assert!(is_team_login("local:backend"));
+        assert!(!is_team_login("alice"));
+        assert!(!is_team_login("github::team"));
+        assert!(!is_team_login("github:org:"));
+    }
+}
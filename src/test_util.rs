@@ -0,0 +1,141 @@
+use std::{
+    collections::HashMap,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use axum::async_trait;
+use semver::Version;
+
+use crate::{
+    crate_file::CrateStorage,
+    crate_name::{CrateName, NormalizedCrateName},
+    index::{IndexBackend, IndexError},
+    version::without_build_metadata,
+};
+
+#[derive(Debug, Default)]
+/// Keeps crate files in memory, versions differing only in build metadata share one
+pub struct InMemoryStorage {
+    files: Mutex<HashMap<(NormalizedCrateName, Version), Vec<u8>>>,
+}
+impl InMemoryStorage {
+    pub fn get_file(&self, crate_name: &CrateName, version: &Version) -> Option<Vec<u8>> {
+        self.files
+            .lock()
+            .unwrap()
+            .get(&storage_key(crate_name, version))
+            .cloned()
+    }
+}
+
+fn storage_key(crate_name: &CrateName, version: &Version) -> (NormalizedCrateName, Version) {
+    (crate_name.normalized(), without_build_metadata(version))
+}
+
+#[async_trait]
+impl CrateStorage for InMemoryStorage {
+    async fn store(
+        &self,
+        crate_name: &CrateName,
+        version: &Version,
+        file_content: &[u8],
+    ) -> Result<(), std::io::Error> {
+        let mut files = self.files.lock().unwrap();
+        let key = storage_key(crate_name, version);
+        if files.contains_key(&key) {
+            return Err(ErrorKind::AlreadyExists.into());
+        }
+        files.insert(key, file_content.to_vec());
+        Ok(())
+    }
+    async fn get(
+        &self,
+        crate_name: &CrateName,
+        version: &Version,
+    ) -> Result<Vec<u8>, std::io::Error> {
+        self.get_file(crate_name, version)
+            .ok_or_else(|| ErrorKind::NotFound.into())
+    }
+    async fn size(&self, crate_name: &CrateName, version: &Version) -> Result<u64, std::io::Error> {
+        self.get(crate_name, version)
+            .await
+            .map(|file| file.len() as u64)
+    }
+    async fn delete(
+        &self,
+        crate_name: &CrateName,
+        version: &Version,
+    ) -> Result<(), std::io::Error> {
+        self.files
+            .lock()
+            .unwrap()
+            .remove(&storage_key(crate_name, version));
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+/// Keeps the index files in memory and records every commit instead of running git
+///
+/// The commits keep a copy of the committed content.
+pub struct InMemoryIndex {
+    files: Mutex<HashMap<PathBuf, String>>,
+    commits: Mutex<Vec<IndexCommit>>,
+}
+impl InMemoryIndex {
+    pub fn file(&self, file_path: &Path) -> Option<String> {
+        self.files.lock().unwrap().get(file_path).cloned()
+    }
+    pub fn commits(&self) -> Vec<IndexCommit> {
+        self.commits.lock().unwrap().clone()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IndexCommit {
+    /// Relative to the repository
+    pub file_path: PathBuf,
+    pub message: String,
+    /// `None` if the commit removed the file
+    pub content: Option<String>,
+}
+
+#[async_trait]
+impl IndexBackend for InMemoryIndex {
+    async fn read(&self, file_path: &Path) -> Result<Option<String>, IndexError> {
+        Ok(self.file(file_path))
+    }
+    async fn append(&self, file_path: &Path, line: &str) -> Result<(), IndexError> {
+        let mut files = self.files.lock().unwrap();
+        let content = files.entry(file_path.to_path_buf()).or_default();
+        content.push_str(line);
+        content.push('\n');
+        Ok(())
+    }
+    async fn write(&self, file_path: &Path, content: &str) -> Result<(), IndexError> {
+        self.files
+            .lock()
+            .unwrap()
+            .insert(file_path.to_path_buf(), content.to_string());
+        Ok(())
+    }
+    async fn remove(&self, file_path: &Path) -> Result<(), IndexError> {
+        self.files
+            .lock()
+            .unwrap()
+            .remove(file_path)
+            .map(|_| ())
+            .ok_or_else(|| IndexError::WriteIndexFile(ErrorKind::NotFound.into()))
+    }
+    async fn commit(&self, file_path: &Path, commit_message: &str) -> Result<(), IndexError> {
+        let content = self.file(file_path);
+        self.commits.lock().unwrap().push(IndexCommit {
+            file_path: file_path.to_path_buf(),
+            message: commit_message.to_string(),
+            content,
+        });
+        Ok(())
+    }
+}
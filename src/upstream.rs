@@ -0,0 +1,550 @@
+use std::{
+    fmt::Display,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, SystemTime},
+};
+
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::{
+    fs::{create_dir_all, metadata, read, read_to_string, remove_file, rename, write},
+    sync::{Mutex, OnceCell},
+};
+
+use crate::{
+    auth::Admin, crate_name::CrateName, index::index_directories,
+    middleware::internal_server_error, version::without_build_metadata, ServerState,
+};
+
+#[derive(Debug)]
+/// Registry that crates not published here are fetched from, like crates.io
+///
+/// Fetched files are cached below the cache path. Crate files never change, index files are
+/// fetched again once they are older than the index TTL. The crate file cache is kept apart
+/// from the published crates, so publishing a name that was proxied before doesn't collide.
+///
+/// Cached crate files are checked against the upstream index on every download. If a size
+/// limit is set, the least recently downloaded files are evicted once it is exceeded. Their
+/// modification time is the last download, so the order survives restarts.
+pub struct Upstream {
+    client: reqwest::Client,
+    /// Root of the upstream's sparse index, without the `sparse+` prefix
+    index_url: String,
+    /// The `dl` field of the upstream's `config.json`, read on first use
+    download_template: OnceCell<String>,
+    cache_path: PathBuf,
+    index_ttl: Duration,
+    /// Bytes of cached crate files, unbounded if `None`
+    crate_cache_limit: Option<u64>,
+    /// Serializes evictions and purges, which both walk the whole cache
+    cache_maintenance: Mutex<()>,
+    cache_hits: AtomicU64,
+    cache_misses: AtomicU64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// Whether a proxied crate file was served from the cache
+pub enum CacheStatus {
+    Hit,
+    Miss,
+}
+impl CacheStatus {
+    /// Value of the `X-Cache` response header
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Hit => "HIT",
+            Self::Miss => "MISS",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+/// The part of the upstream's `config.json` needed to download crates
+struct UpstreamIndexConfig {
+    dl: String,
+}
+
+#[derive(Debug, Deserialize)]
+/// The part of an upstream index line needed to download the version
+struct UpstreamIndexLine {
+    vers: Version,
+    cksum: String,
+}
+
+impl Upstream {
+    pub fn new(
+        index_url: &str,
+        cache_path: PathBuf,
+        timeout: Duration,
+        index_ttl: Duration,
+        crate_cache_limit: Option<u64>,
+    ) -> Result<Self, UpstreamError> {
+        Ok(Self {
+            client: reqwest::Client::builder()
+                .timeout(timeout)
+                .build()
+                .map_err(UpstreamError::Request)?,
+            index_url: index_url
+                .trim_start_matches("sparse+")
+                .trim_end_matches('/')
+                .to_string(),
+            download_template: OnceCell::new(),
+            cache_path,
+            index_ttl,
+            crate_cache_limit,
+            cache_maintenance: Mutex::new(()),
+            cache_hits: AtomicU64::new(0),
+            cache_misses: AtomicU64::new(0),
+        })
+    }
+    pub fn crate_cache_limit(&self) -> Option<u64> {
+        self.crate_cache_limit
+    }
+    pub fn cache_hits(&self) -> u64 {
+        self.cache_hits.load(Ordering::Relaxed)
+    }
+    pub fn cache_misses(&self) -> u64 {
+        self.cache_misses.load(Ordering::Relaxed)
+    }
+    /// The crate's index file as the upstream serves it, `None` if the upstream doesn't know the crate
+    pub async fn index_file(
+        &self,
+        crate_name: &CrateName,
+    ) -> Result<Option<String>, UpstreamError> {
+        let relative_path = sparse_index_path(crate_name);
+        let cache_path = self.cache_path.join("index").join(&relative_path);
+        if let Some(content) = self.fresh_cached_index_file(&cache_path).await {
+            return Ok(Some(content));
+        }
+        let url = format!("{}/{relative_path}", self.index_url);
+        let Some(content) = self.fetch(&url).await? else {
+            return Ok(None);
+        };
+        let content = String::from_utf8(content).map_err(|_e| UpstreamError::InvalidIndex)?;
+        if let Err(e) = write_to_cache(&cache_path, content.as_bytes()).await {
+            eprintln!("Failed to cache upstream index file: {e}");
+        }
+        Ok(Some(content))
+    }
+    /// The `.crate` file of the version, `None` if the upstream doesn't have it
+    ///
+    /// Cached and downloaded files are both checked against the checksum in the upstream's
+    /// index, a cached file that doesn't match is downloaded again.
+    pub async fn crate_file(
+        &self,
+        crate_name: &CrateName,
+        version: &Version,
+    ) -> Result<Option<(Vec<u8>, CacheStatus)>, UpstreamError> {
+        let Some(index_file) = self.index_file(crate_name).await? else {
+            return Ok(None);
+        };
+        let Some(cksum) = find_checksum(&index_file, version) else {
+            return Ok(None);
+        };
+        let cache_path = self
+            .cache_path
+            .join("crates")
+            .join(crate_name.normalized().as_str())
+            .join(without_build_metadata(version).to_string());
+        match read(&cache_path).await {
+            Ok(file) if sha256_hex(&file) == cksum => {
+                self.cache_hits.fetch_add(1, Ordering::Relaxed);
+                mark_used(cache_path).await;
+                return Ok(Some((file, CacheStatus::Hit)));
+            }
+            Ok(_) => {
+                eprintln!(
+                    "Cached upstream crate file {} doesn't match its checksum, downloading it again",
+                    cache_path.display()
+                );
+                match remove_file(&cache_path).await {
+                    Err(e) if e.kind() != ErrorKind::NotFound => {
+                        return Err(UpstreamError::Cache(e))
+                    }
+                    _ => {}
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(UpstreamError::Cache(e)),
+        }
+        self.cache_misses.fetch_add(1, Ordering::Relaxed);
+        let url = self.download_url(crate_name, version, &cksum).await?;
+        let Some(file) = self.fetch(&url).await? else {
+            return Ok(None);
+        };
+        let actual = sha256_hex(&file);
+        if actual != cksum {
+            return Err(UpstreamError::ChecksumMismatch {
+                expected: cksum,
+                actual,
+            });
+        }
+        match write_to_cache(&cache_path, &file).await {
+            Ok(()) => self.evict_least_recently_used().await,
+            Err(e) => eprintln!("Failed to cache upstream crate file: {e}"),
+        }
+        Ok(Some((file, CacheStatus::Miss)))
+    }
+    /// Deletes every cached file, crate files are downloaded again on their next download
+    pub async fn purge_cache(&self) -> Result<PurgedCache, UpstreamError> {
+        let _maintenance = self.cache_maintenance.lock().await;
+        let cache_path = self.cache_path.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut purged = PurgedCache::default();
+            for file in cached_files(&cache_path)? {
+                remove_cached_file(&file.path)?;
+                purged.files_deleted += 1;
+                purged.bytes_freed += file.len;
+            }
+            Ok(purged)
+        })
+        .await
+        .map_err(|e| UpstreamError::Cache(std::io::Error::other(e)))?
+        .map_err(UpstreamError::Cache)
+    }
+    async fn evict_least_recently_used(&self) {
+        let Some(limit) = self.crate_cache_limit else {
+            return;
+        };
+        let _maintenance = self.cache_maintenance.lock().await;
+        let crates_path = self.cache_path.join("crates");
+        let result = tokio::task::spawn_blocking(move || evict_files(&crates_path, limit)).await;
+        match result {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => eprintln!("Failed to evict upstream crate files: {e}"),
+            Err(e) => eprintln!("Failed to evict upstream crate files: {e}"),
+        }
+    }
+    async fn fresh_cached_index_file(&self, cache_path: &Path) -> Option<String> {
+        let modified = metadata(cache_path).await.ok()?.modified().ok()?;
+        if SystemTime::now().duration_since(modified).ok()? > self.index_ttl {
+            return None;
+        }
+        read_to_string(cache_path).await.ok()
+    }
+    async fn download_url(
+        &self,
+        crate_name: &CrateName,
+        version: &Version,
+        cksum: &str,
+    ) -> Result<String, UpstreamError> {
+        let template = self
+            .download_template
+            .get_or_try_init(|| async {
+                let url = format!("{}/config.json", self.index_url);
+                let config = self
+                    .fetch(&url)
+                    .await?
+                    .ok_or(UpstreamError::Status(StatusCode::NOT_FOUND))?;
+                serde_json::from_slice::<UpstreamIndexConfig>(&config)
+                    .map(|config| config.dl)
+                    .map_err(UpstreamError::InvalidConfig)
+            })
+            .await?;
+        Ok(expand_download_template(
+            template, crate_name, version, cksum,
+        ))
+    }
+    /// `None` if the upstream answers 404 or 410
+    async fn fetch(&self, url: &str) -> Result<Option<Vec<u8>>, UpstreamError> {
+        let response = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .map_err(UpstreamError::Request)?;
+        match response.status() {
+            StatusCode::NOT_FOUND | StatusCode::GONE => Ok(None),
+            status if status.is_success() => Ok(Some(
+                response
+                    .bytes()
+                    .await
+                    .map_err(UpstreamError::Request)?
+                    .to_vec(),
+            )),
+            status => Err(UpstreamError::Status(status)),
+        }
+    }
+}
+
+/// Path of the crate's index file below the index root, as cargo requests it
+pub fn sparse_index_path(crate_name: &CrateName) -> String {
+    let name = crate_name.original_str().to_lowercase();
+    let mut components = index_directories(&name);
+    components.push(name);
+    components.join("/")
+}
+
+/// Lines that can't be parsed are skipped, the upstream may use fields this server doesn't know
+fn find_checksum(index_file: &str, version: &Version) -> Option<String> {
+    let version = without_build_metadata(version);
+    index_file
+        .lines()
+        .filter_map(|line| serde_json::from_str::<UpstreamIndexLine>(line).ok())
+        .find(|line| without_build_metadata(&line.vers) == version)
+        .map(|line| line.cksum)
+}
+
+/// Cargo appends `/{crate}/{version}/download` if `dl` has none of its markers
+fn expand_download_template(
+    template: &str,
+    crate_name: &CrateName,
+    version: &Version,
+    cksum: &str,
+) -> String {
+    const MARKERS: [&str; 5] = [
+        "{crate}",
+        "{version}",
+        "{prefix}",
+        "{lowerprefix}",
+        "{sha256-checksum}",
+    ];
+    let name = crate_name.original_str();
+    if !MARKERS.iter().any(|marker| template.contains(marker)) {
+        return format!(
+            "{}/{name}/{version}/download",
+            template.trim_end_matches('/')
+        );
+    }
+    template
+        .replace("{crate}", name)
+        .replace("{version}", &version.to_string())
+        .replace("{prefix}", &index_directories(name).join("/"))
+        .replace(
+            "{lowerprefix}",
+            &index_directories(&name.to_lowercase()).join("/"),
+        )
+        .replace("{sha256-checksum}", cksum)
+}
+
+fn sha256_hex(content: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(content))
+}
+
+/// Sets the modification time, which orders the files for eviction
+async fn mark_used(path: PathBuf) {
+    let result = tokio::task::spawn_blocking(move || {
+        std::fs::File::options()
+            .write(true)
+            .open(path)?
+            .set_modified(SystemTime::now())
+    })
+    .await;
+    if let Ok(Err(e)) = result {
+        eprintln!("Failed to mark upstream crate file as used: {e}");
+    }
+}
+
+#[derive(Debug)]
+struct CachedFile {
+    path: PathBuf,
+    len: u64,
+    modified: SystemTime,
+}
+
+/// Every file below `directory`, without the temporary files of writes in progress
+fn cached_files(directory: &Path) -> Result<Vec<CachedFile>, std::io::Error> {
+    let entries = match std::fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut files = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if metadata.is_dir() {
+            files.extend(cached_files(&entry.path())?);
+        } else if !entry.file_name().to_string_lossy().starts_with('.') {
+            files.push(CachedFile {
+                path: entry.path(),
+                len: metadata.len(),
+                modified: metadata.modified()?,
+            });
+        }
+    }
+    Ok(files)
+}
+
+/// Deletes the files modified longest ago until the rest fit into `limit` bytes
+fn evict_files(directory: &Path, limit: u64) -> Result<(), std::io::Error> {
+    let mut files = cached_files(directory)?;
+    let mut total = files.iter().map(|file| file.len).sum::<u64>();
+    files.sort_by_key(|file| file.modified);
+    for file in files {
+        if total <= limit {
+            break;
+        }
+        remove_cached_file(&file.path)?;
+        total -= file.len;
+    }
+    Ok(())
+}
+
+/// A file that is already gone was removed by a concurrent check of its checksum
+fn remove_cached_file(path: &Path) -> Result<(), std::io::Error> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Writes a temporary file first, so concurrent requests never read a partial file
+async fn write_to_cache(path: &Path, content: &[u8]) -> Result<(), std::io::Error> {
+    let (Some(directory), Some(file_name)) = (path.parent(), path.file_name()) else {
+        return Err(ErrorKind::InvalidInput.into());
+    };
+    create_dir_all(directory).await?;
+    let temporary_path = directory.join(format!(
+        ".{}.{}",
+        file_name.to_string_lossy(),
+        uuid::Uuid::new_v4()
+    ));
+    write(&temporary_path, content).await?;
+    rename(&temporary_path, path).await
+}
+
+#[derive(Debug)]
+pub enum UpstreamError {
+    /// Includes timeouts
+    Request(reqwest::Error),
+    Status(StatusCode),
+    InvalidConfig(serde_json::Error),
+    InvalidIndex,
+    ChecksumMismatch {
+        expected: String,
+        actual: String,
+    },
+    Cache(std::io::Error),
+}
+impl std::error::Error for UpstreamError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Request(request) => Some(request),
+            Self::InvalidConfig(json) => Some(json),
+            Self::Cache(io) => Some(io),
+            Self::Status(_) | Self::InvalidIndex | Self::ChecksumMismatch { .. } => None,
+        }
+    }
+}
+impl Display for UpstreamError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Request(request) => write!(f, "request to upstream failed: {request}"),
+            Self::Status(status) => write!(f, "upstream answered {status}"),
+            Self::InvalidConfig(json) => write!(f, "invalid upstream config.json: {json}"),
+            Self::InvalidIndex => f.write_str("upstream index file isn't valid UTF-8"),
+            Self::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "upstream crate file has checksum {actual}, but its index says {expected}"
+            ),
+            Self::Cache(io) => write!(f, "failed to access upstream cache: {io}"),
+        }
+    }
+}
+
+/// Deletes the whole upstream cache, proxied crates keep working
+pub async fn purge_upstream_cache_handler(
+    _admin: Admin,
+    State(state): State<ServerState>,
+) -> Result<Json<PurgedCache>, Response> {
+    let upstream = state
+        .registry
+        .upstream
+        .as_ref()
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "proxying is disabled").into_response())?;
+    let purged = upstream
+        .purge_cache()
+        .await
+        .inspect_err(|e| eprintln!("Failed to purge upstream cache: {e}"))
+        .map_err(|_e| internal_server_error("couldn't purge upstream cache"))?;
+    Ok(Json(purged))
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct PurgedCache {
+    files_deleted: u64,
+    bytes_freed: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use crate::upstream::{
+        evict_files, expand_download_template, find_checksum, sparse_index_path,
+    };
+
+    #[test]
+    fn least_recently_used_files_are_evicted() {
+        let directory =
+            std::env::temp_dir().join(format!("registry_server_upstream_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        let now = SystemTime::now();
+        for (name, age) in [("old", 30), ("recent", 10), ("middle", 20)] {
+            let path = directory.join(name).join("1.0.0");
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, [0; 10]).unwrap();
+            std::fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(now - Duration::from_secs(age))
+                .unwrap();
+        }
+        std::fs::write(directory.join("recent").join(".1.0.1.tmp"), [0; 10]).unwrap();
+        evict_files(&directory, 15).unwrap();
+        assert!(!directory.join("old/1.0.0").exists());
+        assert!(!directory.join("middle/1.0.0").exists());
+        assert!(directory.join("recent/1.0.0").exists());
+        assert!(directory.join("recent/.1.0.1.tmp").exists());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn download_urls_follow_cargo_templates() {
+        let name = "Serde".parse().unwrap();
+        let version = "1.0.0".parse().unwrap();
+        assert_eq!(
+            expand_download_template("https://static.crates.io/crates", &name, &version, "ab"),
+            "https://static.crates.io/crates/Serde/1.0.0/download"
+        );
+        assert_eq!(
+            expand_download_template(
+                "https://example.com/{lowerprefix}/{crate}-{version}.crate?{sha256-checksum}",
+                &name,
+                &version,
+                "ab"
+            ),
+            "https://example.com/se/rd/Serde-1.0.0.crate?ab"
+        );
+    }
+    #[test]
+    fn checksums_are_found_by_version() {
+        let index_file = concat!(
+            r#"{"name":"foo","vers":"1.0.0","cksum":"aa","new_field":1}"#,
+            "\n",
+            r#"{"name":"foo","vers":"1.1.0+build","cksum":"bb"}"#,
+            "\n"
+        );
+        assert_eq!(
+            find_checksum(index_file, &"1.1.0".parse().unwrap()).as_deref(),
+            Some("bb")
+        );
+        assert_eq!(find_checksum(index_file, &"2.0.0".parse().unwrap()), None);
+    }
+    #[test]
+    fn sparse_paths_are_lowercase() {
+        assert_eq!(sparse_index_path(&"Serde".parse().unwrap()), "se/rd/serde");
+        assert_eq!(sparse_index_path(&"abc".parse().unwrap()), "3/a/abc");
+    }
+}
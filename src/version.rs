@@ -0,0 +1,154 @@
+use semver::{BuildMetadata, Op, Version, VersionReq};
+
+/// The version with its build metadata removed
+///
+/// Cargo ignores build metadata when comparing versions, so `1.0.0+build.1` is stored, and
+/// its crate file kept, as `1.0.0`.
+pub fn without_build_metadata(version: &Version) -> Version {
+    Version {
+        build: BuildMetadata::EMPTY,
+        ..version.clone()
+    }
+}
+
+/// Whether `version` takes precedence over every one of `existing`
+///
+/// Pre-releases come before their release, so `1.0.0-rc.1` is older than `1.0.0` but newer
+/// than `0.9.0`. Build metadata is ignored.
+pub fn is_newest<'a>(version: &Version, existing: impl IntoIterator<Item = &'a Version>) -> bool {
+    existing
+        .into_iter()
+        .all(|existing| version.cmp_precedence(existing).is_gt())
+}
+
+/// Whether `version` is newer than every version still `available`, which leaves out yanked ones
+///
+/// Without `count_prereleases` a pre-release only is while no release is available,
+/// so a `3.0.0-alpha` doesn't keep a later `2.1.0` from being the newest.
+pub fn is_newest_available(
+    version: &Version,
+    available: &[&Version],
+    count_prereleases: bool,
+) -> bool {
+    if count_prereleases {
+        return is_newest(version, available.iter().copied());
+    }
+    let mut releases = available.iter().copied().filter(|vers| vers.pre.is_empty());
+    if version.pre.is_empty() {
+        is_newest(version, releases)
+    } else {
+        releases.next().is_none() && is_newest(version, available.iter().copied())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+/// Which publishes may have versions like `1.0.0-alpha.1`
+pub enum PrereleasePolicy {
+    #[default]
+    Allow,
+    /// Only crates that already have a version, so a crate can't start out as a pre-release
+    ExistingCrates,
+    Deny,
+}
+
+/// Whether `req` excludes versions above some bound, unlike `*` or `>=1.0`
+///
+/// Without one, any future breaking release of the dependency is accepted.
+pub fn has_upper_bound(req: &VersionReq) -> bool {
+    req.comparators
+        .iter()
+        .any(|comparator| !matches!(comparator.op, Op::Greater | Op::GreaterEq))
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+/// What a publish does with dependencies whose requirement has no upper bound
+pub enum WildcardDependencyPolicy {
+    /// Publishes with a warning, like cargo does
+    #[default]
+    Warn,
+    /// Fails the publish, like crates.io does for `*`
+    Reject,
+}
+
+#[cfg(test)]
+mod tests {
+    use semver::Version;
+
+    use crate::version::{has_upper_bound, is_newest, is_newest_available, without_build_metadata};
+
+    fn versions(versions: &[&str]) -> Vec<Version> {
+        versions
+            .iter()
+            .map(|version| version.parse().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn wildcard_and_open_ranges_have_no_upper_bound() {
+        for req in ["*", ">=0.0.0", ">1.0", ">=1.0, >2"] {
+            assert!(!has_upper_bound(&req.parse().unwrap()), "{req}");
+        }
+        for req in ["^1.2", "1.*", "~0.3", "=1.0.0", ">=1.0, <2", "<0.5"] {
+            assert!(has_upper_bound(&req.parse().unwrap()), "{req}");
+        }
+    }
+    #[test]
+    fn build_metadata_is_removed() {
+        let version: Version = "1.0.0-alpha.1+build.5".parse().unwrap();
+        assert_eq!(
+            without_build_metadata(&version).to_string(),
+            "1.0.0-alpha.1"
+        );
+    }
+    #[test]
+    fn pre_releases_are_older_than_their_release() {
+        let existing = versions(&["1.0.0"]);
+        assert!(!is_newest(&"1.0.0-rc.1".parse().unwrap(), &existing));
+        assert!(is_newest(&"1.0.1-alpha".parse().unwrap(), &existing));
+        let existing = versions(&["0.9.0", "1.0.0-rc.1"]);
+        assert!(is_newest(&"1.0.0".parse().unwrap(), &existing));
+        assert!(!is_newest(&"1.0.0-beta".parse().unwrap(), &existing));
+    }
+    #[test]
+    fn prereleases_only_count_as_newest_if_configured() {
+        let existing = versions(&["2.0.0", "3.0.0-alpha"]);
+        let available: Vec<&Version> = existing.iter().collect();
+        let release = "2.1.0".parse().unwrap();
+        assert!(!is_newest_available(&release, &available, true));
+        assert!(is_newest_available(&release, &available, false));
+        let pre_release = "3.0.0-beta".parse().unwrap();
+        assert!(is_newest_available(&pre_release, &available, true));
+        assert!(!is_newest_available(&pre_release, &available, false));
+        // Until the first release, pre-releases are all there is
+        let existing = versions(&["0.1.0-alpha"]);
+        let available: Vec<&Version> = existing.iter().collect();
+        assert!(is_newest_available(
+            &"0.1.0-beta".parse().unwrap(),
+            &available,
+            false
+        ));
+    }
+    #[test]
+    fn pre_release_identifiers_are_ordered_by_semver() {
+        let ordered = versions(&[
+            "1.0.0-alpha",
+            "1.0.0-alpha.1",
+            "1.0.0-alpha.beta",
+            "1.0.0-beta",
+            "1.0.0-beta.2",
+            "1.0.0-beta.11",
+            "1.0.0-rc.1",
+            "1.0.0",
+        ]);
+        for (index, version) in ordered.iter().enumerate() {
+            assert!(is_newest(version, &ordered[..index]), "{version}");
+            assert!(!is_newest(version, &ordered[index..]), "{version}");
+        }
+    }
+    #[test]
+    fn build_metadata_doesnt_make_a_version_newer() {
+        let existing = versions(&["1.0.0"]);
+        assert!(!is_newest(&"1.0.0+build.2".parse().unwrap(), &existing));
+        assert!(is_newest(&"1.0.0".parse().unwrap(), &[]));
+    }
+}
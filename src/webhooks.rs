@@ -0,0 +1,176 @@
+use std::{path::Path, time::Duration};
+
+use hmac::{Hmac, Mac};
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::crate_name::CrateName;
+
+/// Header carrying `sha256=<hex HMAC of the body>`
+const SIGNATURE_HEADER: &str = "X-Registry-Signature";
+/// Waits before each retry, the first attempt happens right away
+const RETRY_DELAYS: [Duration; 3] = [
+    Duration::from_secs(5),
+    Duration::from_secs(30),
+    Duration::from_secs(120),
+];
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookEventKind {
+    Publish,
+    Yank,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+/// One entry of the webhook configuration file
+pub struct Webhook {
+    url: String,
+    secret: String,
+    /// Events this webhook receives, all of them if not given
+    events: Option<Vec<WebhookEventKind>>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct WebhookEvent {
+    pub event: WebhookEventKind,
+    #[serde(rename = "crate")]
+    pub krate: CrateName,
+    pub version: Version,
+    pub cksum: String,
+    /// Login of the authenticated publisher
+    pub publisher: Option<String>,
+    pub links: WebhookLinks,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct WebhookLinks {
+    download: String,
+}
+impl WebhookLinks {
+    pub fn new(crate_name: &CrateName, version: &Version) -> Self {
+        Self {
+            download: format!("/api/v1/crates/{crate_name}/{version}/download"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+/// Delivers events to the configured webhooks without blocking the request
+pub struct WebhookDispatcher {
+    webhooks: Vec<Webhook>,
+    client: reqwest::Client,
+}
+
+impl WebhookDispatcher {
+    /// Reads a JSON list of `{"url", "secret", "events"}` objects
+    pub fn from_config_file(path: &Path) -> Result<Self, WebhookConfigError> {
+        let content = std::fs::read(path).map_err(WebhookConfigError::Read)?;
+        let webhooks = serde_json::from_slice(&content).map_err(WebhookConfigError::Parse)?;
+        Ok(Self {
+            webhooks,
+            client: reqwest::Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .build()
+                .map_err(WebhookConfigError::Client)?,
+        })
+    }
+    pub fn is_empty(&self) -> bool {
+        self.webhooks.is_empty()
+    }
+    /// Spawns one delivery task per interested webhook, failures are only logged
+    pub fn notify(&self, event: &WebhookEvent) {
+        let body = match serde_json::to_vec(event) {
+            Ok(body) => body,
+            Err(e) => {
+                eprintln!("Failed to serialize webhook event: {e}");
+                return;
+            }
+        };
+        for webhook in self.webhooks.iter().filter(|webhook| {
+            webhook
+                .events
+                .as_ref()
+                .is_none_or(|events| events.contains(&event.event))
+        }) {
+            let client = self.client.clone();
+            let webhook = webhook.clone();
+            let body = body.clone();
+            tokio::spawn(async move { deliver(&client, &webhook, body).await });
+        }
+    }
+}
+
+async fn deliver(client: &reqwest::Client, webhook: &Webhook, body: Vec<u8>) {
+    let signature = format!("sha256={}", sign(&webhook.secret, &body));
+    let mut delays = RETRY_DELAYS.iter();
+    loop {
+        let result = client
+            .post(&webhook.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(SIGNATURE_HEADER, &signature)
+            .body(body.clone())
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status);
+        let Err(e) = result else {
+            return;
+        };
+        let Some(delay) = delays.next() else {
+            eprintln!("Giving up delivering webhook to {}: {e}", webhook.url);
+            return;
+        };
+        eprintln!(
+            "Failed to deliver webhook to {}, retrying in {delay:?}: {e}",
+            webhook.url
+        );
+        tokio::time::sleep(*delay).await;
+    }
+}
+
+fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    format!("{:x}", mac.finalize().into_bytes())
+}
+
+#[derive(Debug)]
+pub enum WebhookConfigError {
+    Read(std::io::Error),
+    Parse(serde_json::Error),
+    Client(reqwest::Error),
+}
+impl std::error::Error for WebhookConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Read(io) => Some(io),
+            Self::Parse(json) => Some(json),
+            Self::Client(client) => Some(client),
+        }
+    }
+}
+impl std::fmt::Display for WebhookConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Read(io) => write!(f, "failed to read webhook config: {io}"),
+            Self::Parse(json) => write!(f, "invalid webhook config: {json}"),
+            Self::Client(client) => write!(f, "failed to create http client: {client}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::webhooks::sign;
+
+    #[test]
+    fn signature_matches_rfc_4231_test_case_2() {
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+}
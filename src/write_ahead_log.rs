@@ -0,0 +1,118 @@
+use std::fmt::Display;
+
+use sqlx::{Pool, Postgres};
+
+use crate::{
+    index::{append_to_index, index_contains_version, remove_from_index, IndexEntry, IndexError},
+    postgres::{delete_pending_publish, get_pending_publishes, version_exists, PendingPublishId},
+    registry::RegistryConfig,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Resolution {
+    /// The version is in the database, the index was brought up to date
+    Completed,
+    /// The version never made it into the database, index line, crate file and docs were removed
+    Reverted,
+}
+
+/// Makes index and crate file agree with the database for one unfinished publish or deletion
+///
+/// The database is the source of truth: a committed version row means the publish happened
+/// (or the deletion didn't).
+pub async fn resolve_pending_publish(
+    id: PendingPublishId,
+    entry: &IndexEntry,
+    database_connection_pool: &Pool<Postgres>,
+    registry: &RegistryConfig,
+) -> Result<Resolution, RecoveryError> {
+    let repository = registry.index_repository.lock().await;
+    let in_database = version_exists(&entry.name, &entry.vers, database_connection_pool)
+        .await
+        .map_err(RecoveryError::Database)?;
+    let in_index = index_contains_version(&entry.name, &entry.vers, &repository)
+        .await
+        .map_err(RecoveryError::Index)?;
+    let resolution = match (in_database, in_index) {
+        (true, true) => Resolution::Completed,
+        (true, false) => {
+            append_to_index(entry, &repository)
+                .await
+                .map_err(RecoveryError::Index)?;
+            Resolution::Completed
+        }
+        (false, in_index) => {
+            if in_index {
+                let commit_message = format!(
+                    "REVERT CRATE: [{}] version: {}",
+                    entry.name.original_str(),
+                    entry.vers
+                );
+                remove_from_index(&entry.name, &entry.vers, &repository, &commit_message)
+                    .await
+                    .map_err(RecoveryError::Index)?;
+            }
+            registry
+                .crate_storage
+                .delete(&entry.name, &entry.vers)
+                .await
+                .map_err(RecoveryError::CrateFile)?;
+            registry
+                .docs_storage
+                .delete(&entry.name, &entry.vers)
+                .await
+                .map_err(RecoveryError::Docs)?;
+            Resolution::Reverted
+        }
+    };
+    delete_pending_publish(id, database_connection_pool)
+        .await
+        .map_err(RecoveryError::Database)?;
+    Ok(resolution)
+}
+
+/// Resolves publishes interrupted by a crash, meant to run before serving requests
+pub async fn recover_pending_publishes(
+    database_connection_pool: &Pool<Postgres>,
+    registry: &RegistryConfig,
+) -> Result<(), RecoveryError> {
+    let pending = get_pending_publishes(database_connection_pool)
+        .await
+        .map_err(RecoveryError::Database)?;
+    for (id, entry) in pending {
+        let resolution =
+            resolve_pending_publish(id, &entry, database_connection_pool, registry).await?;
+        eprintln!(
+            "Recovered unfinished publish of {} {}: {resolution:?}",
+            entry.name, entry.vers
+        );
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum RecoveryError {
+    Database(sqlx::Error),
+    Index(IndexError),
+    CrateFile(std::io::Error),
+    Docs(std::io::Error),
+}
+impl std::error::Error for RecoveryError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Database(db) => Some(db),
+            Self::Index(index) => Some(index),
+            Self::CrateFile(io) | Self::Docs(io) => Some(io),
+        }
+    }
+}
+impl Display for RecoveryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Database(db) => write!(f, "database error: {db}"),
+            Self::Index(index) => write!(f, "index error: {index}"),
+            Self::CrateFile(io) => write!(f, "failed to remove crate file: {io}"),
+            Self::Docs(io) => write!(f, "failed to remove docs: {io}"),
+        }
+    }
+}
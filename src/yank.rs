@@ -0,0 +1,169 @@
+use axum::{
+    extract::{Extension, Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use semver::Version;
+use serde::Serialize;
+use sqlx::{Postgres, Transaction};
+
+use crate::{
+    audit::{record_failure, AuditAction, AuditContext, AuditEvent, AuditOutcome},
+    auth::{ensure_crate_owner, AuthenticatedUser},
+    concurrency::index_busy,
+    crate_info::VersionPath,
+    crate_name::CrateName,
+    index::set_yanked_in_index,
+    middleware::internal_server_error,
+    postgres::{add_audit_event, get_version_state, set_version_yanked},
+    request_id::RequestId,
+    webhooks::{WebhookEvent, WebhookEventKind, WebhookLinks},
+    ServerState,
+};
+
+pub async fn yank_handler(
+    State(state): State<ServerState>,
+    Path(VersionPath {
+        crate_name,
+        version,
+    }): Path<VersionPath>,
+    user: AuthenticatedUser,
+    Extension(request_id): Extension<RequestId>,
+) -> Result<Json<YankResponse>, Response> {
+    change_yanked(&state, &crate_name, &version, user, request_id, true).await?;
+    Ok(Json(YankResponse { ok: true }))
+}
+
+pub async fn unyank_handler(
+    State(state): State<ServerState>,
+    Path(VersionPath {
+        crate_name,
+        version,
+    }): Path<VersionPath>,
+    user: AuthenticatedUser,
+    Extension(request_id): Extension<RequestId>,
+) -> Result<Json<YankResponse>, Response> {
+    change_yanked(&state, &crate_name, &version, user, request_id, false).await?;
+    Ok(Json(YankResponse { ok: true }))
+}
+
+async fn change_yanked(
+    state: &ServerState,
+    crate_name: &CrateName,
+    version: &Version,
+    user: AuthenticatedUser,
+    request_id: RequestId,
+    yanked: bool,
+) -> Result<(), Response> {
+    let audit = AuditContext {
+        actor: Some(user.login.clone()),
+        request_id,
+    };
+    let result = async {
+        let mut transaction = state
+            .database_connection_pool
+            .begin()
+            .await
+            .map_err(|_e| internal_server_error("couldn't start transaction"))?;
+        ensure_crate_owner(crate_name, &user, &mut transaction).await?;
+        set_yanked(state, crate_name, version, yanked, &audit, transaction).await
+    }
+    .await;
+    if let Err(response) = &result {
+        record_failure(
+            AuditEvent {
+                context: &audit,
+                action: yank_action(yanked),
+                crate_name: Some(crate_name),
+                version: Some(version),
+                outcome: AuditOutcome::from_status(response.status()),
+            },
+            &state.database_connection_pool,
+        )
+        .await;
+    }
+    result
+}
+
+/// Flags the version in database and index, committing the transaction last
+///
+/// Yanking a yanked version (or unyanking one that isn't) only records the audit event.
+pub async fn set_yanked(
+    ServerState {
+        registry,
+        webhooks,
+        index_lock_timeout,
+        ..
+    }: &ServerState,
+    crate_name: &CrateName,
+    version: &Version,
+    yanked: bool,
+    audit: &AuditContext,
+    mut transaction: Transaction<'_, Postgres>,
+) -> Result<(), Response> {
+    let version_state = get_version_state(crate_name, version, &mut *transaction)
+        .await
+        .inspect_err(|e| eprintln!("Failed to get version: {e}"))
+        .map_err(|_e| internal_server_error("couldn't get version"))?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "version doesn't exist").into_response())?;
+    let changed = version_state.yanked != yanked;
+    // Held until the database commit, so index and database change together
+    let repository = registry
+        .index_repository
+        .lock_timeout(*index_lock_timeout)
+        .await
+        .ok_or_else(index_busy)?;
+    if changed {
+        set_version_yanked(crate_name, version, yanked, &mut *transaction)
+            .await
+            .inspect_err(|e| eprintln!("Failed to set yanked: {e}"))
+            .map_err(|_e| internal_server_error("couldn't update version"))?;
+        set_yanked_in_index(crate_name, version, yanked, &repository)
+            .await
+            .inspect_err(|e| eprintln!("Failed to update index: {e}"))
+            .map_err(|_e| internal_server_error("failed to update index"))?;
+    }
+    add_audit_event(
+        AuditEvent {
+            context: audit,
+            action: yank_action(yanked),
+            crate_name: Some(crate_name),
+            version: Some(version),
+            outcome: AuditOutcome::Success,
+        },
+        &mut *transaction,
+    )
+    .await
+    .inspect_err(|e| eprintln!("Failed to record audit event: {e}"))
+    .map_err(|_e| internal_server_error("failed to record audit event"))?;
+    transaction
+        .commit()
+        .await
+        .map_err(|_e| internal_server_error("committing to database failed"))?;
+    drop(repository);
+    if changed && yanked {
+        webhooks.notify(&WebhookEvent {
+            event: WebhookEventKind::Yank,
+            links: WebhookLinks::new(crate_name, version),
+            krate: crate_name.clone(),
+            version: version.clone(),
+            cksum: version_state.cksum,
+            publisher: audit.actor.clone(),
+        });
+    }
+    Ok(())
+}
+
+fn yank_action(yanked: bool) -> AuditAction {
+    if yanked {
+        AuditAction::Yank
+    } else {
+        AuditAction::Unyank
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct YankResponse {
+    ok: bool,
+}
@@ -0,0 +1,97 @@
+use std::time::Duration;
+
+use chrono::{SecondsFormat, Utc};
+use serde_json::Value;
+
+use crate::test_server::{TestServer, ADMIN_TOKEN};
+
+/// Action and crate of the audit events matching the query parameters, newest first
+async fn audit_events(server: &TestServer, parameters: &[(&str, &str)]) -> Vec<(String, String)> {
+    let response = server
+        .client
+        .get(server.url("/api/v1/admin/audit"))
+        .header("Authorization", ADMIN_TOKEN)
+        .query(parameters)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let response: Value = response.json().await.unwrap();
+    response["events"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|event| {
+            (
+                event["action"].as_str().unwrap().to_string(),
+                event["crate"].as_str().unwrap().to_string(),
+            )
+        })
+        .collect()
+}
+
+/// Current time, with some distance to the events before and after it
+async fn checkpoint() -> String {
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let now = Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    now
+}
+
+#[tokio::test]
+async fn audit_log_filters_by_crate_and_time() {
+    let server = TestServer::start().await;
+    let token = server.add_user("alice").await;
+    let start = checkpoint().await;
+    assert_eq!(
+        server
+            .publish(&token, "foo", "1.0.0", b"foo")
+            .await
+            .status(),
+        200
+    );
+    assert_eq!(
+        server
+            .publish(&token, "bar", "1.0.0", b"bar")
+            .await
+            .status(),
+        200
+    );
+    let published = checkpoint().await;
+    let yank = server
+        .client
+        .delete(server.url("/api/v1/crates/foo/1.0.0/yank"))
+        .header("Authorization", &token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(yank.status(), 200);
+    let event = |action: &str, crate_name: &str| (action.to_string(), crate_name.to_string());
+    assert_eq!(
+        audit_events(&server, &[("crate", "foo")]).await,
+        [event("yank", "foo"), event("publish", "foo")]
+    );
+    assert_eq!(
+        audit_events(&server, &[("since", &start), ("until", &published)]).await,
+        [event("publish", "bar"), event("publish", "foo")]
+    );
+    assert_eq!(
+        audit_events(&server, &[("crate", "foo"), ("since", &published)]).await,
+        [event("yank", "foo")]
+    );
+    assert!(audit_events(&server, &[("until", &start)]).await.is_empty());
+}
+
+#[tokio::test]
+async fn audit_log_needs_the_admin_token() {
+    let server = TestServer::start().await;
+    let token = server.add_user("alice").await;
+    let response = server
+        .client
+        .get(server.url("/api/v1/admin/audit"))
+        .header("Authorization", &token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 403);
+}
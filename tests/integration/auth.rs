@@ -0,0 +1,68 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use crate::test_server::TestServer;
+
+#[tokio::test]
+async fn authenticated_requests_record_token_use() {
+    let server = TestServer::start().await;
+    let token = server.add_user("alice").await;
+    assert!(!server.token_used("alice").await);
+    let response = server
+        .client
+        .get(server.url("/api/v1/me/usage"))
+        .header("Authorization", &token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    // Recorded in the background after the response
+    for _ in 0..50 {
+        if server.token_used("alice").await {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("token use wasn't recorded");
+}
+
+#[tokio::test]
+async fn tokens_refresh_only_when_allowed_and_unexpired() {
+    let server = TestServer::start().await;
+    let token = server.add_user("alice").await;
+    let refresh = |token: &str| {
+        server
+            .client
+            .post(server.url("/api/v1/tokens/refresh"))
+            .header("Authorization", token)
+            .send()
+    };
+    // Tokens without expiry have nothing to extend
+    server
+        .execute("UPDATE tokens SET self_refresh = TRUE")
+        .await;
+    assert_eq!(refresh(&token).await.unwrap().status(), 400);
+    server
+        .execute("UPDATE tokens SET expires_at = NOW() + INTERVAL '1 day'")
+        .await;
+    let response = refresh(&token).await.unwrap();
+    assert_eq!(response.status(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    let expires_at: DateTime<Utc> = body["expires_at"].as_str().unwrap().parse().unwrap();
+    assert!(expires_at > Utc::now() + chrono::Duration::days(89));
+    server
+        .execute("UPDATE tokens SET self_refresh = FALSE")
+        .await;
+    assert_eq!(refresh(&token).await.unwrap().status(), 403);
+    server
+        .execute("UPDATE tokens SET self_refresh = TRUE, expires_at = NOW() - INTERVAL '1 day'")
+        .await;
+    assert_eq!(refresh(&token).await.unwrap().status(), 401);
+    // Like after the hourly cleanup of expired tokens
+    server
+        .execute("DELETE FROM tokens WHERE expires_at <= NOW()")
+        .await;
+    assert_eq!(refresh(&token).await.unwrap().status(), 401);
+    assert_eq!(refresh("not-a-token").await.unwrap().status(), 401);
+}
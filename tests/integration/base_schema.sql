@@ -0,0 +1,57 @@
+-- Tables as they were before the first migration, the migrations are applied on top
+CREATE FUNCTION normalize_crate_name(name TEXT) RETURNS TEXT
+    LANGUAGE SQL IMMUTABLE STRICT
+    AS $$ SELECT lower(replace(name, '-', '_')) $$;
+CREATE TABLE crates (
+    crate_id SERIAL PRIMARY KEY,
+    original_name TEXT NOT NULL UNIQUE,
+    description TEXT NOT NULL,
+    documentation TEXT,
+    homepage TEXT,
+    readme TEXT,
+    readme_file TEXT,
+    license TEXT,
+    license_file TEXT,
+    repository TEXT
+);
+CREATE UNIQUE INDEX crates_normalized_name ON crates (normalize_crate_name(original_name));
+CREATE TABLE keywords (
+    crate_id INTEGER NOT NULL REFERENCES crates (crate_id),
+    keyword TEXT NOT NULL
+);
+CREATE TABLE valid_categories (
+    category_id SERIAL PRIMARY KEY,
+    category_name TEXT NOT NULL UNIQUE
+);
+CREATE TABLE crate_categories (
+    crate_id INTEGER NOT NULL REFERENCES crates (crate_id),
+    category_id INTEGER NOT NULL REFERENCES valid_categories (category_id)
+);
+CREATE TABLE versions (
+    crate INTEGER NOT NULL REFERENCES crates (crate_id),
+    vers TEXT NOT NULL,
+    cksum TEXT NOT NULL,
+    links TEXT,
+    rust_version TEXT,
+    PRIMARY KEY (crate, vers)
+);
+CREATE TABLE version_features (
+    crate_id INTEGER NOT NULL,
+    crate_version TEXT NOT NULL,
+    feature_name TEXT NOT NULL,
+    PRIMARY KEY (crate_id, crate_version, feature_name),
+    FOREIGN KEY (crate_id, crate_version) REFERENCES versions (crate, vers)
+);
+CREATE TABLE feature_dependencies (
+    crate_id INTEGER NOT NULL,
+    crate_version TEXT NOT NULL,
+    feature_name TEXT NOT NULL,
+    dependency_name TEXT NOT NULL,
+    FOREIGN KEY (crate_id, crate_version, feature_name) REFERENCES version_features (crate_id, crate_version, feature_name)
+);
+CREATE TABLE version_authors (
+    crate_id INTEGER NOT NULL,
+    version TEXT NOT NULL,
+    author TEXT NOT NULL,
+    FOREIGN KEY (crate_id, version) REFERENCES versions (crate, vers)
+);
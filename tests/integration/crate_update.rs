@@ -0,0 +1,157 @@
+use serde_json::{json, Value};
+
+use crate::test_server::TestServer;
+
+async fn patch_crate(
+    server: &TestServer,
+    token: &str,
+    name: &str,
+    body: Value,
+) -> reqwest::Response {
+    server
+        .client
+        .patch(server.url(&format!("/api/v1/crates/{name}")))
+        .header("Authorization", token)
+        .json(&body)
+        .send()
+        .await
+        .unwrap()
+}
+
+/// Names of the crates listed at `path`
+async fn listed_crates(server: &TestServer, path: &str) -> Vec<String> {
+    let response = server.client.get(server.url(path)).send().await.unwrap();
+    if response.status() == 404 {
+        return Vec::new();
+    }
+    let listing: Value = response.json().await.unwrap();
+    listing["crates"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|krate| krate["name"].as_str().unwrap().to_owned())
+        .collect()
+}
+
+#[tokio::test]
+async fn crate_metadata_is_updated_without_a_new_version() {
+    let server = TestServer::start().await;
+    let token = server.add_user("alice").await;
+    server.add_category("network").await;
+    server.add_category("parsing").await;
+    let metadata = json!({
+        "description": "An HTTP client",
+        "keywords": ["http"],
+        "categories": ["network"],
+    });
+    let response = server
+        .publish_with(&token, "client", "1.0.0", metadata, b"client")
+        .await;
+    assert_eq!(response.status(), 200);
+    let response = patch_crate(
+        &server,
+        &token,
+        "client",
+        json!({
+            "description": "An HTTP and JSON client",
+            "homepage": "https://example.com",
+            "keywords": ["JSON", "http"],
+            "categories": ["parsing"],
+        }),
+    )
+    .await;
+    assert_eq!(response.status(), 200);
+
+    let info: Value = server
+        .client
+        .get(server.url("/api/v1/crates/client"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(info["crate"]["description"], "An HTTP and JSON client");
+    assert_eq!(info["crate"]["homepage"], "https://example.com");
+    assert_eq!(info["versions"], json!(["1.0.0"]));
+    assert_eq!(
+        listed_crates(&server, "/api/v1/keywords/json/crates").await,
+        ["client"]
+    );
+    assert_eq!(
+        listed_crates(&server, "/api/v1/keywords/http/crates").await,
+        ["client"]
+    );
+    assert_eq!(
+        listed_crates(&server, "/api/v1/categories/parsing/crates").await,
+        ["client"]
+    );
+    assert!(listed_crates(&server, "/api/v1/categories/network/crates")
+        .await
+        .is_empty());
+}
+
+#[tokio::test]
+async fn crate_updates_need_an_owner_and_known_categories() {
+    let server = TestServer::start().await;
+    let alice = server.add_user("alice").await;
+    let bob = server.add_user("bob").await;
+    server.publish(&alice, "client", "1.0.0", b"client").await;
+
+    let update = json!({"description": "Taken over"});
+    let response = patch_crate(&server, &bob, "client", update).await;
+    assert_eq!(response.status(), 403);
+
+    let update = json!({"description": "Updated", "categories": ["nonexistent"]});
+    let response = patch_crate(&server, &alice, "client", update).await;
+    assert_eq!(response.status(), 400);
+    let error: Value = response.json().await.unwrap();
+    assert_eq!(
+        error["errors"][0]["detail"],
+        "unknown categories: nonexistent"
+    );
+
+    let info: Value = server
+        .client
+        .get(server.url("/api/v1/crates/client"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_ne!(info["crate"]["description"], "Updated");
+    assert_ne!(info["crate"]["description"], "Taken over");
+}
+
+#[tokio::test]
+async fn updated_links_have_to_be_web_urls() {
+    let server = TestServer::start().await;
+    let token = server.add_user("alice").await;
+    let metadata = json!({"homepage": "https://example.com"});
+    server
+        .publish_with(&token, "client", "1.0.0", metadata, b"client")
+        .await;
+
+    let update =
+        json!({"homepage": "javascript:alert(1)", "repository": "https://git.example.com"});
+    let response = patch_crate(&server, &token, "client", update).await;
+    assert_eq!(response.status(), 400);
+    let error: Value = response.json().await.unwrap();
+    assert_eq!(
+        error["errors"][0]["detail"],
+        "homepage javascript:alert(1) is not an http or https URL"
+    );
+
+    let info: Value = server
+        .client
+        .get(server.url("/api/v1/crates/client"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(info["crate"]["homepage"], "https://example.com");
+    assert!(info["crate"]["repository"].is_null());
+}
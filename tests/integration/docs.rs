@@ -0,0 +1,131 @@
+use flate2::{write::GzEncoder, Compression};
+use reqwest::Response;
+use serde_json::Value;
+
+use crate::test_server::TestServer;
+
+fn docs_archive(files: &[(&str, &str)]) -> Vec<u8> {
+    let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::fast()));
+    for (path, content) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, path, content.as_bytes())
+            .unwrap();
+    }
+    builder.into_inner().unwrap().finish().unwrap()
+}
+
+async fn upload_docs(
+    server: &TestServer,
+    token: &str,
+    version: &str,
+    archive: Vec<u8>,
+) -> Response {
+    server
+        .client
+        .put(server.url(&format!("/api/v1/crates/foo/{version}/docs")))
+        .header("Authorization", token)
+        .body(archive)
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn get_docs(server: &TestServer, path: &str) -> Response {
+    server
+        .client
+        .get(server.url(&format!("/docs/foo/1.0.0/{path}")))
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn uploaded_docs_are_served_and_replaced() {
+    let server = TestServer::start().await;
+    let token = server.add_user("alice").await;
+    for version in ["1.0.0", "1.1.0"] {
+        let response = server
+            .publish(&token, "foo", version, version.as_bytes())
+            .await;
+        assert_eq!(response.status(), 200);
+    }
+    let archive = docs_archive(&[
+        ("./foo/index.html", "<h1>foo</h1>"),
+        ("./static.files/main.js", "main()"),
+    ]);
+    assert_eq!(
+        upload_docs(&server, &token, "1.0.0", archive)
+            .await
+            .status(),
+        200
+    );
+
+    let page = get_docs(&server, "foo/").await;
+    assert_eq!(page.status(), 200);
+    assert_eq!(page.headers()["content-type"], "text/html; charset=utf-8");
+    assert_eq!(page.text().await.unwrap(), "<h1>foo</h1>");
+    let script = get_docs(&server, "static.files/main.js").await;
+    assert_eq!(
+        script.headers()["content-type"],
+        "text/javascript; charset=utf-8"
+    );
+    let info: Value = server
+        .client
+        .get(server.url("/api/v1/crates/foo"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(info["documented_versions"], serde_json::json!(["1.0.0"]));
+
+    let archive = docs_archive(&[("./foo/index.html", "<h1>foo 2</h1>")]);
+    assert_eq!(
+        upload_docs(&server, &token, "1.0.0", archive)
+            .await
+            .status(),
+        200
+    );
+    let page = get_docs(&server, "foo/index.html").await;
+    assert_eq!(page.text().await.unwrap(), "<h1>foo 2</h1>");
+    assert_eq!(
+        get_docs(&server, "static.files/main.js").await.status(),
+        404
+    );
+}
+
+#[tokio::test]
+async fn docs_uploads_are_checked() {
+    let server = TestServer::start().await;
+    let alice = server.add_user("alice").await;
+    let bob = server.add_user("bob").await;
+    let response = server.publish(&alice, "foo", "1.0.0", b"foo").await;
+    assert_eq!(response.status(), 200);
+    let archive = docs_archive(&[("./foo/index.html", "")]);
+    assert_eq!(
+        upload_docs(&server, &bob, "1.0.0", archive.clone())
+            .await
+            .status(),
+        403
+    );
+    assert_eq!(
+        upload_docs(&server, &alice, "2.0.0", archive)
+            .await
+            .status(),
+        404
+    );
+    let response = upload_docs(&server, &alice, "1.0.0", b"not gzip".to_vec()).await;
+    assert_eq!(response.status(), 400);
+    for path in [
+        "foo/index.html",
+        "..%2F..%2Fcrates/foo/1.0.0",
+        "%2Fetc%2Fpasswd",
+    ] {
+        assert_eq!(get_docs(&server, path).await.status(), 404, "{path}");
+    }
+}
@@ -0,0 +1,96 @@
+use serde_json::Value;
+
+use crate::test_server::TestServer;
+
+#[tokio::test]
+async fn head_has_the_download_headers_without_counting() {
+    let server = TestServer::start().await;
+    let token = server.add_user("alice").await;
+    let response = server.publish(&token, "foo", "1.0.0", b"foo 1.0.0").await;
+    assert_eq!(response.status(), 200);
+    let url = server.url("/api/v1/crates/foo/1.0.0/download");
+
+    let head = server.client.head(&url).send().await.unwrap();
+    assert_eq!(head.status(), 200);
+    let get = server.client.get(&url).send().await.unwrap();
+    assert_eq!(get.status(), 200);
+    for header in ["content-length", "content-type", "etag"] {
+        assert_eq!(
+            head.headers().get(header),
+            get.headers().get(header),
+            "{header}"
+        );
+    }
+    assert_eq!(head.headers()["content-length"], "9");
+    let cksum = &server.index_entries("foo").await[0]["cksum"];
+    assert_eq!(head.headers()["etag"], format!("{cksum}"));
+    assert!(head.bytes().await.unwrap().is_empty());
+
+    let downloads: Value = server
+        .client
+        .get(server.url("/api/v1/crates/foo/downloads/total"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(downloads["total_downloads"], 1);
+}
+
+#[tokio::test]
+async fn head_of_missing_version_is_not_found() {
+    let server = TestServer::start().await;
+    let token = server.add_user("alice").await;
+    let response = server.publish(&token, "foo", "1.0.0", b"foo").await;
+    assert_eq!(response.status(), 200);
+    for path in ["foo/2.0.0", "bar/1.0.0"] {
+        let head = server
+            .client
+            .head(server.url(&format!("/api/v1/crates/{path}/download")))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(head.status(), 404, "{path}");
+    }
+}
+
+#[tokio::test]
+async fn version_download_count_is_cacheable() {
+    let server = TestServer::start().await;
+    let token = server.add_user("alice").await;
+    for version in ["1.0.0", "1.1.0"] {
+        let response = server
+            .publish(&token, "foo", version, version.as_bytes())
+            .await;
+        assert_eq!(response.status(), 200);
+    }
+    for _ in 0..2 {
+        let download = server
+            .client
+            .get(server.url("/api/v1/crates/foo/1.0.0/download"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(download.status(), 200);
+    }
+    for (version, count) in [("1.0.0", 2), ("1.1.0", 0)] {
+        let response = server
+            .client
+            .get(server.url(&format!("/api/v1/crates/foo/{version}/download-count")))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers()["cache-control"], "public, max-age=300");
+        let body: Value = response.json().await.unwrap();
+        assert_eq!(body, serde_json::json!({ "downloads": count }), "{version}");
+    }
+    let missing = server
+        .client
+        .get(server.url("/api/v1/crates/foo/2.0.0/download-count"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(missing.status(), 404);
+}
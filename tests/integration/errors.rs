@@ -0,0 +1,49 @@
+use serde_json::{json, Value};
+
+use crate::test_server::TestServer;
+
+#[tokio::test]
+async fn unknown_routes_are_json_not_found() {
+    let server = TestServer::start().await;
+    for path in ["/api/v1/nothing", "/api/v1/crates/foo/1.0.0/nothing", "/"] {
+        let response = server.client.get(server.url(path)).send().await.unwrap();
+        assert_eq!(response.status(), 404, "{path}");
+        let body: Value = response.json().await.unwrap();
+        assert_eq!(
+            body,
+            json!({ "errors": [{ "detail": "not found" }] }),
+            "{path}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn rejections_are_json() {
+    let server = TestServer::start().await;
+    let wrong_method = server
+        .client
+        .delete(server.url("/api/v1/crates/new"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(wrong_method.status(), 405);
+    assert!(wrong_method.headers().contains_key("allow"));
+    let body: Value = wrong_method.json().await.unwrap();
+    assert_eq!(
+        body,
+        json!({ "errors": [{ "detail": "method not allowed" }] })
+    );
+
+    let malformed_version = server
+        .client
+        .get(server.url("/api/v1/crates/foo/not-a-version/download"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(malformed_version.status(), 400);
+    let body: Value = malformed_version.json().await.unwrap();
+    assert!(body["errors"][0]["detail"]
+        .as_str()
+        .unwrap()
+        .contains("version"));
+}
@@ -0,0 +1,14 @@
+//! Runs the registry in-process against a throwaway schema of the database in `DATABASE_URL`
+
+mod audit;
+mod auth;
+mod crate_update;
+mod docs;
+mod download;
+mod errors;
+mod owners;
+mod publish;
+mod readme;
+mod search;
+mod test_server;
+mod yank;
@@ -0,0 +1,96 @@
+use crate::test_server::TestServer;
+use serde_json::json;
+
+#[tokio::test]
+async fn owners_are_invited_by_login_or_email() {
+    let server = TestServer::start().await;
+    let token = server.add_user("alice").await;
+    server.add_user("bob").await;
+    server.add_user("carol").await;
+    server.set_email("carol", "carol@localhost").await;
+    let response = server.publish(&token, "foo", "1.0.0", b"foo").await;
+    assert_eq!(response.status(), 200);
+    let invite = |user: &str| {
+        server
+            .client
+            .put(server.url("/api/v1/crates/foo/owners"))
+            .header("Authorization", &token)
+            .json(&json!({ "users": [user] }))
+            .send()
+    };
+    let response = invite("bob").await.unwrap();
+    assert_eq!(response.status(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        body,
+        json!({ "ok": true, "msg": "user bob has been invited to the crate" })
+    );
+    let response = invite("Carol@localhost").await.unwrap();
+    assert_eq!(response.status(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        body,
+        json!({ "ok": true, "msg": "user carol has been invited to the crate" })
+    );
+    let response = invite("dave@localhost").await.unwrap();
+    assert_eq!(response.status(), 404);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        body,
+        json!({ "errors": [{ "detail": "user 'dave@localhost' not found" }] })
+    );
+}
+
+#[tokio::test]
+async fn pending_invitations_are_listed_for_owners() {
+    let server = TestServer::start().await;
+    let token = server.add_user("alice").await;
+    let bob_token = server.add_user("bob").await;
+    let response = server.publish(&token, "foo", "1.0.0", b"foo").await;
+    assert_eq!(response.status(), 200);
+    let response = server
+        .client
+        .put(server.url("/api/v1/crates/foo/owners"))
+        .header("Authorization", &token)
+        .json(&json!({ "users": ["bob"] }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let invitations = |token: &str| {
+        server
+            .client
+            .get(server.url("/api/v1/crates/foo/owner-invitations"))
+            .header("Authorization", token)
+            .send()
+    };
+    let response = invitations(&bob_token).await.unwrap();
+    assert_eq!(response.status(), 403);
+    let response = invitations(&token).await.unwrap();
+    assert_eq!(response.status(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    let pending = body["crate_owner_invitations"].as_array().unwrap();
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0]["invitee_username"], "bob");
+    assert_eq!(pending[0]["invited_by_username"], "alice");
+    let response = server
+        .client
+        .get(server.url("/api/v1/me/crate_owner_invitations"))
+        .header("Authorization", &bob_token)
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = response.json().await.unwrap();
+    let crate_id = body["crate_owner_invitations"][0]["crate_id"].clone();
+    let response = server
+        .client
+        .put(server.url(&format!("/api/v1/me/crate_owner_invitations/{crate_id}")))
+        .header("Authorization", &bob_token)
+        .json(&json!({ "crate_owner_invite": { "crate_id": crate_id, "accepted": true } }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let body: serde_json::Value = invitations(&token).await.unwrap().json().await.unwrap();
+    assert_eq!(body, json!({ "crate_owner_invitations": [] }));
+}
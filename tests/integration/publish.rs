@@ -0,0 +1,380 @@
+use crate::test_server::{TestServer, ADMIN_TOKEN};
+use serde_json::json;
+
+#[tokio::test]
+async fn publish_succeeds() {
+    let server = TestServer::start().await;
+    let token = server.add_user("alice").await;
+    let response = server.publish(&token, "foo", "1.0.0", b"foo 1.0.0").await;
+    assert_eq!(response.status(), 200);
+    let published: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(published["published"]["name"], "foo");
+    assert_eq!(published["published"]["vers"], "1.0.0");
+    let cksum = &published["published"]["cksum"];
+    assert_eq!(&server.index_entries("foo").await[0]["cksum"], cksum);
+    let stored: serde_json::Value = server
+        .client
+        .get(server.url("/api/v1/crates/foo/1.0.0/checksum"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(&stored["cksum"], cksum);
+    let info: serde_json::Value = server
+        .client
+        .get(server.url("/api/v1/crates/foo"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(info["crate"]["name"], "foo");
+    assert_eq!(info["crate"]["max_version"], "1.0.0");
+    assert_eq!(server.index_entries("foo").await.len(), 1);
+}
+
+#[tokio::test]
+async fn publish_with_duplicate_name_fails() {
+    let server = TestServer::start().await;
+    let token = server.add_user("alice").await;
+    let response = server.publish(&token, "foo-bar", "1.0.0", b"first").await;
+    assert_eq!(response.status(), 200);
+    let same_version = server.publish(&token, "foo-bar", "1.0.0", b"second").await;
+    assert_eq!(same_version.status(), 409);
+    let normalized_name = server.publish(&token, "Foo_Bar", "1.1.0", b"third").await;
+    assert_eq!(normalized_name.status(), 409);
+    assert_eq!(server.index_entries("foo-bar").await.len(), 1);
+}
+
+#[tokio::test]
+async fn publish_with_invalid_crate_name_fails() {
+    let server = TestServer::start().await;
+    let token = server.add_user("alice").await;
+    for name in ["1foo", "foo bar", "", "foo/bar"] {
+        let response = server.publish(&token, name, "1.0.0", b"content").await;
+        assert_eq!(response.status(), 400, "name {name:?} was accepted");
+    }
+}
+
+#[tokio::test]
+async fn download_after_publish_returns_the_file() {
+    let server = TestServer::start().await;
+    let token = server.add_user("alice").await;
+    let file_content = b"not really a tarball";
+    let response = server.publish(&token, "foo", "1.0.0", file_content).await;
+    assert_eq!(response.status(), 200);
+    let download = server
+        .client
+        .get(server.url("/api/v1/crates/foo/1.0.0/download"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(download.status(), 200);
+    assert_eq!(download.bytes().await.unwrap(), file_content.as_slice());
+    let missing = server
+        .client
+        .get(server.url("/api/v1/crates/foo/2.0.0/download"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(missing.status(), 404);
+}
+
+#[tokio::test]
+async fn publish_with_outdated_if_match_fails() {
+    let server = TestServer::start().await;
+    let token = server.add_user("alice").await;
+    let publish_if_match = |version: &'static str, if_match: &'static str| {
+        server
+            .publish_request(&token, "foo", version, json!({}), version.as_bytes())
+            .header("If-Match", if_match)
+            .send()
+    };
+    let new_crate = publish_if_match("1.0.0", "*").await.unwrap();
+    assert_eq!(new_crate.status(), 412);
+    assert_eq!(
+        server
+            .publish(&token, "foo", "1.0.0", b"1.0.0")
+            .await
+            .status(),
+        200
+    );
+    let matching = publish_if_match("1.1.0", "\"1.0.0\"").await.unwrap();
+    assert_eq!(matching.status(), 200);
+    let outdated = publish_if_match("1.2.0", "\"1.0.0\"").await.unwrap();
+    assert_eq!(outdated.status(), 412);
+    let error: serde_json::Value = outdated.json().await.unwrap();
+    assert_eq!(
+        error["errors"][0]["detail"],
+        "max version of the crate is 1.1.0, If-Match expected 1.0.0"
+    );
+    let invalid = publish_if_match("1.2.0", "latest").await.unwrap();
+    assert_eq!(invalid.status(), 400);
+    assert_eq!(server.index_entries("foo").await.len(), 2);
+}
+
+#[tokio::test]
+async fn version_authors_are_listed() {
+    let server = TestServer::start().await;
+    let token = server.add_user("alice").await;
+    let authors = json!(["Alice <alice@localhost>", "Bob"]);
+    let response = server
+        .publish_with(
+            &token,
+            "foo",
+            "1.0.0",
+            json!({ "authors": authors }),
+            b"foo",
+        )
+        .await;
+    assert_eq!(response.status(), 200);
+    let response = server
+        .client
+        .get(server.url("/api/v1/crates/foo/1.0.0/authors"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body, json!({ "authors": authors }));
+    let missing = server
+        .client
+        .get(server.url("/api/v1/crates/foo/2.0.0/authors"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(missing.status(), 404);
+}
+
+#[tokio::test]
+async fn publish_reports_all_validation_errors_at_once() {
+    let server = TestServer::start().await;
+    let token = server.add_user("alice").await;
+    let metadata = json!({
+        "homepage": "not a url",
+        "deps": [{
+            "name": "serde",
+            "version_req": "^1",
+            "features": [],
+            "optional": true,
+            "default_features": true,
+            "target": "cfg(unix",
+            "kind": "normal",
+            "registry": null,
+            "explicit_name_in_toml": null,
+        }],
+        "features": { "std": ["missing?/std"] },
+    });
+    let response = server
+        .publish_with(&token, "foo", "1.0.0", metadata, b"foo")
+        .await;
+    assert_eq!(response.status(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    let details: Vec<&str> = body["errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|error| error["detail"].as_str().unwrap())
+        .collect();
+    assert_eq!(details.len(), 3, "{details:?}");
+    assert!(details.iter().any(|detail| detail.contains("homepage")));
+    assert!(details.iter().any(|detail| detail.contains("cfg(unix")));
+    assert!(details.iter().any(|detail| detail.contains("missing?/std")));
+}
+
+#[tokio::test]
+async fn name_availability_is_reported() {
+    let server = TestServer::start().await;
+    let token = server.add_user("alice").await;
+    let response = server.publish(&token, "foo_bar", "1.0.0", b"foo").await;
+    assert_eq!(response.status(), 200);
+    let response = server
+        .client
+        .post(server.url("/api/v1/admin/reserved_names"))
+        .header("Authorization", ADMIN_TOKEN)
+        .json(&json!({ "pattern": "secret", "allowed_users": ["alice"] }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let availability = |name: &'static str, token: Option<&str>| {
+        let mut request = server
+            .client
+            .get(server.url(&format!("/api/v1/crates/{name}/available")));
+        if let Some(token) = token {
+            request = request.header("Authorization", token);
+        }
+        async move {
+            let response = request.send().await.unwrap();
+            assert_eq!(response.status(), 200);
+            response.json::<serde_json::Value>().await.unwrap()
+        }
+    };
+    assert_eq!(
+        availability("baz", None).await,
+        json!({ "available": true, "reason": null })
+    );
+    assert_eq!(
+        availability("foo_bar", None).await,
+        json!({ "available": false, "reason": "taken" })
+    );
+    assert_eq!(
+        availability("Foo-Bar", None).await,
+        json!({ "available": false, "reason": "taken by a name that normalizes the same" })
+    );
+    assert_eq!(
+        availability("secret", None).await,
+        json!({ "available": false, "reason": "reserved" })
+    );
+    assert_eq!(
+        availability("secret", Some(&token)).await,
+        json!({ "available": true, "reason": null })
+    );
+    assert_eq!(availability("std", None).await["available"], false);
+}
+
+#[tokio::test]
+async fn large_feature_maps_are_stored_completely() {
+    let server = TestServer::start().await;
+    let token = server.add_user("alice").await;
+    let features: serde_json::Map<String, serde_json::Value> = (0..200)
+        .map(|i| {
+            // Listed backwards so sorting them anywhere along the way would show
+            let feature_deps: Vec<String> = (i.max(3) - 3..i)
+                .rev()
+                .map(|j| format!("f{j:03}"))
+                .collect();
+            (format!("f{i:03}"), json!(feature_deps))
+        })
+        .collect();
+    let authors: Vec<String> = (0..20)
+        .map(|i| format!("Author {i} <a{i}@localhost>"))
+        .collect();
+    let response = server
+        .publish_with(
+            &token,
+            "foo",
+            "1.0.0",
+            json!({ "features": features, "authors": authors }),
+            b"foo",
+        )
+        .await;
+    assert_eq!(response.status(), 200);
+    let stored: serde_json::Value = server
+        .client
+        .get(server.url("/api/v1/crates/foo/1.0.0/features"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(stored, json!({ "features": features }));
+    let stored: serde_json::Value = server
+        .client
+        .get(server.url("/api/v1/crates/foo/1.0.0/authors"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(stored, json!({ "authors": authors }));
+}
+
+#[tokio::test]
+async fn corrupted_versions_fail_publishes_gracefully() {
+    let server = TestServer::start().await;
+    let token = server.add_user("alice").await;
+    let response = server.publish(&token, "foo", "1.0.0", b"foo").await;
+    assert_eq!(response.status(), 200);
+    server.execute("UPDATE versions SET vers = 'garbage'").await;
+    let response = server.publish(&token, "foo", "1.1.0", b"foo 1.1").await;
+    assert_eq!(response.status(), 500);
+    let body: serde_json::Value = response.json().await.unwrap();
+    let detail = body["errors"][0]["detail"].as_str().unwrap();
+    assert!(detail.contains("\"garbage\""), "{detail}");
+    assert!(detail.contains("foo"), "{detail}");
+    let response = server.publish(&token, "bar", "1.0.0", b"bar").await;
+    assert_eq!(response.status(), 200);
+    for path in [
+        "/feed.xml",
+        "/api/v1/summary",
+        "/api/v1/crates?sort=downloads",
+    ] {
+        let response = server.client.get(server.url(path)).send().await.unwrap();
+        assert_eq!(response.status(), 500, "{path}");
+    }
+}
+
+#[tokio::test]
+async fn made_up_tokens_share_the_rate_limit_of_their_address() {
+    let server = TestServer::start_with(&[
+        ("REGISTRY_SERVER_PUBLISH_RATE_LIMIT", "1"),
+        ("REGISTRY_SERVER_PUBLISH_RATE_BURST", "2"),
+    ])
+    .await;
+    let token = server.add_user("alice").await;
+    // Charged to the address, then the token gets a bucket of its own
+    let response = server.publish(&token, "foo", "1.0.0", b"foo 1.0.0").await;
+    assert_eq!(response.status(), 200);
+    let response = server.publish("made-up-1", "bar", "1.0.0", b"bar").await;
+    assert_eq!(response.status(), 403);
+    let response = server.publish("made-up-2", "bar", "1.0.0", b"bar").await;
+    assert_eq!(response.status(), 429);
+    for version in ["1.0.1", "1.0.2"] {
+        let response = server
+            .publish(&token, "foo", version, version.as_bytes())
+            .await;
+        assert_eq!(response.status(), 200, "{version}");
+    }
+    let response = server.publish(&token, "foo", "1.0.3", b"foo 1.0.3").await;
+    assert_eq!(response.status(), 429);
+}
+
+#[tokio::test]
+async fn stricter_name_policy_only_applies_to_new_crates() {
+    let mut server =
+        TestServer::start_with(&[("REGISTRY_SERVER_CRATE_NAME_POLICY", "unicode")]).await;
+    let token = server.add_user("alice").await;
+    // Cyrillic 'а' in place of the Latin 'a'
+    let response = server.publish(&token, "pаrser", "1.0.0", b"1.0.0").await;
+    assert_eq!(response.status(), 200);
+    server
+        .restart_with(&[("REGISTRY_SERVER_CRATE_NAME_POLICY", "ascii")])
+        .await;
+    let response = server.publish(&token, "pаrser", "1.1.0", b"1.1.0").await;
+    assert_eq!(response.status(), 200);
+    let response = server.publish(&token, "lеxer", "1.0.0", b"lexer").await;
+    assert_eq!(response.status(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    let detail = body["errors"][0]["detail"].as_str().unwrap();
+    assert!(detail.contains("only ASCII"), "{detail}");
+}
+
+#[tokio::test]
+async fn legacy_crates_with_rust_names_keep_getting_versions() {
+    let server = TestServer::start().await;
+    let token = server.add_user("alice").await;
+    // From before names of crates shipped with Rust were refused
+    server
+        .execute("INSERT INTO crates (original_name, description) VALUES ('core', 'legacy')")
+        .await;
+    server
+        .execute(
+            "INSERT INTO crate_owners (crate_id, user_id)
+            SELECT crate_id, user_id FROM crates, users
+            WHERE original_name = 'core' AND login = 'alice'",
+        )
+        .await;
+    let response = server.publish(&token, "core", "1.0.0", b"core").await;
+    assert_eq!(response.status(), 200);
+    let response = server.publish(&token, "std", "1.0.0", b"std").await;
+    assert_eq!(response.status(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    let detail = body["errors"][0]["detail"].as_str().unwrap();
+    assert!(detail.contains("shipped with Rust"), "{detail}");
+}
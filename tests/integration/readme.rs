@@ -0,0 +1,79 @@
+use flate2::{write::GzEncoder, Compression};
+use serde_json::json;
+
+use crate::test_server::TestServer;
+
+fn crate_file(root: &str, files: &[(&str, &str)]) -> Vec<u8> {
+    let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::fast()));
+    for (path, content) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, format!("{root}/{path}"), content.as_bytes())
+            .unwrap();
+    }
+    builder.into_inner().unwrap().finish().unwrap()
+}
+
+#[tokio::test]
+async fn packaged_readme_is_served_as_markdown() {
+    let server = TestServer::start().await;
+    let token = server.add_user("alice").await;
+    let file = crate_file(
+        "foo-1.0.0",
+        &[
+            ("Cargo.toml", ""),
+            ("docs/README.md", "# foo\n\nDoes things.\n"),
+        ],
+    );
+    let response = server
+        .publish_with(
+            &token,
+            "foo",
+            "1.0.0",
+            json!({ "readme": "outdated", "readme_file": "docs/README.md" }),
+            &file,
+        )
+        .await;
+    assert_eq!(response.status(), 200);
+    let readme = server
+        .client
+        .get(server.url("/api/v1/crates/foo/1.0.0/readme"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(readme.status(), 200);
+    assert_eq!(
+        readme.headers()["content-type"],
+        "text/markdown; charset=utf-8"
+    );
+    assert_eq!(readme.text().await.unwrap(), "# foo\n\nDoes things.\n");
+}
+
+#[tokio::test]
+async fn missing_readme_is_not_found() {
+    let server = TestServer::start().await;
+    let token = server.add_user("alice").await;
+    let file = crate_file("foo-1.0.0", &[("Cargo.toml", "")]);
+    let response = server
+        .publish_with(
+            &token,
+            "foo",
+            "1.0.0",
+            json!({ "readme_file": "README.md" }),
+            &file,
+        )
+        .await;
+    assert_eq!(response.status(), 200);
+    for path in ["foo/1.0.0", "foo/2.0.0", "bar/1.0.0"] {
+        let readme = server
+            .client
+            .get(server.url(&format!("/api/v1/crates/{path}/readme")))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(readme.status(), 404, "{path}");
+    }
+}
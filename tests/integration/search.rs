@@ -0,0 +1,334 @@
+use serde_json::{json, Value};
+
+use crate::test_server::TestServer;
+
+/// Names of the crates found for `query`, in the order of the response
+async fn search(server: &TestServer, query: &str) -> Vec<String> {
+    let response: Value = server
+        .client
+        .get(server.url("/api/v1/crates"))
+        .query(&[("q", query)])
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    response["crates"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|krate| krate["name"].as_str().unwrap().to_string())
+        .collect()
+}
+
+async fn publish_corpus(server: &TestServer, token: &str) {
+    let corpus = [
+        (
+            "fetchy",
+            "An HTTP client with connection pooling",
+            json!(["http", "client"]),
+        ),
+        (
+            "http-server",
+            "A small web server speaking HTTP/1.1",
+            json!(["http", "server"]),
+        ),
+        (
+            "client-tools",
+            "Helpers for writing API clients over HTTP",
+            json!([]),
+        ),
+        (
+            "jsonish",
+            "Parses and prints JSON documents",
+            json!(["json"]),
+        ),
+        (
+            "tls",
+            "Transport layer security for the http client",
+            json!([]),
+        ),
+    ];
+    for (name, description, keywords) in corpus {
+        let metadata = json!({"description": description, "keywords": keywords});
+        let response = server
+            .publish_with(token, name, "1.0.0", metadata, name.as_bytes())
+            .await;
+        assert_eq!(response.status(), 200, "publishing {name} failed");
+    }
+}
+
+#[tokio::test]
+async fn search_ranks_by_relevance() {
+    let server = TestServer::start().await;
+    let token = server.add_user("alice").await;
+    publish_corpus(&server, &token).await;
+    // Name and keywords weigh more than the description, every word has to match
+    let http_client = search(&server, "http client").await;
+    assert_eq!(http_client[0], "fetchy");
+    assert_eq!(http_client.len(), 3);
+    assert!(http_client.contains(&String::from("client-tools")));
+    assert!(http_client.contains(&String::from("tls")));
+    assert_eq!(search(&server, "server").await, ["http-server"]);
+    // Stemmed, "documents" matches "document"
+    assert_eq!(search(&server, "json document").await, ["jsonish"]);
+    assert_eq!(search(&server, "http -client").await, ["http-server"]);
+}
+
+#[tokio::test]
+async fn short_words_and_stop_words_match_substrings() {
+    let server = TestServer::start().await;
+    let token = server.add_user("alice").await;
+    publish_corpus(&server, &token).await;
+    assert_eq!(search(&server, "tl").await, ["tls"]);
+    assert_eq!(search(&server, "jso").await, ["jsonish"]);
+    assert_eq!(search(&server, "the").await, ["tls"]);
+    // Only stop words, a full-text search would find nothing
+    assert_eq!(search(&server, "for the").await, ["tls"]);
+}
+
+#[tokio::test]
+async fn equally_relevant_crates_are_ordered_by_downloads() {
+    let server = TestServer::start().await;
+    let token = server.add_user("alice").await;
+    for name in ["alpha-parser", "beta-parser", "gamma-parser"] {
+        let metadata = json!({"description": "A parser for config files"});
+        let response = server
+            .publish_with(&token, name, "1.0.0", metadata, name.as_bytes())
+            .await;
+        assert_eq!(response.status(), 200);
+    }
+    for (name, downloads) in [("gamma-parser", 2), ("beta-parser", 1)] {
+        for _ in 0..downloads {
+            let download = server
+                .client
+                .get(server.url(&format!("/api/v1/crates/{name}/1.0.0/download")))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(download.status(), 200);
+        }
+    }
+    assert_eq!(
+        search(&server, "config parser").await,
+        ["gamma-parser", "beta-parser", "alpha-parser"]
+    );
+}
+
+#[tokio::test]
+async fn misspelled_names_find_similar_crates_after_matches() {
+    let server = TestServer::start().await;
+    let token = server.add_user("alice").await;
+    for (name, description) in [
+        ("serde", "Serialization framework"),
+        ("tokio", "An asynchronous runtime"),
+        ("serd-utils", "Helpers around serde"),
+    ] {
+        let metadata = json!({"description": description});
+        let response = server
+            .publish_with(&token, name, "1.0.0", metadata, name.as_bytes())
+            .await;
+        assert_eq!(response.status(), 200);
+    }
+    assert_eq!(search(&server, "tokoi").await, ["tokio"]);
+    // The substring match comes first, the near-match below it
+    assert_eq!(search(&server, "serd").await, ["serd-utils", "serde"]);
+    assert!(search(&server, "xyzzy").await.is_empty());
+}
+
+/// Names of the crates found with the query parameters, in the order of the response
+async fn search_with(server: &TestServer, parameters: &[(&str, &str)]) -> Vec<String> {
+    let response = server
+        .client
+        .get(server.url("/api/v1/crates"))
+        .query(parameters)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200, "{parameters:?}");
+    let response: Value = response.json().await.unwrap();
+    response["crates"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|krate| krate["name"].as_str().unwrap().to_string())
+        .collect()
+}
+
+/// Crates in the categories `network` and `parsing`, downloaded as often as listed
+async fn publish_categorized(server: &TestServer, token: &str) {
+    server.add_category("network").await;
+    server.add_category("parsing").await;
+    let crates = [
+        ("http-parse", "network", json!(["http", "parser"]), 3),
+        ("websocket", "network", json!(["ws"]), 1),
+        ("json-parse", "parsing", json!(["json", "parser"]), 2),
+        ("toml-parse", "parsing", json!(["toml", "parser"]), 0),
+    ];
+    for (name, category, keywords, downloads) in crates {
+        let metadata = json!({
+            "description": format!("The {name} crate"),
+            "categories": [category],
+            "keywords": keywords,
+        });
+        let response = server
+            .publish_with(token, name, "1.0.0", metadata, name.as_bytes())
+            .await;
+        assert_eq!(response.status(), 200);
+        for _ in 0..downloads {
+            let url = server.url(&format!("/api/v1/crates/{name}/1.0.0/download"));
+            assert_eq!(server.client.get(url).send().await.unwrap().status(), 200);
+        }
+    }
+}
+
+#[tokio::test]
+async fn search_filters_by_category_and_keyword() {
+    let server = TestServer::start().await;
+    let token = server.add_user("alice").await;
+    publish_categorized(&server, &token).await;
+    assert_eq!(
+        search_with(&server, &[("category", "network")]).await,
+        ["http-parse", "websocket"]
+    );
+    assert_eq!(
+        search_with(&server, &[("keyword", "Parser")]).await,
+        ["http-parse", "json-parse", "toml-parse"]
+    );
+    assert_eq!(
+        search_with(&server, &[("category", "parsing"), ("keyword", "json")]).await,
+        ["json-parse"]
+    );
+    assert!(
+        search_with(&server, &[("category", "network"), ("keyword", "toml")])
+            .await
+            .is_empty()
+    );
+    assert!(search_with(&server, &[("category", "unknown")])
+        .await
+        .is_empty());
+}
+
+#[tokio::test]
+async fn search_filters_combine_with_queries() {
+    let server = TestServer::start().await;
+    let token = server.add_user("alice").await;
+    publish_categorized(&server, &token).await;
+    // Substring search
+    assert_eq!(
+        search_with(&server, &[("q", "pars"), ("category", "parsing")]).await,
+        ["json-parse", "toml-parse"]
+    );
+    // Full-text search
+    assert_eq!(
+        search_with(&server, &[("q", "crate"), ("keyword", "parser")]).await,
+        ["http-parse", "json-parse", "toml-parse"]
+    );
+    assert_eq!(
+        search_with(
+            &server,
+            &[
+                ("q", "parse"),
+                ("category", "network"),
+                ("keyword", "parser")
+            ]
+        )
+        .await,
+        ["http-parse"]
+    );
+    // Near-matches of the name have to pass the filters too
+    assert_eq!(
+        search_with(&server, &[("q", "websockt"), ("category", "network")]).await,
+        ["websocket"]
+    );
+    assert!(
+        search_with(&server, &[("q", "websockt"), ("category", "parsing")])
+            .await
+            .is_empty()
+    );
+}
+
+#[tokio::test]
+async fn search_sorts_filtered_results() {
+    let server = TestServer::start().await;
+    let token = server.add_user("alice").await;
+    publish_categorized(&server, &token).await;
+    let sorted = |sort: &'static str| {
+        let server = &server;
+        async move {
+            search_with(
+                server,
+                &[("q", "parse"), ("keyword", "parser"), ("sort", sort)],
+            )
+            .await
+        }
+    };
+    assert_eq!(
+        sorted("downloads").await,
+        ["http-parse", "json-parse", "toml-parse"]
+    );
+    // Published in this order
+    assert_eq!(
+        sorted("recent").await,
+        ["toml-parse", "json-parse", "http-parse"]
+    );
+    assert_eq!(
+        sorted("alphabetical").await,
+        ["http-parse", "json-parse", "toml-parse"]
+    );
+    // Equally relevant, so by downloads
+    assert_eq!(
+        sorted("relevance").await,
+        ["http-parse", "json-parse", "toml-parse"]
+    );
+    assert_eq!(
+        search_with(&server, &[("category", "parsing"), ("sort", "downloads")]).await,
+        ["json-parse", "toml-parse"]
+    );
+}
+
+#[tokio::test]
+async fn unknown_sort_is_rejected() {
+    let server = TestServer::start().await;
+    let response = server
+        .client
+        .get(server.url("/api/v1/crates"))
+        .query(&[("q", "parse"), ("sort", "random")])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+    let error: Value = response.json().await.unwrap();
+    assert!(error["errors"][0]["detail"]
+        .as_str()
+        .unwrap()
+        .contains("unknown variant `random`"));
+}
+
+#[tokio::test]
+async fn keywords_differing_in_case_are_merged() {
+    let server = TestServer::start().await;
+    let token = server.add_user("alice").await;
+    let metadata = json!({"description": "An HTTP client", "keywords": ["HTTP", "http", "web"]});
+    let response = server
+        .publish_with(&token, "client", "1.0.0", metadata, b"client")
+        .await;
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(
+        body["warnings"]["other"],
+        json!(["keywords HTTP, http are all stored as http"])
+    );
+    let listing: Value = server
+        .client
+        .get(server.url("/api/v1/keywords/Http/crates"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(listing["meta"]["total"], 1);
+    assert_eq!(listing["crates"][0]["name"], "client");
+}
@@ -0,0 +1,356 @@
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use reqwest::{Client, RequestBuilder, Response};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use sqlx::{Connection, PgConnection};
+use tokio::{net::TcpListener, sync::Mutex, task::JoinHandle};
+
+/// Configuration is read from the environment, tests starting at once would mix it up
+static STARTUP: Mutex<()> = Mutex::const_new(());
+
+/// Token of the admin API of every test server
+pub const ADMIN_TOKEN: &str = "admin-token";
+
+const GIT_IDENTITY: [(&str, &str); 4] = [
+    ("GIT_AUTHOR_NAME", "registry test"),
+    ("GIT_AUTHOR_EMAIL", "test@localhost"),
+    ("GIT_COMMITTER_NAME", "registry test"),
+    ("GIT_COMMITTER_EMAIL", "test@localhost"),
+];
+
+/// The registry on a random port with its own database schema, index, crate and docs storage
+///
+/// Everything is removed again when it is dropped.
+pub struct TestServer {
+    pub client: Client,
+    address: SocketAddr,
+    database_url: String,
+    schema: String,
+    directory: PathBuf,
+    server: JoinHandle<()>,
+}
+impl TestServer {
+    pub async fn start() -> Self {
+        Self::start_with(&[]).await
+    }
+    /// Starts with additional environment variables, which only this server sees
+    pub async fn start_with(settings: &[(&str, &str)]) -> Self {
+        let database_url =
+            std::env::var("DATABASE_URL").expect("DATABASE_URL has to point to a test database");
+        let id = uuid::Uuid::new_v4().simple().to_string();
+        let schema = format!("registry_test_{id}");
+        let directory = std::env::temp_dir().join(format!("registry_server_test_{id}"));
+        let repository_path = directory.join("index");
+        std::fs::create_dir_all(&repository_path).unwrap();
+        for git_arguments in [
+            &["init", "--quiet"][..],
+            &["commit", "--quiet", "--allow-empty", "--message", "init"],
+        ] {
+            let git = Command::new("git")
+                .args(git_arguments)
+                .current_dir(&repository_path)
+                .envs(GIT_IDENTITY)
+                .status()
+                .unwrap();
+            assert!(git.success(), "git {git_arguments:?} failed");
+        }
+        create_schema(&database_url, &schema).await;
+        let (address, server) = serve(&database_url, &schema, &directory, settings).await;
+        Self {
+            client: Client::new(),
+            address,
+            database_url,
+            schema,
+            directory,
+            server,
+        }
+    }
+    /// Stops the server and starts it again with `settings`, keeping database, index and storage
+    pub async fn restart_with(&mut self, settings: &[(&str, &str)]) {
+        self.server.abort();
+        let (address, server) =
+            serve(&self.database_url, &self.schema, &self.directory, settings).await;
+        self.address = address;
+        self.server = server;
+    }
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{path}", self.address)
+    }
+    /// Creates a user, returns its API token
+    pub async fn add_user(&self, login: &str) -> String {
+        let token = format!("token-of-{login}");
+        let mut connection = self.connect().await;
+        sqlx::query("INSERT INTO users (login) VALUES ($1)")
+            .bind(login)
+            .execute(&mut connection)
+            .await
+            .unwrap();
+        sqlx::query(
+            "INSERT INTO tokens (user_id, token_hash)
+            SELECT user_id, $2 FROM users WHERE login = $1",
+        )
+        .bind(login)
+        .bind(format!("{:x}", Sha256::digest(token.as_bytes())))
+        .execute(&mut connection)
+        .await
+        .unwrap();
+        token
+    }
+    pub async fn set_email(&self, login: &str, email: &str) {
+        sqlx::query("UPDATE users SET email = $2 WHERE login = $1")
+            .bind(login)
+            .bind(email)
+            .execute(&mut self.connect().await)
+            .await
+            .unwrap();
+    }
+    /// Runs SQL against the test's schema, for states the API can't produce
+    pub async fn execute(&self, query: &str) {
+        sqlx::query(query)
+            .execute(&mut self.connect().await)
+            .await
+            .unwrap();
+    }
+    /// Whether a token of the user has authenticated a request
+    pub async fn token_used(&self, login: &str) -> bool {
+        sqlx::query_scalar(
+            "SELECT bool_or(tokens.last_used_at IS NOT NULL) FROM tokens
+            JOIN users ON users.user_id = tokens.user_id
+            WHERE users.login = $1",
+        )
+        .bind(login)
+        .fetch_one(&mut self.connect().await)
+        .await
+        .unwrap()
+    }
+    /// Makes `name` a category crates can be published in
+    pub async fn add_category(&self, name: &str) {
+        sqlx::query("INSERT INTO valid_categories (category_name) VALUES ($1)")
+            .bind(name)
+            .execute(&mut self.connect().await)
+            .await
+            .unwrap();
+    }
+    /// Publishes with the metadata cargo sends for a crate without dependencies or features
+    pub async fn publish(
+        &self,
+        token: &str,
+        name: &str,
+        version: &str,
+        file_content: &[u8],
+    ) -> Response {
+        self.publish_with(token, name, version, json!({}), file_content)
+            .await
+    }
+    /// Publishes with the fields of `metadata` replacing the defaults of [`Self::publish`]
+    pub async fn publish_with(
+        &self,
+        token: &str,
+        name: &str,
+        version: &str,
+        metadata: Value,
+        file_content: &[u8],
+    ) -> Response {
+        self.publish_request(token, name, version, metadata, file_content)
+            .send()
+            .await
+            .unwrap()
+    }
+    /// Publish request of [`Self::publish_with`], for adding headers before sending
+    pub fn publish_request(
+        &self,
+        token: &str,
+        name: &str,
+        version: &str,
+        metadata: Value,
+        file_content: &[u8],
+    ) -> RequestBuilder {
+        let mut full_metadata = json!({
+            "name": name,
+            "vers": version,
+            "deps": [],
+            "features": {},
+            "authors": ["Test <test@localhost>"],
+            "description": format!("{name} for testing"),
+            "documentation": null,
+            "homepage": null,
+            "readme": null,
+            "readme_file": null,
+            "keywords": [],
+            "categories": [],
+            "license": "MIT",
+            "license_file": null,
+            "repository": null,
+            "badges": {},
+            "links": null,
+            "rust_version": null,
+        });
+        if let (Some(fields), Value::Object(replaced)) = (full_metadata.as_object_mut(), metadata) {
+            fields.extend(replaced);
+        }
+        let metadata = full_metadata.to_string();
+        let mut body = Vec::new();
+        body.extend((metadata.len() as u32).to_le_bytes());
+        body.extend(metadata.as_bytes());
+        body.extend((file_content.len() as u32).to_le_bytes());
+        body.extend(file_content);
+        self.client
+            .put(self.url("/api/v1/crates/new"))
+            .header("Authorization", token)
+            .body(body)
+    }
+    /// Lines of the crate's index file, parsed
+    pub async fn index_entries(&self, name: &str) -> Vec<Value> {
+        let response = self
+            .client
+            .get(self.url(&format!("/api/v1/crates/{name}/index")))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        response
+            .text()
+            .await
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+    async fn connect(&self) -> PgConnection {
+        let mut connection = PgConnection::connect(&self.database_url).await.unwrap();
+        sqlx::raw_sql(&format!("SET search_path = {}, public", self.schema))
+            .execute(&mut connection)
+            .await
+            .unwrap();
+        connection
+    }
+}
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.server.abort();
+        let database_url = self.database_url.clone();
+        let schema = self.schema.clone();
+        // Drop can't wait for the test's runtime, the schema is removed on a runtime of its own
+        let dropped = std::thread::spawn(move || {
+            tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap()
+                .block_on(async {
+                    let mut connection = PgConnection::connect(&database_url).await?;
+                    sqlx::raw_sql(&format!("DROP SCHEMA {schema} CASCADE"))
+                        .execute(&mut connection)
+                        .await
+                })
+        })
+        .join();
+        if !matches!(dropped, Ok(Ok(_))) {
+            eprintln!("Failed to drop test schema {}", self.schema);
+        }
+        let _ = std::fs::remove_dir_all(&self.directory);
+    }
+}
+
+/// Starts the registry on a random port, configured for the schema and directory of a test
+async fn serve(
+    database_url: &str,
+    schema: &str,
+    directory: &Path,
+    settings: &[(&str, &str)],
+) -> (SocketAddr, JoinHandle<()>) {
+    let repository_path = directory.join("index");
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let separator = if database_url.contains('?') { '&' } else { '?' };
+    let state = {
+        let _startup = STARTUP.lock().await;
+        for (variable, value) in [
+            ("REGISTRY_SERVER_IP", address.ip().to_string()),
+            ("REGISTRY_SERVER_PORT", address.port().to_string()),
+            (
+                "REGISTRY_SERVER_DATABASE_URL",
+                format!("{database_url}{separator}options=-c%20search_path%3D{schema}%2Cpublic"),
+            ),
+            (
+                "REGISTRY_SERVER_REPOSITORY_PATH",
+                repository_path.display().to_string(),
+            ),
+            (
+                "REGISTRY_SERVER_CRATE_STORAGE_PATH",
+                directory.join("crates").display().to_string(),
+            ),
+            (
+                "REGISTRY_SERVER_DOCS_STORAGE_PATH",
+                directory.join("docs").display().to_string(),
+            ),
+            ("REGISTRY_SERVER_ADMIN_TOKEN", ADMIN_TOKEN.to_string()),
+        ] {
+            std::env::set_var(variable, value);
+        }
+        // Index commits are made by git processes of the server
+        for (variable, value) in GIT_IDENTITY {
+            std::env::set_var(variable, value);
+        }
+        for (variable, value) in settings {
+            std::env::set_var(variable, value);
+        }
+        let state = registry_server::state_from_env().await;
+        for (variable, _) in settings {
+            std::env::remove_var(variable);
+        }
+        state
+    };
+    let router = registry_server::router(state);
+    let server = tokio::spawn(async move {
+        axum::serve(
+            listener,
+            router.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .await
+        .unwrap();
+    });
+    (address, server)
+}
+
+/// Applies the base schema and every migration in a new schema
+async fn create_schema(database_url: &str, schema: &str) {
+    let mut connection = PgConnection::connect(database_url).await.unwrap();
+    {
+        // Extensions belong to the database, one created in a test schema would go with it
+        let _startup = STARTUP.lock().await;
+        sqlx::raw_sql(
+            "CREATE EXTENSION IF NOT EXISTS fuzzystrmatch SCHEMA public;
+            CREATE EXTENSION IF NOT EXISTS pg_trgm SCHEMA public",
+        )
+        .execute(&mut connection)
+        .await
+        .unwrap();
+    }
+    sqlx::raw_sql(&format!(
+        "CREATE SCHEMA {schema}; SET search_path = {schema}, public"
+    ))
+    .execute(&mut connection)
+    .await
+    .unwrap();
+    let manifest_directory = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let mut scripts = vec![manifest_directory.join("tests/integration/base_schema.sql")];
+    let mut migrations: Vec<_> = std::fs::read_dir(manifest_directory.join("migrations"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "sql"))
+        .collect();
+    migrations.sort();
+    scripts.extend(migrations);
+    for script in scripts {
+        let sql = std::fs::read_to_string(&script).unwrap();
+        sqlx::raw_sql(&sql)
+            .execute(&mut connection)
+            .await
+            .unwrap_or_else(|e| panic!("{} failed: {e}", script.display()));
+    }
+}
@@ -0,0 +1,84 @@
+use crate::test_server::TestServer;
+
+#[tokio::test]
+async fn yank_changes_the_index_entry() {
+    let server = TestServer::start().await;
+    let token = server.add_user("alice").await;
+    for version in ["1.0.0", "1.1.0"] {
+        let response = server
+            .publish(&token, "foo", version, version.as_bytes())
+            .await;
+        assert_eq!(response.status(), 200);
+    }
+    let yank = server
+        .client
+        .delete(server.url("/api/v1/crates/foo/1.0.0/yank"))
+        .header("Authorization", &token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(yank.status(), 200);
+    let yanked: Vec<_> = server
+        .index_entries("foo")
+        .await
+        .into_iter()
+        .map(|entry| (entry["vers"].clone(), entry["yanked"].clone()))
+        .collect();
+    assert_eq!(
+        yanked,
+        [
+            ("1.0.0".into(), true.into()),
+            ("1.1.0".into(), false.into())
+        ]
+    );
+}
+
+/// Crate metadata is only kept from older versions, with a warning saying so
+async fn publish_warnings(server: &TestServer, token: &str, version: &str) -> serde_json::Value {
+    let response = server
+        .publish(token, "foo", version, version.as_bytes())
+        .await;
+    assert_eq!(response.status(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    body["warnings"]["other"].clone()
+}
+
+#[tokio::test]
+async fn yanked_versions_dont_count_as_newest() {
+    let server = TestServer::start().await;
+    let token = server.add_user("alice").await;
+    for version in ["1.0.0", "2.0.0"] {
+        publish_warnings(&server, &token, version).await;
+    }
+    let yank = server
+        .client
+        .delete(server.url("/api/v1/crates/foo/2.0.0/yank"))
+        .header("Authorization", &token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(yank.status(), 200);
+    assert_eq!(
+        publish_warnings(&server, &token, "1.9.1").await,
+        serde_json::json!([])
+    );
+    let older = publish_warnings(&server, &token, "1.5.0").await;
+    assert_eq!(older.as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn prereleases_count_as_newest_unless_configured() {
+    for (count_prereleases, warnings) in [("true", 1), ("false", 0)] {
+        let server = TestServer::start_with(&[(
+            "REGISTRY_SERVER_PRERELEASES_COUNT_AS_NEWEST",
+            count_prereleases,
+        )])
+        .await;
+        let token = server.add_user("alice").await;
+        for version in ["2.0.0", "3.0.0-alpha"] {
+            publish_warnings(&server, &token, version).await;
+        }
+        let stable = publish_warnings(&server, &token, "2.1.0").await;
+        assert_eq!(stable.as_array().unwrap().len(), warnings, "{stable}");
+    }
+}